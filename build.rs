@@ -0,0 +1,25 @@
+#[cfg(feature = "grpc")]
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/contacts.proto");
+    #[cfg(feature = "grpc")]
+    generate_grpc();
+}
+
+/// Generates the gRPC service of `proto/contacts.proto`, compiling it with
+/// protox so that building needs no `protoc`.
+#[cfg(feature = "grpc")]
+fn generate_grpc() {
+    let descriptors =
+        protox::compile(["contacts.proto"], ["proto"]).expect("a valid contacts.proto");
+    let path = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR to be set")).join("contacts.bin");
+    fs::write(&path, prost::Message::encode_to_vec(&descriptors))
+        .expect("to write the file descriptor set");
+    let mut config = prost_build::Config::new();
+    config.file_descriptor_set_path(&path).skip_protoc_run();
+    tonic_build::configure()
+        .build_client(false)
+        .compile_with_config(config, &["proto/contacts.proto"], &["proto"])
+        .expect("to generate the gRPC service");
+}
@@ -1,16 +1,63 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    iter, mem,
+    sync::Arc,
+};
+
 use axum::{
     extract::{FromRef, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::{IntoResponse, Redirect, Response},
     routing::{delete, get, post},
-    Form, Router,
+    Json, Router,
 };
 use axum_flash::{Flash, IncomingFlashes, Level};
-use axum_htmx::HxTrigger;
+use axum_htmx::{HxRequest, HxTrigger};
 use axum_template::{engine::Engine, Key, RenderHtml};
-use minijinja::{path_loader, Environment};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use minijinja::{
+    path_loader,
+    value::{Kwargs, Value, ViaDeserialize},
+    Environment, HtmlEscape,
+};
 use tower_http::services::ServeDir;
 
-use crate::model::{Contact, MemContactRepo, SharedContactRepo};
+use crate::{
+    config::Config,
+    model::{
+        format_phone, highlights, initials, normalize_text, possible_duplicates, regex_pattern,
+        Address, Contact, ContactFilter, ContactKey, Cursor, CustomField, Direction,
+        DisposableDomains, EmailAddress, Group, IdStrategy, ImportantDate, MailDomains, NameOrder,
+        Page, PhoneNumber, PhoneRegion, RepoError, SearchQuery, SharedAttachmentRepo,
+        SharedContactRepo, SharedCustomFieldRepo, SharedDisposableDomains, SharedGroupRepo,
+        SharedHistoryRepo, SharedPhotoRepo, SharedSavedSearchRepo, SocialProfile, Sort, SortBy,
+        StoreKey, ValidationErrors, DEFAULT_FUZZY_THRESHOLD,
+    },
+};
+
+mod admin;
+mod api;
+mod attachments;
+mod batch;
+mod dates;
+mod etag;
+mod fields;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod groups;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod history;
+mod jsonapi;
+mod markdown;
+mod photos;
+mod searches;
+mod socials;
+mod suggest;
+mod validate;
+
+use photos::{ContactForm, PhotoChange};
 
 pub type AppEngine = Engine<Environment<'static>>;
 
@@ -18,18 +65,114 @@ pub type AppEngine = Engine<Environment<'static>>;
 pub struct AppState {
     engine: AppEngine,
     contact_repo: SharedContactRepo,
+    groups: SharedGroupRepo,
+    fields: SharedCustomFieldRepo,
+    searches: SharedSavedSearchRepo,
+    photos: SharedPhotoRepo,
+    attachments: SharedAttachmentRepo,
+    history: SharedHistoryRepo,
     flash_config: axum_flash::Config,
+    admin_token: Option<Arc<str>>,
+    /// To read encrypted backups.
+    store_key: Option<StoreKey>,
+    id_strategy: IdStrategy,
+    name_order: NameOrder,
+    /// See [`Config::author_header`].
+    #[from_ref(skip)]
+    author_header: Option<Arc<str>>,
+    /// Set if [`Config::email_mx_check`] is.
+    mail_domains: Option<Arc<MailDomains>>,
+    disposable_domains: SharedDisposableDomains,
+    /// See [`Config::phone_region`].
+    phone_region: Option<PhoneRegion>,
+    /// See [`Config::fuzzy_threshold`].
+    fuzzy_threshold: f64,
+}
+
+/// The repos the app keeps what isn't a contact in, each opened for the
+/// storage of the contacts.
+pub struct Repos {
+    pub groups: SharedGroupRepo,
+    pub fields: SharedCustomFieldRepo,
+    pub searches: SharedSavedSearchRepo,
+    pub photos: SharedPhotoRepo,
+    pub attachments: SharedAttachmentRepo,
+    pub history: SharedHistoryRepo,
 }
 
-pub fn create_app() -> Router {
+pub fn create_app(repo: SharedContactRepo, repos: Repos, config: &Config) -> Router {
+    let Repos {
+        groups,
+        fields,
+        searches,
+        photos,
+        attachments,
+        history,
+    } = repos;
     let mut jinja = Environment::new();
     jinja.set_loader(path_loader("templates"));
     jinja.add_function("get_flashed_messages", get_flashed_messages);
-    let repo = MemContactRepo::shared_from_path("contacts.json");
-    Router::new()
+    jinja.add_filter("datetime", datetime);
+    jinja.add_filter("local_time", local_time);
+    jinja.add_filter("age", age);
+    jinja.add_filter("markdown", render_markdown);
+    jinja.add_filter("highlight", highlight);
+    jinja.add_filter("avatar", photos::avatar);
+    jinja.add_filter("filesize", attachments::filesize);
+    let name_order = config.name_order;
+    jinja.add_filter("display_name", move |contact: ViaDeserialize<Contact>| {
+        contact.display_name(name_order)
+    });
+    jinja.add_filter("social_url", socials::social_url);
+    let disposable_domains = Arc::new(match &config.disposable_domains_file {
+        Some(file) => {
+            DisposableDomains::from_file(file).expect("a readable DISPOSABLE_DOMAINS_FILE")
+        }
+        None => DisposableDomains::bundled(),
+    });
+    let domains = disposable_domains.clone();
+    jinja.add_function("disposable", move |email: &str| {
+        domains.is_disposable(email)
+    });
+    let phone_region = config.phone_region;
+    jinja.add_filter("phone", move |phone: &str| {
+        format_phone(phone, phone_region)
+    });
+    jinja.add_global("social_networks", Value::from_iter(socials::NETWORKS));
+    jinja.add_global(
+        "timezones",
+        Value::from_iter(chrono_tz::TZ_VARIANTS.iter().map(|tz| tz.name())),
+    );
+    let state = AppState {
+        engine: Engine::from(jinja),
+        contact_repo: repo,
+        groups,
+        fields,
+        searches,
+        photos,
+        attachments,
+        history,
+        flash_config: axum_flash::Config::new(axum_flash::Key::generate()),
+        admin_token: config.admin_token.as_deref().map(Arc::from),
+        store_key: config.storage_options.key.clone(),
+        id_strategy: config.id_strategy,
+        name_order: config.name_order,
+        author_header: config.author_header.as_deref().map(Arc::from),
+        mail_domains: config.email_mx_check.then(|| Arc::new(MailDomains::new())),
+        disposable_domains,
+        phone_region,
+        fuzzy_threshold: config.fuzzy_threshold.unwrap_or(DEFAULT_FUZZY_THRESHOLD),
+    };
+    let router = Router::new()
         .route("/", get(|| async { Redirect::to("/contacts") }))
         .route("/contacts", get(contacts))
+        .route("/contacts/archived", get(contacts_archived_get))
         .route("/contacts/count", get(contacts_count_get))
+        .route("/contacts/tags", get(contacts_tags_get))
+        .route("/contacts/phone-row", get(phone_row_get))
+        .route("/contacts/email-row", get(email_row_get))
+        .route("/contacts/social-row", get(socials::social_row_get))
+        .route("/contacts/address-row", get(address_row_get))
         .route(
             "/contacts/new",
             get(get_contacts_new).post(post_contacts_new),
@@ -39,31 +182,190 @@ pub fn create_app() -> Router {
             get(contacts_edit_get).post(contacts_edit_post),
         )
         .route("/contacts/:contact_id/email", get(contacts_email_get))
+        .route("/contacts/:contact_id/star", post(contacts_star_post))
+        .route("/contacts/:contact_id/archive", post(contacts_archive_post))
         .route(
             "/contacts/:contact_id",
             delete(contacts_delete).get(contact_view),
         )
+        .merge(groups::routes())
+        .merge(fields::routes())
+        .merge(searches::routes())
+        .merge(photos::routes())
+        .merge(attachments::routes())
+        .merge(dates::routes())
+        .merge(history::routes())
+        .merge(validate::routes())
+        .merge(suggest::routes())
+        .merge(admin::routes())
+        .merge(api::routes())
+        .merge(batch::routes());
+    #[cfg(feature = "graphql")]
+    let router = router.merge(graphql::routes(config.graphql_playground));
+    #[cfg(feature = "grpc")]
+    let router = router.merge(grpc::routes(state.clone()));
+    router
         .nest_service("/static", ServeDir::new("static"))
-        .with_state(AppState {
-            engine: Engine::from(jinja),
-            contact_repo: repo,
-            flash_config: axum_flash::Config::new(axum_flash::Key::generate()),
-        })
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            history::record_author,
+        ))
+        .with_state(state)
 }
 
+/// The texts of the flashed messages, or with `with_levels=true` their
+/// `(level, text)` pairs, the level lowercase as in `warning`.
 fn get_flashed_messages(
     state: &minijinja::State,
-    _args: Vec<minijinja::Value>,
+    kwargs: Kwargs,
 ) -> Result<minijinja::Value, minijinja::Error> {
+    let with_levels = kwargs.get::<Option<bool>>("with_levels")?.unwrap_or(false);
+    kwargs.assert_all_used()?;
     match state.lookup("messages") {
         None => Ok(minijinja::Value::from(())),
-        Some(messages) => Ok(minijinja::Value::from_iter(
-            messages
-                .try_iter()?
-                .map(|v| v.get_item_by_index(1).unwrap().clone()),
-        )),
+        Some(messages) => Ok(minijinja::Value::from_iter(messages.try_iter()?.map(|v| {
+            let text = v.get_item_by_index(1).unwrap();
+            if with_levels {
+                let level = v.get_item_by_index(0).unwrap().to_string().to_lowercase();
+                minijinja::Value::from(vec![minijinja::Value::from(level), text])
+            } else {
+                text
+            }
+        }))),
+    }
+}
+
+/// Formats a serialized timestamp for display, in UTC to the minute.
+fn datetime(value: String) -> String {
+    match DateTime::parse_from_rfc3339(&value) {
+        Ok(time) => time.format("%Y-%m-%d %H:%M UTC").to_string(),
+        Err(_) => value,
+    }
+}
+
+/// The current time in the time zone named by `timezone`, with its weekday
+/// and UTC offset, or nothing for names that aren't one.
+fn local_time(timezone: String) -> String {
+    match timezone.parse::<chrono_tz::Tz>() {
+        Ok(tz) => Utc::now()
+            .with_timezone(&tz)
+            .format("%a %H:%M (UTC%:z)")
+            .to_string(),
+        Err(_) => String::new(),
     }
 }
+
+/// The age in years today of someone born on a serialized date, or nothing
+/// for values that aren't one.
+fn age(value: String) -> String {
+    let Ok(birthday) = NaiveDate::parse_from_str(&value, BIRTHDAY_FORMAT) else {
+        return String::new();
+    };
+    let today = Utc::now().date_naive();
+    let years = today.year() - birthday.year();
+    let before_birthday = (today.month(), today.day()) < (birthday.month(), birthday.day());
+    (years - i32::from(before_birthday)).to_string()
+}
+
+/// Renders Markdown text, such as a contact's notes, as safe HTML.
+fn render_markdown(value: String) -> Value {
+    Value::from_safe_string(markdown::to_html(&value))
+}
+
+/// `text` as safe HTML with the parts [found](highlights) searching for
+/// `query` wrapped in `<mark>`, or as it is without a query.
+fn highlight(text: String, query: Option<String>) -> Value {
+    let Some(query) = query.filter(|query| !query.trim().is_empty()) else {
+        return Value::from(text);
+    };
+    let mut html = String::new();
+    let mut end = 0;
+    for range in highlights(&text, &query) {
+        html.push_str(&HtmlEscape(&text[end..range.start]).to_string());
+        html.push_str("<mark>");
+        html.push_str(&HtmlEscape(&text[range.clone()]).to_string());
+        html.push_str("</mark>");
+        end = range.end;
+    }
+    html.push_str(&HtmlEscape(&text[end..]).to_string());
+    Value::from_safe_string(html)
+}
+
+/// How birthdays are written in the forms, as sent by `<input type="date">`.
+const BIRTHDAY_FORMAT: &str = "%Y-%m-%d";
+
+/// The status of a response failing with `err`.
+fn repo_status(err: &RepoError) -> StatusCode {
+    match err {
+        RepoError::NotFound(_) | RepoError::UuidNotFound(_) => StatusCode::NOT_FOUND,
+        RepoError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        RepoError::Conflict(_) => StatusCode::CONFLICT,
+        RepoError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for RepoError {
+    fn into_response(self) -> Response {
+        let status = repo_status(&self);
+        if status.is_server_error() {
+            eprintln!("{self}");
+            return status.into_response();
+        }
+        match &self {
+            // With the errors by field and their codes, for clients to tell
+            // them apart without reading the messages.
+            RepoError::Validation(contact) | RepoError::Conflict(contact) => (
+                status,
+                Json(serde_json::json!({
+                    "message": self.to_string(),
+                    "errors": contact.errors.errors(),
+                })),
+            )
+                .into_response(),
+            _ => (status, self.to_string()).into_response(),
+        }
+    }
+}
+
+/// Why mail to `email` may not be delivered for long or at all: it is
+/// disposable or, if emails are checked for that, its domain doesn't accept
+/// mail.
+async fn email_warning(state: &AppState, email: Option<&str>) -> Option<String> {
+    let email = email?;
+    if state.disposable_domains.is_disposable(email) {
+        return Some("Disposable Email Domain".to_owned());
+    }
+    state.mail_domains.as_ref()?.warning(email).await
+}
+
+/// `flash` with the `warnings` about a contact saved and the
+/// [warning](email_warning) about its `email`, if any.
+async fn warn_saved(
+    state: &AppState,
+    mut flash: Flash,
+    warnings: &ValidationErrors,
+    email: Option<String>,
+) -> Flash {
+    for (_, warning) in warnings.warnings() {
+        flash = flash.warning(warning);
+    }
+    match email_warning(state, email.as_deref()).await {
+        Some(warning) => flash.warning(warning),
+        None => flash,
+    }
+}
+
+/// The contact with `key`, or [`RepoError::NotFound`].
+async fn find_contact(repo: &SharedContactRepo, key: ContactKey) -> Result<Contact, RepoError> {
+    match key {
+        ContactKey::Id(id) => repo.find(id).await?.ok_or(RepoError::NotFound(id)),
+        ContactKey::Uuid(uuid) => repo
+            .find_by_uuid(uuid)
+            .await?
+            .ok_or(RepoError::UuidNotFound(uuid)),
+    }
+}
+
 // Our state type must implement this trait. That is how the config
 // is passed to axum-flash in a type safe way.
 // impl FromRef<AppState> for axum_flash::Config {
@@ -75,38 +377,236 @@ fn get_flashed_messages(
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct IndexState {
     q: Option<String>,
-    contacts: Vec<Contact>,
+    /// Whether `q` was searched for fuzzily.
+    fuzzy: bool,
+    /// Whether searching for `q` found archived contacts too.
+    include_archived: bool,
+    /// Why the regex pattern `q` is doesn't compile, if it doesn't.
+    query_error: Option<String>,
+    /// The tag the contacts listed have, if filtered by one.
+    tag: Option<String>,
+    /// The group the contacts listed are in, if filtered by one.
+    group: Option<Group>,
+    /// The [initial](Contact::initial) of the contacts listed, if filtered
+    /// by one.
+    letter: Option<char>,
+    /// Each initial with the number of contacts listed under it, for the
+    /// letter strip; empty where it isn't shown.
+    initials: Vec<(char, usize)>,
+    /// Whether only starred contacts are listed.
+    starred: bool,
+    /// Whether only contacts with a disposable email are listed.
+    suspect: bool,
+    /// Whether the archived contacts are listed, rather than the others.
+    archived: bool,
+    contacts: Page<Contact>,
+    sort: Sort,
+    /// Where "Load More" continues the listing, if it was listed by cursor.
+    next: Option<Cursor>,
     messages: Vec<(Level, String)>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct ContactsParams {
     q: Option<String>,
+    /// Lists only the contacts with this tag, also when searching.
+    tag: Option<String>,
+    /// Lists only the contacts in the group with this id, also when
+    /// searching; takes precedence over `tag` unless searching.
+    group: Option<u64>,
+    /// Lists only the contacts whose names start with this letter, or with
+    /// none for [`OTHER_INITIAL`](crate::model::OTHER_INITIAL), unless
+    /// searching or filtering by group or tag.
+    letter: Option<String>,
+    /// Lists only the starred contacts, unless searching or filtering by
+    /// group, tag or letter.
+    #[serde(default)]
+    starred: bool,
+    /// Lists only the contacts with a disposable email, like `starred`.
+    #[serde(default)]
+    suspect: bool,
+    /// Searches for `q` despite typos, most similar contacts first.
+    #[serde(default)]
+    fuzzy: bool,
+    /// Finds archived contacts too when searching.
+    #[serde(default)]
+    include_archived: bool,
+    #[serde(default)]
+    mode: SearchMode,
+    page: Option<usize>,
+    /// Continues a listing after the cursor instead of showing a page.
+    after: Option<Cursor>,
+    #[serde(default)]
+    sort: SortBy,
+    #[serde(default)]
+    direction: Direction,
 }
 
+/// How the `q` of [`ContactsParams`] is read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// As a [`SearchQuery`], which is a regex pattern if written `/q/`.
+    #[default]
+    Text,
+    /// As a regex pattern, as if written `/q/`.
+    Regex,
+}
+
+/// Contacts listed per page of the index.
+const PAGE_SIZE: usize = 10;
+
 async fn contacts(
     engine: AppEngine,
     State(state): State<AppState>,
-    Query(params): Query<ContactsParams>,
+    Query(mut params): Query<ContactsParams>,
     flashes: IncomingFlashes,
     HxTrigger(trigger): HxTrigger,
+    HxRequest(is_htmx): HxRequest,
 ) -> Response {
     let mut messages = Vec::new();
     for (level, text) in &flashes {
         messages.push((level, text.to_string()));
     }
     dbg!(&params);
+    let sort = Sort::new(params.sort, params.direction).with_names(state.name_order);
+    let number = params.page.unwrap_or(1).max(1);
+    let mut next = None;
+    if params.mode == SearchMode::Regex {
+        params.q = params.q.map(|q| match regex_pattern(&q) {
+            Some(_) => q,
+            None => format!("/{q}/"),
+        });
+    }
+    let query_error = params
+        .q
+        .as_deref()
+        .and_then(|q| SearchQuery::parse(q).error().map(str::to_owned));
+    let tag = params.tag.as_deref().and_then(tag_filter);
+    let letter = params.letter.as_deref().and_then(initial_filter);
+    let group = match params.group {
+        Some(id) => match groups::find_group(&state, id).await {
+            Ok(group) => Some(group),
+            Err(err) => return err.into_response(),
+        },
+        None => None,
+    };
     let contacts = match &params.q {
-        None => state.contact_repo.all().await,
+        None if params.after.is_some() => {
+            let mut listed = state.contact_repo.all_after(params.after, PAGE_SIZE).await;
+            // Leaves fewer on the page, the cursor still continues after
+            // them all.
+            listed.items.retain(|contact| !contact.archived());
+            if is_htmx {
+                return RenderHtml(
+                    Key("rows.html".to_owned()),
+                    engine,
+                    IndexState {
+                        contacts: Page::single(listed.items),
+                        sort,
+                        next: listed.next,
+                        q: None,
+                        fuzzy: false,
+                        include_archived: false,
+                        query_error: None,
+                        tag: None,
+                        group: None,
+                        letter: None,
+                        initials: vec![],
+                        starred: false,
+                        suspect: false,
+                        archived: false,
+                        messages: vec![],
+                    },
+                )
+                .into_response();
+            }
+            next = listed.next;
+            Page::single(listed.items)
+        }
+        None => match (&group, &tag, letter, params.starred) {
+            (Some(group), _, _, _) => {
+                let id = group.id().expect("a stored group to have an id");
+                state
+                    .contact_repo
+                    .members(id, sort, number, PAGE_SIZE)
+                    .await
+            }
+            (None, Some(tag), _, _) => {
+                state
+                    .contact_repo
+                    .tagged(tag, sort, number, PAGE_SIZE)
+                    .await
+            }
+            (None, None, Some(letter), _) => {
+                state
+                    .contact_repo
+                    .by_initial(letter, sort, number, PAGE_SIZE)
+                    .await
+            }
+            (None, None, None, true) => state.contact_repo.starred(sort, number, PAGE_SIZE).await,
+            (None, None, None, false) if params.suspect => {
+                let contacts = state.contact_repo.all(sort).await;
+                let suspect = contacts.iter().filter(|contact| {
+                    !contact.archived() && state.disposable_domains.suspect(contact)
+                });
+                Page::of(suspect, sort, number, PAGE_SIZE)
+            }
+            (None, None, None, false) => {
+                state
+                    .contact_repo
+                    .archived(false, sort, number, PAGE_SIZE)
+                    .await
+            }
+        },
         Some(search) => {
-            let contacts = state.contact_repo.search(search).await;
+            let filter = ContactFilter {
+                tag: tag.clone(),
+                group: params.group,
+                archived: params.include_archived,
+            };
+            let threshold = state.fuzzy_threshold;
+            // Patterns are matched as written, never fuzzily.
+            let fuzzy = params.fuzzy && regex_pattern(search).is_none();
+            let contacts = if fuzzy && filter.is_empty() {
+                state
+                    .contact_repo
+                    .fuzzy_search(search, threshold, sort, number, PAGE_SIZE)
+                    .await
+            } else if fuzzy {
+                let contacts = state.contact_repo.all(sort).await;
+                let allowed = contacts.iter().filter(|contact| filter.allows(contact));
+                Page::by_similarity(allowed, search, threshold, sort, number, PAGE_SIZE)
+            } else if filter.is_empty() {
+                state
+                    .contact_repo
+                    .search(search, sort, number, PAGE_SIZE)
+                    .await
+            } else {
+                state
+                    .contact_repo
+                    .search_filtered(search, &filter, sort, number, PAGE_SIZE)
+                    .await
+            };
             if trigger.as_ref() == Some(&"search".to_string()) {
                 return RenderHtml(
                     Key("rows.html".to_owned()),
                     engine,
                     IndexState {
                         contacts,
+                        sort,
+                        next: None,
                         q: params.q,
+                        fuzzy: params.fuzzy,
+                        include_archived: params.include_archived,
+                        query_error,
+                        tag: tag.filter(|_| group.is_none()),
+                        group,
+                        letter: None,
+                        initials: vec![],
+                        starred: false,
+                        suspect: false,
+                        archived: false,
                         messages: vec![],
                     },
                 )
@@ -116,9 +616,27 @@ async fn contacts(
             }
         }
     };
+    let unfiltered = params.q.is_none() && group.is_none() && tag.is_none();
+    let letter = letter.filter(|_| unfiltered);
+    let counts = state.contact_repo.initials(state.name_order).await;
+    let initials = initials()
+        .map(|initial| (initial, counts.get(&initial).copied().unwrap_or_default()))
+        .collect();
     let state = IndexState {
+        starred: params.starred && letter.is_none() && unfiltered,
+        suspect: params.suspect && !params.starred && letter.is_none() && unfiltered,
+        letter,
+        initials,
+        tag: tag.filter(|_| group.is_none()),
+        archived: false,
+        group,
+        fuzzy: params.fuzzy && params.q.is_some(),
+        include_archived: params.include_archived && params.q.is_some(),
+        query_error,
         q: params.q,
         contacts,
+        sort,
+        next,
         messages,
     };
     dbg!(&state);
@@ -129,162 +647,728 @@ async fn contacts(
         .into_response()
 }
 
-async fn contacts_count_get(State(state): State<AppState>) -> impl IntoResponse {
-    let count = state.contact_repo.count().await;
-    format!("({} total Contacts)", count)
+/// Lists the archived contacts, paged and sorted like the index.
+async fn contacts_archived_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Query(params): Query<ContactsParams>,
+    flashes: IncomingFlashes,
+) -> Response {
+    let mut messages = Vec::new();
+    for (level, text) in &flashes {
+        messages.push((level, text.to_string()));
+    }
+    let sort = Sort::new(params.sort, params.direction).with_names(state.name_order);
+    let number = params.page.unwrap_or(1).max(1);
+    let contacts = state
+        .contact_repo
+        .archived(true, sort, number, PAGE_SIZE)
+        .await;
+    let state = IndexState {
+        q: None,
+        fuzzy: false,
+        include_archived: false,
+        query_error: None,
+        tag: None,
+        group: None,
+        letter: None,
+        initials: vec![],
+        starred: false,
+        suspect: false,
+        archived: true,
+        contacts,
+        sort,
+        next: None,
+        messages,
+    };
+    (
+        flashes,
+        RenderHtml(Key("index.html".to_owned()), engine, state),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CountParams {
+    q: Option<String>,
+}
+
+async fn contacts_count_get(
+    State(state): State<AppState>,
+    Query(params): Query<CountParams>,
+) -> impl IntoResponse {
+    // Archived contacts are neither listed nor found.
+    let count = state
+        .contact_repo
+        .archived(false, Sort::default(), 1, 1)
+        .await
+        .total();
+    match params.q.as_deref().filter(|q| !q.is_empty()) {
+        Some(q) => {
+            let matching = state.contact_repo.count(Some(q)).await;
+            format!("({} matching / {} total)", matching, count)
+        }
+        None => format!("({} total Contacts)", count),
+    }
+}
+
+/// The [initial](Contact::initial) `letter` names, uppercase, `None` unless
+/// it is one.
+fn initial_filter(letter: &str) -> Option<char> {
+    let mut chars = letter.trim().chars();
+    let initial = chars.next()?.to_ascii_uppercase();
+    (chars.next().is_none() && initials().any(|other| other == initial)).then_some(initial)
+}
+
+/// `tag` as stored on contacts, `None` if blank.
+fn tag_filter(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    (!tag.is_empty()).then(|| tag.to_lowercase())
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TagsParams {
+    /// The tag the listing is filtered by, to highlight.
+    tag: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TagsCtx {
+    /// Every tag, with the number of contacts that have it.
+    tags: BTreeMap<String, usize>,
+    tag: Option<String>,
+}
+
+/// The tag sidebar of the index.
+async fn contacts_tags_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Query(params): Query<TagsParams>,
+) -> impl IntoResponse {
+    RenderHtml(
+        Key("tags.html".to_owned()),
+        engine,
+        TagsCtx {
+            tags: state.contact_repo.tags().await,
+            tag: params.tag.as_deref().and_then(tag_filter),
+        },
+    )
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct NewContactCtx {
     contact: Contact,
+    /// Why the contact entered can't be saved, if it can't, as the contact
+    /// doesn't serialize them.
+    errors: ValidationErrors,
+    /// What about the contact entered may be mistaken, by field.
+    warnings: BTreeMap<String, String>,
+    /// All groups, to show or pick those the contact is in.
+    groups: Vec<Group>,
+    /// The custom field schema, to show or enter the contact's values.
+    fields: Vec<CustomField>,
+    /// Contacts the new contact may be a duplicate of, to be confirmed
+    /// before it is created.
+    duplicates: Vec<Contact>,
 }
 
-async fn get_contacts_new(engine: AppEngine) -> impl IntoResponse {
+impl NewContactCtx {
+    async fn load(state: &AppState, mut contact: Contact) -> Self {
+        let errors = mem::take(&mut contact.errors);
+        let warnings = errors
+            .warnings()
+            .map(|(field, warning)| (field.to_owned(), warning.to_owned()))
+            .collect();
+        Self {
+            errors,
+            warnings,
+            contact,
+            groups: state.groups.all().await,
+            fields: state.fields.all().await,
+            duplicates: Vec::new(),
+        }
+    }
+}
+
+async fn get_contacts_new(engine: AppEngine, State(state): State<AppState>) -> impl IntoResponse {
     RenderHtml(
         Key("new.html".to_owned()),
         engine,
-        NewContactCtx {
-            contact: Contact::default(),
-        },
+        NewContactCtx::load(&state, Contact::default()).await,
     )
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+/// Fields of the new and edit forms, read from their `(name, value)` pairs
+/// as each phone number is a `phone_label` and a `phone` field, repeated for
+/// every row of the form, each further email an `other_email_label` and an
+/// `other_email` field, and each address one `address_<field>` field per
+/// field of [`Address`].
+///
+/// The birthday is kept as entered until [applied](Self::set_birthday), so a
+/// date that doesn't parse can be rejected like any invalid field.
+#[derive(Debug, Clone, Default)]
 pub struct NewContact {
+    prefix: Option<String>,
     first_name: Option<String>,
+    middle_name: Option<String>,
     last_name: Option<String>,
-    phone: Option<String>,
+    suffix: Option<String>,
+    nickname: Option<String>,
+    phones: Vec<PhoneNumber>,
     email: Option<String>,
+    emails: Vec<EmailAddress>,
+    addresses: Vec<Address>,
+    birthday: Option<String>,
+    /// Labels and dates as entered, each row a `date_label` and a `date`
+    /// field, applied like the birthday.
+    dates: Vec<(String, String)>,
+    company: Option<String>,
+    job_title: Option<String>,
+    socials: Vec<SocialProfile>,
+    website: Option<String>,
+    timezone: Option<String>,
+    notes: Option<String>,
+    /// Entered comma-separated, in one field.
+    tags: Vec<String>,
+    /// Ids of the groups checked, one `group` field each.
+    groups: Vec<u64>,
+    /// As picked, applied like the birthday.
+    preferred: Option<String>,
+    /// Values entered for custom fields, by name, from `custom.<name>`
+    /// fields; see [`fields::set_custom`].
+    custom: HashMap<String, String>,
+    /// Version of the contact the edit form was filled from.
+    version: Option<u64>,
+    /// Whether the new contact is to be created even if it may be a
+    /// [duplicate](possible_duplicates) of another.
+    confirmed: bool,
+}
+
+impl From<Vec<(String, String)>> for NewContact {
+    fn from(fields: Vec<(String, String)>) -> Self {
+        let mut form = Self::default();
+        let mut phone_labels = Vec::new();
+        let mut numbers = Vec::new();
+        let mut email_labels = Vec::new();
+        let mut emails = Vec::new();
+        let mut date_labels = Vec::new();
+        let mut dates = Vec::new();
+        let mut networks = Vec::new();
+        let mut handles = Vec::new();
+        let mut address_fields: [Vec<String>; ADDRESS_FIELDS.len()] = Default::default();
+        for (name, value) in fields {
+            match name.as_str() {
+                "first_name" => form.first_name = filled(value),
+                "last_name" => form.last_name = filled(value),
+                "prefix" => form.prefix = filled(value),
+                "middle_name" => form.middle_name = filled(value),
+                "suffix" => form.suffix = filled(value),
+                "nickname" => form.nickname = filled(value),
+                "email" => form.email = Some(value),
+                "birthday" => form.birthday = Some(value),
+                "company" => form.company = filled(value),
+                "job_title" => form.job_title = filled(value),
+                "website" => form.website = filled(value),
+                "timezone" => form.timezone = filled(value),
+                "notes" => form.notes = Some(value),
+                "tags" => form.tags = value.split(',').map(str::to_owned).collect(),
+                "group" => form.groups.extend(value.parse::<u64>().ok()),
+                "preferred" => form.preferred = Some(value),
+                "version" => form.version = value.parse().ok(),
+                "confirmed" => form.confirmed = value == "true",
+                "phone_label" => phone_labels.push(value),
+                "phone" => numbers.push(value),
+                "other_email_label" => email_labels.push(value),
+                "other_email" => emails.push(value),
+                "date_label" => date_labels.push(value),
+                "date" => dates.push(value),
+                "social_network" => networks.push(value),
+                "social_handle" => handles.push(value),
+                name if name.starts_with("custom.") => {
+                    form.custom
+                        .insert(name["custom.".len()..].to_owned(), value);
+                }
+                name => {
+                    let field = name
+                        .strip_prefix("address_")
+                        .and_then(|field| ADDRESS_FIELDS.iter().position(|known| *known == field));
+                    if let Some(field) = field {
+                        address_fields[field].push(value);
+                    }
+                }
+            }
+        }
+        form.phones = rows(phone_labels, numbers)
+            .map(|(label, number)| PhoneNumber::new(label, number))
+            .collect();
+        form.emails = rows(email_labels, emails)
+            .map(|(label, email)| EmailAddress::new(label, email))
+            .collect();
+        form.dates = rows(date_labels, dates).collect();
+        form.socials = rows(networks, handles)
+            .map(|(network, handle)| SocialProfile::new(network, handle))
+            .collect();
+        let rows = address_fields
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or_default();
+        form.addresses = (0..rows)
+            .map(|row| {
+                let field = |field: usize| {
+                    let value = address_fields[field].get(row).map(|value| value.trim());
+                    value.unwrap_or_default().to_owned()
+                };
+                Address {
+                    label: field(0),
+                    street: field(1),
+                    city: field(2),
+                    postal_code: field(3),
+                    country: field(4),
+                }
+            })
+            .filter(|address| !address.is_empty())
+            .collect();
+        form
+    }
+}
+
+/// The [normalized](normalize_text) `value` of an optional field, `None`
+/// if left empty.
+fn filled(value: String) -> Option<String> {
+    normalize_text(&value)
+}
+
+/// Fields of an [`Address`] in the forms, in the order [`NewContact`] reads
+/// them.
+const ADDRESS_FIELDS: [&str; 5] = ["label", "street", "city", "postal_code", "country"];
+
+/// The trimmed `(label, value)` of each row of a form that has several,
+/// leaving out rows with no value.
+fn rows(labels: Vec<String>, values: Vec<String>) -> impl Iterator<Item = (String, String)> {
+    labels
+        .into_iter()
+        .chain(iter::repeat(String::new()))
+        .zip(values)
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(label, value)| (label.trim().to_owned(), value.trim().to_owned()))
+}
+
+impl NewContact {
+    /// Sets the birthday entered, if any, on `contact`, or records that it
+    /// isn't a date so saving it fails validation.
+    fn set_birthday(birthday: Option<String>, contact: &mut Contact) {
+        let birthday = birthday.unwrap_or_default();
+        let birthday = birthday.trim();
+        if birthday.is_empty() {
+            contact.set_birthday(None);
+            return;
+        }
+        match NaiveDate::parse_from_str(birthday, BIRTHDAY_FORMAT) {
+            Ok(date) => contact.set_birthday(Some(date)),
+            Err(_) => {
+                contact.errors.insert("birthday", "invalid", "Invalid Date");
+            }
+        }
+    }
+
+    /// Sets the important dates entered on `contact`, or records that one
+    /// of them isn't a date, leaving it out, so saving it fails validation.
+    fn set_dates(dates: Vec<(String, String)>, contact: &mut Contact) {
+        let mut parsed = Vec::new();
+        for (label, date) in dates {
+            match NaiveDate::parse_from_str(&date, BIRTHDAY_FORMAT) {
+                Ok(date) => parsed.push(ImportantDate::new(label, date)),
+                Err(_) => {
+                    contact.errors.insert("dates", "invalid", "Invalid Date");
+                }
+            }
+        }
+        contact.set_dates(parsed);
+    }
+
+    /// Sets the contact method picked, if any, on `contact`, or records that
+    /// it isn't one so saving it fails validation.
+    fn set_preferred(preferred: Option<String>, contact: &mut Contact) {
+        match preferred.unwrap_or_default().parse() {
+            Ok(preferred) => contact.set_preferred(preferred),
+            Err(_) => {
+                contact
+                    .errors
+                    .insert("preferred", "unknown", "Unknown Method");
+            }
+        }
+    }
 }
 
 impl From<NewContact> for Contact {
     fn from(value: NewContact) -> Self {
-        Self::new(value.first_name, value.last_name, value.phone, value.email)
+        let mut contact = Self::new(value.first_name, value.last_name, value.phones, value.email);
+        contact.set_name_parts(
+            value.prefix,
+            value.middle_name,
+            value.suffix,
+            value.nickname,
+        );
+        contact.set_emails(value.emails);
+        contact.set_addresses(value.addresses);
+        NewContact::set_birthday(value.birthday, &mut contact);
+        NewContact::set_dates(value.dates, &mut contact);
+        contact.set_company(value.company);
+        contact.set_job_title(value.job_title);
+        contact.set_socials(value.socials);
+        contact.set_website(value.website);
+        contact.set_timezone(value.timezone);
+        contact.set_notes(value.notes);
+        contact.set_tags(value.tags);
+        contact.set_groups(value.groups);
+        NewContact::set_preferred(value.preferred, &mut contact);
+        contact
     }
 }
 async fn post_contacts_new(
     engine: AppEngine,
     State(state): State<AppState>,
     flash: Flash,
-    Form(new_contact): Form<NewContact>,
+    form: ContactForm,
 ) -> Response {
-    let contact = Contact::from(new_contact);
-    match state.contact_repo.save(contact).await {
-        Ok(()) => (
-            flash.info("Created new contact!"),
-            Redirect::to("/contacts"),
-        )
+    let mut new_contact = NewContact::from(form.fields);
+    let custom = mem::take(&mut new_contact.custom);
+    let confirmed = new_contact.confirmed;
+    let mut contact = Contact::from(new_contact);
+    fields::set_custom(&state.fields.all().await, custom, &mut contact);
+    let photo = PhotoChange::of(form.photo, false, &mut contact);
+    state.id_strategy.assign(&mut contact);
+    let (email, validation) = (contact.email.clone(), contact.validation());
+    if !confirmed && validation.is_empty() {
+        let others = state.contact_repo.all(Sort::default()).await;
+        let duplicates = possible_duplicates(&contact, &others, state.phone_region);
+        if !duplicates.is_empty() {
+            let mut ctx = NewContactCtx::load(&state, contact).await;
+            ctx.duplicates = duplicates;
+            return RenderHtml(Key("new.html".to_owned()), engine, ctx).into_response();
+        }
+    }
+    match state.contact_repo.create(contact).await {
+        Ok(id) => match photo.apply(&state.photos, id).await {
+            Ok(()) => (
+                warn_saved(
+                    &state,
+                    flash.info("Created new contact!"),
+                    &validation,
+                    email,
+                )
+                .await,
+                Redirect::to("/contacts"),
+            )
+                .into_response(),
+            Err(err) => err.into_response(),
+        },
+        Err(err) => match err.into_contact() {
+            Ok(contact) => RenderHtml(
+                Key("new.html".to_owned()),
+                engine,
+                NewContactCtx::load(&state, contact).await,
+            )
             .into_response(),
-        Err(contact) => RenderHtml(
-            Key("new.html".to_owned()),
-            engine,
-            NewContactCtx { contact },
-        )
-        .into_response(),
+            Err(err) => err.into_response(),
+        },
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PhoneRowCtx {
+    phone: PhoneNumber,
+}
+
+/// An empty phone number row for the new and edit forms.
+async fn phone_row_get(engine: AppEngine) -> impl IntoResponse {
+    let ctx = PhoneRowCtx {
+        phone: PhoneNumber::default(),
+    };
+    RenderHtml(Key("phone_row.html".to_owned()), engine, ctx)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EmailRowCtx {
+    email: EmailAddress,
+}
+
+/// An empty row for a further email in the new and edit forms.
+async fn email_row_get(engine: AppEngine) -> impl IntoResponse {
+    let ctx = EmailRowCtx {
+        email: EmailAddress::default(),
+    };
+    RenderHtml(Key("email_row.html".to_owned()), engine, ctx)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AddressRowCtx {
+    address: Address,
+}
+
+/// Empty fields for an address in the new and edit forms.
+async fn address_row_get(engine: AppEngine) -> impl IntoResponse {
+    let ctx = AddressRowCtx {
+        address: Address::default(),
+    };
+    RenderHtml(Key("address_row.html".to_owned()), engine, ctx)
+}
+
 async fn contact_view(
     engine: AppEngine,
     State(state): State<AppState>,
-    Path(contact_id): Path<u64>,
-) -> impl IntoResponse {
-    let contact = state
-        .contact_repo
-        .find(contact_id)
-        .await
-        .expect("a existing id");
-    RenderHtml(
-        Key("show.html".to_owned()),
-        engine,
-        NewContactCtx { contact },
-    )
+    Path(contact_key): Path<ContactKey>,
+    headers: HeaderMap,
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let ctx = NewContactCtx::load(&state, contact).await;
+    let etag = etag::page_etag(&ctx.contact, &ctx.groups, &ctx.fields);
+    if let Some(etag) = etag
+        .as_ref()
+        .filter(|etag| etag::none_match(&headers, etag))
+    {
+        return Ok(etag::not_modified(etag.clone()));
+    }
+    let page = RenderHtml(Key("show.html".to_owned()), engine, ctx);
+    Ok(match etag {
+        Some(etag) => etag::tagged(etag, page),
+        None => page.into_response(),
+    })
 }
 
+/// The form editing the contact, tagged with the contact's entity tag for
+/// clients to give back in `If-Match` when posting it.
 async fn contacts_edit_get(
     engine: AppEngine,
     State(state): State<AppState>,
-    Path(contact_id): Path<u64>,
-) -> impl IntoResponse {
-    let contact = state
-        .contact_repo
-        .find(contact_id)
-        .await
-        .expect("a existing id");
-    RenderHtml(
-        Key("edit.html".to_owned()),
+    Path(contact_key): Path<ContactKey>,
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let etag = etag::etag(&contact, None);
+    Ok(etag::tagged(
+        etag,
+        RenderHtml(
+            Key("edit.html".to_owned()),
+            engine,
+            NewContactCtx::load(&state, contact).await,
+        ),
+    ))
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StarCtx {
+    contact: Contact,
+}
+
+/// Stars the contact, or unstars it if starred, and renders its star button.
+async fn contacts_star_post(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(contact_key): Path<ContactKey>,
+) -> Result<impl IntoResponse, RepoError> {
+    let mut contact = find_contact(&state.contact_repo, contact_key).await?;
+    contact.set_starred(!contact.starred());
+    state.contact_repo.update(contact.clone()).await?;
+    Ok(RenderHtml(
+        Key("star.html".to_owned()),
         engine,
-        NewContactCtx { contact },
-    )
+        StarCtx { contact },
+    ))
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArchiveCtx {
+    contact: Contact,
 }
 
+/// Archives the contact, or unarchives it if archived, and renders its
+/// archive button.
+async fn contacts_archive_post(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(contact_key): Path<ContactKey>,
+) -> Result<impl IntoResponse, RepoError> {
+    let mut contact = find_contact(&state.contact_repo, contact_key).await?;
+    contact.set_archived(!contact.archived());
+    state.contact_repo.update(contact.clone()).await?;
+    Ok(RenderHtml(
+        Key("archive.html".to_owned()),
+        engine,
+        ArchiveCtx { contact },
+    ))
+}
+
+/// Which email the edit form is validating: the primary `email`, or the
+/// `other_email` at `index` of the contact's other emails, or the one with
+/// `label`.
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct ContactsEmailParams {
     email: Option<String>,
+    other_email: Option<String>,
+    index: Option<usize>,
+    label: Option<String>,
 }
 
 async fn contacts_email_get(
     State(state): State<AppState>,
-    Path(contact_id): Path<u64>,
-    Query(email): Query<ContactsEmailParams>,
-) -> impl IntoResponse {
-    let mut contact = state
-        .contact_repo
-        .find(contact_id)
-        .await
-        .expect("a existing id");
-    contact.email = email.email;
+    Path(contact_key): Path<ContactKey>,
+    Query(params): Query<ContactsEmailParams>,
+) -> Result<impl IntoResponse, RepoError> {
+    let mut contact = find_contact(&state.contact_repo, contact_key).await?;
+    let (field, email) = match (params.index, params.label) {
+        (None, None) => {
+            contact.email = params.email.clone();
+            ("email".to_owned(), params.email)
+        }
+        (index, label) => {
+            let labeled = label.and_then(|label| {
+                let emails = contact.emails();
+                emails.iter().position(|email| email.label == label)
+            });
+            let index = index.or(labeled).unwrap_or(contact.emails().len());
+            let value = params.other_email.or(params.email).unwrap_or_default();
+            let index = contact.set_other_email(index, value.clone());
+            (format!("emails.{index}"), Some(value))
+        }
+    };
     contact.validate();
-    contact
-        .errors
-        .get("email")
-        .cloned()
-        .unwrap_or_else(|| String::new())
+    Ok(match contact.errors.get(&field) {
+        Some(error) => error.to_owned(),
+        None => email_warning(&state, email.as_deref())
+            .await
+            .unwrap_or_default(),
+    })
 }
 
 async fn contacts_edit_post(
     engine: AppEngine,
     State(state): State<AppState>,
     flash: Flash,
-    Path(contact_id): Path<u64>,
-    Form(new_contact): Form<NewContact>,
+    Path(contact_key): Path<ContactKey>,
+    headers: HeaderMap,
+    form: ContactForm,
 ) -> Response {
-    let mut contact = state.contact_repo.find(contact_id).await.unwrap();
+    let mut contact = match find_contact(&state.contact_repo, contact_key).await {
+        Ok(contact) => contact,
+        Err(err) => return err.into_response(),
+    };
+    let current = etag::etag(&contact, None);
     let NewContact {
+        prefix,
         first_name,
+        middle_name,
         last_name,
-        phone,
+        suffix,
+        nickname,
+        phones,
         email,
-    } = new_contact;
-    contact.update(first_name, last_name, phone, email);
+        emails,
+        addresses,
+        birthday,
+        dates,
+        company,
+        job_title,
+        socials,
+        website,
+        timezone,
+        notes,
+        tags,
+        groups,
+        preferred,
+        custom,
+        version,
+        confirmed: _,
+    } = NewContact::from(form.fields);
+    contact.update(first_name, last_name, phones, email);
+    contact.set_name_parts(prefix, middle_name, suffix, nickname);
+    contact.set_emails(emails);
+    contact.set_addresses(addresses);
+    NewContact::set_birthday(birthday, &mut contact);
+    NewContact::set_dates(dates, &mut contact);
+    contact.set_company(company);
+    contact.set_job_title(job_title);
+    contact.set_socials(socials);
+    contact.set_website(website);
+    contact.set_timezone(timezone);
+    contact.set_notes(notes);
+    contact.set_tags(tags);
+    contact.set_groups(groups);
+    NewContact::set_preferred(preferred, &mut contact);
+    fields::set_custom(&state.fields.all().await, custom, &mut contact);
+    // Edits must say which copy they were made to, so that they can't
+    // silently overwrite changes saved since.
+    if let Some(version) = version {
+        contact.set_version(version);
+    }
+    let failed = match (etag::if_match(&headers, &current), version) {
+        (Some(false), _) => Some((
+            StatusCode::PRECONDITION_FAILED,
+            "stale",
+            "Contact Was Changed Meanwhile, Reload To See The Changes",
+        )),
+        (None, None) => Some((
+            StatusCode::PRECONDITION_REQUIRED,
+            "required",
+            "Version Or If-Match Required",
+        )),
+        _ => None,
+    };
+    if let Some((status, reason, message)) = failed {
+        contact.errors.insert("version", reason, message);
+        let ctx = NewContactCtx::load(&state, contact).await;
+        return (status, RenderHtml(Key("edit.html".to_owned()), engine, ctx)).into_response();
+    }
+    let photo = PhotoChange::of(form.photo, form.remove_photo, &mut contact);
+    let id = contact.id().expect("a stored contact to have an id");
+    let (email, validation) = (contact.email.clone(), contact.validation());
 
-    match state.contact_repo.save(contact).await {
-        Ok(()) => (
-            flash.info("Updated contact!"),
-            Redirect::to(&format!("/contacts/{contact_id}")),
-        )
+    match state.contact_repo.update(contact).await {
+        Ok(()) => match photo.apply(&state.photos, id).await {
+            Ok(()) => (
+                warn_saved(&state, flash.info("Updated contact!"), &validation, email).await,
+                Redirect::to(&format!("/contacts/{contact_key}")),
+            )
+                .into_response(),
+            Err(err) => err.into_response(),
+        },
+        Err(err) => match err.into_contact() {
+            Ok(contact) => RenderHtml(
+                Key("edit.html".to_owned()),
+                engine,
+                NewContactCtx::load(&state, contact).await,
+            )
             .into_response(),
-        Err(contact) => RenderHtml(
-            Key("edit.html".to_owned()),
-            engine,
-            NewContactCtx { contact },
-        )
-        .into_response(),
+            Err(err) => err.into_response(),
+        },
     }
 }
 
+/// Deletes the stored `contact` with its photo and attachments.
+async fn delete_contact(state: &AppState, contact: &Contact) -> Result<(), RepoError> {
+    let id = contact.id().expect("a stored contact to have an id");
+    state.contact_repo.delete_by_id(id).await?;
+    delete_files(state, id).await
+}
+
+/// Deletes the photo and attachments of the deleted contact with `id`.
+async fn delete_files(state: &AppState, id: u64) -> Result<(), RepoError> {
+    state.photos.delete(id).await.map_err(RepoError::Io)?;
+    state.attachments.delete_all(id).await?;
+    Ok(())
+}
+
 async fn contacts_delete(
     State(state): State<AppState>,
     flash: Flash,
-    Path(contact_id): Path<u64>,
+    Path(contact_key): Path<ContactKey>,
     HxTrigger(trigger): HxTrigger,
-) -> Response {
-    let contact = state.contact_repo.find(contact_id).await.unwrap();
-
-    state.contact_repo.delete(contact).await;
-    if trigger.as_ref().map(|s| s.as_str()) == Some("delete-btn") {
-        (flash.info("Deleted contact!"), Redirect::to("/contacts")).into_response()
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    delete_contact(&state, &contact).await?;
+    if trigger.as_deref() == Some("delete-btn") {
+        Ok((flash.info("Deleted contact!"), Redirect::to("/contacts")).into_response())
     } else {
-        "".into_response()
+        Ok("".into_response())
     }
 }
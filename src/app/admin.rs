@@ -0,0 +1,177 @@
+use axum::{
+    async_trait,
+    extract::{DefaultBodyLimit, FromRequestParts, Multipart, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, WWW_AUTHENTICATE},
+        request::Parts,
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use axum_template::{Key, RenderHtml};
+use base64::Engine as _;
+
+use super::{AppEngine, AppState};
+use crate::{
+    backup,
+    model::{ContactStore, RepoError, RepoStats, Sort, LATENCY_BUCKETS_MS},
+};
+
+/// Largest backup, in bytes, that can be restored.
+const MAX_BACKUP_SIZE: usize = 256 * 1024 * 1024;
+
+/// Routes for operators, only reachable with the admin token.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/status", get(status_get))
+        .route("/admin/backup", get(backup_get))
+        .route(
+            "/admin/restore",
+            // Room for the rest of the multipart body besides the file.
+            post(restore_post).layer(DefaultBodyLimit::max(MAX_BACKUP_SIZE + 64 * 1024)),
+        )
+        .route(
+            "/admin/disposable-domains/refresh",
+            post(disposable_domains_refresh_post),
+        )
+}
+
+/// Extracts only from requests carrying the admin token, either as a bearer
+/// token or as the password of basic auth (with any user name), so browsers
+/// can prompt for it. Without a configured token the admin routes are not
+/// found.
+pub struct Admin;
+
+#[async_trait]
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(token) = &state.admin_token else {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        };
+        let given = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(credentials);
+        match given {
+            Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => Ok(Admin),
+            _ => Err((
+                StatusCode::UNAUTHORIZED,
+                [(WWW_AUTHENTICATE, "Basic realm=\"admin\"")],
+            )
+                .into_response()),
+        }
+    }
+}
+
+/// The token in an `Authorization` header.
+fn credentials(header: &str) -> Option<String> {
+    let (scheme, value) = header.split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return Some(value.trim().to_owned());
+    }
+    if scheme.eq_ignore_ascii_case("basic") {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(value.trim())
+            .ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (_user, password) = decoded.split_once(':')?;
+        return Some(password.to_owned());
+    }
+    None
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StatusCtx {
+    stats: RepoStats,
+    /// Bounds of the latency buckets in `stats.calls`.
+    latency_buckets: &'static [u64],
+}
+
+/// Shows how the store is doing, see [`crate::model::ContactRepo::stats`].
+async fn status_get(
+    _: Admin,
+    engine: AppEngine,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let stats = state.contact_repo.stats().await;
+    let ctx = StatusCtx {
+        stats,
+        latency_buckets: &LATENCY_BUCKETS_MS,
+    };
+    RenderHtml(Key("status.html".to_owned()), engine, ctx)
+}
+
+/// Downloads all contacts in the format of a backup, see [`backup::encode`].
+async fn backup_get(_: Admin, State(state): State<AppState>) -> impl IntoResponse {
+    let data = backup::encode(&state.contact_repo.all(Sort::default()).await);
+    let name = backup::new_name();
+    (
+        [
+            (CONTENT_TYPE, "application/json".to_owned()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}\""),
+            ),
+        ],
+        data,
+    )
+}
+
+/// Replaces all contacts with those of a backup, uploaded as the `backup`
+/// field of a multipart form, e.g. `curl -F backup=@contacts.json`. The
+/// backup may also be a snapshot of a `json://` store in any format.
+async fn restore_post(_: Admin, State(state): State<AppState>, mut form: Multipart) -> Response {
+    let mut data = None;
+    loop {
+        match form.next_field().await {
+            Ok(Some(field)) if field.name() == Some("backup") => match field.bytes().await {
+                Ok(bytes) => data = Some(bytes.to_vec()),
+                Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+            },
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+        }
+    }
+    let Some(data) = data else {
+        return (StatusCode::BAD_REQUEST, "no backup uploaded").into_response();
+    };
+    let contacts = match ContactStore::parse(data, state.store_key.as_ref()) {
+        Ok((store, _)) => store.into_contacts(),
+        Err(err) => {
+            let msg = format!("not a valid backup: {err}");
+            return (StatusCode::BAD_REQUEST, msg).into_response();
+        }
+    };
+    let count = contacts.len();
+    match state.contact_repo.replace_all(contacts).await {
+        Ok(()) => format!("restored {count} contacts").into_response(),
+        // Conflicts are between contacts of the backup, so the backup is
+        // what needs fixing.
+        Err(err @ RepoError::Conflict(_)) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Rereads the list of disposable email domains from
+/// `DISPOSABLE_DOMAINS_FILE`, e.g. after downloading a newer one.
+async fn disposable_domains_refresh_post(_: Admin, State(state): State<AppState>) -> Response {
+    match state.disposable_domains.refresh() {
+        Ok(count) => format!("{count} disposable domains").into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
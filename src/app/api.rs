@@ -0,0 +1,344 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Query, RawQuery, State},
+    http::{
+        header::{LINK, LOCATION, VARY},
+        request::Parts,
+        HeaderMap, HeaderName, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::{any, get},
+    Json, Router,
+};
+
+use serde_json::Value;
+
+use super::{
+    delete_contact, etag, find_contact, groups,
+    jsonapi::{self, ApiFormat},
+    tag_filter, AppState, PAGE_SIZE,
+};
+use crate::model::{
+    Contact, ContactFilter, ContactKey, Direction, GroupError, Page, RepoError, Sort, SortBy,
+};
+
+/// Most contacts listed per page of the API, whatever `size` asks for.
+const MAX_PAGE_SIZE: usize = 100;
+
+/// The header giving the number of contacts on all pages of a listing.
+const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// Routes listing, creating, reading, replacing, changing and deleting
+/// contacts as JSON, for clients other than the browser.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/contacts", get(contacts_get).post(contacts_post))
+        .route(
+            "/api/v1/contacts/:contact_id",
+            get(contact_get)
+                .put(contact_put)
+                .patch(contact_patch)
+                .delete(contact_delete),
+        )
+        .route("/api/*path", any(not_found))
+}
+
+/// The response to a request for a URL under `/api` there is nothing at,
+/// as an error like the others of the API.
+async fn not_found(format: ApiFormat) -> Response {
+    format.invalid(StatusCode::NOT_FOUND, "Not Found".to_owned())
+}
+
+/// The id or uuid of the contact in the path of a request, or the response
+/// that there is no such contact if it is neither.
+struct ApiKey(ContactKey);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiKey {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let format = match ApiFormat::from_request_parts(parts, state).await {
+            Ok(format) => format,
+            Err(never) => match never {},
+        };
+        let Path(key) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        key.parse()
+            .map(Self)
+            .map_err(|err| format.invalid(StatusCode::NOT_FOUND, err))
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct ListParams {
+    /// Lists only the contacts searching for this finds, see
+    /// [`SearchQuery`](crate::model::SearchQuery).
+    pub(super) q: Option<String>,
+    /// Lists only the contacts with this tag.
+    pub(super) tag: Option<String>,
+    /// Lists only the contacts in the group with this id; takes precedence
+    /// over `tag` unless searching.
+    pub(super) group: Option<u64>,
+    /// Lists archived contacts too.
+    #[serde(default)]
+    pub(super) include_archived: bool,
+    pub(super) page: Option<usize>,
+    /// Contacts per page, [`PAGE_SIZE`] unless given and at most
+    /// [`MAX_PAGE_SIZE`].
+    pub(super) size: Option<usize>,
+    #[serde(default)]
+    pub(super) sort: SortBy,
+    #[serde(default)]
+    pub(super) direction: Direction,
+}
+
+/// The page of contacts `params` asks for, or [`GroupError::NotFound`] if
+/// filtered by a group that doesn't exist.
+pub(super) async fn list_contacts(
+    state: &AppState,
+    params: ListParams,
+) -> Result<Page<Contact>, GroupError> {
+    let sort = Sort::new(params.sort, params.direction).with_names(state.name_order);
+    let number = params.page.unwrap_or(1).max(1);
+    let size = params.size.unwrap_or(PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let tag = params.tag.as_deref().and_then(tag_filter);
+    if let Some(id) = params.group {
+        groups::find_group(state, id).await?;
+    }
+    let repo = &state.contact_repo;
+    Ok(match (&params.q, params.group, &tag) {
+        (Some(q), group, tag) => {
+            let filter = ContactFilter {
+                tag: tag.clone(),
+                group,
+                archived: params.include_archived,
+            };
+            repo.search_filtered(q, &filter, sort, number, size).await
+        }
+        (None, Some(group), _) => repo.members(group, sort, number, size).await,
+        (None, None, Some(tag)) => repo.tagged(tag, sort, number, size).await,
+        (None, None, None) if params.include_archived => repo.page(sort, number, size).await,
+        (None, None, None) => repo.archived(false, sort, number, size).await,
+    })
+}
+
+/// A page of the contacts, as the index lists them, with the URLs of the
+/// pages around it in `Link` and the number of contacts in `X-Total-Count`
+/// for clients that don't read the body to page through them.
+async fn contacts_get(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+    Query(params): Query<ListParams>,
+) -> Response {
+    match list_contacts(&state, params).await {
+        Ok(page) => {
+            let links = page_links(query.as_deref(), &page);
+            let link = links
+                .iter()
+                .map(|(rel, url)| format!("<{url}>; rel=\"{rel}\""))
+                .collect::<Vec<_>>()
+                .join(", ");
+            (
+                [(LINK, link), (TOTAL_COUNT, page.total().to_string())],
+                format.page(&page, &links),
+            )
+                .into_response()
+        }
+        Err(err) => format.group_error(err),
+    }
+}
+
+/// The URLs of `page` of the listing with `query` and of the pages around
+/// it, by relation.
+pub(super) fn page_links(query: Option<&str>, page: &Page<Contact>) -> Vec<(&'static str, String)> {
+    let url = |number: usize| {
+        let mut url = url::form_urlencoded::Serializer::new(String::new());
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            if key != "page" {
+                url.append_pair(&key, &value);
+            }
+        }
+        url.append_pair("page", &number.to_string());
+        format!("/api/v1/contacts?{}", url.finish())
+    };
+    let mut links = vec![("self", url(page.number())), ("first", url(1))];
+    if page.number() > 1 {
+        links.push(("prev", url((page.number() - 1).min(page.pages()))));
+    }
+    if page.number() < page.pages() {
+        links.push(("next", url(page.number() + 1)));
+    }
+    links.push(("last", url(page.pages())));
+    links
+}
+
+/// The contact sent as `body`, plain or as a JSON:API document, or why it
+/// isn't one.
+pub(super) fn contact_from(body: Value) -> Result<Contact, String> {
+    serde_json::from_value(jsonapi::fields(body)).map_err(|err| err.to_string())
+}
+
+/// The contact a request gave, or why it failed.
+fn respond(format: ApiFormat, contact: Result<Contact, RepoError>) -> Response {
+    match contact {
+        Ok(contact) => tagged(format, StatusCode::OK, &contact),
+        Err(err) => format.repo_error(err),
+    }
+}
+
+/// The entity tag of `contact` written in `format`.
+fn contact_etag(format: ApiFormat, contact: &Contact) -> String {
+    etag::etag(contact, (format == ApiFormat::JsonApi).then_some("jsonapi"))
+}
+
+/// The response refusing to change `stored` if the request's `If-Match`
+/// gives none of its entity tags, in either format, as it was changed since
+/// the client read it.
+fn precondition_failed(
+    format: ApiFormat,
+    headers: &HeaderMap,
+    stored: &Contact,
+) -> Option<Response> {
+    let matched = [ApiFormat::Plain, ApiFormat::JsonApi]
+        .into_iter()
+        .filter_map(|tagged| etag::if_match(headers, &contact_etag(tagged, stored)))
+        .reduce(|matched, also| matched || also);
+    (matched == Some(false)).then(|| {
+        format.invalid(
+            StatusCode::PRECONDITION_FAILED,
+            "Contact Was Changed Meanwhile".to_owned(),
+        )
+    })
+}
+
+/// `contact` with `status`, tagged with its entity tag.
+fn tagged(format: ApiFormat, status: StatusCode, contact: &Contact) -> Response {
+    let response = format.contact(status, contact);
+    (
+        [(VARY, "accept")],
+        etag::tagged(contact_etag(format, contact), response),
+    )
+        .into_response()
+}
+
+/// Creates the contact sent, validated as if entered in the form, and
+/// returns it as stored.
+async fn contacts_post(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut contact = match contact_from(body) {
+        Ok(contact) => contact,
+        Err(err) => return format.invalid(StatusCode::UNPROCESSABLE_ENTITY, err),
+    };
+    contact.forget_identity();
+    state.id_strategy.assign(&mut contact);
+    let created = async {
+        let id = state.contact_repo.create(contact).await?;
+        find_contact(&state.contact_repo, ContactKey::Id(id)).await
+    };
+    match created.await {
+        Ok(contact) => {
+            let key = contact.key().expect("a stored contact to have a key");
+            (
+                [(LOCATION, format!("/api/v1/contacts/{key}"))],
+                tagged(format, StatusCode::CREATED, &contact),
+            )
+                .into_response()
+        }
+        Err(err) => format.repo_error(err),
+    }
+}
+
+/// The contact, or that the copy tagged in `If-None-Match` is current.
+async fn contact_get(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    ApiKey(contact_key): ApiKey,
+    headers: HeaderMap,
+) -> Response {
+    match find_contact(&state.contact_repo, contact_key).await {
+        Ok(contact) => {
+            let etag = contact_etag(format, &contact);
+            if etag::none_match(&headers, &etag) {
+                return ([(VARY, "accept")], etag::not_modified(etag)).into_response();
+            }
+            tagged(format, StatusCode::OK, &contact)
+        }
+        Err(err) => format.repo_error(err),
+    }
+}
+
+/// Replaces the contact with the one sent, leaving out fields nulls them,
+/// and returns it as stored. Sending the `version` read rejects the update
+/// if the contact was changed since.
+async fn contact_put(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    ApiKey(contact_key): ApiKey,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut contact = match contact_from(body) {
+        Ok(contact) => contact,
+        Err(err) => return format.invalid(StatusCode::UNPROCESSABLE_ENTITY, err),
+    };
+    let stored = match find_contact(&state.contact_repo, contact_key).await {
+        Ok(stored) => stored,
+        Err(err) => return format.repo_error(err),
+    };
+    if let Some(failed) = precondition_failed(format, &headers, &stored) {
+        return failed;
+    }
+    contact.replace(&stored);
+    respond(format, save(&state, contact).await)
+}
+
+/// Changes only the fields of the contact sent, [merged](Contact::merge) as
+/// a JSON merge patch where `null` clears a field and fields left out are
+/// kept, and returns it as stored.
+async fn contact_patch(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    ApiKey(contact_key): ApiKey,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Response {
+    let mut contact = match find_contact(&state.contact_repo, contact_key).await {
+        Ok(contact) => contact,
+        Err(err) => return format.repo_error(err),
+    };
+    if let Some(failed) = precondition_failed(format, &headers, &contact) {
+        return failed;
+    }
+    if let Err(err) = contact.merge(jsonapi::fields(patch)) {
+        return format.invalid(StatusCode::UNPROCESSABLE_ENTITY, err.to_string());
+    }
+    respond(format, save(&state, contact).await)
+}
+
+/// Updates the stored contact with `contact` and returns it as stored.
+async fn save(state: &AppState, contact: Contact) -> Result<Contact, RepoError> {
+    let key = contact.key().expect("a stored contact to have a key");
+    state.contact_repo.update(contact).await?;
+    find_contact(&state.contact_repo, key).await
+}
+
+async fn contact_delete(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    ApiKey(contact_key): ApiKey,
+) -> Response {
+    let deleted = async {
+        let contact = find_contact(&state.contact_repo, contact_key).await?;
+        delete_contact(&state, &contact).await
+    };
+    match deleted.await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => format.repo_error(err),
+    }
+}
@@ -0,0 +1,158 @@
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+        StatusCode,
+    },
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, post},
+    Router,
+};
+use axum_flash::Flash;
+use uuid::Uuid;
+
+use super::{find_contact, AppState};
+use crate::model::{Attachment, ContactKey, RepoError, MAX_ATTACHMENT_SIZE};
+
+/// Routes uploading, downloading and deleting contacts' attachments.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/contacts/:contact_id/attachments",
+            // Room for the rest of the multipart body besides the file.
+            post(attachments_post).layer(DefaultBodyLimit::max(MAX_ATTACHMENT_SIZE + 64 * 1024)),
+        )
+        .route(
+            "/contacts/:contact_id/attachments/:attachment_id",
+            delete(attachment_delete).get(attachment_get),
+        )
+}
+
+/// A size in bytes for display, e.g. `12.5 KB`.
+pub fn filesize(size: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Attaches the file of the `file` field, posted as `multipart/form-data`
+/// from the show page. The file is saved before the contact records it, so
+/// a failed save leaves no attachment without a file.
+async fn attachments_post(
+    State(state): State<AppState>,
+    flash: Flash,
+    Path(contact_key): Path<ContactKey>,
+    mut multipart: Multipart,
+) -> Result<Response, RepoError> {
+    let mut contact = find_contact(&state.contact_repo, contact_key).await?;
+    let id = contact.id().expect("a stored contact to have an id");
+    let bad_request = |message: String| Ok((StatusCode::BAD_REQUEST, message).into_response());
+    let mut upload = None;
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => {
+                let name = field.file_name().unwrap_or_default().to_owned();
+                let content_type = field.content_type().map(str::to_owned);
+                match field.bytes().await {
+                    Ok(data) => upload = Some((name, content_type, data)),
+                    Err(err) => return bad_request(err.to_string()),
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(err) => return bad_request(err.to_string()),
+        }
+    }
+    let Some((name, content_type, data)) = upload.filter(|(_, _, data)| !data.is_empty()) else {
+        return bad_request("No file chosen".to_owned());
+    };
+    if data.len() > MAX_ATTACHMENT_SIZE {
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE, "Attachment Too Large").into_response());
+    }
+    let attachment = Attachment::new(&name, content_type.as_deref(), data.len() as u64);
+    let attachment_id = attachment.id;
+    state
+        .attachments
+        .save(id, attachment_id, data.to_vec())
+        .await?;
+    contact.add_attachment(attachment);
+    if let Err(err) = state.contact_repo.update(contact).await {
+        state.attachments.delete(id, attachment_id).await?;
+        return Err(err);
+    }
+    Ok((
+        flash.info("Attached file!"),
+        Redirect::to(&format!("/contacts/{contact_key}")),
+    )
+        .into_response())
+}
+
+/// Serves the file for download, never to be shown inline, whatever type it
+/// was uploaded as.
+async fn attachment_get(
+    State(state): State<AppState>,
+    Path((contact_key, attachment_id)): Path<(ContactKey, Uuid)>,
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let id = contact.id().expect("a stored contact to have an id");
+    let Some(attachment) = contact.attachment(attachment_id) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(data) = state.attachments.find(id, attachment_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    Ok((
+        [
+            (CONTENT_TYPE, attachment.content_type.clone()),
+            (CONTENT_DISPOSITION, content_disposition(&attachment.name)),
+            (X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// An `attachment` disposition naming the file `name`: as is in
+/// `filename*`, and with anything but printable ASCII replaced in
+/// `filename`, for clients that don't read the former.
+fn content_disposition(name: &str) -> String {
+    let ascii: String = name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    format!("attachment; filename=\"{ascii}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Deletes the attachment, its file once the contact no longer records it.
+async fn attachment_delete(
+    State(state): State<AppState>,
+    Path((contact_key, attachment_id)): Path<(ContactKey, Uuid)>,
+) -> Result<impl IntoResponse, RepoError> {
+    let mut contact = find_contact(&state.contact_repo, contact_key).await?;
+    let id = contact.id().expect("a stored contact to have an id");
+    if contact.remove_attachment(attachment_id).is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    state.contact_repo.update(contact).await?;
+    state.attachments.delete(id, attachment_id).await?;
+    Ok("".into_response())
+}
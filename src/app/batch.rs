@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+use super::{
+    api::contact_from,
+    delete_files, find_contact,
+    jsonapi::{self, ApiFormat},
+    repo_status, AppState,
+};
+use crate::model::{BatchError, Contact, ContactKey, RepoError};
+
+/// Most items a batch may have.
+const MAX_BATCH: usize = 1000;
+
+/// Routes creating, changing and deleting many contacts in one request, for
+/// sync tools that would otherwise make a request per contact.
+pub fn routes() -> Router<AppState> {
+    // The router reads `:` as starting a parameter, which so captures the
+    // method with its colon, e.g. `:batchCreate`.
+    Router::new().route("/api/v1/contacts:method", post(batch))
+}
+
+async fn batch(
+    format: ApiFormat,
+    State(state): State<AppState>,
+    Path(method): Path<String>,
+    Json(items): Json<Vec<Value>>,
+) -> Response {
+    if items.len() > MAX_BATCH {
+        let message = "Too Many Items In Batch".to_owned();
+        return format.invalid(StatusCode::PAYLOAD_TOO_LARGE, message);
+    }
+    match method.as_str() {
+        ":batchCreate" => batch_create(&state, format, items).await,
+        ":batchUpdate" => batch_update(&state, format, items).await,
+        ":batchDelete" => batch_delete(&state, format, items).await,
+        _ => format.invalid(StatusCode::NOT_FOUND, "Not Found".to_owned()),
+    }
+}
+
+/// The results of a batch, one per item in the order they were sent, with
+/// the contacts in the format asked for.
+struct Results {
+    items: Vec<Value>,
+    format: ApiFormat,
+}
+
+impl Results {
+    fn new(len: usize, format: ApiFormat) -> Self {
+        Self {
+            items: vec![Value::Null; len],
+            format,
+        }
+    }
+
+    fn done(&mut self, index: usize, status: StatusCode, contact: Option<&Contact>) {
+        self.items[index] = match contact {
+            Some(contact) => json!({
+                "status": status.as_u16(),
+                "contact": self.format.contact_value(contact),
+            }),
+            None => json!({ "status": status.as_u16() }),
+        };
+    }
+
+    /// Records that the item at `index` failed with `err`, with the errors
+    /// by field if it is invalid or conflicts with another contact.
+    fn failed(&mut self, index: usize, err: &RepoError) {
+        let status = repo_status(err);
+        self.items[index] = if status.is_server_error() {
+            eprintln!("{err}");
+            json!({ "status": status.as_u16() })
+        } else {
+            match err {
+                RepoError::Validation(contact) | RepoError::Conflict(contact) => json!({
+                    "status": status.as_u16(),
+                    "message": err.to_string(),
+                    "errors": contact.errors.errors(),
+                }),
+                _ => json!({ "status": status.as_u16(), "message": err.to_string() }),
+            }
+        };
+    }
+
+    /// Records what became of the items at `indices`, written together, as
+    /// writing them failed with `err`: those rejected failed for their own
+    /// errors, and the others weren't written with them.
+    fn batch_failed(&mut self, indices: &[usize], err: BatchError) {
+        match err {
+            BatchError::Rejected(rejected) => {
+                for (position, &index) in indices.iter().enumerate() {
+                    match rejected.get(&position) {
+                        Some(err) => self.failed(index, err),
+                        None => {
+                            self.items[index] = json!({
+                                "status": StatusCode::FAILED_DEPENDENCY.as_u16(),
+                                "message": "Not Saved As Another Item Was Rejected",
+                            });
+                        }
+                    }
+                }
+            }
+            BatchError::Failed(err) => {
+                for &index in indices {
+                    self.failed(index, &err);
+                }
+            }
+        }
+    }
+
+    /// Records that the item at `index` isn't one the batch takes.
+    fn invalid(&mut self, index: usize, message: String) {
+        self.items[index] = json!({
+            "status": StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+            "message": message,
+        });
+    }
+}
+
+impl IntoResponse for Results {
+    fn into_response(self) -> Response {
+        Json(json!({ "results": self.items })).into_response()
+    }
+}
+
+/// The contact with the id or uuid `key`, a number or a string.
+fn key_of(key: &Value) -> Result<ContactKey, String> {
+    match key {
+        Value::Number(id) => id
+            .as_u64()
+            .map(ContactKey::Id)
+            .ok_or_else(|| format!("'{id}' is neither a contact id nor a uuid")),
+        Value::String(key) => key.parse(),
+        _ => Err("Contact Id Or Uuid Required".to_owned()),
+    }
+}
+
+/// Creates the contacts sent, each validated as if entered in the form.
+/// They are created together, so if one of them is rejected, none of them
+/// is.
+async fn batch_create(state: &AppState, format: ApiFormat, items: Vec<Value>) -> Response {
+    let mut results = Results::new(items.len(), format);
+    let mut pending = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let mut contact = match contact_from(item) {
+            Ok(contact) => contact,
+            Err(err) => {
+                results.invalid(index, err);
+                continue;
+            }
+        };
+        contact.forget_identity();
+        state.id_strategy.assign(&mut contact);
+        pending.push((index, contact));
+    }
+    let (indices, contacts): (Vec<_>, Vec<_>) = pending.into_iter().unzip();
+    match state.contact_repo.create_many(contacts).await {
+        Ok(ids) => {
+            let created = stored(state, &ids).await;
+            for (index, id) in indices.into_iter().zip(ids) {
+                results.done(index, StatusCode::CREATED, created.get(&id));
+            }
+        }
+        Err(err) => results.batch_failed(&indices, err),
+    }
+    results.into_response()
+}
+
+/// Changes the fields each item sends, plain or as a JSON:API document, of
+/// the contact with its `uuid` or `id`, as `PATCH` does. The items whose contacts exist are saved
+/// together, so if one of them is rejected, none of them is. A contact may
+/// only be changed by one item.
+async fn batch_update(state: &AppState, format: ApiFormat, items: Vec<Value>) -> Response {
+    let mut results = Results::new(items.len(), format);
+    let mut pending = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let item = jsonapi::fields(item);
+        let key = match item.get("uuid").filter(|uuid| !uuid.is_null()) {
+            Some(uuid) => key_of(uuid),
+            None => key_of(item.get("id").unwrap_or(&Value::Null)),
+        };
+        let key = match key {
+            Ok(key) => key,
+            Err(err) => {
+                results.invalid(index, err);
+                continue;
+            }
+        };
+        let mut contact = match find_contact(&state.contact_repo, key).await {
+            Ok(contact) => contact,
+            Err(err) => {
+                results.failed(index, &err);
+                continue;
+            }
+        };
+        if !seen.insert(contact.id().expect("a stored contact to have an id")) {
+            results.invalid(index, "Contact Changed By Another Item".to_owned());
+            continue;
+        }
+        if let Err(err) = contact.merge(item) {
+            results.invalid(index, err.to_string());
+            continue;
+        }
+        pending.push((index, contact));
+    }
+    let (indices, contacts): (Vec<_>, Vec<_>) = pending.into_iter().unzip();
+    let ids: Vec<u64> = contacts
+        .iter()
+        .map(|contact| contact.id().expect("a stored contact to have an id"))
+        .collect();
+    match state.contact_repo.update_many(contacts).await {
+        Ok(()) => {
+            let updated = stored(state, &ids).await;
+            for (index, id) in indices.into_iter().zip(ids) {
+                results.done(index, StatusCode::OK, updated.get(&id));
+            }
+        }
+        Err(err) => results.batch_failed(&indices, err),
+    }
+    results.into_response()
+}
+
+/// Deletes the contacts with the ids or uuids sent, with their photos and
+/// attachments. Those that exist are deleted together, once however many
+/// items give them.
+async fn batch_delete(state: &AppState, format: ApiFormat, items: Vec<Value>) -> Response {
+    let mut results = Results::new(items.len(), format);
+    // The indices of the items giving each contact, by id.
+    let mut pending: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (index, item) in items.iter().enumerate() {
+        let key = match key_of(item) {
+            Ok(key) => key,
+            Err(err) => {
+                results.invalid(index, err);
+                continue;
+            }
+        };
+        match find_contact(&state.contact_repo, key).await {
+            Ok(contact) => pending
+                .entry(contact.id().expect("a stored contact to have an id"))
+                .or_default()
+                .push(index),
+            Err(err) => results.failed(index, &err),
+        }
+    }
+    let ids: Vec<u64> = pending.keys().copied().collect();
+    if let Err(err) = state.contact_repo.delete_many(&ids).await {
+        for &index in pending.values().flatten() {
+            results.failed(index, &err);
+        }
+        return results.into_response();
+    }
+    for (id, indices) in pending {
+        let deleted = delete_files(state, id).await;
+        for index in indices {
+            match &deleted {
+                Ok(()) => results.done(index, StatusCode::NO_CONTENT, None),
+                Err(err) => results.failed(index, err),
+            }
+        }
+    }
+    results.into_response()
+}
+
+/// The stored contacts with `ids`, by id, or none if reading them fails.
+async fn stored(state: &AppState, ids: &[u64]) -> HashMap<u64, Contact> {
+    let contacts = match state.contact_repo.find_many(ids).await {
+        Ok(contacts) => contacts,
+        Err(err) => {
+            eprintln!("{err}");
+            Vec::new()
+        }
+    };
+    contacts
+        .into_iter()
+        .map(|contact| {
+            (
+                contact.id().expect("a stored contact to have an id"),
+                contact,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,63 @@
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use axum_template::{Key, RenderHtml};
+use chrono::Utc;
+
+use super::{AppEngine, AppState};
+use crate::model::{upcoming_dates, ImportantDate, Sort, UpcomingDate};
+
+/// Days ahead the upcoming dates are listed for by default.
+const DEFAULT_DAYS: i64 = 30;
+
+/// Routes listing upcoming dates and serving rows of the date form fields.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/dates", get(dates_get))
+        .route("/contacts/date-row", get(date_row_get))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DatesParams {
+    /// Days ahead to list, up to a year.
+    days: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DatesCtx {
+    days: i64,
+    dates: Vec<UpcomingDate>,
+}
+
+/// The birthdays and important dates of all contacts but archived ones
+/// coming around in the next days.
+async fn dates_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Query(params): Query<DatesParams>,
+) -> impl IntoResponse {
+    let days = params.days.unwrap_or(DEFAULT_DAYS).clamp(0, 366);
+    let mut contacts = state.contact_repo.all(Sort::default()).await;
+    contacts.retain(|contact| !contact.archived());
+    let dates = upcoming_dates(contacts, Utc::now().date_naive(), days);
+    RenderHtml(
+        Key("dates.html".to_owned()),
+        engine,
+        DatesCtx { days, dates },
+    )
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DateRowCtx {
+    /// `None` for an empty row, which has no date to fill in.
+    date: Option<ImportantDate>,
+}
+
+/// An empty row for an important date in the new and edit forms.
+async fn date_row_get(engine: AppEngine) -> impl IntoResponse {
+    let ctx = DateRowCtx { date: None };
+    RenderHtml(Key("date_row.html".to_owned()), engine, ctx)
+}
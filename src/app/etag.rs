@@ -0,0 +1,99 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use axum::{
+    http::{
+        header::{CACHE_CONTROL, ETAG, IF_MATCH, IF_NONE_MATCH},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+
+use crate::model::{Contact, CustomField, Group};
+
+/// The entity tag of the stored `contact`, which changes each time it is
+/// saved, extended with `variant` to tell apart representations of it.
+pub fn etag(contact: &Contact, variant: Option<&str>) -> String {
+    let saved = contact
+        .updated_at()
+        .map_or(0, |updated_at| updated_at.timestamp_micros());
+    match variant {
+        Some(variant) => format!("\"{}-{saved}-{variant}\"", contact.version()),
+        None => format!("\"{}-{saved}\"", contact.version()),
+    }
+}
+
+/// The weak entity tag of the page showing `contact` with the names of the
+/// `groups` it is in and its values of the custom `fields`, which also
+/// changes each day for the age it shows and whenever those groups or fields
+/// do, or none if it shows the contact's local time, which changes each
+/// minute.
+pub fn page_etag(contact: &Contact, groups: &[Group], fields: &[CustomField]) -> Option<String> {
+    if contact.timezone().is_some() {
+        return None;
+    }
+    let today = Utc::now().date_naive();
+    let mut hasher = DefaultHasher::new();
+    for group in groups
+        .iter()
+        .filter(|group| group.id().is_some_and(|id| contact.groups().contains(&id)))
+    {
+        (group.id(), &group.name).hash(&mut hasher);
+    }
+    for field in fields {
+        (&field.name, field.kind).hash(&mut hasher);
+    }
+    let shown = hasher.finish();
+    Some(format!(
+        "W/{}",
+        etag(contact, Some(&format!("html-{today}-{shown:x}")))
+    ))
+}
+
+/// Whether the `If-None-Match` header of a request lists `etag`, or is
+/// `*`, so the representation it has cached is still current. Tags are
+/// compared weakly, ignoring a `W/` prefix on either.
+pub fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Whether the `If-Match` header of a request lists `etag`, or is `*`, so
+/// the change it asks for was made to the current representation, or none
+/// if it has no such header. Tags are compared strongly, a weak one never
+/// matching.
+pub fn if_match(headers: &HeaderMap, etag: &str) -> Option<bool> {
+    let mut tags = headers
+        .get_all(IF_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .peekable();
+    tags.peek()?;
+    Some(tags.any(|tag| tag == "*" || tag == etag))
+}
+
+/// `response` tagged with `etag`, which caches must check is still current
+/// before reusing it.
+pub fn tagged(etag: String, response: impl IntoResponse) -> Response {
+    (
+        [(ETAG, etag), (CACHE_CONTROL, "no-cache".to_owned())],
+        response,
+    )
+        .into_response()
+}
+
+/// The response telling a client that its copy tagged `etag` is current.
+pub fn not_modified(etag: String) -> Response {
+    tagged(etag, StatusCode::NOT_MODIFIED)
+}
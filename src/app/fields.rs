@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get},
+    Form, Router,
+};
+use axum_flash::Flash;
+use axum_template::{Key, RenderHtml};
+use chrono::NaiveDate;
+use serde_json::Value;
+
+use super::{filled, AppEngine, AppState, BIRTHDAY_FORMAT};
+use crate::model::{Contact, CustomField, FieldError, FieldKind};
+
+/// Routes listing, adding, editing and deleting custom fields.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/fields", get(fields_get).post(fields_post))
+        .route("/fields/:name", delete(field_delete))
+        .route(
+            "/fields/:name/edit",
+            get(field_edit_get).post(field_edit_post),
+        )
+}
+
+impl IntoResponse for FieldError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            FieldError::NotFound(_) => StatusCode::NOT_FOUND,
+            FieldError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            FieldError::Conflict(_) => StatusCode::CONFLICT,
+            FieldError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            eprintln!("{self}");
+            return status.into_response();
+        }
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// Sets the values entered for the custom fields of `schema` on `contact`,
+/// from the `custom.<name>` fields of the new and edit forms, or records
+/// why they aren't valid so saving it fails validation. Values of fields no
+/// longer in the schema are kept.
+pub(super) fn set_custom(
+    schema: &[CustomField],
+    mut values: HashMap<String, String>,
+    contact: &mut Contact,
+) {
+    for field in schema {
+        let raw = values.remove(&field.name).and_then(filled);
+        let parsed = match (field.kind, raw) {
+            (FieldKind::Checkbox, raw) => Ok(raw.map(|_| Value::Bool(true))),
+            (_, None) => Ok(None),
+            (FieldKind::Text, Some(raw)) => Ok(Some(Value::String(raw))),
+            (FieldKind::Number, Some(raw)) => match number(&raw) {
+                Some(number) => Ok(Some(number)),
+                None => Err(("Invalid Number", raw)),
+            },
+            (FieldKind::Date, Some(raw)) => {
+                match NaiveDate::parse_from_str(&raw, BIRTHDAY_FORMAT) {
+                    Ok(date) => Ok(Some(Value::String(
+                        date.format(BIRTHDAY_FORMAT).to_string(),
+                    ))),
+                    Err(_) => Err(("Invalid Date", raw)),
+                }
+            }
+        };
+        let key = format!("custom.{}", field.name);
+        match parsed {
+            Ok(None) if field.required => {
+                contact.errors.insert(key, "required", "Required");
+                contact.set_custom(&field.name, None);
+            }
+            Ok(value) => contact.set_custom(&field.name, value),
+            // Kept as entered, to show it again in the form.
+            Err((error, raw)) => {
+                contact.errors.insert(key, "invalid", error);
+                contact.set_custom(&field.name, Some(Value::String(raw)));
+            }
+        }
+    }
+}
+
+/// `raw` as a JSON number, an integer if it is one.
+fn number(raw: &str) -> Option<Value> {
+    if let Ok(integer) = raw.parse::<i64>() {
+        return Some(integer.into());
+    }
+    let float = raw.parse::<f64>().ok()?;
+    serde_json::Number::from_f64(float).map(Value::Number)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldsCtx {
+    fields: Vec<CustomField>,
+    /// The field being added, with its errors if adding it failed.
+    field: CustomField,
+}
+
+async fn fields_get(engine: AppEngine, State(state): State<AppState>) -> impl IntoResponse {
+    RenderHtml(
+        Key("fields.html".to_owned()),
+        engine,
+        FieldsCtx {
+            fields: state.fields.all().await,
+            field: CustomField::default(),
+        },
+    )
+}
+
+/// Fields of the add and edit custom field forms. The name is only read
+/// when adding one.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FieldForm {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    kind: FieldKind,
+    /// `on` if the box is ticked, missing otherwise.
+    required: Option<String>,
+}
+
+async fn fields_post(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    flash: Flash,
+    Form(form): Form<FieldForm>,
+) -> Response {
+    let field = CustomField::new(form.name, form.kind, form.required.is_some());
+    match state.fields.create(field).await {
+        Ok(()) => (flash.info("Added field!"), Redirect::to("/fields")).into_response(),
+        Err(err) => match err.into_field() {
+            Ok(field) => RenderHtml(
+                Key("fields.html".to_owned()),
+                engine,
+                FieldsCtx {
+                    fields: state.fields.all().await,
+                    field,
+                },
+            )
+            .into_response(),
+            Err(err) => err.into_response(),
+        },
+    }
+}
+
+/// The field called `name`, or [`FieldError::NotFound`].
+async fn find_field(state: &AppState, name: &str) -> Result<CustomField, FieldError> {
+    state
+        .fields
+        .find(name)
+        .await?
+        .ok_or_else(|| FieldError::NotFound(name.to_owned()))
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldCtx {
+    field: CustomField,
+}
+
+async fn field_edit_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, FieldError> {
+    let field = find_field(&state, &name).await?;
+    Ok(RenderHtml(
+        Key("field_edit.html".to_owned()),
+        engine,
+        FieldCtx { field },
+    ))
+}
+
+async fn field_edit_post(
+    State(state): State<AppState>,
+    flash: Flash,
+    Path(name): Path<String>,
+    Form(form): Form<FieldForm>,
+) -> Result<Response, FieldError> {
+    let mut field = find_field(&state, &name).await?;
+    field.kind = form.kind;
+    field.required = form.required.is_some();
+    state.fields.update(field).await?;
+    Ok((flash.info("Updated field!"), Redirect::to("/fields")).into_response())
+}
+
+/// Deletes the field, keeping the values contacts have for it.
+async fn field_delete(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, FieldError> {
+    state.fields.delete(&name).await?;
+    Ok("")
+}
@@ -0,0 +1,586 @@
+use async_graphql::{
+    http::{playground_source, GraphQLPlaygroundConfig},
+    Context, EmptySubscription, Error, ErrorExtensions, InputObject, MaybeUndefined, Object,
+    Schema,
+};
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    routing::post,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::{
+    api::{self, ListParams},
+    delete_contact, find_contact, AppState,
+};
+use crate::model::{
+    Address, Contact, ContactKey, EmailAddress, Group, GroupError, Page, PhoneNumber, RepoError,
+    SocialProfile,
+};
+
+pub type ContactSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Routes answering GraphQL queries of the contacts at `/graphql`, and
+/// serving GraphQL Playground there too if `playground`.
+pub fn routes(playground: bool) -> Router<AppState> {
+    let schema = Schema::new(QueryRoot, MutationRoot, EmptySubscription);
+    let mut route = post(graphql_post);
+    if playground {
+        route = route.get(playground_get);
+    }
+    Router::new()
+        .route("/graphql", route)
+        .layer(Extension(schema))
+}
+
+async fn graphql_post(
+    State(state): State<AppState>,
+    Extension(schema): Extension<ContactSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request.data(state)).await)
+}
+
+async fn playground_get() -> impl IntoResponse {
+    Html(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+}
+
+/// The error a query or mutation failing with `err` gives, with a `code`
+/// and, if the contact is invalid or conflicts with another, its `errors`
+/// by field as extensions.
+fn repo_error(err: RepoError) -> Error {
+    let code = match &err {
+        RepoError::NotFound(_) | RepoError::UuidNotFound(_) => "NOT_FOUND",
+        RepoError::Validation(_) => "INVALID",
+        RepoError::Conflict(_) => "CONFLICT",
+        RepoError::Io(_) => {
+            eprintln!("{err}");
+            return Error::new("storage failed").extend_with(|_, e| e.set("code", "INTERNAL"));
+        }
+    };
+    let errors = match &err {
+        RepoError::Validation(contact) | RepoError::Conflict(contact) => {
+            async_graphql::Value::from_json(serde_json::json!(contact.errors.errors())).ok()
+        }
+        _ => None,
+    };
+    Error::new(err.to_string()).extend_with(|_, e| {
+        e.set("code", code);
+        if let Some(errors) = &errors {
+            e.set("errors", errors.clone());
+        }
+    })
+}
+
+/// Like [`repo_error`], for the groups read with the contacts.
+fn group_error(err: GroupError) -> Error {
+    let code = match &err {
+        GroupError::NotFound(_) => "NOT_FOUND",
+        GroupError::Validation(_) => "INVALID",
+        GroupError::Conflict(_) => "CONFLICT",
+        GroupError::Io(_) => {
+            eprintln!("{err}");
+            return Error::new("storage failed").extend_with(|_, e| e.set("code", "INTERNAL"));
+        }
+    };
+    Error::new(err.to_string()).extend_with(|_, e| e.set("code", code))
+}
+
+/// `key` read as a contact's id or uuid.
+fn contact_key(key: &str) -> async_graphql::Result<ContactKey> {
+    key.parse()
+        .map_err(|err: String| Error::new(err).extend_with(|_, e| e.set("code", "NOT_FOUND")))
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A page of the contacts, as the index lists them: those searching for
+    /// `q` finds, if given, and `filter` lets through, with `size` contacts,
+    /// 10 unless given and at most 100, per page.
+    async fn contacts(
+        &self,
+        ctx: &Context<'_>,
+        q: Option<String>,
+        #[graphql(default)] filter: ContactsFilter,
+        page: Option<usize>,
+        size: Option<usize>,
+    ) -> async_graphql::Result<ContactPage> {
+        let state = ctx.data::<AppState>()?;
+        let params = ListParams {
+            q,
+            tag: filter.tag,
+            group: filter.group,
+            include_archived: filter.include_archived,
+            page,
+            size,
+            ..Default::default()
+        };
+        let contacts = api::list_contacts(state, params)
+            .await
+            .map_err(group_error)?;
+        Ok(ContactPage(contacts))
+    }
+
+    /// The contact with `key`, its id or uuid, if there is one.
+    async fn contact(
+        &self,
+        ctx: &Context<'_>,
+        key: String,
+    ) -> async_graphql::Result<Option<ContactObject>> {
+        let state = ctx.data::<AppState>()?;
+        match find_contact(&state.contact_repo, contact_key(&key)?).await {
+            Ok(contact) => Ok(Some(ContactObject(contact))),
+            Err(RepoError::NotFound(_) | RepoError::UuidNotFound(_)) => Ok(None),
+            Err(err) => Err(repo_error(err)),
+        }
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Creates a contact with the fields of `input`, validated as if entered
+    /// in the form, and returns it as stored.
+    async fn create_contact(
+        &self,
+        ctx: &Context<'_>,
+        input: ContactInput,
+    ) -> async_graphql::Result<ContactObject> {
+        let state = ctx.data::<AppState>()?;
+        let mut contact = Contact::default();
+        input.apply(&mut contact);
+        state.id_strategy.assign(&mut contact);
+        let id = state
+            .contact_repo
+            .create(contact)
+            .await
+            .map_err(repo_error)?;
+        let contact = find_contact(&state.contact_repo, ContactKey::Id(id))
+            .await
+            .map_err(repo_error)?;
+        Ok(ContactObject(contact))
+    }
+
+    /// Changes the fields of the contact with `key` given in `input`,
+    /// keeping the others, and returns it as stored. Giving the `version`
+    /// read rejects the change if the contact was changed since.
+    async fn update_contact(
+        &self,
+        ctx: &Context<'_>,
+        key: String,
+        input: ContactInput,
+        version: Option<u64>,
+    ) -> async_graphql::Result<ContactObject> {
+        let state = ctx.data::<AppState>()?;
+        let key = contact_key(&key)?;
+        let mut contact = find_contact(&state.contact_repo, key)
+            .await
+            .map_err(repo_error)?;
+        input.apply(&mut contact);
+        if let Some(version) = version {
+            contact.set_version(version);
+        }
+        state
+            .contact_repo
+            .update(contact)
+            .await
+            .map_err(repo_error)?;
+        let contact = find_contact(&state.contact_repo, key)
+            .await
+            .map_err(repo_error)?;
+        Ok(ContactObject(contact))
+    }
+
+    /// Deletes the contact with `key` with its photo and attachments.
+    async fn delete_contact(&self, ctx: &Context<'_>, key: String) -> async_graphql::Result<bool> {
+        let state = ctx.data::<AppState>()?;
+        let contact = find_contact(&state.contact_repo, contact_key(&key)?)
+            .await
+            .map_err(repo_error)?;
+        delete_contact(state, &contact).await.map_err(repo_error)?;
+        Ok(true)
+    }
+}
+
+/// Which contacts to list, besides those a search finds.
+#[derive(Debug, Default, InputObject)]
+pub struct ContactsFilter {
+    /// Only those with this tag.
+    tag: Option<String>,
+    /// Only those in the group with this id; takes precedence over `tag`
+    /// unless searching.
+    group: Option<u64>,
+    /// Archived contacts too, which are left out otherwise.
+    #[graphql(default)]
+    include_archived: bool,
+}
+
+/// A page of contacts, see [`Page`].
+pub struct ContactPage(Page<Contact>);
+
+#[Object]
+impl ContactPage {
+    async fn items(&self) -> Vec<ContactObject> {
+        self.0.items().iter().cloned().map(ContactObject).collect()
+    }
+
+    /// Counted from 1.
+    async fn number(&self) -> usize {
+        self.0.number()
+    }
+
+    async fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    /// Number of contacts on all pages.
+    async fn total(&self) -> usize {
+        self.0.total()
+    }
+
+    async fn pages(&self) -> usize {
+        self.0.pages()
+    }
+}
+
+pub struct ContactObject(Contact);
+
+#[Object(name = "Contact")]
+impl ContactObject {
+    async fn id(&self) -> Option<u64> {
+        self.0.id()
+    }
+
+    /// Its uuid or id, as in its URL.
+    async fn key(&self) -> Option<String> {
+        self.0.key().map(|key| key.to_string())
+    }
+
+    /// Its name in the configured order, see `NAME_ORDER`.
+    async fn display_name(&self, ctx: &Context<'_>) -> async_graphql::Result<String> {
+        let state = ctx.data::<AppState>()?;
+        Ok(self.0.display_name(state.name_order))
+    }
+
+    async fn prefix(&self) -> Option<&str> {
+        self.0.prefix()
+    }
+
+    async fn first(&self) -> Option<&str> {
+        self.0.first()
+    }
+
+    async fn middle(&self) -> Option<&str> {
+        self.0.middle()
+    }
+
+    async fn last(&self) -> Option<&str> {
+        self.0.last()
+    }
+
+    async fn suffix(&self) -> Option<&str> {
+        self.0.suffix()
+    }
+
+    async fn nickname(&self) -> Option<&str> {
+        self.0.nickname()
+    }
+
+    /// The primary email.
+    async fn email(&self) -> Option<&str> {
+        self.0.email.as_deref()
+    }
+
+    async fn phones(&self) -> &[PhoneNumber] {
+        self.0.phones()
+    }
+
+    /// Emails besides the primary one.
+    async fn emails(&self) -> &[EmailAddress] {
+        self.0.emails()
+    }
+
+    async fn addresses(&self) -> &[Address] {
+        self.0.addresses()
+    }
+
+    async fn birthday(&self) -> Option<NaiveDate> {
+        self.0.birthday()
+    }
+
+    async fn company(&self) -> Option<&str> {
+        self.0.company()
+    }
+
+    async fn job_title(&self) -> Option<&str> {
+        self.0.job_title()
+    }
+
+    async fn socials(&self) -> &[SocialProfile] {
+        self.0.socials()
+    }
+
+    async fn website(&self) -> Option<&str> {
+        self.0.website()
+    }
+
+    async fn timezone(&self) -> Option<&str> {
+        self.0.timezone()
+    }
+
+    async fn notes(&self) -> Option<&str> {
+        self.0.notes()
+    }
+
+    async fn tags(&self) -> &[String] {
+        self.0.tags()
+    }
+
+    /// The groups the contact is in, in order.
+    async fn groups(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GroupObject>> {
+        let state = ctx.data::<AppState>()?;
+        let mut groups = Vec::new();
+        for id in self.0.groups() {
+            if let Some(group) = state.groups.find(*id).await.map_err(group_error)? {
+                groups.push(GroupObject(group));
+            }
+        }
+        Ok(groups)
+    }
+
+    async fn starred(&self) -> bool {
+        self.0.starred()
+    }
+
+    async fn archived(&self) -> bool {
+        self.0.archived()
+    }
+
+    async fn has_photo(&self) -> bool {
+        self.0.has_photo()
+    }
+
+    /// Bumped each time the contact is saved, to give when updating it.
+    async fn version(&self) -> u64 {
+        self.0.version()
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.0.created_at()
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.0.updated_at()
+    }
+}
+
+pub struct GroupObject(Group);
+
+#[Object(name = "Group")]
+impl GroupObject {
+    async fn id(&self) -> Option<u64> {
+        self.0.id()
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+}
+
+#[Object]
+impl PhoneNumber {
+    async fn label(&self) -> &str {
+        &self.label
+    }
+
+    async fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+#[Object]
+impl EmailAddress {
+    async fn label(&self) -> &str {
+        &self.label
+    }
+
+    async fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+#[Object]
+impl Address {
+    async fn label(&self) -> &str {
+        &self.label
+    }
+
+    async fn street(&self) -> &str {
+        &self.street
+    }
+
+    async fn city(&self) -> &str {
+        &self.city
+    }
+
+    async fn postal_code(&self) -> &str {
+        &self.postal_code
+    }
+
+    async fn country(&self) -> &str {
+        &self.country
+    }
+}
+
+#[Object]
+impl SocialProfile {
+    async fn network(&self) -> &str {
+        &self.network
+    }
+
+    async fn handle(&self) -> &str {
+        &self.handle
+    }
+}
+
+/// Fields of a contact to set; those left out are kept, and an empty text
+/// clears its field.
+#[derive(Debug, InputObject)]
+pub struct ContactInput {
+    prefix: Option<String>,
+    first: Option<String>,
+    middle: Option<String>,
+    last: Option<String>,
+    suffix: Option<String>,
+    nickname: Option<String>,
+    email: Option<String>,
+    phones: Option<Vec<LabeledInput>>,
+    emails: Option<Vec<LabeledInput>>,
+    addresses: Option<Vec<AddressInput>>,
+    /// `null` clears it.
+    birthday: MaybeUndefined<NaiveDate>,
+    company: Option<String>,
+    job_title: Option<String>,
+    socials: Option<Vec<SocialInput>>,
+    website: Option<String>,
+    timezone: Option<String>,
+    notes: Option<String>,
+    tags: Option<Vec<String>>,
+    /// Ids of the groups the contact is in.
+    groups: Option<Vec<u64>>,
+    starred: Option<bool>,
+    archived: Option<bool>,
+}
+
+/// A phone number or an email, with what kind it is.
+#[derive(Debug, InputObject)]
+pub struct LabeledInput {
+    #[graphql(default)]
+    label: String,
+    value: String,
+}
+
+#[derive(Debug, InputObject)]
+pub struct AddressInput {
+    #[graphql(default)]
+    label: String,
+    #[graphql(default)]
+    street: String,
+    #[graphql(default)]
+    city: String,
+    #[graphql(default)]
+    postal_code: String,
+    #[graphql(default)]
+    country: String,
+}
+
+#[derive(Debug, InputObject)]
+pub struct SocialInput {
+    network: String,
+    handle: String,
+}
+
+impl ContactInput {
+    /// Sets the fields given on `contact`, keeping the others.
+    fn apply(self, contact: &mut Contact) {
+        let keep =
+            |given: Option<String>, current: Option<&str>| given.or(current.map(str::to_owned));
+        let first = keep(self.first, contact.first());
+        let last = keep(self.last, contact.last());
+        let email = self.email.or(contact.email.take());
+        let phones = match self.phones {
+            Some(phones) => phones
+                .into_iter()
+                .map(|phone| PhoneNumber::new(phone.label, phone.value))
+                .collect(),
+            None => contact.phones().to_vec(),
+        };
+        contact.update(first, last, phones, email);
+        let prefix = keep(self.prefix, contact.prefix());
+        let middle = keep(self.middle, contact.middle());
+        let suffix = keep(self.suffix, contact.suffix());
+        let nickname = keep(self.nickname, contact.nickname());
+        contact.set_name_parts(prefix, middle, suffix, nickname);
+        if let Some(emails) = self.emails {
+            let emails = emails
+                .into_iter()
+                .map(|email| EmailAddress::new(email.label, email.value));
+            contact.set_emails(emails.collect());
+        }
+        if let Some(addresses) = self.addresses {
+            let addresses = addresses.into_iter().map(|address| Address {
+                label: address.label,
+                street: address.street,
+                city: address.city,
+                postal_code: address.postal_code,
+                country: address.country,
+            });
+            contact.set_addresses(addresses.collect());
+        }
+        let mut birthday = contact.birthday();
+        self.birthday.update_to(&mut birthday);
+        contact.set_birthday(birthday);
+        if let Some(company) = self.company {
+            contact.set_company(Some(company));
+        }
+        if let Some(job_title) = self.job_title {
+            contact.set_job_title(Some(job_title));
+        }
+        if let Some(socials) = self.socials {
+            let socials = socials
+                .into_iter()
+                .map(|social| SocialProfile::new(social.network, social.handle));
+            contact.set_socials(socials.collect());
+        }
+        if let Some(website) = self.website {
+            contact.set_website(Some(website));
+        }
+        if let Some(timezone) = self.timezone {
+            contact.set_timezone(Some(timezone));
+        }
+        if let Some(notes) = self.notes {
+            contact.set_notes(Some(notes));
+        }
+        if let Some(tags) = self.tags {
+            contact.set_tags(tags);
+        }
+        if let Some(groups) = self.groups {
+            contact.set_groups(groups);
+        }
+        if let Some(starred) = self.starred {
+            contact.set_starred(starred);
+        }
+        if let Some(archived) = self.archived {
+            contact.set_archived(archived);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_flash::Flash;
+use axum_htmx::HxTrigger;
+use axum_template::{Key, RenderHtml};
+
+use super::{AppEngine, AppState, PAGE_SIZE};
+use crate::model::{Contact, Group, GroupError, Page, Sort};
+
+/// Routes listing, creating, editing and deleting contact groups.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/groups", get(groups_get))
+        .route("/groups/new", get(group_new_get).post(group_new_post))
+        .route("/groups/:group_id", get(group_view).delete(group_delete))
+        .route(
+            "/groups/:group_id/edit",
+            get(group_edit_get).post(group_edit_post),
+        )
+}
+
+/// The status of a response failing with `err`.
+pub(super) fn group_status(err: &GroupError) -> StatusCode {
+    match err {
+        GroupError::NotFound(_) => StatusCode::NOT_FOUND,
+        GroupError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        GroupError::Conflict(_) => StatusCode::CONFLICT,
+        GroupError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for GroupError {
+    fn into_response(self) -> Response {
+        let status = group_status(&self);
+        if status.is_server_error() {
+            eprintln!("{self}");
+            return status.into_response();
+        }
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// The group with `id`, or [`GroupError::NotFound`].
+pub(super) async fn find_group(state: &AppState, id: u64) -> Result<Group, GroupError> {
+    state.groups.find(id).await?.ok_or(GroupError::NotFound(id))
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GroupsCtx {
+    groups: Vec<Group>,
+    /// Number of contacts in each group, by id, leaving out empty groups.
+    sizes: BTreeMap<u64, usize>,
+}
+
+async fn groups_get(engine: AppEngine, State(state): State<AppState>) -> impl IntoResponse {
+    RenderHtml(
+        Key("groups.html".to_owned()),
+        engine,
+        GroupsCtx {
+            groups: state.groups.all().await,
+            sizes: state.contact_repo.group_sizes().await,
+        },
+    )
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GroupCtx {
+    group: Group,
+}
+
+/// Fields of the new and edit group forms.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct GroupForm {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+impl GroupForm {
+    fn apply(self, group: &mut Group) {
+        group.name = self.name.trim().to_owned();
+        group.description = self.description.trim().to_owned();
+    }
+}
+
+async fn group_new_get(engine: AppEngine) -> impl IntoResponse {
+    RenderHtml(
+        Key("group_new.html".to_owned()),
+        engine,
+        GroupCtx {
+            group: Group::default(),
+        },
+    )
+}
+
+async fn group_new_post(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    flash: Flash,
+    Form(form): Form<GroupForm>,
+) -> Response {
+    let mut group = Group::default();
+    form.apply(&mut group);
+    match state.groups.create(group).await {
+        Ok(id) => (
+            flash.info("Created new group!"),
+            Redirect::to(&format!("/groups/{id}")),
+        )
+            .into_response(),
+        Err(err) => match err.into_group() {
+            Ok(group) => RenderHtml(Key("group_new.html".to_owned()), engine, GroupCtx { group })
+                .into_response(),
+            Err(err) => err.into_response(),
+        },
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct GroupParams {
+    page: Option<usize>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GroupViewCtx {
+    group: Group,
+    members: Page<Contact>,
+}
+
+async fn group_view(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(group_id): Path<u64>,
+    Query(params): Query<GroupParams>,
+) -> Result<impl IntoResponse, GroupError> {
+    let group = find_group(&state, group_id).await?;
+    let number = params.page.unwrap_or(1).max(1);
+    let members = state
+        .contact_repo
+        .members(group_id, Sort::default(), number, PAGE_SIZE)
+        .await;
+    Ok(RenderHtml(
+        Key("group.html".to_owned()),
+        engine,
+        GroupViewCtx { group, members },
+    ))
+}
+
+async fn group_edit_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(group_id): Path<u64>,
+) -> Result<impl IntoResponse, GroupError> {
+    let group = find_group(&state, group_id).await?;
+    Ok(RenderHtml(
+        Key("group_edit.html".to_owned()),
+        engine,
+        GroupCtx { group },
+    ))
+}
+
+async fn group_edit_post(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    flash: Flash,
+    Path(group_id): Path<u64>,
+    Form(form): Form<GroupForm>,
+) -> Response {
+    let mut group = match find_group(&state, group_id).await {
+        Ok(group) => group,
+        Err(err) => return err.into_response(),
+    };
+    form.apply(&mut group);
+    match state.groups.update(group).await {
+        Ok(()) => (
+            flash.info("Updated group!"),
+            Redirect::to(&format!("/groups/{group_id}")),
+        )
+            .into_response(),
+        Err(err) => match err.into_group() {
+            Ok(group) => RenderHtml(
+                Key("group_edit.html".to_owned()),
+                engine,
+                GroupCtx { group },
+            )
+            .into_response(),
+            Err(err) => err.into_response(),
+        },
+    }
+}
+
+/// Deletes the group after taking its members out of it.
+async fn group_delete(
+    State(state): State<AppState>,
+    flash: Flash,
+    Path(group_id): Path<u64>,
+    HxTrigger(trigger): HxTrigger,
+) -> Response {
+    if let Err(err) = find_group(&state, group_id).await {
+        return err.into_response();
+    }
+    let members: Vec<Contact> = state
+        .contact_repo
+        .all(Sort::default())
+        .await
+        .into_iter()
+        .filter(|contact| contact.in_group(group_id))
+        .map(|mut contact| {
+            let groups: Vec<u64> = contact.groups().to_vec();
+            contact.set_groups(groups.into_iter().filter(|&group| group != group_id));
+            contact
+        })
+        .collect();
+    if let Err(err) = state.contact_repo.save_many(members).await {
+        return err.into_response();
+    }
+    if let Err(err) = state.groups.delete_by_id(group_id).await {
+        return err.into_response();
+    }
+    if trigger.as_deref() == Some("delete-btn") {
+        (flash.info("Deleted group!"), Redirect::to("/groups")).into_response()
+    } else {
+        "".into_response()
+    }
+}
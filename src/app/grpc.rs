@@ -0,0 +1,308 @@
+use axum::Router;
+use tonic::{Request, Response, Status};
+
+use super::{
+    api::{self, ListParams},
+    delete_contact, find_contact, AppState, NewContact,
+};
+use crate::model::{
+    Address, Contact, ContactKey, EmailAddress, GroupError, Page, PhoneNumber, RepoError,
+    SocialProfile,
+};
+
+/// The messages and service of `proto/contacts.proto`.
+pub mod proto {
+    tonic::include_proto!("contacts.v1");
+}
+
+use proto::contacts_server::{Contacts, ContactsServer};
+
+/// Routes the calls of the `contacts.v1.Contacts` gRPC service, which
+/// clients make over HTTP/2 on the same port as the pages.
+pub fn routes(state: AppState) -> Router<AppState> {
+    Router::new().route_service(
+        "/contacts.v1.Contacts/*rpc",
+        ContactsServer::new(ContactsService { state }),
+    )
+}
+
+/// Implements the `contacts.v1.Contacts` service on the app's repos.
+pub struct ContactsService {
+    state: AppState,
+}
+
+/// The status of a call failing with `err`.
+fn repo_status(err: RepoError) -> Status {
+    match &err {
+        RepoError::NotFound(_) | RepoError::UuidNotFound(_) => Status::not_found(err.to_string()),
+        RepoError::Validation(_) => Status::invalid_argument(err.to_string()),
+        RepoError::Conflict(_) => Status::aborted(err.to_string()),
+        RepoError::Io(_) => {
+            eprintln!("{err}");
+            Status::internal("storage failed")
+        }
+    }
+}
+
+/// Like [`repo_status`], for the group a listing is filtered by.
+fn group_status(err: GroupError) -> Status {
+    match &err {
+        GroupError::NotFound(_) => Status::not_found(err.to_string()),
+        GroupError::Validation(_) | GroupError::Conflict(_) => {
+            Status::invalid_argument(err.to_string())
+        }
+        GroupError::Io(_) => {
+            eprintln!("{err}");
+            Status::internal("storage failed")
+        }
+    }
+}
+
+impl ContactsService {
+    async fn list(
+        &self,
+        q: Option<String>,
+        filter: proto::ListRequest,
+    ) -> Result<Response<proto::ContactPage>, Status> {
+        let params = ListParams {
+            q,
+            tag: Some(filter.tag),
+            group: filter.group,
+            include_archived: filter.include_archived,
+            page: (filter.page != 0).then_some(filter.page as usize),
+            size: (filter.size != 0).then_some(filter.size as usize),
+            ..Default::default()
+        };
+        let page = api::list_contacts(&self.state, params)
+            .await
+            .map_err(group_status)?;
+        Ok(Response::new(page.into()))
+    }
+
+    /// The stored contact with `key`, as a message.
+    async fn stored(&self, key: ContactKey) -> Result<Response<proto::Contact>, Status> {
+        let contact = find_contact(&self.state.contact_repo, key)
+            .await
+            .map_err(repo_status)?;
+        Ok(Response::new((&contact).into()))
+    }
+}
+
+#[tonic::async_trait]
+impl Contacts for ContactsService {
+    async fn list(
+        &self,
+        request: Request<proto::ListRequest>,
+    ) -> Result<Response<proto::ContactPage>, Status> {
+        ContactsService::list(self, None, request.into_inner()).await
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::ContactPage>, Status> {
+        let request = request.into_inner();
+        ContactsService::list(self, Some(request.q), request.filter.unwrap_or_default()).await
+    }
+
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::Contact>, Status> {
+        let key = request.into_inner().key.parse();
+        self.stored(key.map_err(Status::invalid_argument)?).await
+    }
+
+    async fn create(
+        &self,
+        request: Request<proto::CreateRequest>,
+    ) -> Result<Response<proto::Contact>, Status> {
+        let mut contact = Contact::default();
+        set_fields(
+            request.into_inner().contact.unwrap_or_default(),
+            &mut contact,
+        );
+        self.state.id_strategy.assign(&mut contact);
+        let id = self
+            .state
+            .contact_repo
+            .create(contact)
+            .await
+            .map_err(repo_status)?;
+        self.stored(ContactKey::Id(id)).await
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::UpdateRequest>,
+    ) -> Result<Response<proto::Contact>, Status> {
+        let request = request.into_inner();
+        let key = request.key.parse().map_err(Status::invalid_argument)?;
+        let mut contact = find_contact(&self.state.contact_repo, key)
+            .await
+            .map_err(repo_status)?;
+        let message = request.contact.unwrap_or_default();
+        if message.version != 0 {
+            contact.set_version(message.version);
+        }
+        set_fields(message, &mut contact);
+        self.state
+            .contact_repo
+            .update(contact)
+            .await
+            .map_err(repo_status)?;
+        self.stored(key).await
+    }
+
+    async fn delete(
+        &self,
+        request: Request<proto::DeleteRequest>,
+    ) -> Result<Response<proto::DeleteResponse>, Status> {
+        let key = request.into_inner().key.parse();
+        let key = key.map_err(Status::invalid_argument)?;
+        let contact = find_contact(&self.state.contact_repo, key)
+            .await
+            .map_err(repo_status)?;
+        delete_contact(&self.state, &contact)
+            .await
+            .map_err(repo_status)?;
+        Ok(Response::new(proto::DeleteResponse {}))
+    }
+}
+
+/// Sets the fields a user enters on `contact` to those of `message`, an
+/// empty text clearing its field.
+fn set_fields(message: proto::Contact, contact: &mut Contact) {
+    let text = |text: String| (!text.is_empty()).then_some(text);
+    let phones = message
+        .phones
+        .into_iter()
+        .map(|phone| PhoneNumber::new(phone.label, phone.value));
+    contact.update(
+        text(message.first),
+        text(message.last),
+        phones.collect(),
+        text(message.email),
+    );
+    contact.set_name_parts(
+        text(message.prefix),
+        text(message.middle),
+        text(message.suffix),
+        text(message.nickname),
+    );
+    let emails = message
+        .emails
+        .into_iter()
+        .map(|email| EmailAddress::new(email.label, email.value));
+    contact.set_emails(emails.collect());
+    let addresses = message.addresses.into_iter().map(|address| Address {
+        label: address.label,
+        street: address.street,
+        city: address.city,
+        postal_code: address.postal_code,
+        country: address.country,
+    });
+    contact.set_addresses(addresses.collect());
+    NewContact::set_birthday(Some(message.birthday), contact);
+    contact.set_company(text(message.company));
+    contact.set_job_title(text(message.job_title));
+    let socials = message
+        .socials
+        .into_iter()
+        .map(|social| SocialProfile::new(social.network, social.handle));
+    contact.set_socials(socials.collect());
+    contact.set_website(text(message.website));
+    contact.set_timezone(text(message.timezone));
+    contact.set_notes(text(message.notes));
+    contact.set_tags(message.tags);
+    contact.set_groups(message.groups);
+    contact.set_starred(message.starred);
+    contact.set_archived(message.archived);
+}
+
+impl From<&Contact> for proto::Contact {
+    fn from(contact: &Contact) -> Self {
+        let text = |text: Option<&str>| text.unwrap_or_default().to_owned();
+        Self {
+            id: contact.id().unwrap_or_default(),
+            key: contact.key().map(|key| key.to_string()).unwrap_or_default(),
+            prefix: text(contact.prefix()),
+            first: text(contact.first()),
+            middle: text(contact.middle()),
+            last: text(contact.last()),
+            suffix: text(contact.suffix()),
+            nickname: text(contact.nickname()),
+            email: text(contact.email.as_deref()),
+            phones: contact
+                .phones()
+                .iter()
+                .map(|phone| proto::Labeled {
+                    label: phone.label.clone(),
+                    value: phone.value.clone(),
+                })
+                .collect(),
+            emails: contact
+                .emails()
+                .iter()
+                .map(|email| proto::Labeled {
+                    label: email.label.clone(),
+                    value: email.value.clone(),
+                })
+                .collect(),
+            addresses: contact
+                .addresses()
+                .iter()
+                .map(|address| proto::Address {
+                    label: address.label.clone(),
+                    street: address.street.clone(),
+                    city: address.city.clone(),
+                    postal_code: address.postal_code.clone(),
+                    country: address.country.clone(),
+                })
+                .collect(),
+            birthday: contact
+                .birthday()
+                .map(|birthday| birthday.to_string())
+                .unwrap_or_default(),
+            company: text(contact.company()),
+            job_title: text(contact.job_title()),
+            socials: contact
+                .socials()
+                .iter()
+                .map(|social| proto::SocialProfile {
+                    network: social.network.clone(),
+                    handle: social.handle.clone(),
+                })
+                .collect(),
+            website: text(contact.website()),
+            timezone: text(contact.timezone()),
+            notes: text(contact.notes()),
+            tags: contact.tags().to_vec(),
+            groups: contact.groups().to_vec(),
+            starred: contact.starred(),
+            archived: contact.archived(),
+            has_photo: contact.has_photo(),
+            version: contact.version(),
+            created_at: contact
+                .created_at()
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+            updated_at: contact
+                .updated_at()
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Page<Contact>> for proto::ContactPage {
+    fn from(page: Page<Contact>) -> Self {
+        Self {
+            items: page.items().iter().map(proto::Contact::from).collect(),
+            number: page.number() as u64,
+            size: page.size() as u64,
+            total: page.total() as u64,
+            pages: page.pages() as u64,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Path, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Router,
+};
+use axum_flash::Flash;
+use axum_template::{Key, RenderHtml};
+use serde_json::Value;
+
+use super::{find_contact, AppEngine, AppState};
+use crate::model::{with_author, Contact, ContactKey, RepoError, Revision};
+
+/// Routes showing a contact's history and reverting it to a revision.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/contacts/:contact_id/history", get(history_get))
+        .route(
+            "/contacts/:contact_id/history/:version/revert",
+            post(revert_post),
+        )
+}
+
+/// Records the value of the configured author header, if any, as who saves
+/// the contacts saved while handling the request.
+pub async fn record_author<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let author = state.author_header.as_deref().and_then(|header| {
+        let value = request.headers().get(header)?.to_str().ok()?.trim();
+        (!value.is_empty()).then(|| value.to_owned())
+    });
+    with_author(author, next.run(request)).await
+}
+
+/// A [`Revision`] with its changed values written out for display.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RevisionCtx {
+    version: u64,
+    at: String,
+    author: Option<String>,
+    changes: Vec<ChangeCtx>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChangeCtx {
+    field: String,
+    before: String,
+    after: String,
+}
+
+impl From<Revision> for RevisionCtx {
+    fn from(revision: Revision) -> Self {
+        Self {
+            version: revision.version,
+            at: revision.at.to_rfc3339(),
+            author: revision.author,
+            changes: revision
+                .changes
+                .into_iter()
+                .map(|change| ChangeCtx {
+                    field: change.field,
+                    before: display_value(change.before),
+                    after: display_value(change.after),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Strings as they are, lists of them such as tags separated by commas,
+/// nothing for unset values, and other values as JSON.
+fn display_value(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        Value::Array(values) if values.iter().all(Value::is_string) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(", "),
+        value => value.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HistoryCtx {
+    contact: Contact,
+    revisions: Vec<RevisionCtx>,
+}
+
+async fn history_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(contact_key): Path<ContactKey>,
+) -> Result<impl IntoResponse, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let id = contact.id().expect("a stored contact to have an id");
+    let revisions = state.history.revisions(id).await?;
+    Ok(RenderHtml(
+        Key("history.html".to_owned()),
+        engine,
+        HistoryCtx {
+            contact,
+            revisions: revisions.into_iter().map(RevisionCtx::from).collect(),
+        },
+    ))
+}
+
+/// Saves the contact as it was at `version`, as a new revision.
+async fn revert_post(
+    State(state): State<AppState>,
+    flash: Flash,
+    Path((contact_key, version)): Path<(ContactKey, u64)>,
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let id = contact.id().expect("a stored contact to have an id");
+    let Some(revision) = state.history.revision(id, version).await? else {
+        return Err(RepoError::NotFound(id));
+    };
+    state.contact_repo.restore(revision.contact).await?;
+    Ok((
+        flash.info(format!("Reverted to version {version}!")),
+        Redirect::to(&format!("/contacts/{contact_key}/history")),
+    )
+        .into_response())
+}
@@ -0,0 +1,238 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        request::Parts,
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+
+use super::{groups::group_status, repo_status};
+use crate::model::{Contact, ContactKey, GroupError, Page, RepoError};
+
+/// The media type of JSON:API documents.
+pub const MEDIA_TYPE: &str = "application/vnd.api+json";
+
+/// Fields of a contact given as relationships of its resource object
+/// rather than as attributes, each to resources of the type it is named.
+const RELATIONSHIPS: [&str; 2] = ["groups", "tags"];
+
+/// How the API writes its responses, picked by the `Accept` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiFormat {
+    /// Contacts as stored, for `application/json` or anything else.
+    #[default]
+    Plain,
+    /// [JSON:API](https://jsonapi.org) documents, for [`MEDIA_TYPE`].
+    JsonApi,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiFormat {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let json_api = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|accept| accept.to_str().ok())
+            .flat_map(|accept| accept.split(','))
+            .any(|media_type| media_type.split(';').next().map(str::trim) == Some(MEDIA_TYPE));
+        Ok(if json_api { Self::JsonApi } else { Self::Plain })
+    }
+}
+
+impl ApiFormat {
+    /// `contact` with `status`.
+    pub fn contact(self, status: StatusCode, contact: &Contact) -> Response {
+        match self {
+            Self::Plain => (status, Json(contact)).into_response(),
+            Self::JsonApi => document(status, json!({ "data": resource(contact) })),
+        }
+    }
+
+    /// `contact` as a value for a document of its own, as stored or as its
+    /// resource object, such as an item of a batch's results.
+    pub fn contact_value(self, contact: &Contact) -> Value {
+        match self {
+            Self::Plain => serde_json::to_value(contact).expect("a contact to serialize"),
+            Self::JsonApi => resource(contact),
+        }
+    }
+
+    /// `page` of contacts, with the `links` to it and the pages around it
+    /// by relation, such as `next`.
+    pub fn page(self, page: &Page<Contact>, links: &[(&str, String)]) -> Response {
+        if self == Self::Plain {
+            return Json(page).into_response();
+        }
+        let links: Map<String, Value> = links
+            .iter()
+            .map(|(rel, url)| (rel.to_string(), url.as_str().into()))
+            .collect();
+        document(
+            StatusCode::OK,
+            json!({
+                "data": page.items().iter().map(resource).collect::<Vec<_>>(),
+                "meta": {
+                    "number": page.number(),
+                    "size": page.size(),
+                    "total": page.total(),
+                    "pages": page.pages(),
+                },
+                "links": links,
+            }),
+        )
+    }
+
+    /// The response to a request failing with `err`, with an error object
+    /// per invalid field, pointing to its attribute, if the contact is
+    /// invalid or conflicts with another.
+    pub fn repo_error(self, err: RepoError) -> Response {
+        let status = repo_status(&err);
+        if status.is_server_error() {
+            return err.into_response();
+        }
+        let (RepoError::Validation(contact) | RepoError::Conflict(contact)) = &err else {
+            return self.invalid(status, err.to_string());
+        };
+        if self == Self::Plain {
+            return err.into_response();
+        }
+        let errors: Vec<_> = contact
+            .errors
+            .errors()
+            .iter()
+            .map(|(field, error)| {
+                json!({
+                    "status": status.as_str(),
+                    "code": error.code,
+                    "title": error.message,
+                    "source": { "pointer": pointer(field) },
+                })
+            })
+            .collect();
+        document(status, json!({ "errors": errors }))
+    }
+
+    /// Like [`Self::repo_error`], for the group a listing is filtered by.
+    pub fn group_error(self, err: GroupError) -> Response {
+        let status = group_status(&err);
+        if status.is_server_error() {
+            return err.into_response();
+        }
+        self.invalid(status, err.to_string())
+    }
+
+    /// The response to a request failing with `status`, as `title` says,
+    /// given as the `message` of plain errors.
+    pub fn invalid(self, status: StatusCode, title: String) -> Response {
+        match self {
+            Self::Plain => (status, Json(json!({ "message": title }))).into_response(),
+            Self::JsonApi => document(
+                status,
+                json!({ "errors": [{ "status": status.as_str(), "title": title }] }),
+            ),
+        }
+    }
+}
+
+fn document(status: StatusCode, document: Value) -> Response {
+    (status, [(CONTENT_TYPE, MEDIA_TYPE)], Json(document)).into_response()
+}
+
+/// The JSON pointer to the attribute of the invalid `field`, such as
+/// `phones.0`, or to the whole resource for errors about it as a whole.
+fn pointer(field: &str) -> String {
+    match field {
+        crate::model::ValidationErrors::FORM => "/data".to_owned(),
+        field => format!("/data/attributes/{}", field.replace('.', "/")),
+    }
+}
+
+/// The resource object of the stored `contact`, identified by its key,
+/// with its groups and tags as relationships.
+fn resource(contact: &Contact) -> Value {
+    let key = contact.key().expect("a stored contact to have a key");
+    let Value::Object(mut attributes) =
+        serde_json::to_value(contact).expect("a contact to serialize")
+    else {
+        unreachable!("a contact to serialize as an object");
+    };
+    attributes.remove("id");
+    attributes.remove("uuid");
+    let mut relationships = Map::new();
+    for field in RELATIONSHIPS {
+        let linked = match attributes.remove(field) {
+            Some(Value::Array(linked)) => linked,
+            _ => Vec::new(),
+        };
+        let data: Vec<_> = linked
+            .into_iter()
+            .map(|id| match id {
+                Value::String(id) => json!({ "type": field, "id": id }),
+                id => json!({ "type": field, "id": id.to_string() }),
+            })
+            .collect();
+        relationships.insert(field.to_owned(), json!({ "data": data }));
+    }
+    json!({
+        "type": "contacts",
+        "id": key.to_string(),
+        "attributes": attributes,
+        "relationships": relationships,
+        "links": { "self": format!("/api/v1/contacts/{key}") },
+    })
+}
+
+/// The fields of the contact sent as `body`: its attributes and
+/// relationships, and its `id` or `uuid` if the resource has one, if it is
+/// a JSON:API document, and otherwise `body` itself.
+pub fn fields(body: Value) -> Value {
+    let Value::Object(mut body) = body else {
+        return body;
+    };
+    let Some(Value::Object(mut data)) = body.remove("data") else {
+        return Value::Object(body);
+    };
+    let mut fields = match data.remove("attributes") {
+        Some(Value::Object(attributes)) => attributes,
+        _ => Map::new(),
+    };
+    let key = data.remove("id").and_then(|key| key.as_str()?.parse().ok());
+    match key {
+        Some(ContactKey::Id(id)) => fields.insert("id".to_owned(), id.into()),
+        Some(ContactKey::Uuid(uuid)) => fields.insert("uuid".to_owned(), uuid.to_string().into()),
+        None => None,
+    };
+    if let Some(Value::Object(relationships)) = data.remove("relationships") {
+        for field in RELATIONSHIPS {
+            let Some(Value::Array(linked)) = relationships
+                .get(field)
+                .and_then(|relationship| relationship.get("data"))
+            else {
+                continue;
+            };
+            let ids = linked
+                .iter()
+                .filter_map(|linked| linked.get("id")?.as_str());
+            // Groups are linked by their numeric ids, tags by name.
+            let ids: Vec<Value> = match field {
+                "groups" => ids
+                    .filter_map(|id| id.parse::<u64>().ok())
+                    .map(Value::from)
+                    .collect(),
+                _ => ids.map(Value::from).collect(),
+            };
+            fields.insert(field.to_owned(), ids.into());
+        }
+    }
+    Value::Object(fields)
+}
@@ -0,0 +1,222 @@
+/// Renders the subset of Markdown used in contact notes as HTML: paragraphs,
+/// `#` headings, `-`, `*` and `1.` lists, fenced code blocks, `**strong**`,
+/// `*emphasis*`, `` `code` `` and `[links](https://example.com)`.
+///
+/// All text is escaped, raw HTML included, so the output is safe to embed
+/// in a page. Links only keep `http`, `https` and `mailto` URLs; others are
+/// rendered as their text.
+pub fn to_html(text: &str) -> String {
+    let mut html = String::new();
+    let mut open = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if open == Some(Block::Code) {
+            if trimmed.starts_with("```") {
+                close(&mut html, &mut open);
+            } else {
+                escape(line, &mut html);
+                html.push('\n');
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            close(&mut html, &mut open);
+            html.push_str("<pre><code>");
+            open = Some(Block::Code);
+        } else if trimmed.is_empty() {
+            close(&mut html, &mut open);
+        } else if let Some((level, heading)) = heading(trimmed) {
+            close(&mut html, &mut open);
+            html.push_str(&format!("<h{level}>"));
+            inline(heading, &mut html);
+            html.push_str(&format!("</h{level}>"));
+        } else if let Some((list, item)) = list_item(trimmed) {
+            if open != Some(list) {
+                close(&mut html, &mut open);
+                html.push_str(list.start());
+                open = Some(list);
+            }
+            html.push_str("<li>");
+            inline(item, &mut html);
+            html.push_str("</li>");
+        } else {
+            if open == Some(Block::Paragraph) {
+                html.push('\n');
+            } else {
+                close(&mut html, &mut open);
+                html.push_str(Block::Paragraph.start());
+                open = Some(Block::Paragraph);
+            }
+            inline(trimmed, &mut html);
+        }
+    }
+    close(&mut html, &mut open);
+    html
+}
+
+/// A block of lines that is still open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Block {
+    Paragraph,
+    List,
+    OrderedList,
+    Code,
+}
+
+impl Block {
+    fn start(self) -> &'static str {
+        match self {
+            Self::Paragraph => "<p>",
+            Self::List => "<ul>",
+            Self::OrderedList => "<ol>",
+            Self::Code => "<pre><code>",
+        }
+    }
+
+    fn end(self) -> &'static str {
+        match self {
+            Self::Paragraph => "</p>",
+            Self::List => "</ul>",
+            Self::OrderedList => "</ol>",
+            Self::Code => "</code></pre>",
+        }
+    }
+}
+
+fn close(html: &mut String, open: &mut Option<Block>) {
+    if let Some(block) = open.take() {
+        html.push_str(block.end());
+    }
+}
+
+/// The level and text of a heading, which starts below `<h3>` so notes
+/// don't outrank the headings of the page they are shown on.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    let text = line[hashes..].strip_prefix(' ')?;
+    (1..=4).contains(&hashes).then(|| (hashes + 2, text.trim()))
+}
+
+fn list_item(line: &str) -> Option<(Block, &str)> {
+    if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+        return Some((Block::List, item));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let item = line[digits..].strip_prefix(". ")?;
+    (digits > 0).then_some((Block::OrderedList, item))
+}
+
+/// Renders the spans of a line, escaping everything else.
+fn inline(mut text: &str, html: &mut String) {
+    while let Some(c) = text.chars().next() {
+        if let Some((tag, inner, rest)) = span(text) {
+            html.push_str(&format!("<{tag}>"));
+            if tag == "code" {
+                escape(inner, html);
+            } else {
+                inline(inner, html);
+            }
+            html.push_str(&format!("</{tag}>"));
+            text = rest;
+        } else if let Some((label, url, rest)) = link(text) {
+            if is_safe_url(url) {
+                html.push_str("<a href=\"");
+                escape(url, html);
+                html.push_str("\" rel=\"nofollow noopener\">");
+                inline(label, html);
+                html.push_str("</a>");
+            } else {
+                inline(label, html);
+            }
+            text = rest;
+        } else {
+            escape(&text[..c.len_utf8()], html);
+            text = &text[c.len_utf8()..];
+        }
+    }
+}
+
+/// The tag, the text within and the text after a span that `text` starts
+/// with.
+fn span(text: &str) -> Option<(&'static str, &str, &str)> {
+    let (tag, delimiter) = if text.starts_with("**") {
+        ("strong", "**")
+    } else if text.starts_with('*') {
+        ("em", "*")
+    } else if text.starts_with('`') {
+        ("code", "`")
+    } else {
+        return None;
+    };
+    let text = &text[delimiter.len()..];
+    let end = text.find(delimiter).filter(|&end| end > 0)?;
+    Some((tag, &text[..end], &text[end + delimiter.len()..]))
+}
+
+/// The label, the URL and the text after a link that `text` starts with.
+fn link(text: &str) -> Option<(&str, &str, &str)> {
+    let text = text.strip_prefix('[')?;
+    let (label, text) = text.split_once("](")?;
+    let (url, rest) = text.split_once(')')?;
+    Some((label, url.trim(), rest))
+}
+
+fn is_safe_url(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+fn escape(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#x27;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_http_and_mailto_links() {
+        assert_eq!(
+            to_html("[site](https://example.com)"),
+            "<p><a href=\"https://example.com\" rel=\"nofollow noopener\">site</a></p>"
+        );
+        assert!(to_html("[mail](mailto:ann@example.com)").contains("href=\"mailto:"));
+    }
+
+    #[test]
+    fn strips_javascript_links() {
+        let html = to_html("[click](javascript:alert(1))");
+        assert!(!html.contains("href"), "{html}");
+        assert!(html.contains("click"));
+        let html = to_html("[click](JavaScript:alert(1))");
+        assert!(!html.contains("href"), "{html}");
+    }
+
+    #[test]
+    fn strips_data_links() {
+        let html = to_html("[x](data:text/html;base64,PHNjcmlwdD4=)");
+        assert!(!html.contains("href"), "{html}");
+        assert!(!html.contains("data:"), "{html}");
+    }
+
+    #[test]
+    fn escapes_raw_html() {
+        assert_eq!(
+            to_html("<script>alert('x')</script>"),
+            "<p>&lt;script&gt;alert(&#x27;x&#x27;)&lt;/script&gt;</p>"
+        );
+        let html = to_html("[a](https://example.com/\"onmouseover=\"x)");
+        assert!(!html.contains("\"onmouseover"), "{html}");
+    }
+}
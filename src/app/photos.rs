@@ -0,0 +1,220 @@
+use std::fmt::Write;
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequest, Multipart, Path, State},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Form, Router,
+};
+use minijinja::value::Value;
+use sha2::{Digest, Sha256};
+
+use super::{find_contact, AppState};
+use crate::model::{Contact, ContactKey, Photo, RepoError, SharedPhotoRepo, MAX_PHOTO_SIZE};
+
+/// Routes serving contacts' photos and generated avatars.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/contacts/:contact_id/photo", get(photo_get))
+        .route("/contacts/:contact_id/avatar", get(avatar_get))
+}
+
+/// The fields of the new and edit contact forms, posted urlencoded or, to
+/// upload a photo, as `multipart/form-data`.
+#[derive(Debug, Clone, Default)]
+pub struct ContactForm {
+    /// All fields but the photo ones, in the order posted.
+    pub fields: Vec<(String, String)>,
+    /// The file of the `photo` field, if one was chosen.
+    pub photo: Option<Vec<u8>>,
+    /// Whether the `remove_photo` box was ticked.
+    pub remove_photo: bool,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S, Body> for ContactForm {
+    type Rejection = Response;
+
+    async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_multipart = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data"));
+        let mut form = Self::default();
+        if !is_multipart {
+            let Form(fields) = Form::<Vec<(String, String)>>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            for (name, value) in fields {
+                form.push(name, value);
+            }
+            return Ok(form);
+        }
+        let mut multipart = Multipart::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let bad_request = |err: axum::extract::multipart::MultipartError| {
+            (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        };
+        while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+            let name = field.name().unwrap_or_default().to_owned();
+            if name == "photo" {
+                let data = field.bytes().await.map_err(bad_request)?;
+                form.photo = (!data.is_empty()).then(|| data.to_vec());
+            } else {
+                let value = field.text().await.map_err(bad_request)?;
+                form.push(name, value);
+            }
+        }
+        Ok(form)
+    }
+}
+
+impl ContactForm {
+    fn push(&mut self, name: String, value: String) {
+        if name == "remove_photo" {
+            self.remove_photo = true;
+        } else {
+            self.fields.push((name, value));
+        }
+    }
+}
+
+/// What saving a contact does to its photo.
+#[derive(Debug, Clone)]
+pub enum PhotoChange {
+    Keep,
+    Replace(Photo),
+    Remove,
+}
+
+impl PhotoChange {
+    /// The change `photo` and `remove` ask for, recording on `contact`
+    /// whether it has a photo afterwards, or why the upload isn't one so
+    /// saving it fails validation.
+    ///
+    /// Photos are stored as uploaded, up to [`MAX_PHOTO_SIZE`], and cropped
+    /// where shown.
+    pub fn of(photo: Option<Vec<u8>>, remove: bool, contact: &mut Contact) -> Self {
+        let (reason, error) = match photo {
+            None if remove => {
+                contact.set_has_photo(false);
+                return Self::Remove;
+            }
+            None => return Self::Keep,
+            Some(data) if data.len() > MAX_PHOTO_SIZE => ("too_large", "Photo Too Large"),
+            Some(data) => match Photo::new(data) {
+                Some(photo) => {
+                    contact.set_has_photo(true);
+                    return Self::Replace(photo);
+                }
+                None => ("unsupported", "Unsupported Image"),
+            },
+        };
+        contact.errors.insert("photo", reason, error);
+        Self::Keep
+    }
+
+    /// Applies the change to the photo of the saved contact with `id`.
+    pub async fn apply(self, photos: &SharedPhotoRepo, id: u64) -> Result<(), RepoError> {
+        match self {
+            Self::Keep => Ok(()),
+            Self::Replace(photo) => photos.save(id, photo).await.map_err(RepoError::Io),
+            Self::Remove => photos.delete(id).await.map_err(RepoError::Io),
+        }
+    }
+}
+
+async fn photo_get(
+    State(state): State<AppState>,
+    Path(contact_key): Path<ContactKey>,
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let id = contact.id().expect("a stored contact to have an id");
+    match state.photos.find(id).await.map_err(RepoError::Io)? {
+        Some(photo) => Ok(([(CONTENT_TYPE, photo.content_type)], photo.data).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// The URL of the image to show for a serialized contact, `size` pixels
+/// wide: its photo if it has one, else the Gravatar of its email, else an
+/// SVG of its initials.
+pub fn avatar(contact: Value, size: Option<u32>) -> Result<String, minijinja::Error> {
+    let uuid = contact.get_attr("uuid")?;
+    let key = if uuid.is_none() {
+        contact.get_attr("id")?
+    } else {
+        uuid
+    };
+    if contact.get_attr("has_photo")?.is_true() {
+        let version = contact.get_attr("version")?;
+        return Ok(format!("/contacts/{key}/photo?v={version}"));
+    }
+    let email = contact.get_attr("email")?;
+    match email
+        .as_str()
+        .map(str::trim)
+        .filter(|email| !email.is_empty())
+    {
+        Some(email) => Ok(gravatar_url(email, size.unwrap_or(80))),
+        None => Ok(format!("/contacts/{key}/avatar")),
+    }
+}
+
+/// The Gravatar of `email`, falling back to an identicon for emails
+/// without one.
+fn gravatar_url(email: &str, size: u32) -> String {
+    let hash = Sha256::digest(email.to_lowercase().as_bytes());
+    let mut url = "https://www.gravatar.com/avatar/".to_owned();
+    for byte in hash.iter() {
+        write!(url, "{byte:02x}").unwrap();
+    }
+    write!(url, "?s={size}&d=identicon").unwrap();
+    url
+}
+
+/// An SVG of the contact's initials, on a color picked by its id.
+async fn avatar_get(
+    State(state): State<AppState>,
+    Path(contact_key): Path<ContactKey>,
+) -> Result<impl IntoResponse, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    let initial = |name: Option<&str>| {
+        let initial = name.and_then(|name| name.trim().chars().next());
+        initial.into_iter().flat_map(char::to_uppercase)
+    };
+    let mut initials: String = initial(contact.first())
+        .chain(initial(contact.last()))
+        .collect();
+    if initials.is_empty() {
+        initials.push('?');
+    }
+    let hue = contact.id().unwrap_or_default() * 47 % 360;
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"64\" viewBox=\"0 0 64 64\">\
+         <rect width=\"64\" height=\"64\" fill=\"hsl({hue}, 45%, 55%)\"/>\
+         <text x=\"32\" y=\"32\" dy=\".35em\" text-anchor=\"middle\" \
+         font-family=\"sans-serif\" font-size=\"26\" fill=\"#fff\">{}</text></svg>",
+        escape_xml(&initials)
+    );
+    Ok(([(CONTENT_TYPE, "image/svg+xml")], svg))
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
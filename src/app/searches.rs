@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_flash::Flash;
+use axum_template::{Key, RenderHtml};
+
+use super::{AppEngine, AppState};
+use crate::model::{SavedSearch, SavedSearchError};
+
+/// The parameters of the contact list a saved search keeps, leaving out the
+/// page and cursor.
+const SAVED_PARAMS: &[&str] = &[
+    "q",
+    "fuzzy",
+    "include_archived",
+    "mode",
+    "tag",
+    "group",
+    "letter",
+    "starred",
+    "suspect",
+    "sort",
+    "direction",
+];
+
+/// Routes saving, listing, running and deleting searches of the contact
+/// list.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/searches", get(searches_get).post(searches_post))
+        .route(
+            "/searches/:search_id",
+            get(search_run).delete(search_delete),
+        )
+}
+
+impl IntoResponse for SavedSearchError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            SavedSearchError::NotFound(_) => StatusCode::NOT_FOUND,
+            SavedSearchError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            SavedSearchError::Conflict(_) => StatusCode::CONFLICT,
+            SavedSearchError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            eprintln!("{self}");
+            return status.into_response();
+        }
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SearchesCtx {
+    searches: Vec<SavedSearch>,
+}
+
+/// The saved searches sidebar of the index.
+async fn searches_get(engine: AppEngine, State(state): State<AppState>) -> impl IntoResponse {
+    RenderHtml(
+        Key("searches.html".to_owned()),
+        engine,
+        SearchesCtx {
+            searches: state.searches.all().await,
+        },
+    )
+}
+
+/// Fields of the form saving the search the index shows.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SearchForm {
+    name: String,
+    /// The query string of the listing, see [`SavedSearch::query`].
+    #[serde(default)]
+    query: String,
+}
+
+/// `query` with only the [`SAVED_PARAMS`], and those not left blank.
+fn saved_query(query: &str) -> String {
+    let mut saved = url::form_urlencoded::Serializer::new(String::new());
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        if SAVED_PARAMS.contains(&key.as_ref()) && !value.trim().is_empty() {
+            saved.append_pair(&key, &value);
+        }
+    }
+    saved.finish()
+}
+
+/// Saves the search and lists its contacts, or says why it couldn't.
+async fn searches_post(
+    State(state): State<AppState>,
+    flash: Flash,
+    Form(form): Form<SearchForm>,
+) -> Response {
+    let search = SavedSearch::new(form.name.trim(), saved_query(&form.query));
+    let listing = format!("/contacts?{}", search.query);
+    match state.searches.create(search).await {
+        Ok(_) => (flash.info("Saved search!"), Redirect::to(&listing)).into_response(),
+        Err(SavedSearchError::Validation(search) | SavedSearchError::Conflict(search)) => {
+            let errors: Vec<_> = search.errors.into_values().collect();
+            let message = format!("Couldn't save the search: {}", errors.join(", "));
+            (flash.error(message), Redirect::to(&listing)).into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Lists the contacts the saved search finds now.
+async fn search_run(
+    State(state): State<AppState>,
+    Path(search_id): Path<u64>,
+) -> Result<Redirect, SavedSearchError> {
+    let search = state
+        .searches
+        .find(search_id)
+        .await?
+        .ok_or(SavedSearchError::NotFound(search_id))?;
+    Ok(Redirect::to(&format!("/contacts?{}", search.query)))
+}
+
+async fn search_delete(
+    State(state): State<AppState>,
+    Path(search_id): Path<u64>,
+) -> Result<&'static str, SavedSearchError> {
+    state.searches.delete_by_id(search_id).await?;
+    Ok("")
+}
@@ -0,0 +1,80 @@
+use axum::response::IntoResponse;
+use axum_template::{Key, RenderHtml};
+use minijinja::value::Value;
+
+use super::AppEngine;
+use crate::model::SocialProfile;
+
+/// The networks offered in the form, as stored in
+/// [`SocialProfile::network`].
+pub const NETWORKS: [&str; 8] = [
+    "LinkedIn",
+    "GitHub",
+    "Mastodon",
+    "Bluesky",
+    "X",
+    "Instagram",
+    "Facebook",
+    "Other",
+];
+
+/// The profile page of a serialized [`SocialProfile`], or none if its
+/// handle can't be made into one. Handles that are `http` or `https` URLs
+/// are linked as they are, whatever the network.
+pub fn social_url(profile: Value) -> Result<Value, minijinja::Error> {
+    let network = profile.get_attr("network")?;
+    let handle = profile.get_attr("handle")?;
+    let url = profile_url(
+        network.as_str().unwrap_or_default(),
+        handle.as_str().unwrap_or_default().trim(),
+    );
+    Ok(url.map_or(Value::from(()), Value::from))
+}
+
+fn profile_url(network: &str, handle: &str) -> Option<String> {
+    if let Ok(url) = url::Url::parse(handle) {
+        return matches!(url.scheme(), "http" | "https").then(|| url.into());
+    }
+    let handle = handle.strip_prefix('@').unwrap_or(handle);
+    if network.eq_ignore_ascii_case("mastodon") {
+        // `user@instance`, whose profile is on the instance.
+        let (user, instance) = handle.split_once('@')?;
+        let valid = is_name(user) && instance.contains('.') && is_name(instance);
+        return valid.then(|| format!("https://{instance}/@{user}"));
+    }
+    if !is_name(handle) {
+        return None;
+    }
+    let base = match network.to_lowercase().as_str() {
+        "linkedin" => "https://www.linkedin.com/in/",
+        "github" => "https://github.com/",
+        "bluesky" => "https://bsky.app/profile/",
+        "x" | "twitter" => "https://x.com/",
+        "instagram" => "https://www.instagram.com/",
+        "facebook" => "https://www.facebook.com/",
+        _ => return None,
+    };
+    Some(format!("{base}{handle}"))
+}
+
+/// Whether `name` is a non-empty handle or host name that needs no escaping
+/// in a URL.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SocialRowCtx {
+    social: SocialProfile,
+}
+
+/// An empty row for a social profile in the new and edit forms.
+pub async fn social_row_get(engine: AppEngine) -> impl IntoResponse {
+    let ctx = SocialRowCtx {
+        social: SocialProfile::default(),
+    };
+    RenderHtml(Key("social_row.html".to_owned()), engine, ctx)
+}
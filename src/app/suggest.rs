@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Query, State},
+    http::{
+        header::{ACCEPT, VARY},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use axum_template::{Key, RenderHtml};
+
+use super::{AppEngine, AppState};
+use crate::model::{Contact, Direction, NameOrder, Sort, SortBy};
+
+/// Most suggestions given for a query.
+const MAX_SUGGESTIONS: usize = 8;
+
+/// Routes suggesting contacts as a search is typed.
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/contacts/suggest", get(suggest_get))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SuggestParams {
+    q: Option<String>,
+}
+
+/// A contact searching may complete to.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Suggestion {
+    id: u64,
+    /// Its uuid or id, as in its URL.
+    key: String,
+    name: String,
+    email: Option<String>,
+}
+
+impl Suggestion {
+    fn new(contact: &Contact, names: NameOrder) -> Self {
+        let id = contact.id().expect("a stored contact to have an id");
+        Self {
+            id,
+            key: contact
+                .uuid()
+                .map_or(id.to_string(), |uuid| uuid.to_string()),
+            name: contact.display_name(names),
+            email: contact.email.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SuggestionsCtx {
+    q: Option<String>,
+    suggestions: Vec<Suggestion>,
+}
+
+/// The names and emails of the first contacts, by name, searching for `q`
+/// finds: as a list of links for the typeahead under the search box, or as
+/// JSON if asked for with `Accept: application/json`, so caches tell the two
+/// apart by it. None for a blank query.
+async fn suggest_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Query(params): Query<SuggestParams>,
+    headers: HeaderMap,
+) -> Response {
+    let q = params.q.filter(|q| !q.trim().is_empty());
+    let suggestions = match &q {
+        Some(q) => {
+            let sort = Sort::new(SortBy::Name, Direction::Asc).with_names(state.name_order);
+            let found = state.contact_repo.search(q, sort, 1, MAX_SUGGESTIONS).await;
+            found
+                .items()
+                .iter()
+                .map(|contact| Suggestion::new(contact, state.name_order))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    let json = headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+    if json {
+        return ([(VARY, "accept")], Json(suggestions)).into_response();
+    }
+    (
+        [(VARY, "accept")],
+        RenderHtml(
+            Key("suggestions.html".to_owned()),
+            engine,
+            SuggestionsCtx { q, suggestions },
+        ),
+    )
+        .into_response()
+}
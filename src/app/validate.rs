@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use axum_template::{Key, RenderHtml};
+
+use super::{email_warning, find_contact, AppEngine, AppState, NewContact};
+use crate::model::{Contact, ContactKey, RepoError};
+
+/// Routes validating a field of the new and edit forms as it is entered.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/contacts/validate/:field", get(validate_new_get))
+        .route("/contacts/:contact_id/validate/:field", get(validate_get))
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldErrorCtx {
+    error: Option<String>,
+    /// The [code](crate::model::ValidationError::code) of the error, for
+    /// scripts and tests to check.
+    code: Option<String>,
+    /// What may be mistaken about a valid field, such as an email that may
+    /// not be delivered.
+    warning: Option<String>,
+}
+
+/// Validates `field` of the new contact form, as if it were the only field
+/// entered.
+async fn validate_new_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path(field): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    validate_field(engine, &state, Contact::default(), &field, params).await
+}
+
+/// Validates `field` of the contact's edit form, as if it were the only
+/// field changed.
+async fn validate_get(
+    engine: AppEngine,
+    State(state): State<AppState>,
+    Path((contact_key, field)): Path<(ContactKey, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, RepoError> {
+    let contact = find_contact(&state.contact_repo, contact_key).await?;
+    Ok(validate_field(engine, &state, contact, &field, params).await)
+}
+
+/// Sets the form field `field` of `contact` to the value sent under its name,
+/// or as `value`, and renders why it isn't valid, if it isn't, as the
+/// field's error element, or else the warning about it, such as the
+/// [one](email_warning) about an email. Further emails and phone numbers
+/// are the row at `index`, or a new row without one.
+async fn validate_field(
+    engine: AppEngine,
+    state: &AppState,
+    mut contact: Contact,
+    field: &str,
+    mut params: HashMap<String, String>,
+) -> Response {
+    let value = params
+        .remove(field)
+        .or_else(|| params.remove("value"))
+        .unwrap_or_default();
+    let index = params.get("index").and_then(|index| index.parse().ok());
+    let some = |value: String| (!value.trim().is_empty()).then_some(value);
+    let (first, last) = (
+        contact.first().map(str::to_owned),
+        contact.last().map(str::to_owned),
+    );
+    let phones = contact.phones().to_vec();
+    let email = contact.email.clone();
+    let checked_email = matches!(field, "email" | "other_email").then(|| value.clone());
+    let key = match field {
+        "first_name" => {
+            contact.update(some(value), last, phones, email);
+            "first".to_owned()
+        }
+        "last_name" => {
+            contact.update(first, some(value), phones, email);
+            "last".to_owned()
+        }
+        "email" => {
+            contact.email = some(value);
+            "email".to_owned()
+        }
+        "other_email" => {
+            let index = contact.set_other_email(index.unwrap_or(usize::MAX), value);
+            format!("emails.{index}")
+        }
+        "phone" => {
+            let index = contact.set_phone(index.unwrap_or(usize::MAX), value);
+            format!("phones.{index}")
+        }
+        "website" => {
+            contact.set_website(some(value));
+            "website".to_owned()
+        }
+        "timezone" => {
+            contact.set_timezone(some(value));
+            "timezone".to_owned()
+        }
+        "birthday" => {
+            NewContact::set_birthday(Some(value), &mut contact);
+            "birthday".to_owned()
+        }
+        "preferred" => {
+            NewContact::set_preferred(Some(value), &mut contact);
+            "preferred".to_owned()
+        }
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    contact.validate();
+    let error = contact.errors.get(&key).map(str::to_owned);
+    let code = contact.errors.code(&key).map(str::to_owned);
+    let warning = match (&error, contact.errors.warning(&key), checked_email) {
+        (Some(_), _, _) => None,
+        (None, Some(warning), _) => Some(warning.to_owned()),
+        (None, None, Some(email)) => email_warning(state, Some(&email)).await,
+        (None, None, None) => None,
+    };
+    RenderHtml(
+        Key("field_error.html".to_owned()),
+        engine,
+        FieldErrorCtx {
+            error,
+            code,
+            warning,
+        },
+    )
+    .into_response()
+}
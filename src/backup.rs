@@ -0,0 +1,185 @@
+//! Periodic copies of the contacts, so that a bad edit can be undone.
+
+use std::{fmt, fs, io, path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::Utc;
+
+use crate::model::{
+    write_atomic, Contact, SharedContactRepo, Snapshot, Sort, StoreKey, SCHEMA_VERSION,
+};
+
+#[cfg(feature = "s3")]
+mod s3;
+
+#[cfg(feature = "s3")]
+pub use self::s3::S3Target;
+
+/// Where backups are kept, by name.
+#[async_trait::async_trait]
+pub trait BackupTarget: fmt::Debug + Send + Sync {
+    /// Names of all backups, in any order.
+    async fn list(&self) -> io::Result<Vec<String>>;
+    async fn read(&self, name: &str) -> io::Result<Vec<u8>>;
+    async fn write(&self, name: &str, data: &[u8]) -> io::Result<()>;
+    async fn delete(&self, name: &str) -> io::Result<()>;
+
+    /// Where the backup called `name` is, for logging.
+    fn location(&self, name: &str) -> String;
+}
+
+/// Keeps backups as files in a local directory, creating it if needed.
+#[derive(Debug, Clone)]
+pub struct DirTarget {
+    dir: PathBuf,
+}
+
+impl DirTarget {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl BackupTarget for DirTarget {
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            if let Ok(name) = entry?.file_name().into_string() {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    async fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.dir.join(name))
+    }
+
+    async fn write(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.dir.join(name), data)
+    }
+
+    async fn delete(&self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.dir.join(name))
+    }
+
+    fn location(&self, name: &str) -> String {
+        self.dir.join(name).display().to_string()
+    }
+}
+
+/// Where and how often [`spawn`] backs up the contacts.
+#[derive(Debug, Clone)]
+pub struct BackupPolicy {
+    pub target: Arc<dyn BackupTarget>,
+    pub interval: Duration,
+    /// Number of backups to keep, older ones are deleted.
+    pub keep: usize,
+    /// Encrypts backups when set, like the store itself.
+    pub key: Option<StoreKey>,
+}
+
+impl BackupPolicy {
+    pub fn new(target: Arc<dyn BackupTarget>) -> Self {
+        Self {
+            target,
+            interval: Duration::from_secs(60 * 60),
+            keep: 24,
+            key: None,
+        }
+    }
+
+    /// Backs up to files in `dir`.
+    pub fn to_dir(dir: impl Into<PathBuf>) -> Self {
+        Self::new(Arc::new(DirTarget::new(dir)))
+    }
+}
+
+const PREFIX: &str = "contacts-";
+const SUFFIX: &str = ".json";
+
+/// Name for a backup made now.
+pub fn new_name() -> String {
+    format!("{PREFIX}{}{SUFFIX}", Utc::now().format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Encodes `contacts` like the snapshot of a `json://` store, so a backup
+/// can be put back by copying it over the store's file.
+pub fn encode(contacts: &[Contact]) -> Vec<u8> {
+    let mut contacts: Vec<&Contact> = contacts.iter().collect();
+    contacts.sort_by_key(|contact| contact.id());
+    let snapshot = Snapshot {
+        schema_version: SCHEMA_VERSION,
+        contacts,
+        tombstones: Vec::new(),
+    };
+    serde_json::to_vec_pretty(&snapshot).expect("serializing succeed")
+}
+
+/// Backs up `repo` under a timestamped name to `policy.target` and deletes
+/// the backups beyond `policy.keep`. Nothing is written if the newest backup
+/// holds the same contacts; otherwise the new backup's location is returned.
+pub async fn back_up(
+    repo: &SharedContactRepo,
+    policy: &BackupPolicy,
+) -> io::Result<Option<String>> {
+    let target = &policy.target;
+    let data = encode(&repo.all(Sort::default()).await);
+    if let Some(newest) = list(target.as_ref()).await?.last() {
+        let mut previous = target.read(newest).await?;
+        if let Some(key) = &policy.key {
+            previous = key.open(&previous)?;
+        }
+        if previous == data {
+            return Ok(None);
+        }
+    }
+
+    let name = new_name();
+    match &policy.key {
+        None => target.write(&name, &data).await?,
+        Some(key) => target.write(&name, &key.seal(&data)).await?,
+    }
+
+    let backups = list(target.as_ref()).await?;
+    let expired = backups.len().saturating_sub(policy.keep);
+    for old in &backups[..expired] {
+        target.delete(old).await?;
+    }
+    Ok(Some(target.location(&name)))
+}
+
+/// Names of the backups in `target`, oldest first.
+async fn list(target: &dyn BackupTarget) -> io::Result<Vec<String>> {
+    let mut backups: Vec<String> = target
+        .list()
+        .await?
+        .into_iter()
+        .filter(|name| name.starts_with(PREFIX) && name.ends_with(SUFFIX))
+        .collect();
+    // The timestamps sort chronologically.
+    backups.sort();
+    Ok(backups)
+}
+
+/// Backs up `repo` right away and then every `policy.interval`, in a
+/// background task. Must be called from within a tokio runtime.
+pub fn spawn(repo: SharedContactRepo, policy: BackupPolicy) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.interval);
+        loop {
+            interval.tick().await;
+            match back_up(&repo, &policy).await {
+                Ok(Some(location)) => println!("backed up contacts to {location}"),
+                Ok(None) => {}
+                Err(err) => eprintln!("backing up contacts failed: {err}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,97 @@
+use std::{env, io};
+
+use s3::{creds::Credentials, Bucket, Region};
+
+use super::BackupTarget;
+
+/// Keeps backups in an S3 bucket, or a bucket of any S3-compatible object
+/// storage, under a key prefix.
+#[derive(Debug, Clone)]
+pub struct S3Target {
+    bucket: Box<Bucket>,
+    prefix: String,
+}
+
+impl S3Target {
+    /// Targets `bucket`, configured by the usual AWS environment variables:
+    /// `AWS_REGION`, `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` (or a
+    /// profile) and, for other object storage than AWS, `AWS_ENDPOINT_URL`.
+    pub fn from_env(bucket: &str, prefix: &str) -> Self {
+        let region_name = env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".into());
+        let credentials = Credentials::default().expect("AWS credentials");
+        let bucket = match env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => {
+                let region = Region::Custom {
+                    region: region_name,
+                    endpoint,
+                };
+                Bucket::new(bucket, region, credentials)
+                    .expect("a valid bucket")
+                    .with_path_style()
+            }
+            Err(_) => {
+                let region = region_name.parse().expect("a valid AWS_REGION");
+                Bucket::new(bucket, region, credentials).expect("a valid bucket")
+            }
+        };
+        let mut prefix = prefix.trim_matches('/').to_owned();
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+        Self { bucket, prefix }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}{name}", self.prefix)
+    }
+}
+
+fn to_io(err: s3::error::S3Error) -> io::Error {
+    io::Error::other(err)
+}
+
+#[async_trait::async_trait]
+impl BackupTarget for S3Target {
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let pages = self
+            .bucket
+            .list(self.prefix.clone(), Some("/".into()))
+            .await
+            .map_err(to_io)?;
+        let names = pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .filter_map(|object| Some(object.key.strip_prefix(&self.prefix)?.to_owned()))
+            .collect();
+        Ok(names)
+    }
+
+    async fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let response = self
+            .bucket
+            .get_object(self.key(name))
+            .await
+            .map_err(to_io)?;
+        Ok(response.to_vec())
+    }
+
+    async fn write(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.bucket
+            .put_object(self.key(name), data)
+            .await
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> io::Result<()> {
+        self.bucket
+            .delete_object(self.key(name))
+            .await
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn location(&self, name: &str) -> String {
+        format!("s3://{}/{}", self.bucket.name(), self.key(name))
+    }
+}
@@ -0,0 +1,417 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::backup::{BackupPolicy, BackupTarget, DirTarget};
+use crate::model::{
+    set_contact_rules, CachedContactRepo, ContactRules, CsvContactRepo, DirAttachmentRepo,
+    DirPhotoRepo, EventedRepo, FlushPolicy, IdStrategy, IndexEngine, InstrumentedRepo,
+    MemAttachmentRepo, MemContactRepo, MemCustomFieldRepo, MemGroupRepo, MemHistoryRepo,
+    MemPhotoRepo, MemSavedSearchRepo, NameOrder, PgContactRepo, PgCustomFieldRepo, PgGroupRepo,
+    PgHistoryRepo, PgPhotoRepo, PgSavedSearchRepo, PhoneRegion, RedisContactRepo, SearchIndexRepo,
+    SharedAttachmentRepo, SharedContactRepo, SharedCustomFieldRepo, SharedGroupRepo,
+    SharedHistoryRepo, SharedPhotoRepo, SharedSavedSearchRepo, SledContactRepo, SnapshotFormat,
+    SqliteContactRepo, SqliteCustomFieldRepo, SqliteGroupRepo, SqliteHistoryRepo, SqlitePhotoRepo,
+    SqliteSavedSearchRepo, StorageOptions, StoreKey, ValidationConfig,
+};
+
+/// Where contacts are stored, parsed from a URL like `json://contacts.json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageUrl {
+    /// `memory://`, nothing is persisted.
+    Memory,
+    /// `json://<path>`, a [`MemContactRepo`] snapshot file.
+    Json(PathBuf),
+    /// `csv://<path>`, see [`CsvContactRepo`].
+    Csv(PathBuf),
+    /// `sled://<path>`
+    Sled(PathBuf),
+    /// `sqlite://<path>`, the URL is handed to sqlx as is.
+    Sqlite(String),
+    /// `postgres://…` or `postgresql://…`
+    Postgres(String),
+    /// `redis://…` or `rediss://…`
+    Redis(String),
+    /// `dynamodb://<table>`, needs the `dynamodb` feature.
+    Dynamo(String),
+}
+
+impl Default for StorageUrl {
+    fn default() -> Self {
+        Self::Json("contacts.json".into())
+    }
+}
+
+impl FromStr for StorageUrl {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = url
+            .split_once(':')
+            .ok_or_else(|| format!("storage url '{url}' has no scheme"))?;
+        let path = rest.strip_prefix("//").unwrap_or(rest);
+        match scheme {
+            "memory" => Ok(Self::Memory),
+            "json" if !path.is_empty() => Ok(Self::Json(path.into())),
+            "csv" if !path.is_empty() => Ok(Self::Csv(path.into())),
+            "sled" if !path.is_empty() => Ok(Self::Sled(path.into())),
+            "sqlite" => Ok(Self::Sqlite(url.into())),
+            "postgres" | "postgresql" => Ok(Self::Postgres(url.into())),
+            "redis" | "rediss" => Ok(Self::Redis(url.into())),
+            "dynamodb" if !path.is_empty() => Ok(Self::Dynamo(path.into())),
+            "json" | "csv" | "sled" => Err(format!("storage url '{url}' has no path")),
+            "dynamodb" => Err(format!("storage url '{url}' has no table")),
+            _ => Err(format!("unknown storage scheme '{scheme}'")),
+        }
+    }
+}
+
+impl StorageUrl {
+    /// Opens the repo, applying `options` if it is a file backed
+    /// [`MemContactRepo`] or a [`CsvContactRepo`]. Must be called from within a tokio runtime.
+    pub async fn open(&self, options: StorageOptions) -> SharedContactRepo {
+        match self {
+            Self::Memory => MemContactRepo::new_shared(),
+            Self::Json(path) => Arc::new(
+                MemContactRepo::from_path_with(path.to_str().expect("a UTF-8 path"), options)
+                    .with_background_flush(FlushPolicy::default())
+                    .with_file_watch()
+                    .await,
+            ),
+            Self::Csv(path) => Arc::new(
+                CsvContactRepo::from_path(path.to_str().expect("a UTF-8 path"))
+                    .with_name_order(options.names),
+            ),
+            Self::Sled(path) => {
+                SledContactRepo::shared_from_path(path.to_str().expect("a UTF-8 path"))
+            }
+            Self::Sqlite(url) => SqliteContactRepo::shared_from_url(url).await,
+            Self::Postgres(url) => PgContactRepo::shared_from_url(url).await,
+            Self::Redis(url) => RedisContactRepo::shared_from_url(url).await,
+            #[cfg(feature = "dynamodb")]
+            Self::Dynamo(table) => crate::model::DynamoContactRepo::shared_from_table(table).await,
+            #[cfg(not(feature = "dynamodb"))]
+            Self::Dynamo(_) => {
+                panic!("dynamodb:// needs the app to be built with the dynamodb feature")
+            }
+        }
+    }
+
+    /// Opens the repo of contact groups: a table in SQL databases, a JSON
+    /// file next to the contacts stored in files, such as `contacts.json`
+    /// keeping its groups in `contacts.groups.json`, and `groups.json` for
+    /// the other storages.
+    pub async fn open_groups(&self) -> SharedGroupRepo {
+        match self {
+            Self::Memory => MemGroupRepo::new_shared(),
+            Self::Json(path) | Self::Csv(path) | Self::Sled(path) => {
+                MemGroupRepo::shared_from_path(path.with_extension("groups.json"))
+            }
+            Self::Sqlite(url) => SqliteGroupRepo::shared_from_url(url).await,
+            Self::Postgres(url) => PgGroupRepo::shared_from_url(url).await,
+            Self::Redis(_) | Self::Dynamo(_) => MemGroupRepo::shared_from_path("groups.json"),
+        }
+    }
+
+    /// Opens the repo of the custom field schema, kept like the groups, see
+    /// [`Self::open_groups`], with `fields` in place of `groups`.
+    pub async fn open_fields(&self) -> SharedCustomFieldRepo {
+        match self {
+            Self::Memory => MemCustomFieldRepo::new_shared(),
+            Self::Json(path) | Self::Csv(path) | Self::Sled(path) => {
+                MemCustomFieldRepo::shared_from_path(path.with_extension("fields.json"))
+            }
+            Self::Sqlite(url) => SqliteCustomFieldRepo::shared_from_url(url).await,
+            Self::Postgres(url) => PgCustomFieldRepo::shared_from_url(url).await,
+            Self::Redis(_) | Self::Dynamo(_) => MemCustomFieldRepo::shared_from_path("fields.json"),
+        }
+    }
+
+    /// Opens the repo of saved searches, kept like the groups, see
+    /// [`Self::open_groups`], with `searches` in place of `groups`.
+    pub async fn open_searches(&self) -> SharedSavedSearchRepo {
+        match self {
+            Self::Memory => MemSavedSearchRepo::new_shared(),
+            Self::Json(path) | Self::Csv(path) | Self::Sled(path) => {
+                MemSavedSearchRepo::shared_from_path(path.with_extension("searches.json"))
+            }
+            Self::Sqlite(url) => SqliteSavedSearchRepo::shared_from_url(url).await,
+            Self::Postgres(url) => PgSavedSearchRepo::shared_from_url(url).await,
+            Self::Redis(_) | Self::Dynamo(_) => {
+                MemSavedSearchRepo::shared_from_path("searches.json")
+            }
+        }
+    }
+
+    /// Opens the repo of contact photos: a table in SQL databases, and files
+    /// in `media_dir` for the other storages but `memory://`.
+    pub async fn open_photos(&self, media_dir: &Path) -> SharedPhotoRepo {
+        match self {
+            Self::Memory => MemPhotoRepo::new_shared(),
+            Self::Sqlite(url) => SqlitePhotoRepo::shared_from_url(url).await,
+            Self::Postgres(url) => PgPhotoRepo::shared_from_url(url).await,
+            _ => DirPhotoRepo::shared(media_dir),
+        }
+    }
+
+    /// Opens the repo of the files attached to contacts: files in the
+    /// `attachments` directory of `media_dir` for all storages but
+    /// `memory://`.
+    pub fn open_attachments(&self, media_dir: &Path) -> SharedAttachmentRepo {
+        match self {
+            Self::Memory => MemAttachmentRepo::new_shared(),
+            _ => DirAttachmentRepo::shared(media_dir.join("attachments")),
+        }
+    }
+
+    /// Opens the repo of contact history: a table in SQL databases, and a
+    /// file otherwise, kept like the custom fields with `history` in place
+    /// of `fields` and encrypted with `key`, if any.
+    pub async fn open_history(&self, key: Option<StoreKey>) -> SharedHistoryRepo {
+        match self {
+            Self::Memory => MemHistoryRepo::new_shared(),
+            Self::Json(path) | Self::Csv(path) | Self::Sled(path) => {
+                MemHistoryRepo::shared_from_path(path.with_extension("history.json"), key)
+            }
+            Self::Sqlite(url) => SqliteHistoryRepo::shared_from_url(url).await,
+            Self::Postgres(url) => PgHistoryRepo::shared_from_url(url).await,
+            Self::Redis(_) | Self::Dynamo(_) => {
+                MemHistoryRepo::shared_from_path("history.json", key)
+            }
+        }
+    }
+}
+
+/// Settings of the app, read from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub storage: StorageUrl,
+    pub storage_options: StorageOptions,
+    /// Whether to put a [`CachedContactRepo`] in front of the storage.
+    pub cache: bool,
+    /// What to search an index kept in memory in, if one, see
+    /// [`SearchIndexRepo`].
+    pub search_index: Option<IndexEngine>,
+    pub backup: Option<BackupPolicy>,
+    /// Enables the `/admin` routes, which require it.
+    pub admin_token: Option<String>,
+    pub id_strategy: IdStrategy,
+    /// How names are written in listings and exports, and sorted.
+    pub name_order: NameOrder,
+    /// Where photos and attachments are kept as files, see
+    /// [`StorageUrl::open_photos`] and [`StorageUrl::open_attachments`].
+    pub media_dir: PathBuf,
+    /// The request header naming who makes changes, recorded in the
+    /// contacts' history.
+    pub author_header: Option<String>,
+    /// The country phone numbers entered without a country code are of,
+    /// and shown without one.
+    pub phone_region: Option<PhoneRegion>,
+    /// Whether contacts may not share a phone number, which the storage
+    /// checks as it saves them, see
+    /// [`unique_phones`](crate::model::unique_phones).
+    pub unique_phones: bool,
+    /// Whether to warn about emails whose domain doesn't accept mail, see
+    /// [`MailDomains`](crate::model::MailDomains).
+    pub email_mx_check: bool,
+    /// The list of disposable email domains to flag emails at, if not the
+    /// bundled one, see [`DisposableDomains`](crate::model::DisposableDomains).
+    pub disposable_domains_file: Option<PathBuf>,
+    /// Which fields contacts must have, see [`ValidationConfig`].
+    pub validation: ValidationConfig,
+    /// How similar contacts must be to a query for a fuzzy search to find
+    /// them, if not
+    /// [`DEFAULT_FUZZY_THRESHOLD`](crate::model::DEFAULT_FUZZY_THRESHOLD).
+    pub fuzzy_threshold: Option<f64>,
+    /// Whether to serve GraphQL Playground at `/graphql`, in builds with the
+    /// `graphql` feature.
+    pub graphql_playground: bool,
+}
+
+impl Config {
+    /// Opens the configured storage, sending its changes to subscribers with
+    /// an [`EventedRepo`] and timing the calls to it with an
+    /// [`InstrumentedRepo`].
+    pub async fn open_repo(&self) -> SharedContactRepo {
+        // Before anything is validated, which fixes the rules used.
+        let _ = set_contact_rules(ContactRules::new(
+            &self.validation,
+            self.phone_region,
+            self.unique_phones,
+        ));
+        // Below the cache, so events look up saved contacts in the storage
+        // rather than reloading the cache the save just dropped.
+        let repo = EventedRepo::shared(self.storage.open(self.storage_options.clone()).await);
+        let repo = if self.cache {
+            CachedContactRepo::shared(repo)
+        } else {
+            repo
+        };
+        let repo = if let Some(engine) = self.search_index {
+            SearchIndexRepo::shared(repo, engine).await
+        } else {
+            repo
+        };
+        InstrumentedRepo::shared(repo)
+    }
+
+    /// Reads the config from these environment variables, panicking on
+    /// invalid values:
+    ///
+    /// - `STORAGE_URL` (or `DATABASE_URL`), see [`StorageUrl`]
+    /// - `SNAPSHOT_FORMAT`, `json` or `msgpack`
+    /// - `CONTACTS_KEY`, a base64 encoded [`StoreKey`]
+    /// - `STORAGE_CACHE`, `true` or `false`
+    /// - `SEARCH_INDEX`, `true` or `false`, whether to search an index kept
+    ///   in memory, or `tantivy` to keep it in tantivy, see [`IndexEngine`]
+    /// - `BACKUP_DIR`, enables backups to that directory, see
+    ///   [`BackupPolicy`]
+    /// - `BACKUP_URL`, enables backups to `s3://<bucket>/<prefix>` instead
+    /// - `BACKUP_INTERVAL`, in seconds
+    /// - `BACKUP_KEEP`, the number of backups to keep
+    /// - `ADMIN_TOKEN`, enables the admin routes
+    /// - `ID_STRATEGY`, `sequential` or `uuid`, see [`IdStrategy`]
+    /// - `MEDIA_DIR`, where photos and attachments are kept, `media` by
+    ///   default
+    /// - `NAME_ORDER`, `first_last` or `last_first`, see [`NameOrder`]
+    /// - `AUTHOR_HEADER`, the request header naming who makes changes, such
+    ///   as `X-Forwarded-User` behind an authenticating proxy
+    /// - `PHONE_REGION`, the country code phone numbers are of unless they
+    ///   start with `+`, such as `SE`
+    /// - `UNIQUE_PHONES`, `true` or `false`, whether contacts may not share
+    ///   a phone number
+    /// - `EMAIL_MX_CHECK`, `true` or `false`, whether to look up the mail
+    ///   servers of emails' domains and warn if there are none
+    /// - `DISPOSABLE_DOMAINS_FILE`, a list of disposable email domains, one
+    ///   per line, to use instead of the bundled one
+    /// - `VALIDATION_RULES`, a JSON file of the [`ValidationConfig`]
+    /// - `FUZZY_THRESHOLD`, from 0 to 1, how similar contacts must be to a
+    ///   query for a fuzzy search to find them
+    /// - `GRAPHQL_PLAYGROUND`, `true` or `false`, whether to serve GraphQL
+    ///   Playground, by default only in debug builds
+    pub fn from_env() -> Self {
+        let storage = match env::var("STORAGE_URL").or_else(|_| env::var("DATABASE_URL")) {
+            Ok(url) => url.parse().expect("a valid STORAGE_URL"),
+            Err(_) => StorageUrl::default(),
+        };
+        let format = match env::var("SNAPSHOT_FORMAT") {
+            Ok(format) => format.parse().expect("a valid SNAPSHOT_FORMAT"),
+            Err(_) => SnapshotFormat::default(),
+        };
+        let key = env::var("CONTACTS_KEY")
+            .ok()
+            .map(|key| StoreKey::from_base64(&key).expect("a valid CONTACTS_KEY"));
+        let cache = match env::var("STORAGE_CACHE") {
+            Ok(cache) => cache.parse().expect("a valid STORAGE_CACHE"),
+            Err(_) => false,
+        };
+        let search_index = match env::var("SEARCH_INDEX").as_deref() {
+            Ok("true") => Some(IndexEngine::Memory),
+            Ok("false") | Err(_) => None,
+            Ok(engine) => Some(engine.parse().expect("a valid SEARCH_INDEX")),
+        };
+        let target = match (env::var("BACKUP_URL"), env::var("BACKUP_DIR")) {
+            (Ok(url), _) => Some(backup_target(&url)),
+            (Err(_), Ok(dir)) => Some(Arc::new(DirTarget::new(dir)) as Arc<dyn BackupTarget>),
+            _ => None,
+        };
+        let backup = target.map(|target| {
+            let mut policy = BackupPolicy::new(target);
+            if let Ok(interval) = env::var("BACKUP_INTERVAL") {
+                let secs = interval.parse().expect("a valid BACKUP_INTERVAL");
+                policy.interval = Duration::from_secs(secs);
+            }
+            if let Ok(keep) = env::var("BACKUP_KEEP") {
+                policy.keep = keep.parse().expect("a valid BACKUP_KEEP");
+            }
+            policy.key = key.clone();
+            policy
+        });
+        let id_strategy = match env::var("ID_STRATEGY") {
+            Ok(strategy) => strategy.parse().expect("a valid ID_STRATEGY"),
+            Err(_) => IdStrategy::default(),
+        };
+        let unique_phones = match env::var("UNIQUE_PHONES") {
+            Ok(unique) => unique.parse().expect("a valid UNIQUE_PHONES"),
+            Err(_) => false,
+        };
+        let validation = match env::var("VALIDATION_RULES") {
+            Ok(path) => ValidationConfig::from_file(path)
+                .unwrap_or_else(|err| panic!("a valid VALIDATION_RULES file: {err}")),
+            Err(_) => ValidationConfig::default(),
+        };
+        let keyed_by_email = matches!(
+            storage,
+            StorageUrl::Sled(_) | StorageUrl::Redis(_) | StorageUrl::Dynamo(_)
+        );
+        if keyed_by_email && !(validation.unique_email && validation.required.contains("email")) {
+            panic!("sled, redis and dynamodb storage need VALIDATION_RULES to keep emails required and unique");
+        }
+        let email_mx_check = match env::var("EMAIL_MX_CHECK") {
+            Ok(check) => check.parse().expect("a valid EMAIL_MX_CHECK"),
+            Err(_) => false,
+        };
+        let fuzzy_threshold = env::var("FUZZY_THRESHOLD").ok().map(|threshold| {
+            let threshold: f64 = threshold.parse().expect("a valid FUZZY_THRESHOLD");
+            assert!(
+                (0.0..=1.0).contains(&threshold),
+                "FUZZY_THRESHOLD must be from 0 to 1"
+            );
+            threshold
+        });
+        let graphql_playground = match env::var("GRAPHQL_PLAYGROUND") {
+            Ok(playground) => playground.parse().expect("a valid GRAPHQL_PLAYGROUND"),
+            Err(_) => cfg!(debug_assertions),
+        };
+        let name_order = match env::var("NAME_ORDER") {
+            Ok(order) => order.parse().expect("a valid NAME_ORDER"),
+            Err(_) => NameOrder::default(),
+        };
+        Self {
+            storage,
+            storage_options: StorageOptions {
+                format,
+                key,
+                names: name_order,
+            },
+            cache,
+            search_index,
+            backup,
+            admin_token: env::var("ADMIN_TOKEN").ok(),
+            id_strategy,
+            name_order,
+            media_dir: env::var("MEDIA_DIR").map_or_else(|_| "media".into(), PathBuf::from),
+            author_header: env::var("AUTHOR_HEADER").ok(),
+            phone_region: env::var("PHONE_REGION")
+                .ok()
+                .map(|region| region.parse().expect("a valid PHONE_REGION")),
+            unique_phones,
+            email_mx_check,
+            validation,
+            fuzzy_threshold,
+            graphql_playground,
+            disposable_domains_file: env::var("DISPOSABLE_DOMAINS_FILE").ok().map(PathBuf::from),
+        }
+    }
+}
+
+/// Parses `BACKUP_URL`, panicking if it is invalid.
+fn backup_target(url: &str) -> Arc<dyn BackupTarget> {
+    let Some(location) = url.strip_prefix("s3://") else {
+        panic!("BACKUP_URL '{url}' is not an s3:// url");
+    };
+    #[cfg(feature = "s3")]
+    {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        Arc::new(crate::backup::S3Target::from_env(bucket, prefix))
+    }
+    #[cfg(not(feature = "s3"))]
+    {
+        let _ = location;
+        panic!("BACKUP_URL needs the app to be built with the s3 feature");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod app;
+pub mod backup;
+pub mod config;
+pub mod model;
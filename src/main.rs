@@ -1,16 +1,49 @@
-mod app;
-mod model;
-
-use app::create_app;
+use contacts_app::{
+    app::{create_app, Repos},
+    backup,
+    config::Config,
+    model,
+};
 
 #[tokio::main]
 async fn main() {
-    let app = create_app();
+    let config = Config::from_env();
+    let repo = config.open_repo().await;
+    if let Some(policy) = config.backup.clone() {
+        backup::spawn(repo.clone(), policy);
+    }
+    let groups = config.storage.open_groups().await;
+    let fields = config.storage.open_fields().await;
+    let searches = config.storage.open_searches().await;
+    let photos = config.storage.open_photos(&config.media_dir).await;
+    let attachments = config.storage.open_attachments(&config.media_dir);
+    let history = config
+        .storage
+        .open_history(config.storage_options.key.clone())
+        .await;
+    model::spawn_history(&repo, history.clone());
+    let repos = Repos {
+        groups,
+        fields,
+        searches,
+        photos,
+        attachments,
+        history,
+    };
+    let app = create_app(repo.clone(), repos, &config);
 
     let address = "127.0.0.1:3000".parse().expect("valid address");
     println!("Listening at {address}");
     axum::Server::bind(&address)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+    repo.flush().await;
+}
+
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
         .await
-        .unwrap()
+        .expect("ctrl-c handler to install");
 }
@@ -1,132 +1,1853 @@
 use std::{
-    collections::HashMap,
-    fs, io,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    hash::{DefaultHasher, Hasher},
+    io,
     path::{Path, PathBuf},
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        self as std_sync,
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use tokio::sync::RwLock;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use futures_util::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use tokio::sync::{broadcast, Mutex, Notify, RwLock};
+use uuid::Uuid;
+
+mod attachments;
+mod cached;
+mod crypto;
+mod csv;
+mod dates;
+mod disposable;
+mod duplicates;
+#[cfg(feature = "dynamodb")]
+mod dynamo;
+mod events;
+mod fields;
+mod groups;
+mod history;
+mod ids;
+mod index;
+mod instrumented;
+mod journal;
+mod migrate;
+mod mx;
+mod names;
+mod normalize;
+mod phones;
+mod phonetic;
+mod photos;
+mod postgres;
+mod query;
+mod redis;
+mod search;
+mod searches;
+mod sled;
+mod snapshot;
+mod sort;
+mod sqlite;
+mod transaction;
+mod validation;
+mod watch;
+
+pub use self::csv::CsvContactRepo;
+pub use self::redis::RedisContactRepo;
+pub use self::sled::SledContactRepo;
+pub use attachments::{
+    Attachment, AttachmentRepo, DirAttachmentRepo, MemAttachmentRepo, SharedAttachmentRepo,
+    MAX_ATTACHMENT_SIZE,
+};
+pub use cached::CachedContactRepo;
+pub use crypto::StoreKey;
+pub use dates::{upcoming as upcoming_dates, ImportantDate, UpcomingDate};
+pub use disposable::{DisposableDomains, SharedDisposableDomains};
+pub use duplicates::{possible_duplicates, MAX_DUPLICATES};
+#[cfg(feature = "dynamodb")]
+pub use dynamo::DynamoContactRepo;
+pub use events::{ContactEvent, EventedRepo, EVENT_BUFFER};
+pub use fields::{
+    CustomField, CustomFieldRepo, FieldError, FieldKind, MemCustomFieldRepo, SharedCustomFieldRepo,
+};
+pub use groups::{Group, GroupError, GroupRepo, MemGroupRepo, SharedGroupRepo};
+pub use history::{
+    spawn as spawn_history, with_author, FieldChange, HistoryRepo, MemHistoryRepo, Revision,
+    SharedHistoryRepo,
+};
+pub use ids::{ContactKey, IdStrategy};
+pub use index::{IndexEngine, SearchIndexRepo};
+pub use instrumented::{CallStats, InstrumentedRepo, LATENCY_BUCKETS_MS};
+pub use journal::{Journal, JournalEntry};
+pub use migrate::SCHEMA_VERSION;
+pub use mx::{MailDomains, MX_CACHE_TTL, MX_TIMEOUT};
+pub use names::{initials, NameOrder, OTHER_INITIAL};
+pub use normalize::normalize_text;
+pub use phones::{format_phone, normalize_phone, PhoneRegion};
+pub use photos::{DirPhotoRepo, MemPhotoRepo, Photo, PhotoRepo, SharedPhotoRepo, MAX_PHOTO_SIZE};
+pub use postgres::{
+    PgContactRepo, PgCustomFieldRepo, PgGroupRepo, PgHistoryRepo, PgPhotoRepo, PgSavedSearchRepo,
+};
+pub use query::{regex_pattern, ContactFilter, SearchField, SearchQuery, MAX_PATTERN_LEN};
+pub use search::{highlights, DEFAULT_FUZZY_THRESHOLD};
+pub use searches::{
+    MemSavedSearchRepo, SavedSearch, SavedSearchError, SavedSearchRepo, SharedSavedSearchRepo,
+};
+pub use snapshot::{write_atomic, Snapshot, SnapshotFormat, Tombstone};
+pub use sort::{Direction, Sort, SortBy};
+pub use sqlite::{
+    SqliteContactRepo, SqliteCustomFieldRepo, SqliteGroupRepo, SqliteHistoryRepo, SqlitePhotoRepo,
+    SqliteSavedSearchRepo,
+};
+pub use transaction::{BatchError, BoxedTransaction, ContactTransaction};
+use transaction::{Changes, Stage, StagedTransaction};
+pub use validation::{
+    contact_rules, set_contact_rules, unique_emails, unique_phones, ContactRules, Length,
+    ValidationConfig, ValidationError, ValidationErrors, Validator, Validators, MAX_NOTES_LENGTH,
+    MAX_TEXT_LENGTH,
+};
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct Contact {
+    id: Option<u64>,
+    /// Identifies the contact in URLs instead of `id`, if it was created
+    /// with [`IdStrategy::Uuid`].
+    #[serde(default)]
+    uuid: Option<Uuid>,
+    /// E.g. `Dr.`, written before the first name.
+    #[serde(default)]
+    prefix: Option<String>,
+    first: Option<String>,
+    /// One or more middle names.
+    #[serde(default)]
+    middle: Option<String>,
+    last: Option<String>,
+    /// E.g. `Jr.` or `PhD`, written after the last name.
+    #[serde(default)]
+    suffix: Option<String>,
+    /// What the contact goes by, which names them in
+    /// [`Self::display_name`] only if they have no other name.
+    #[serde(default)]
+    nickname: Option<String>,
+    /// Read from a single `phone` too, as stored before contacts had
+    /// several numbers.
+    #[serde(default, alias = "phone", deserialize_with = "phone_numbers")]
+    phones: Vec<PhoneNumber>,
+    /// The primary email, which no other contact may have.
+    pub email: Option<String>,
+    /// Further addresses besides the primary `email`, which needn't be
+    /// unique.
+    #[serde(default)]
+    emails: Vec<EmailAddress>,
+    #[serde(default)]
+    addresses: Vec<Address>,
+    #[serde(default)]
+    birthday: Option<NaiveDate>,
+    /// Dates to remember besides the birthday, in the order entered.
+    #[serde(default)]
+    dates: Vec<ImportantDate>,
+    /// The organization the contact works for.
+    #[serde(default)]
+    company: Option<String>,
+    #[serde(default)]
+    job_title: Option<String>,
+    #[serde(default)]
+    socials: Vec<SocialProfile>,
+    /// An `http` or `https` URL, with the scheme added by
+    /// [`Self::validate`] if it was left out.
+    #[serde(default)]
+    website: Option<String>,
+    /// The IANA name of the time zone the contact lives in, such as
+    /// `Europe/Stockholm`.
+    #[serde(default)]
+    timezone: Option<String>,
+    /// Free text, written in Markdown.
+    #[serde(default)]
+    notes: Option<String>,
+    /// Lowercase labels to group contacts by, each listed once.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Ids of the [`Group`]s the contact is in, in order.
+    #[serde(default)]
+    groups: Vec<u64>,
+    /// Checked by [`Self::validate`] to be one the contact can be reached
+    /// by.
+    #[serde(default)]
+    preferred: ContactMethod,
+    /// Whether the contact is a favorite.
+    #[serde(default)]
+    starred: bool,
+    /// Whether the contact is hidden from the listing and from searches,
+    /// still kept with all its data.
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    has_photo: bool,
+    /// The files attached to the contact, in the order added.
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// Values of [`CustomField`]s, by field name.
+    #[serde(default)]
+    custom: HashMap<String, serde_json::Value>,
+    /// Bumped each time the contact is created or updated, so an update
+    /// made from an outdated copy is rejected.
+    #[serde(default)]
+    version: u64,
+    /// When the contact was first saved, `None` for contacts saved before
+    /// this was recorded.
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    /// When the contact was last created or updated.
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    /// Why the contact isn't valid, set by [`Self::validate`] and by a repo
+    /// rejecting it; not stored.
+    #[serde(skip)]
+    pub errors: ValidationErrors,
+}
+
+/// One of a contact's phone numbers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct PhoneNumber {
+    /// What kind of number it is, e.g. `mobile`, `work` or `home`; empty if
+    /// not given.
+    #[serde(default)]
+    pub label: String,
+    pub value: String,
+}
+
+impl PhoneNumber {
+    pub fn new(label: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// One of a contact's email addresses besides the primary one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct EmailAddress {
+    /// E.g. `work` or `home`; empty if not given.
+    #[serde(default)]
+    pub label: String,
+    pub value: String,
+}
+
+impl EmailAddress {
+    pub fn new(label: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// A contact's handle on a social network, e.g. `GitHub` and `octocat`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct SocialProfile {
+    /// The network's name, as picked in the form.
+    pub network: String,
+    pub handle: String,
+}
+
+impl SocialProfile {
+    pub fn new(network: impl Into<String>, handle: impl Into<String>) -> Self {
+        Self {
+            network: network.into(),
+            handle: handle.into(),
+        }
+    }
+}
+
+/// One of a contact's postal addresses.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Address {
+    /// E.g. `home` or `work`; empty if not given.
+    pub label: String,
+    pub street: String,
+    pub city: String,
+    pub postal_code: String,
+    pub country: String,
+}
+
+impl Address {
+    /// Whether the address has nothing but maybe a label.
+    pub fn is_empty(&self) -> bool {
+        self.lines().all(str::is_empty)
+    }
+
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        [&self.street, &self.city, &self.postal_code, &self.country]
+            .into_iter()
+            .map(String::as_str)
+    }
+}
+
+/// How a contact prefers to be reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactMethod {
+    #[default]
+    None,
+    /// At the primary email.
+    Email,
+    /// Calling the first phone number.
+    Phone,
+    /// Texting the first phone number.
+    Sms,
+}
+
+impl FromStr for ContactMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "none" => Ok(Self::None),
+            "email" => Ok(Self::Email),
+            "phone" => Ok(Self::Phone),
+            "sms" => Ok(Self::Sms),
+            _ => Err(format!("unknown contact method '{s}'")),
+        }
+    }
+}
+
+/// Deserializes [`Contact::phones`] from a list of numbers or, as contacts
+/// were stored before, a single optional number.
+fn phone_numbers<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<PhoneNumber>, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Phones {
+        Many(Vec<PhoneNumber>),
+        One(Option<String>),
+    }
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        Phones::Many(phones) => phones,
+        Phones::One(phone) => phone
+            .into_iter()
+            .map(|value| PhoneNumber::new("", value))
+            .collect(),
+    })
+}
+
+impl Contact {
+    pub fn new(
+        first: Option<String>,
+        last: Option<String>,
+        phones: Vec<PhoneNumber>,
+        email: Option<String>,
+    ) -> Self {
+        Self {
+            first,
+            last,
+            phones,
+            email,
+            ..Default::default()
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    pub fn phones(&self) -> &[PhoneNumber] {
+        &self.phones
+    }
+
+    pub fn emails(&self) -> &[EmailAddress] {
+        &self.emails
+    }
+
+    pub fn set_emails(&mut self, emails: Vec<EmailAddress>) {
+        self.emails = emails;
+    }
+
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    pub fn set_addresses(&mut self, addresses: Vec<Address>) {
+        self.addresses = addresses;
+    }
+
+    pub fn birthday(&self) -> Option<NaiveDate> {
+        self.birthday
+    }
+
+    pub fn set_birthday(&mut self, birthday: Option<NaiveDate>) {
+        self.birthday = birthday;
+    }
+
+    pub fn first(&self) -> Option<&str> {
+        self.first.as_deref()
+    }
+
+    pub fn last(&self) -> Option<&str> {
+        self.last.as_deref()
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn middle(&self) -> Option<&str> {
+        self.middle.as_deref()
+    }
+
+    pub fn suffix(&self) -> Option<&str> {
+        self.suffix.as_deref()
+    }
+
+    pub fn nickname(&self) -> Option<&str> {
+        self.nickname.as_deref()
+    }
+
+    /// Sets the parts of the name besides the first and last.
+    pub fn set_name_parts(
+        &mut self,
+        prefix: Option<String>,
+        middle: Option<String>,
+        suffix: Option<String>,
+        nickname: Option<String>,
+    ) {
+        self.prefix = prefix;
+        self.middle = middle;
+        self.suffix = suffix;
+        self.nickname = nickname;
+    }
+
+    pub fn company(&self) -> Option<&str> {
+        self.company.as_deref()
+    }
+
+    pub fn set_company(&mut self, company: Option<String>) {
+        self.company = company;
+    }
+
+    pub fn job_title(&self) -> Option<&str> {
+        self.job_title.as_deref()
+    }
+
+    pub fn set_job_title(&mut self, job_title: Option<String>) {
+        self.job_title = job_title;
+    }
+
+    pub fn socials(&self) -> &[SocialProfile] {
+        &self.socials
+    }
+
+    pub fn set_socials(&mut self, socials: Vec<SocialProfile>) {
+        self.socials = socials;
+    }
+
+    pub fn website(&self) -> Option<&str> {
+        self.website.as_deref()
+    }
+
+    pub fn set_website(&mut self, website: Option<String>) {
+        self.website = website;
+    }
+
+    pub fn timezone(&self) -> Option<&str> {
+        self.timezone.as_deref()
+    }
+
+    pub fn set_timezone(&mut self, timezone: Option<String>) {
+        self.timezone = timezone;
+    }
+
+    pub fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    pub fn set_notes(&mut self, notes: Option<String>) {
+        self.notes = notes;
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Sets the tags, trimmed and lowercased, leaving out empty and repeated
+    /// ones.
+    pub fn set_tags(&mut self, tags: impl IntoIterator<Item = String>) {
+        self.tags.clear();
+        for tag in tags {
+            let tag = tag.trim().to_lowercase();
+            if !tag.is_empty() && !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn groups(&self) -> &[u64] {
+        &self.groups
+    }
+
+    pub fn set_groups(&mut self, groups: impl IntoIterator<Item = u64>) {
+        self.groups = groups.into_iter().collect();
+        self.groups.sort_unstable();
+        self.groups.dedup();
+    }
+
+    pub fn in_group(&self, group: u64) -> bool {
+        self.groups.contains(&group)
+    }
+
+    pub fn custom(&self) -> &HashMap<String, serde_json::Value> {
+        &self.custom
+    }
+
+    /// Sets the value of the custom field called `name`, or clears it if
+    /// `value` is `None`.
+    pub fn set_custom(&mut self, name: &str, value: Option<serde_json::Value>) {
+        match value {
+            Some(value) => self.custom.insert(name.to_owned(), value),
+            None => self.custom.remove(name),
+        };
+    }
+
+    /// Whether a photo of the contact is stored, see [`PhotoRepo`].
+    pub fn has_photo(&self) -> bool {
+        self.has_photo
+    }
+
+    pub fn set_has_photo(&mut self, has_photo: bool) {
+        self.has_photo = has_photo;
+    }
+
+    pub fn starred(&self) -> bool {
+        self.starred
+    }
+
+    pub fn set_starred(&mut self, starred: bool) {
+        self.starred = starred;
+    }
+
+    pub fn archived(&self) -> bool {
+        self.archived
+    }
+
+    pub fn set_archived(&mut self, archived: bool) {
+        self.archived = archived;
+    }
+
+    pub fn preferred(&self) -> ContactMethod {
+        self.preferred
+    }
+
+    pub fn set_preferred(&mut self, preferred: ContactMethod) {
+        self.preferred = preferred;
+    }
+
+    /// Sets the value of the phone number at `index`, or adds one with
+    /// `value` if there is none there, returning the index it is at.
+    pub fn set_phone(&mut self, index: usize, value: String) -> usize {
+        match self.phones.get_mut(index) {
+            Some(phone) => {
+                phone.value = value;
+                index
+            }
+            None => {
+                self.phones.push(PhoneNumber::new("", value));
+                self.phones.len() - 1
+            }
+        }
+    }
+
+    /// Sets the value of the address at `index` of [`Self::emails`], adding
+    /// one if there is none, and returns the index it ended up at.
+    pub fn set_other_email(&mut self, index: usize, value: String) -> usize {
+        match self.emails.get_mut(index) {
+            Some(email) => {
+                email.value = value;
+                index
+            }
+            None => {
+                self.emails.push(EmailAddress::new("", value));
+                self.emails.len() - 1
+            }
+        }
+    }
+
+    pub fn uuid(&self) -> Option<Uuid> {
+        self.uuid
+    }
+
+    /// What identifies the stored contact in URLs: its uuid if it has one,
+    /// and otherwise its id.
+    pub fn key(&self) -> Option<ContactKey> {
+        match self.uuid {
+            Some(uuid) => Some(ContactKey::Uuid(uuid)),
+            None => self.id.map(ContactKey::Id),
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Marks the contact as edited from the copy at `version`.
+    pub fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+
+    /// Makes the contact a new version of `stored`, taking over the fields
+    /// the repo sets rather than the user.
+    fn take_identity(&mut self, stored: &Contact) {
+        self.id = stored.id;
+        self.uuid = stored.uuid.or(self.uuid);
+        self.version = stored.version;
+        self.created_at = stored.created_at;
+    }
+
+    /// Makes the contact, as sent by a client to be created, a new one,
+    /// leaving out the fields the repo and the app set rather than the user.
+    pub fn forget_identity(&mut self) {
+        self.id = None;
+        self.uuid = None;
+        self.version = 0;
+        self.created_at = None;
+        self.updated_at = None;
+        self.has_photo = false;
+        self.attachments.clear();
+        self.errors.clear();
+    }
+
+    /// Makes the contact, as sent by a client in place of `stored`, its next
+    /// version, keeping the fields the repo and the app set rather than the
+    /// user. The version sent, if any, is kept, so an update from an
+    /// outdated copy is rejected.
+    pub fn replace(&mut self, stored: &Contact) {
+        let version = self.version;
+        self.take_identity(stored);
+        if version != 0 {
+            self.version = version;
+        }
+        self.updated_at = stored.updated_at;
+        self.has_photo = stored.has_photo;
+        self.attachments = stored.attachments.clone();
+        self.errors.clear();
+    }
+
+    /// Changes the fields of the contact `patch` gives, as a JSON merge
+    /// patch (RFC 7396): `null` clears a field and fields left out are
+    /// kept. Like [`Self::replace`], the fields the repo and the app set
+    /// are kept, and a `version` in `patch` rejects the update if outdated.
+    pub fn merge(&mut self, patch: serde_json::Value) -> Result<(), serde_json::Error> {
+        let mut merged = serde_json::to_value(&*self)?;
+        merge_patch(&mut merged, patch);
+        let mut contact: Contact = serde_json::from_value(merged)?;
+        contact.replace(self);
+        *self = contact;
+        Ok(())
+    }
+
+    /// Moves the contact to the next version, updated now.
+    fn bump(&mut self) {
+        let now = Utc::now();
+        self.version += 1;
+        self.created_at.get_or_insert(now);
+        self.updated_at = Some(now);
+    }
+
+    /// The contact as saved, see [`Self::bump`].
+    fn bumped(&self) -> Self {
+        let mut contact = self.clone();
+        contact.bump();
+        contact
+    }
+
+    pub fn update(
+        &mut self,
+        first: Option<String>,
+        last: Option<String>,
+        phones: Vec<PhoneNumber>,
+        email: Option<String>,
+    ) {
+        self.first = first;
+        self.last = last;
+        self.phones = phones;
+        self.email = email;
+    }
+}
+
+/// Applies the JSON merge `patch` to `target`.
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().expect("an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(serde_json::Value::Null), value);
+        }
+    }
+}
+#[async_trait::async_trait]
+pub trait ContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact>;
+    /// Number of contacts, or of those [matching](Contact::matches) `filter`.
+    async fn count(&self, filter: Option<&str>) -> usize;
+    /// Page `number` of the contacts [matching](Contact::matches) `query`, in
+    /// `sort` order, with `size` contacts per page.
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact>;
+    /// Page `number` of the contacts [matching](Contact::matches) `query`
+    /// that `filter` [allows](ContactFilter::allows), in `sort` order, with
+    /// `size` contacts per page.
+    async fn search_filtered(
+        &self,
+        query: &str,
+        filter: &ContactFilter,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let contacts = self.all(sort).await;
+        let matches = contacts
+            .iter()
+            .filter(|contact| filter.finds(&query, contact));
+        Page::of(matches, sort, number, size)
+    }
+    /// Page `number` of the contacts that aren't archived at least
+    /// `threshold` [similar](Contact::similarity) to `query`, which finds
+    /// them despite typos, most similar first, with `size` contacts per page.
+    async fn fuzzy_search(
+        &self,
+        query: &str,
+        threshold: f64,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let contacts = self.all(sort).await;
+        let unarchived = contacts.iter().filter(|contact| !contact.archived);
+        Page::by_similarity(unarchived, query, threshold, sort, number, size)
+    }
+    /// Page `number` of the contacts that aren't archived with `tag`, in
+    /// `sort` order, with `size` contacts per page.
+    async fn tagged(&self, tag: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let contacts = self.all(sort).await;
+        let tagged = contacts
+            .iter()
+            .filter(|contact| !contact.archived && contact.has_tag(tag));
+        Page::of(tagged, sort, number, size)
+    }
+    /// Every tag in use, with the number of contacts that aren't archived
+    /// that have it.
+    async fn tags(&self) -> BTreeMap<String, usize> {
+        tag_counts(&self.all(Sort::default()).await)
+    }
+    /// Page `number` of the contacts that aren't archived in the [`Group`]
+    /// with id `group`, in `sort` order, with `size` contacts per page.
+    async fn members(&self, group: u64, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let contacts = self.all(sort).await;
+        let members = contacts
+            .iter()
+            .filter(|contact| !contact.archived && contact.in_group(group));
+        Page::of(members, sort, number, size)
+    }
+    /// The number of contacts that aren't archived in each group that has
+    /// any, by group id.
+    async fn group_sizes(&self) -> BTreeMap<u64, usize> {
+        group_sizes(&self.all(Sort::default()).await)
+    }
+    /// Page `number` of the contacts that aren't archived listed under
+    /// `initial`, in `sort` order and [initialed](Contact::initial) in its
+    /// name order, with `size` contacts per page.
+    async fn by_initial(
+        &self,
+        initial: char,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let contacts = self.all(sort).await;
+        let listed = contacts
+            .iter()
+            .filter(|contact| !contact.archived && contact.initial(sort.names) == initial);
+        Page::of(listed, sort, number, size)
+    }
+    /// The number of contacts that aren't archived listed under each
+    /// [initial](Contact::initial) in `names` order, leaving out those none
+    /// is.
+    async fn initials(&self, names: NameOrder) -> BTreeMap<char, usize> {
+        initial_counts(&self.all(Sort::default()).await, names)
+    }
+    /// Page `number` of the [starred](Contact::starred) contacts that aren't
+    /// archived, in `sort` order, with `size` contacts per page.
+    async fn starred(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let contacts = self.all(sort).await;
+        Page::of(
+            contacts
+                .iter()
+                .filter(|contact| !contact.archived && contact.starred),
+            sort,
+            number,
+            size,
+        )
+    }
+    /// Page `number` of the [archived](Contact::archived) contacts, or of
+    /// those that aren't if `archived` is false, in `sort` order, with `size`
+    /// contacts per page.
+    async fn archived(
+        &self,
+        archived: bool,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let contacts = self.all(sort).await;
+        Page::of(
+            contacts
+                .iter()
+                .filter(|contact| contact.archived == archived),
+            sort,
+            number,
+            size,
+        )
+    }
+    /// Adds `contact`, with a new id unless it has one, and returns its id.
+    /// Like [`Self::update`], it stores the contact with its version bumped
+    /// and its timestamps set.
+    /// Fails with [`RepoError::Conflict`] if its id or email is taken.
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError>;
+    /// Replaces the contact with `contact`'s id, failing with
+    /// [`RepoError::NotFound`] if there is none and with
+    /// [`RepoError::Conflict`] if another contact has its email or the
+    /// contact's version isn't the stored one.
+    async fn update(&self, contact: Contact) -> Result<(), RepoError>;
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError>;
+
+    /// The contacts with `ids`, ordered by id, leaving out ids with no
+    /// contact.
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        let mut contacts = Vec::new();
+        for id in unique_ids(ids) {
+            contacts.extend(self.find(id).await?);
+        }
+        Ok(contacts)
+    }
+
+    /// The contact whose email is exactly `email`, if any.
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        let contacts = self.all(Sort::default()).await;
+        Ok(contacts
+            .into_iter()
+            .find(|contact| contact.email.as_deref() == Some(email)))
+    }
+
+    /// The contact with `uuid`, if any.
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<Contact>, RepoError> {
+        let contacts = self.all(Sort::default()).await;
+        Ok(contacts
+            .into_iter()
+            .find(|contact| contact.uuid == Some(uuid)))
+    }
+
+    async fn exists_by_email(&self, email: &str) -> Result<bool, RepoError> {
+        Ok(self.find_by_email(email).await?.is_some())
+    }
+
+    /// Updates the contact with `contact`'s email, keeping its id, or creates
+    /// one if there is none. A write racing with another one for the same
+    /// email is retried, up to [`UPSERT_ATTEMPTS`] times in all, before its
+    /// [`RepoError::Conflict`] is returned.
+    async fn upsert_by_email(&self, mut contact: Contact) -> Result<Upserted, RepoError> {
+        let Some(email) = contact.email.clone() else {
+            return self.create(contact).await.map(Upserted::Created);
+        };
+        let mut attempts = 1;
+        loop {
+            let upserted = match self.find_by_email(&email).await? {
+                Some(existing) => {
+                    contact.take_identity(&existing);
+                    let id = existing.id.expect("a stored contact to have an id");
+                    self.update(contact.clone())
+                        .await
+                        .map(|()| Upserted::Updated(id))
+                }
+                None => {
+                    contact.id = None;
+                    self.create(contact.clone()).await.map(Upserted::Created)
+                }
+            };
+            match upserted {
+                Err(RepoError::Conflict(rejected)) if attempts < UPSERT_ATTEMPTS => {
+                    // Someone else created, updated or deleted the contact
+                    // with the email meanwhile.
+                    let raced = ["email", "version"]
+                        .iter()
+                        .any(|field| rejected.errors.contains(field));
+                    if !raced {
+                        return Err(RepoError::Conflict(rejected));
+                    }
+                }
+                Err(RepoError::NotFound(_)) if attempts < UPSERT_ATTEMPTS => {}
+                upserted => return upserted,
+            }
+            attempts += 1;
+        }
+    }
+
+    /// Replaces the stored contact with `contact`, an earlier copy of it
+    /// such as a [`Revision`]'s, as its next version. As the photo and the
+    /// files of attachments aren't part of the copy, the contact keeps
+    /// whether it has a photo and its current attachments. Fails like
+    /// [`Self::update`].
+    async fn restore(&self, mut contact: Contact) -> Result<(), RepoError> {
+        let id = contact.id.expect("a restored contact to have an id");
+        let stored = self.find(id).await?.ok_or(RepoError::NotFound(id))?;
+        contact.take_identity(&stored);
+        contact.has_photo = stored.has_photo;
+        contact.attachments = stored.attachments;
+        contact.errors.clear();
+        self.update(contact).await
+    }
+
+    /// Deletes the contact with `id`, failing with [`RepoError::NotFound`]
+    /// if there is none.
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError>;
+
+    /// Starts a transaction, whose changes become visible to the repo's
+    /// readers when it is committed.
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError>;
+
+    /// Replaces all contacts with `contacts`, keeping their ids, e.g. to
+    /// restore a backup. If one of them is rejected, nothing is changed.
+    async fn replace_all(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let mut tx = self.begin().await?;
+        let replaced = async {
+            for contact in tx.all().await? {
+                tx.delete(contact).await?;
+            }
+            for contact in contacts {
+                tx.save(contact).await?;
+            }
+            Ok(())
+        };
+        let replaced = replaced.await;
+        finish(tx, replaced).await
+    }
+
+    /// Adds or replaces all of `contacts` in one transaction, so they are
+    /// written at once, each as its next version. If one of them is
+    /// rejected, nothing is changed.
+    async fn save_many(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let mut tx = self.begin().await?;
+        let saved = async {
+            for mut contact in contacts {
+                contact.bump();
+                tx.save(contact).await?;
+            }
+            Ok(())
+        };
+        let saved = saved.await;
+        finish(tx, saved).await
+    }
+
+    /// Adds `contacts` in one transaction, like [`Self::create`] each, and
+    /// returns their ids in order. If any of them is rejected, nothing is
+    /// changed and the error says why each rejected one was.
+    async fn create_many(&self, contacts: Vec<Contact>) -> Result<Vec<u64>, BatchError> {
+        let mut tx = self.begin().await?;
+        let mut ids = Vec::with_capacity(contacts.len());
+        let mut rejected = BTreeMap::new();
+        for (index, mut contact) in contacts.into_iter().enumerate() {
+            contact.bump();
+            match tx.save(contact).await {
+                Ok(id) => ids.push(id),
+                Err(err) => reject(&mut rejected, index, err)?,
+            }
+        }
+        finish_batch(tx, rejected).await?;
+        Ok(ids)
+    }
+
+    /// Replaces the contacts with the ids of `contacts` in one transaction,
+    /// like [`Self::update`] each and failing like it. If any of them is
+    /// rejected, nothing is changed and the error says why each rejected one
+    /// was.
+    async fn update_many(&self, contacts: Vec<Contact>) -> Result<(), BatchError> {
+        let mut tx = self.begin().await?;
+        let mut rejected = BTreeMap::new();
+        for (index, mut contact) in contacts.into_iter().enumerate() {
+            let id = contact.id.expect("an updated contact to have an id");
+            let updated = match tx.find(id).await {
+                Ok(None) => Err(RepoError::NotFound(id)),
+                Ok(Some(current)) if current.version != contact.version => {
+                    Err(RepoError::stale(contact))
+                }
+                Ok(Some(_)) => {
+                    contact.bump();
+                    tx.save(contact).await.map(|_| ())
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = updated {
+                reject(&mut rejected, index, err)?;
+            }
+        }
+        finish_batch(tx, rejected).await
+    }
+
+    /// Deletes the contacts with `ids` in one transaction. If one of them
+    /// doesn't exist, nothing is deleted and the error is
+    /// [`RepoError::NotFound`].
+    async fn delete_many(&self, ids: &[u64]) -> Result<(), RepoError> {
+        let mut tx = self.begin().await?;
+        let deleted = async {
+            for id in ids {
+                let contact = tx.find(*id).await?.ok_or(RepoError::NotFound(*id))?;
+                tx.delete(contact).await?;
+            }
+            Ok(())
+        };
+        let deleted = deleted.await;
+        finish(tx, deleted).await
+    }
+
+    /// Page `number`, counted from 1, of all contacts in `sort` order, with
+    /// `size` contacts per page.
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        Page::new(
+            self.all(sort)
+                .await
+                .into_iter()
+                .skip(page_offset(number, size))
+                .take(size)
+                .collect(),
+            number,
+            size,
+            self.count(None).await,
+        )
+    }
+
+    /// Up to `limit` contacts ordered by id, starting after `cursor` or at
+    /// the first contact. Unlike [`Self::page`], a listing paged this way
+    /// neither skips nor repeats contacts when others are added or deleted.
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        CursorPage::of(self.all(Sort::default()).await.iter(), cursor, limit)
+    }
+
+    /// All contacts ordered by id, read as they are consumed rather than
+    /// all at once. By default they are read [`STREAM_BATCH`] at a time
+    /// with [`Self::all_after`].
+    fn stream_all(&self) -> BoxStream<'_, Contact>
+    where
+        Self: Sync,
+    {
+        stream::unfold(Some(None), move |cursor| async move {
+            let listed = self.all_after(cursor?, STREAM_BATCH).await;
+            Some((stream::iter(listed.items), listed.next.map(Some)))
+        })
+        .flatten()
+        .boxed()
+    }
+
+    /// What changed after `since`, oldest first: contacts created or
+    /// updated, and contacts deleted, as the repo keeps a tombstone for each
+    /// deleted contact. Contacts last saved before they had timestamps are
+    /// left out.
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange>;
+
+    /// Receives a [`ContactEvent`] for each change made through the repo
+    /// from now on, if it sends them, see [`EventedRepo`]. Repos noticing
+    /// changes made to their storage by anything else, such as a
+    /// [watched](MemContactRepo::with_file_watch) snapshot, send those.
+    fn subscribe(&self) -> Option<broadcast::Receiver<ContactEvent>> {
+        None
+    }
+
+    /// Persists writes the repo has buffered, if any.
+    async fn flush(&self) {}
+
+    /// Number of contacts and, as far as the repo can tell, how they are
+    /// stored.
+    async fn stats(&self) -> RepoStats {
+        RepoStats {
+            count: self.count(None).await,
+            ..RepoStats::default()
+        }
+    }
+}
+
+/// What [`ContactRepo::upsert_by_email`] did, with the contact's id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "action", content = "id", rename_all = "lowercase")]
+pub enum Upserted {
+    Created(u64),
+    Updated(u64),
+}
+
+/// Attempts of [`ContactRepo::upsert_by_email`] at writing a contact.
+pub const UPSERT_ATTEMPTS: usize = 3;
+
+/// Contacts read at a time by [`ContactRepo::stream_all`].
+pub const STREAM_BATCH: usize = 100;
+
+pub type SharedContactRepo = Arc<dyn ContactRepo + Sync + Send>;
+
+/// Every tag of the `contacts` that aren't archived, with the number of
+/// them that have it.
+fn tag_counts<'a>(contacts: impl IntoIterator<Item = &'a Contact>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for tag in contacts
+        .into_iter()
+        .filter(|contact| !contact.archived)
+        .flat_map(Contact::tags)
+    {
+        *counts.entry(tag.clone()).or_default() += 1;
+    }
+    counts
+}
+
+/// The number of `contacts` that aren't archived with each
+/// [initial](Contact::initial) in `names` order, leaving out those none has.
+fn initial_counts<'a>(
+    contacts: impl IntoIterator<Item = &'a Contact>,
+    names: NameOrder,
+) -> BTreeMap<char, usize> {
+    let mut counts = BTreeMap::new();
+    for contact in contacts.into_iter().filter(|contact| !contact.archived) {
+        *counts.entry(contact.initial(names)).or_default() += 1;
+    }
+    counts
+}
+
+/// The number of `contacts` that aren't archived in each group, by group id.
+fn group_sizes<'a>(contacts: impl IntoIterator<Item = &'a Contact>) -> BTreeMap<u64, usize> {
+    let mut sizes = BTreeMap::new();
+    for &group in contacts
+        .into_iter()
+        .filter(|contact| !contact.archived)
+        .flat_map(Contact::groups)
+    {
+        *sizes.entry(group).or_default() += 1;
+    }
+    sizes
+}
+
+/// `at` as stored in the tombstones of the SQL backends, with a fixed number
+/// of digits so the text sorts like the time.
+fn sql_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Number of items before page `number`, counted from 1, of a listing with
+/// `size` items per page. It saturates for pages past any listing, which are
+/// empty, rather than overflowing.
+fn page_offset(number: usize, size: usize) -> usize {
+    number.saturating_sub(1).saturating_mul(size)
+}
+
+/// [`page_offset`] as the SQL backends bind it to `OFFSET`.
+fn sql_offset(number: usize, size: usize) -> i64 {
+    i64::try_from(page_offset(number, size)).unwrap_or(i64::MAX)
+}
+
+/// `ids` sorted, without duplicates.
+fn unique_ids(ids: &[u64]) -> Vec<u64> {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Commits `tx` if the changes made through it succeeded, and rolls it back
+/// otherwise.
+async fn finish(tx: BoxedTransaction, changed: Result<(), RepoError>) -> Result<(), RepoError> {
+    match changed {
+        Ok(()) => tx.commit().await,
+        Err(err) => {
+            tx.rollback().await;
+            Err(err)
+        }
+    }
+}
+
+/// Commits `tx` if none of the items of its batch was `rejected`, and rolls
+/// it back otherwise.
+async fn finish_batch(
+    tx: BoxedTransaction,
+    rejected: BTreeMap<usize, RepoError>,
+) -> Result<(), BatchError> {
+    if rejected.is_empty() {
+        return Ok(tx.commit().await?);
+    }
+    tx.rollback().await;
+    Err(BatchError::Rejected(rejected))
+}
+
+/// Records that the item at `index` of a batch was rejected with `err`, or
+/// fails the whole batch if the storage failed.
+fn reject(
+    rejected: &mut BTreeMap<usize, RepoError>,
+    index: usize,
+    err: RepoError,
+) -> Result<(), BatchError> {
+    match err {
+        RepoError::Io(_) => Err(BatchError::Failed(err)),
+        err => {
+            rejected.insert(index, err);
+            Ok(())
+        }
+    }
+}
+
+/// Which of [`ContactRepo::create`] and [`ContactRepo::update`] a backend
+/// sharing one code path for both is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SaveMode {
+    Create,
+    Update,
+}
+
+/// Why a [`ContactRepo`] operation failed.
+#[derive(Debug)]
+pub enum RepoError {
+    /// There is no contact with this id.
+    NotFound(u64),
+    /// There is no contact with this uuid.
+    UuidNotFound(Uuid),
+    /// The contact is invalid, its `errors` say why.
+    Validation(Box<Contact>),
+    /// The contact clashes with another one, e.g. by having its email, as
+    /// its `errors` say.
+    Conflict(Box<Contact>),
+    /// The storage failed.
+    Io(io::Error),
+}
+
+impl RepoError {
+    /// Wraps an error of the storage.
+    pub fn io(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self::Io(io::Error::other(err))
+    }
+
+    /// [`Self::Validation`] for a contact that failed [`Contact::validate`].
+    pub fn invalid(contact: Contact) -> Self {
+        Self::Validation(Box::new(contact))
+    }
+
+    /// [`Self::Conflict`] for a new contact whose id another contact has.
+    pub fn id_taken(mut contact: Contact) -> Self {
+        contact
+            .errors
+            .insert("id", "duplicate", "Contact Already Exists");
+        Self::Conflict(Box::new(contact))
+    }
+
+    /// [`Self::Conflict`] for an update of a contact that was changed
+    /// since the version it was edited from.
+    pub fn stale(mut contact: Contact) -> Self {
+        contact.errors.insert(
+            "version",
+            "stale",
+            "Contact Was Changed Meanwhile, Reload To See The Changes",
+        );
+        Self::Conflict(Box::new(contact))
+    }
+
+    /// [`Self::Conflict`] for a contact whose email another contact has.
+    pub fn email_taken(mut contact: Contact) -> Self {
+        contact
+            .errors
+            .insert("email", "duplicate", "Email Already Exists");
+        Self::Conflict(Box::new(contact))
+    }
+
+    /// The conflict of another contact having the phone number at `index`.
+    pub fn phone_taken(mut contact: Contact, index: usize) -> Self {
+        contact.errors.insert(
+            format!("phones.{index}"),
+            "duplicate",
+            "Phone Number Already Exists",
+        );
+        Self::Conflict(Box::new(contact))
+    }
+
+    /// The rejected contact, if the error is one the user can fix by
+    /// editing it.
+    pub fn into_contact(self) -> Result<Contact, Self> {
+        match self {
+            Self::Validation(contact) | Self::Conflict(contact) => Ok(*contact),
+            err => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "contact {id} not found"),
+            Self::UuidNotFound(uuid) => write!(f, "contact {uuid} not found"),
+            Self::Validation(contact) => {
+                write!(f, "{} is invalid: {}", subject(contact), contact.errors)
+            }
+            Self::Conflict(contact) => write!(
+                f,
+                "{} conflicts with another: {}",
+                subject(contact),
+                contact.errors
+            ),
+            Self::Io(err) => write!(f, "storage failed: {err}"),
+        }
+    }
+}
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
-pub struct Contact {
-    id: Option<u64>,
-    first: Option<String>,
-    last: Option<String>,
-    phone: Option<String>,
-    pub email: Option<String>,
-    #[serde(default)]
-    pub errors: HashMap<String, String>,
+/// How errors about `contact` name it: by id if it has one.
+fn subject(contact: &Contact) -> String {
+    match contact.id {
+        Some(id) => format!("contact {id}"),
+        None => "new contact".to_owned(),
+    }
 }
 
-impl Default for Contact {
-    fn default() -> Self {
-        Self {
-            id: None,
-            first: None,
-            last: None,
-            phone: None,
-            email: None,
-            errors: HashMap::default(),
+impl std::error::Error for RepoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
         }
     }
 }
 
-impl Contact {
-    pub fn new(
-        first: Option<String>,
-        last: Option<String>,
-        phone: Option<String>,
-        email: Option<String>,
-    ) -> Self {
-        Self {
-            first,
-            last,
-            phone,
-            email,
-            ..Default::default()
+impl From<io::Error> for RepoError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// How a repo's storage is doing, for operators.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct RepoStats {
+    pub count: usize,
+    /// When contacts were last written to storage, if the repo knows.
+    pub last_persisted: Option<DateTime<Utc>>,
+    /// Bytes the contacts take up in storage, if the repo knows.
+    pub storage_size: Option<u64>,
+    /// Calls made to the repo so far, if it is an [`InstrumentedRepo`].
+    pub calls: Vec<CallStats>,
+}
+
+impl RepoStats {
+    /// Stats of a repo stored in `files`, from their metadata. Files that
+    /// don't exist are skipped.
+    fn from_files(count: usize, files: &[&Path]) -> Self {
+        let mut stats = Self {
+            count,
+            ..Self::default()
+        };
+        for file in files {
+            let Ok(metadata) = fs::metadata(file) else {
+                continue;
+            };
+            *stats.storage_size.get_or_insert(0) += metadata.len();
+            if let Ok(modified) = metadata.modified() {
+                let modified = DateTime::<Utc>::from(modified);
+                stats.last_persisted = stats.last_persisted.max(Some(modified));
+            }
         }
+        stats
     }
+}
+
+/// One page of a listing, with what is needed to page through the rest.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Page<T> {
+    items: Vec<T>,
+    /// Counted from 1.
+    number: usize,
+    size: usize,
+    /// Number of items on all pages.
+    total: usize,
+    /// Number of pages, at least 1 even when there are no items.
+    pages: usize,
+}
 
-    pub fn validate(&mut self) -> bool {
-        if self.email.is_none() {
-            self.errors.insert("email".into(), "Email Required".into());
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, number: usize, size: usize, total: usize) -> Self {
+        Self {
+            items,
+            number,
+            size,
+            total,
+            pages: total.div_ceil(size.max(1)).max(1),
         }
-        if self.email.as_ref().is_some_and(|s| s.is_empty()) {
-            self.errors.insert("email".into(), "Email Required".into());
+    }
+
+    /// All of `items` on a single page.
+    pub fn single(items: Vec<T>) -> Self {
+        let total = items.len();
+        Self::new(items, 1, total, total)
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.number < self.pages
+    }
+}
+
+impl Page<Contact> {
+    /// Page `number` of `contacts`, in `sort` order.
+    pub fn of<'a>(
+        contacts: impl Iterator<Item = &'a Contact>,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Self {
+        let mut contacts: Vec<&Contact> = contacts.collect();
+        contacts.sort_unstable_by(|a, b| sort.compare(a, b));
+        let items = contacts
+            .iter()
+            .skip(page_offset(number, size))
+            .take(size)
+            .map(|contact| (*contact).clone())
+            .collect();
+        Self::new(items, number, size, contacts.len())
+    }
+}
+
+/// Where a listing paged with [`ContactRepo::all_after`] continues. It
+/// round-trips through URLs as an opaque token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    /// Id of the last contact listed so far.
+    after: u64,
+}
+
+impl Cursor {
+    pub fn after(id: u64) -> Self {
+        Self { after: id }
+    }
+
+    pub fn id(self) -> u64 {
+        self.after
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&URL_SAFE_NO_PAD.encode(self.after.to_be_bytes()))
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+            .ok_or_else(|| format!("invalid cursor '{token}'"))?;
+        Ok(Self::after(u64::from_be_bytes(bytes)))
+    }
+}
+
+impl serde::Serialize for Cursor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Cursor {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let token = String::deserialize(deserializer)?;
+        token.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Contacts listed with [`ContactRepo::all_after`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    /// Where the listing continues, `None` on the last page.
+    pub next: Option<Cursor>,
+}
+
+impl CursorPage<Contact> {
+    /// Builds a page from the contacts after the cursor, in id order, with
+    /// one more than `limit` to tell whether there is a next page.
+    fn from_sorted(mut contacts: Vec<Contact>, limit: usize) -> Self {
+        let next = if contacts.len() > limit {
+            contacts.truncate(limit);
+            contacts.last().and_then(Contact::id).map(Cursor::after)
+        } else {
+            None
+        };
+        Self {
+            items: contacts,
+            next,
         }
-        self.errors.len() == 0
     }
 
-    pub fn update(
-        &mut self,
-        first: Option<String>,
-        last: Option<String>,
-        phone: Option<String>,
-        email: Option<String>,
-    ) {
-        self.first = first;
-        self.last = last;
-        self.phone = phone;
-        self.email = email;
+    /// The contacts of `contacts`, in any order, following `cursor`.
+    fn of<'a>(
+        contacts: impl Iterator<Item = &'a Contact>,
+        cursor: Option<Cursor>,
+        limit: usize,
+    ) -> Self {
+        let after = cursor.map(Cursor::id);
+        let mut contacts: Vec<&Contact> = contacts
+            .filter(|contact| after.is_none() || contact.id > after)
+            .collect();
+        contacts.sort_unstable_by_key(|contact| contact.id);
+        let contacts = contacts.into_iter().take(limit + 1).cloned().collect();
+        Self::from_sorted(contacts, limit)
     }
 }
-#[async_trait::async_trait]
-pub trait ContactRepo {
-    async fn all(&self) -> Vec<Contact>;
-    async fn count(&self) -> usize;
-    async fn search(&self, query: &str) -> Vec<Contact>;
-    async fn save(&self, contact: Contact) -> Result<(), Contact>;
-    async fn find(&self, id: u64) -> Option<Contact>;
-    async fn delete(&self, contact: Contact);
+
+/// A change listed by [`ContactRepo::changed_since`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum ContactChange {
+    Created { contact: Contact },
+    Updated { contact: Contact },
+    Deleted { id: u64, deleted_at: DateTime<Utc> },
 }
 
-pub type SharedContactRepo = Arc<dyn ContactRepo + Sync + Send>;
+impl ContactChange {
+    /// When the change was made.
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            Self::Created { contact } | Self::Updated { contact } => {
+                contact.updated_at.unwrap_or_default()
+            }
+            Self::Deleted { deleted_at, .. } => *deleted_at,
+        }
+    }
 
-#[derive(Debug, Clone)]
+    /// The changes made after `since` among `contacts` and `tombstones`,
+    /// the ids of deleted contacts with when they were deleted, oldest first.
+    /// Tombstones of ids that were reused by one of `contacts` are skipped.
+    fn since(
+        contacts: impl IntoIterator<Item = Contact>,
+        tombstones: impl IntoIterator<Item = (u64, DateTime<Utc>)>,
+        since: DateTime<Utc>,
+    ) -> Vec<Self> {
+        let contacts: Vec<Contact> = contacts
+            .into_iter()
+            .filter(|contact| contact.updated_at.is_some_and(|at| at > since))
+            .collect();
+        let ids: HashSet<u64> = contacts.iter().filter_map(Contact::id).collect();
+        let saved = contacts.into_iter().map(|contact| {
+            if contact.created_at.is_some_and(|at| at > since) {
+                Self::Created { contact }
+            } else {
+                Self::Updated { contact }
+            }
+        });
+        let deleted = tombstones
+            .into_iter()
+            .filter(|(id, deleted_at)| *deleted_at > since && !ids.contains(id))
+            .map(|(id, deleted_at)| Self::Deleted { id, deleted_at });
+        let mut changes: Vec<Self> = saved.chain(deleted).collect();
+        changes.sort_by_key(|change| (change.at(), change.id()));
+        changes
+    }
+
+    fn id(&self) -> u64 {
+        match self {
+            Self::Created { contact } | Self::Updated { contact } => contact.id.unwrap_or_default(),
+            Self::Deleted { id, .. } => *id,
+        }
+    }
+}
+
+/// Contact repository holding all contacts in memory.
+///
+/// When loaded from a path, the file there is a snapshot of the store and
+/// the [`Journal`] next to it (`<path>.journal`) is a write-ahead log: every
+/// mutation is appended to it and synced to disk before it is applied. The
+/// journal is replayed on load and folded into a new snapshot once it grows
+/// past [`COMPACT_AFTER`] entries, on startup and on [`ContactRepo::flush`].
+#[derive(Debug, Clone, Default)]
 pub struct MemContactRepo {
     path: Option<PathBuf>,
     store: Arc<RwLock<ContactStore>>,
+    options: StorageOptions,
+    /// Held while applying a mutation, so mutations are applied in journal
+    /// order. Also serializes writers of the snapshot.
+    journal: Arc<Mutex<Option<Journal>>>,
+    flush_policy: Option<FlushPolicy>,
+    mutated: Arc<Notify>,
+    /// Advisory lock on `<path>.lock`, held for as long as the repo lives.
+    _lock: Option<Arc<fs::File>>,
+    /// [`digest`] of the snapshot as last read or written by the repo, to
+    /// tell external changes from our own.
+    snapshot_digest: Arc<AtomicU64>,
+    /// Shared by all clones, as the background tasks run on clones.
+    watch: Arc<std_sync::OnceLock<watch::FileWatch>>,
+    /// Held by `save`, `delete` and for the whole of a transaction.
+    writer: Arc<Mutex<()>>,
+}
+
+/// How a [`MemContactRepo`] writes its snapshot and journal, and a
+/// [`CsvContactRepo`] its names.
+#[derive(Debug, Clone, Default)]
+pub struct StorageOptions {
+    pub format: SnapshotFormat,
+    /// Encrypts both files when set.
+    pub key: Option<StoreKey>,
+    /// The order of the `name` column of CSV files.
+    pub names: NameOrder,
+}
+
+/// How a snapshot file was written, as found by [`ContactStore::parse`].
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotInfo {
+    pub format: SnapshotFormat,
+    pub encrypted: bool,
+    pub schema_version: u32,
+}
+
+/// Number of journal entries that triggers writing a new snapshot.
+pub const COMPACT_AFTER: usize = 1000;
+
+/// When a [`MemContactRepo`] with background flushing folds its journal
+/// into a new snapshot.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushPolicy {
+    /// Longest time a mutation stays only in the journal.
+    pub interval: Duration,
+    /// Number of journal entries that triggers a snapshot right away.
+    pub max_entries: usize,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            max_entries: 100,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ContactStore {
     contacts: HashMap<u64, Contact>,
+    /// When each deleted contact was deleted, by id.
+    tombstones: HashMap<u64, DateTime<Utc>>,
 }
 
 impl ContactStore {
     pub fn new() -> Self {
         Self {
             contacts: HashMap::new(),
+            tombstones: HashMap::new(),
         }
     }
 
     pub fn from_path(path: &str) -> Self {
-        let file = fs::File::open(path).expect("a valid path");
-        let reader = io::BufReader::new(file);
-        let contact_list: Vec<Contact> = serde_json::from_reader(reader).expect("valid JSON");
+        Self::load(Path::new(path), None).0
+    }
+
+    /// Reads the snapshot at `path`, decrypting it with `key` if it is
+    /// encrypted and upgrading it to the current [`SCHEMA_VERSION`]. Also
+    /// returns how the snapshot was written.
+    pub fn load(path: &Path, key: Option<&StoreKey>) -> (Self, SnapshotInfo) {
+        let data = fs::read(path).expect("a valid path");
+        Self::parse(data, key).expect("a readable store")
+    }
+
+    /// Like [`Self::load`], from the contents of a snapshot file.
+    pub fn parse(mut data: Vec<u8>, key: Option<&StoreKey>) -> io::Result<(Self, SnapshotInfo)> {
+        let encrypted = crypto::is_sealed(&data);
+        if encrypted {
+            let key = key.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "store is encrypted, but no key is configured",
+                )
+            })?;
+            data = key.open(&data)?;
+        }
+        let format = SnapshotFormat::detect(&data);
+        let mut doc = format.decode(&data)?;
+        let tombstones: Vec<Tombstone> = match doc.as_object_mut() {
+            Some(doc) => match doc.remove("tombstones") {
+                Some(tombstones) => serde_json::from_value(tombstones)?,
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+        let (schema_version, values) = migrate::split_snapshot(doc)?;
         let mut contacts = HashMap::new();
-        for contact in contact_list {
-            contacts.insert(contact.id.unwrap(), contact);
+        for mut value in values {
+            migrate::upgrade_contact(&mut value, schema_version);
+            let contact: Contact = serde_json::from_value(value)?;
+            let id = contact.id.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "store has a contact without id")
+            })?;
+            contacts.insert(id, contact);
+        }
+        let info = SnapshotInfo {
+            format,
+            encrypted,
+            schema_version,
+        };
+        let tombstones = tombstones
+            .into_iter()
+            .map(|tombstone| (tombstone.id, tombstone.deleted_at))
+            .collect();
+        Ok((
+            Self {
+                contacts,
+                tombstones,
+            },
+            info,
+        ))
+    }
+
+    pub fn into_contacts(self) -> Vec<Contact> {
+        self.contacts.into_values().collect()
+    }
+
+    pub fn apply(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Put { contact } => {
+                let id = contact.id.unwrap();
+                self.tombstones.remove(&id);
+                self.contacts.insert(id, *contact);
+            }
+            JournalEntry::Delete { id, deleted_at } => {
+                self.contacts.remove(&id);
+                if let Some(deleted_at) = deleted_at {
+                    self.tombstones.insert(id, deleted_at);
+                }
+            }
+            JournalEntry::Batch { entries } => {
+                for entry in entries {
+                    self.apply(entry);
+                }
+            }
+        }
+    }
+
+    fn snapshot(&self, options: &StorageOptions) -> Vec<u8> {
+        let data = options.format.encode(&Snapshot {
+            schema_version: SCHEMA_VERSION,
+            contacts: self.contacts.values().collect(),
+            tombstones: self
+                .tombstones
+                .iter()
+                .map(|(&id, &deleted_at)| Tombstone { id, deleted_at })
+                .collect(),
+        });
+        match &options.key {
+            None => data,
+            Some(key) => key.seal(&data),
         }
-        Self { contacts }
     }
 }
 
-const PAGE_SIZE: usize = 10;
+pub const PAGE_SIZE: usize = 10;
 
 impl MemContactRepo {
     pub fn new() -> Self {
         Self {
             path: None,
             store: Arc::new(RwLock::new(ContactStore::new())),
+            ..Default::default()
         }
     }
 
     pub fn from_path(path: &str) -> Self {
-        let store = ContactStore::from_path(path); //.expect("valid JSON");
+        Self::from_path_with(path, StorageOptions::default())
+    }
+
+    /// Loads the repo from `path` like [`Self::from_path`], writing files
+    /// according to `options` from now on. Files written with other options
+    /// are converted.
+    ///
+    /// Panics if another repo, in this or another process, has `path` open.
+    pub fn from_path_with(path: &str, options: StorageOptions) -> Self {
+        let path = PathBuf::from(path);
+        let lock = lock_file(&path);
+        let data = fs::read(&path).expect("a valid path");
+        let mut snapshot_digest = digest(&data);
+        let (mut store, found) =
+            ContactStore::parse(data, options.key.as_ref()).expect("a readable store");
+        let (mut journal, entries) = Journal::open(
+            &journal_path(&path),
+            options.key.clone(),
+            found.schema_version,
+        )
+        .expect("a readable journal");
+        let converting = found.format != options.format
+            || found.encrypted != options.key.is_some()
+            || found.schema_version != SCHEMA_VERSION;
+        if !entries.is_empty() || converting {
+            for entry in entries {
+                store.apply(entry);
+            }
+            let data = store.snapshot(&options);
+            write_atomic(&path, &data).expect("writing succeed");
+            snapshot_digest = digest(&data);
+            journal.clear().expect("writing succeed");
+        }
         Self {
-            path: Some(path.into()),
+            path: Some(path),
             store: Arc::new(RwLock::new(store)),
+            options,
+            journal: Arc::new(Mutex::new(Some(journal))),
+            _lock: Some(Arc::new(lock)),
+            snapshot_digest: Arc::new(AtomicU64::new(snapshot_digest)),
+            ..Default::default()
         }
     }
 
@@ -137,117 +1858,467 @@ impl MemContactRepo {
     pub fn shared_from_path(path: &str) -> SharedContactRepo {
         Arc::new(Self::from_path(path))
     }
+
+    /// Moves writing snapshots into a background task that coalesces
+    /// mutations according to `policy`, instead of only compacting the
+    /// journal once it is large. Mutations are still journaled right away.
+    ///
+    /// Must be called from within a tokio runtime. Call
+    /// [`ContactRepo::flush`] on shutdown to fold the last mutations into
+    /// the snapshot.
+    pub fn with_background_flush(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = Some(policy);
+        tokio::spawn(self.clone().flush_loop(policy));
+        self
+    }
 }
 
 impl MemContactRepo {
-    async fn validate(&self, contact: &mut Contact) -> bool {
+    async fn validate(&self, mut contact: Contact) -> Result<Contact, RepoError> {
         if !contact.validate() {
-            return false;
+            return Err(RepoError::invalid(contact));
         }
-        let contact_email = contact.email.as_ref().unwrap();
-        for cont in self.search(contact_email).await {
-            if contact.id != cont.id && cont.email.as_ref().unwrap() == contact_email {
-                contact
-                    .errors
-                    .insert("email".into(), "Email Already Exists".into());
-                return false;
-            }
+        if unique_phones() {
+            let store = self.store.read().await;
+            contact = phones::check_phones(contact, store.contacts.values())?;
+        }
+        let Some(email) = contact.email.as_deref().filter(|_| unique_emails()) else {
+            return Ok(contact);
+        };
+        let owner = self.find_by_email(email).await?;
+        if owner.is_some_and(|owner| owner.id != contact.id) {
+            return Err(RepoError::email_taken(contact));
         }
-        true
+        Ok(contact)
     }
 
+    /// The highest id of a contact or of a deleted one, whose ids aren't
+    /// reused so that sync clients don't take a new contact for it.
     async fn max_id(&self) -> u64 {
-        self.store
-            .read()
-            .await
+        let store = self.store.read().await;
+        store
             .contacts
             .keys()
+            .chain(store.tombstones.keys())
             .max()
             .cloned()
             .unwrap_or(1)
     }
 
-    async fn save_db(&self) {
-        let path = self
-            .path
-            .as_ref()
-            .map(|p| p.as_path())
-            .unwrap_or_else(|| Path::new("contacts.json"));
-        let file = fs::File::create(path).expect("file exist");
-        let writer = io::BufWriter::new(file);
+    /// Applies `entry` to the store, after appending it to the journal and
+    /// syncing that to disk if the repo has one.
+    async fn log_and_apply(&self, entry: JournalEntry) -> io::Result<()> {
+        let mut journal = self.journal.lock().await;
+        if let Some(journal) = journal.as_mut() {
+            journal.append(std::slice::from_ref(&entry))?;
+        }
+        self.store.write().await.apply(entry);
+        match (&self.flush_policy, journal.as_mut()) {
+            (Some(_), _) => self.mutated.notify_one(),
+            (None, Some(journal)) if journal.len() >= COMPACT_AFTER => {
+                let path = self.path.as_deref().unwrap();
+                self.compact(path, journal).await;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-        let store = self.store.read().await;
-        let contacts: Vec<&Contact> = store.contacts.values().collect();
-        serde_json::to_writer(writer, &contacts).expect("writing succeed");
+    async fn journal_len(&self) -> usize {
+        self.journal.lock().await.as_ref().map_or(0, Journal::len)
+    }
+
+    async fn flush_loop(self, policy: FlushPolicy) {
+        loop {
+            self.mutated.notified().await;
+            let burst = async {
+                while self.journal_len().await < policy.max_entries {
+                    self.mutated.notified().await;
+                }
+            };
+            let _ = tokio::time::timeout(policy.interval, burst).await;
+            self.flush().await;
+        }
+    }
+
+    async fn compact(&self, path: &Path, journal: &mut Journal) {
+        self.write_snapshot(path, &*self.store.read().await);
+        journal.clear().expect("writing succeed");
+    }
+
+    fn write_snapshot(&self, path: &Path, store: &ContactStore) {
+        let data = store.snapshot(&self.options);
+        write_atomic(path, &data).expect("writing succeed");
+        self.snapshot_digest.store(digest(&data), Ordering::SeqCst);
+        if let Some(watch) = self.watch.get() {
+            watch.set_base(store);
+        }
+    }
+}
+
+/// Cheap fingerprint of a snapshot's contents.
+fn digest(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+fn lock_file(path: &Path) -> fs::File {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .expect("lock file to open");
+    match lock.try_lock() {
+        Ok(()) => lock,
+        Err(fs::TryLockError::WouldBlock) => {
+            panic!("{} is in use by another instance", path.display())
+        }
+        Err(fs::TryLockError::Error(err)) => panic!("locking failed: {err}"),
     }
 }
 
+fn journal_path(path: &Path) -> PathBuf {
+    let mut journal_path = path.as_os_str().to_owned();
+    journal_path.push(".journal");
+    journal_path.into()
+}
+
 #[async_trait::async_trait]
 impl ContactRepo for MemContactRepo {
-    async fn all(&self) -> Vec<Contact> {
-        self.store.read().await.contacts.values().cloned().collect()
-    }
-
-    async fn count(&self) -> usize {
-        self.store.read().await.contacts.len()
-    }
-    async fn search(&self, query: &str) -> Vec<Contact> {
-        let mut result = Vec::new();
-        for contact in self.store.read().await.contacts.values() {
-            let match_first = contact
-                .first
-                .as_ref()
-                .map(|s| s.contains(query))
-                .unwrap_or(false);
-            let match_last = contact
-                .last
-                .as_ref()
-                .map(|s| s.contains(query))
-                .unwrap_or(false);
-            let match_phone = contact
-                .phone
-                .as_ref()
-                .map(|s| s.contains(query))
-                .unwrap_or(false);
-            let match_email = contact
-                .email
-                .as_ref()
-                .map(|s| s.contains(query))
-                .unwrap_or(false);
-            if match_first || match_last || match_phone || match_email {
-                result.push(contact.clone());
-            }
-        }
-        result
-    }
-
-    async fn save(&self, mut contact: Contact) -> Result<(), Contact> {
-        if !self.validate(&mut contact).await {
-            return Err(contact);
-        }
-        if contact.id.is_none() {
-            let max_id = self.max_id().await;
-            contact.id = Some(max_id + 1);
-        }
-        self.store
-            .write()
-            .await
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let mut contacts: Vec<Contact> =
+            self.store.read().await.contacts.values().cloned().collect();
+        sort.apply(&mut contacts);
+        contacts
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        let store = self.store.read().await;
+        match filter.map(SearchQuery::parse).as_ref() {
+            None => store.contacts.len(),
+            Some(query) => store.contacts.values().filter(|c| c.matches(query)).count(),
+        }
+    }
+
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        Page::of(
+            self.store.read().await.contacts.values(),
+            sort,
+            number,
+            size,
+        )
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        CursorPage::of(self.store.read().await.contacts.values(), cursor, limit)
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let store = self.store.read().await;
+        let matches = store
             .contacts
-            .insert(contact.id.unwrap(), contact);
-        self.save_db().await;
+            .values()
+            .filter(|contact| contact.matches(&query));
+        Page::of(matches, sort, number, size)
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        let _writer = self.writer.lock().await;
+        let mut contact = self.validate(contact).await?;
+        let id = match contact.id {
+            Some(id) if self.store.read().await.contacts.contains_key(&id) => {
+                return Err(RepoError::id_taken(contact));
+            }
+            Some(id) => id,
+            None => self.max_id().await + 1,
+        };
+        contact.id = Some(id);
+        contact.bump();
+        self.log_and_apply(JournalEntry::Put {
+            contact: Box::new(contact),
+        })
+        .await?;
+        Ok(id)
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        let _writer = self.writer.lock().await;
+        let id = contact.id.expect("an updated contact to have an id");
+        match self.store.read().await.contacts.get(&id) {
+            None => return Err(RepoError::NotFound(id)),
+            Some(current) if current.version != contact.version => {
+                return Err(RepoError::stale(contact));
+            }
+            Some(_) => {}
+        }
+        let mut contact = self.validate(contact).await?;
+        contact.bump();
+        self.log_and_apply(JournalEntry::Put {
+            contact: Box::new(contact),
+        })
+        .await?;
         Ok(())
     }
 
-    async fn find(&self, id: u64) -> Option<Contact> {
-        self.store.read().await.contacts.get(&id).cloned()
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        Ok(self.store.read().await.contacts.get(&id).cloned())
     }
 
-    async fn delete(&self, contact: Contact) {
-        self.store
-            .write()
-            .await
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        let store = self.store.read().await;
+        Ok(unique_ids(ids)
+            .into_iter()
+            .filter_map(|id| store.contacts.get(&id).cloned())
+            .collect())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .contacts
+            .values()
+            .find(|contact| contact.email.as_deref() == Some(email))
+            .cloned())
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let _writer = self.writer.lock().await;
+        if !self.store.read().await.contacts.contains_key(&id) {
+            return Err(RepoError::NotFound(id));
+        }
+        let deleted_at = Some(Utc::now());
+        self.log_and_apply(JournalEntry::Delete { id, deleted_at })
+            .await?;
+        Ok(())
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let store = self.store.read().await;
+        ContactChange::since(
+            store.contacts.values().cloned(),
+            store.tombstones.iter().map(|(&id, &at)| (id, at)),
+            since,
+        )
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        let writer = self.writer.clone().lock_owned().await;
+        Ok(Box::new(StagedTransaction::new(self.clone(), Some(writer))))
+    }
+
+    async fn replace_all(&self, mut contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let mut emails = HashSet::new();
+        for contact in &mut contacts {
+            if !contact.validate() {
+                return Err(RepoError::invalid(contact.clone()));
+            }
+            let unique = unique_emails() && contact.email.is_some();
+            if unique && !emails.insert(contact.email.clone()) {
+                return Err(RepoError::email_taken(contact.clone()));
+            }
+        }
+
+        let _writer = self.writer.lock().await;
+        let mut journal = self.journal.lock().await;
+        let mut store = self.store.write().await;
+        // Above every id ever given, deleted ones included, so none is
+        // given to another contact.
+        let max_id = contacts
+            .iter()
+            .filter_map(Contact::id)
+            .chain(store.contacts.keys().copied())
+            .chain(store.tombstones.keys().copied())
+            .max();
+        let mut next_id = max_id.unwrap_or(1) + 1;
+        let mut replacement = ContactStore::new();
+        for mut contact in contacts {
+            let id = *contact.id.get_or_insert_with(|| {
+                next_id += 1;
+                next_id - 1
+            });
+            replacement.contacts.insert(id, contact);
+        }
+        let deleted_at = Utc::now();
+        replacement.tombstones = std::mem::take(&mut store.tombstones);
+        for id in store.contacts.keys() {
+            if !replacement.contacts.contains_key(id) {
+                replacement.tombstones.insert(*id, deleted_at);
+            }
+        }
+        for id in replacement.contacts.keys() {
+            replacement.tombstones.remove(id);
+        }
+        *store = replacement;
+        if let (Some(path), Some(journal)) = (&self.path, journal.as_mut()) {
+            self.write_snapshot(path, &store);
+            journal.clear()?;
+        }
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ContactEvent>> {
+        self.watch.get().map(watch::FileWatch::subscribe)
+    }
+
+    async fn flush(&self) {
+        let mut journal = self.journal.lock().await;
+        let Some(journal) = journal.as_mut() else {
+            return;
+        };
+        if !journal.is_empty() {
+            let path = self.path.as_deref().unwrap();
+            self.compact(path, journal).await;
+        }
+    }
+
+    async fn stats(&self) -> RepoStats {
+        let count = self.count(None).await;
+        match &self.path {
+            Some(path) => RepoStats::from_files(count, &[path, &journal_path(path)]),
+            None => RepoStats {
+                count,
+                ..RepoStats::default()
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Stage for MemContactRepo {
+    async fn email_owner(&self, email: &str) -> Result<Option<u64>, RepoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .contacts
+            .values()
+            .find(|contact| contact.email.as_deref() == Some(email))
+            .and_then(|contact| contact.id))
+    }
+
+    async fn next_id(&self, changes: &Changes) -> Result<u64, RepoError> {
+        let store = self.store.read().await;
+        let max_id = store
             .contacts
-            .remove(contact.id.as_ref().unwrap());
-        self.save_db().await;
+            .keys()
+            .chain(store.tombstones.keys())
+            .chain(changes.keys())
+            .max();
+        Ok(max_id.cloned().unwrap_or(1) + 1)
+    }
+
+    async fn apply(&self, changes: &Changes) -> Result<(), RepoError> {
+        // The transaction holds the writer lock, so nothing can conflict.
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let deleted_at = Some(Utc::now());
+        let entries = changes
+            .iter()
+            .map(|(id, change)| match change {
+                Some(contact) => JournalEntry::Put {
+                    contact: Box::new(contact.clone()),
+                },
+                None => JournalEntry::Delete {
+                    id: *id,
+                    deleted_at,
+                },
+            })
+            .collect();
+        // One journal line, so a crash can't leave half of it applied.
+        self.log_and_apply(JournalEntry::Batch { entries }).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn merged(mut target: serde_json::Value, patch: serde_json::Value) -> serde_json::Value {
+        merge_patch(&mut target, patch);
+        target
+    }
+
+    #[test]
+    fn null_removes_a_key() {
+        assert_eq!(
+            merged(json!({ "a": 1, "b": 2 }), json!({ "a": null, "c": 3 })),
+            json!({ "b": 2, "c": 3 })
+        );
+        assert_eq!(
+            merged(json!({ "b": 2 }), json!({ "a": null })),
+            json!({ "b": 2 })
+        );
+    }
+
+    #[test]
+    fn objects_are_merged_deeply() {
+        assert_eq!(
+            merged(
+                json!({ "a": { "b": 1, "c": 2 } }),
+                json!({ "a": { "b": null, "d": { "e": 3 } } })
+            ),
+            json!({ "a": { "c": 2, "d": { "e": 3 } } })
+        );
+    }
+
+    #[test]
+    fn other_values_are_replaced() {
+        assert_eq!(
+            merged(json!({ "a": [1, 2] }), json!({ "a": [3] })),
+            json!({ "a": [3] })
+        );
+        assert_eq!(merged(json!({ "a": 1 }), json!(["a"])), json!(["a"]));
+        assert_eq!(merged(json!("a"), json!({ "b": 1 })), json!({ "b": 1 }));
+    }
+
+    #[test]
+    fn merging_null_clears_a_field() {
+        let mut contact = Contact {
+            first: Some("Ann".to_owned()),
+            nickname: Some("Annie".to_owned()),
+            ..Default::default()
+        };
+        contact
+            .merge(json!({ "nickname": null, "last": "Lee" }))
+            .unwrap();
+        assert_eq!(contact.first.as_deref(), Some("Ann"));
+        assert_eq!(contact.last.as_deref(), Some("Lee"));
+        assert_eq!(contact.nickname, None);
+    }
+
+    fn reachable(first: &str) -> Contact {
+        Contact {
+            first: Some(first.to_owned()),
+            email: Some(format!("{}@example.com", first.to_lowercase())),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn restores_never_reuse_deleted_ids() {
+        let repo = MemContactRepo::new();
+        let kept = repo.create(reachable("Ann")).await.unwrap();
+        let deleted = repo.create(reachable("Bob")).await.unwrap();
+        let replaced = repo.create(reachable("Cid")).await.unwrap();
+        repo.delete_by_id(deleted).await.unwrap();
+        let kept = repo.find(kept).await.unwrap().unwrap();
+        repo.replace_all(vec![kept, reachable("Dan")])
+            .await
+            .unwrap();
+        let ids: Vec<u64> = repo
+            .all(Sort::default())
+            .await
+            .iter()
+            .filter_map(Contact::id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.iter().all(|&id| id != deleted && id != replaced));
     }
 }
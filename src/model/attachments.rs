@@ -0,0 +1,175 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use super::{write_atomic, Contact};
+
+/// Largest attachment accepted, in bytes.
+pub const MAX_ATTACHMENT_SIZE: usize = 10 * 1024 * 1024;
+
+/// A file attached to a contact, such as a business card or a contract,
+/// as recorded on the contact. The file itself is kept in an
+/// [`AttachmentRepo`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Attachment {
+    pub id: Uuid,
+    /// The name of the file as uploaded, without any directories.
+    pub name: String,
+    /// The media type given with the upload.
+    pub content_type: String,
+    /// In bytes.
+    pub size: u64,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+impl Attachment {
+    /// A new attachment of `size` bytes uploaded as `name`, keeping only its
+    /// last path component.
+    pub fn new(name: &str, content_type: Option<&str>, size: u64) -> Self {
+        let name = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+        Self {
+            id: Uuid::now_v7(),
+            name: if name.is_empty() { "attachment" } else { name }.to_owned(),
+            content_type: content_type
+                .filter(|content_type| !content_type.is_empty())
+                .unwrap_or("application/octet-stream")
+                .to_owned(),
+            size,
+            uploaded_at: Utc::now(),
+        }
+    }
+}
+
+impl Contact {
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+
+    pub fn attachment(&self, id: Uuid) -> Option<&Attachment> {
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.id == id)
+    }
+
+    pub fn add_attachment(&mut self, attachment: Attachment) {
+        self.attachments.push(attachment);
+    }
+
+    /// Removes the attachment with `id`, returning it if there was one.
+    pub fn remove_attachment(&mut self, id: Uuid) -> Option<Attachment> {
+        let index = self
+            .attachments
+            .iter()
+            .position(|attachment| attachment.id == id)?;
+        Some(self.attachments.remove(index))
+    }
+}
+
+/// Where the files of contacts' attachments are kept, by contact id and
+/// [`Attachment::id`].
+#[async_trait::async_trait]
+pub trait AttachmentRepo {
+    async fn find(&self, contact_id: u64, id: Uuid) -> io::Result<Option<Vec<u8>>>;
+    async fn save(&self, contact_id: u64, id: Uuid, data: Vec<u8>) -> io::Result<()>;
+    /// Deletes the file of the attachment, if there is one.
+    async fn delete(&self, contact_id: u64, id: Uuid) -> io::Result<()>;
+    /// Deletes the files of all the contact's attachments, once it is
+    /// deleted.
+    async fn delete_all(&self, contact_id: u64) -> io::Result<()>;
+}
+
+pub type SharedAttachmentRepo = Arc<dyn AttachmentRepo + Sync + Send>;
+
+/// Attachment repository kept in memory, for `memory://` storage.
+#[derive(Debug, Default)]
+pub struct MemAttachmentRepo {
+    files: RwLock<HashMap<(u64, Uuid), Vec<u8>>>,
+}
+
+impl MemAttachmentRepo {
+    pub fn new_shared() -> SharedAttachmentRepo {
+        Arc::new(Self::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl AttachmentRepo for MemAttachmentRepo {
+    async fn find(&self, contact_id: u64, id: Uuid) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.files.read().await.get(&(contact_id, id)).cloned())
+    }
+
+    async fn save(&self, contact_id: u64, id: Uuid, data: Vec<u8>) -> io::Result<()> {
+        self.files.write().await.insert((contact_id, id), data);
+        Ok(())
+    }
+
+    async fn delete(&self, contact_id: u64, id: Uuid) -> io::Result<()> {
+        self.files.write().await.remove(&(contact_id, id));
+        Ok(())
+    }
+
+    async fn delete_all(&self, contact_id: u64) -> io::Result<()> {
+        let mut files = self.files.write().await;
+        files.retain(|(contact, _), _| *contact != contact_id);
+        Ok(())
+    }
+}
+
+/// Keeps attachments as files in a directory per contact id under a media
+/// directory, named by their id rather than as uploaded.
+#[derive(Debug, Clone)]
+pub struct DirAttachmentRepo {
+    dir: PathBuf,
+}
+
+impl DirAttachmentRepo {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn shared(dir: impl Into<PathBuf>) -> SharedAttachmentRepo {
+        Arc::new(Self::new(dir))
+    }
+
+    fn contact_dir(&self, contact_id: u64) -> PathBuf {
+        self.dir.join(contact_id.to_string())
+    }
+}
+
+/// `result`, with a file or directory that isn't there counting as
+/// removed.
+fn removed(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait::async_trait]
+impl AttachmentRepo for DirAttachmentRepo {
+    async fn find(&self, contact_id: u64, id: Uuid) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.contact_dir(contact_id).join(id.to_string())) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn save(&self, contact_id: u64, id: Uuid, data: Vec<u8>) -> io::Result<()> {
+        let dir = self.contact_dir(contact_id);
+        fs::create_dir_all(&dir)?;
+        write_atomic(&dir.join(id.to_string()), &data)
+    }
+
+    async fn delete(&self, contact_id: u64, id: Uuid) -> io::Result<()> {
+        removed(fs::remove_file(
+            self.contact_dir(contact_id).join(id.to_string()),
+        ))
+    }
+
+    async fn delete_all(&self, contact_id: u64) -> io::Result<()> {
+        removed(fs::remove_dir_all(self.contact_dir(contact_id)))
+    }
+}
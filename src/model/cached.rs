@@ -0,0 +1,317 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::{broadcast, RwLock, RwLockReadGuard};
+use uuid::Uuid;
+
+use super::{
+    group_sizes, initial_counts, tag_counts, unique_ids, BoxedTransaction, Contact, ContactChange,
+    ContactEvent, ContactFilter, ContactRepo, ContactTransaction, Cursor, CursorPage, NameOrder,
+    Page, RepoError, RepoStats, SearchQuery, SharedContactRepo, Sort,
+};
+
+/// Serves reads from an in-memory copy of another repo and writes through to
+/// it, e.g. to keep listing and searching fast on a remote database.
+///
+/// The copy is loaded on first use and dropped whenever a contact is saved or
+/// deleted, or the underlying repo [sends](ContactRepo::subscribe) that one
+/// was. Other changes made to the underlying repo by anything else, such as
+/// another instance sharing the database, are not seen until then.
+pub struct CachedContactRepo {
+    inner: SharedContactRepo,
+    cache: Arc<Cache>,
+}
+
+type Cache = RwLock<Option<BTreeMap<u64, Contact>>>;
+
+/// A transaction on the underlying repo, dropping the cache when committed.
+struct CachedTransaction {
+    inner: BoxedTransaction,
+    cache: Arc<Cache>,
+}
+
+impl CachedContactRepo {
+    /// Caches `inner`, dropping the cache whenever it sends a change. Must
+    /// be called from within a tokio runtime if it sends any.
+    pub fn new(inner: SharedContactRepo) -> Self {
+        let cache: Arc<Cache> = Arc::default();
+        if let Some(mut changes) = inner.subscribe() {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                // Missed changes drop the cache all the same.
+                while !matches!(
+                    changes.recv().await,
+                    Err(broadcast::error::RecvError::Closed)
+                ) {
+                    *cache.write().await = None;
+                }
+            });
+        }
+        Self { inner, cache }
+    }
+
+    pub fn shared(inner: SharedContactRepo) -> SharedContactRepo {
+        Arc::new(Self::new(inner))
+    }
+
+    /// Drops the cache after a write. A load that started before the write
+    /// still holds the lock, so its result is dropped too.
+    async fn invalidate(&self) {
+        *self.cache.write().await = None;
+    }
+
+    async fn contacts(&self) -> RwLockReadGuard<'_, BTreeMap<u64, Contact>> {
+        let cache = self.cache.read().await;
+        if cache.is_some() {
+            return RwLockReadGuard::map(cache, |cache| cache.as_ref().unwrap());
+        }
+        drop(cache);
+
+        let mut cache = self.cache.write().await;
+        if cache.is_none() {
+            let contacts = self.inner.all(Sort::default()).await;
+            *cache = Some(
+                contacts
+                    .into_iter()
+                    .map(|contact| (contact.id.unwrap(), contact))
+                    .collect(),
+            );
+        }
+        RwLockReadGuard::map(cache.downgrade(), |cache| cache.as_ref().unwrap())
+    }
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for CachedContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let mut contacts: Vec<Contact> = self.contacts().await.values().cloned().collect();
+        sort.apply(&mut contacts);
+        contacts
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        let contacts = self.contacts().await;
+        match filter.map(SearchQuery::parse).as_ref() {
+            None => contacts.len(),
+            Some(query) => contacts.values().filter(|c| c.matches(query)).count(),
+        }
+    }
+
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        Page::of(self.contacts().await.values(), sort, number, size)
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        let start = cursor.map_or(0, |cursor| cursor.id() + 1);
+        let contacts = self.contacts().await;
+        let contacts = contacts.range(start..).take(limit + 1);
+        CursorPage::from_sorted(
+            contacts.map(|(_, contact)| contact.clone()).collect(),
+            limit,
+        )
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let contacts = self.contacts().await;
+        let matches = contacts.values().filter(|contact| contact.matches(&query));
+        Page::of(matches, sort, number, size)
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        filter: &ContactFilter,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let contacts = self.contacts().await;
+        let matches = contacts
+            .values()
+            .filter(|contact| filter.finds(&query, contact));
+        Page::of(matches, sort, number, size)
+    }
+
+    async fn fuzzy_search(
+        &self,
+        query: &str,
+        threshold: f64,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let contacts = self.contacts().await;
+        let unarchived = contacts.values().filter(|contact| !contact.archived);
+        Page::by_similarity(unarchived, query, threshold, sort, number, size)
+    }
+
+    async fn tagged(&self, tag: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let contacts = self.contacts().await;
+        let tagged = contacts
+            .values()
+            .filter(|contact| !contact.archived && contact.has_tag(tag));
+        Page::of(tagged, sort, number, size)
+    }
+
+    async fn tags(&self) -> BTreeMap<String, usize> {
+        tag_counts(self.contacts().await.values())
+    }
+
+    async fn members(&self, group: u64, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let contacts = self.contacts().await;
+        let members = contacts
+            .values()
+            .filter(|contact| !contact.archived && contact.in_group(group));
+        Page::of(members, sort, number, size)
+    }
+
+    async fn group_sizes(&self) -> BTreeMap<u64, usize> {
+        group_sizes(self.contacts().await.values())
+    }
+
+    async fn by_initial(
+        &self,
+        initial: char,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let contacts = self.contacts().await;
+        let listed = contacts
+            .values()
+            .filter(|contact| !contact.archived && contact.initial(sort.names) == initial);
+        Page::of(listed, sort, number, size)
+    }
+
+    async fn initials(&self, names: NameOrder) -> BTreeMap<char, usize> {
+        initial_counts(self.contacts().await.values(), names)
+    }
+
+    async fn starred(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let contacts = self.contacts().await;
+        let starred = contacts
+            .values()
+            .filter(|contact| !contact.archived && contact.starred);
+        Page::of(starred, sort, number, size)
+    }
+
+    async fn archived(
+        &self,
+        archived: bool,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let contacts = self.contacts().await;
+        let listed = contacts
+            .values()
+            .filter(|contact| contact.archived == archived);
+        Page::of(listed, sort, number, size)
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        let created = self.inner.create(contact).await;
+        self.invalidate().await;
+        created
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        let updated = self.inner.update(contact).await;
+        self.invalidate().await;
+        updated
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        let contacts = self.contacts().await;
+        Ok(contacts
+            .values()
+            .find(|contact| contact.email.as_deref() == Some(email))
+            .cloned())
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<Contact>, RepoError> {
+        let contacts = self.contacts().await;
+        Ok(contacts
+            .values()
+            .find(|contact| contact.uuid == Some(uuid))
+            .cloned())
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        Ok(self.contacts().await.get(&id).cloned())
+    }
+
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        let contacts = self.contacts().await;
+        Ok(unique_ids(ids)
+            .into_iter()
+            .filter_map(|id| contacts.get(&id).cloned())
+            .collect())
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let deleted = self.inner.delete_by_id(id).await;
+        self.invalidate().await;
+        deleted
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        self.inner.changed_since(since).await
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        Ok(Box::new(CachedTransaction {
+            inner: self.inner.begin().await?,
+            cache: self.cache.clone(),
+        }))
+    }
+
+    async fn replace_all(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let replaced = self.inner.replace_all(contacts).await;
+        self.invalidate().await;
+        replaced
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ContactEvent>> {
+        self.inner.subscribe()
+    }
+
+    async fn flush(&self) {
+        self.inner.flush().await;
+    }
+
+    async fn stats(&self) -> RepoStats {
+        self.inner.stats().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ContactTransaction for CachedTransaction {
+    async fn all(&mut self) -> Result<Vec<Contact>, RepoError> {
+        self.inner.all().await
+    }
+
+    async fn find(&mut self, id: u64) -> Result<Option<Contact>, RepoError> {
+        self.inner.find(id).await
+    }
+
+    async fn save(&mut self, contact: Contact) -> Result<u64, RepoError> {
+        self.inner.save(contact).await
+    }
+
+    async fn delete(&mut self, contact: Contact) -> Result<(), RepoError> {
+        self.inner.delete(contact).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), RepoError> {
+        let committed = self.inner.commit().await;
+        *self.cache.write().await = None;
+        committed
+    }
+
+    async fn rollback(self: Box<Self>) {
+        self.inner.rollback().await;
+    }
+}
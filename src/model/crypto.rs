@@ -0,0 +1,75 @@
+use std::{fmt, io};
+
+use aes_gcm::{
+    aead::{Aead, Generate, Key, KeyInit, Nonce},
+    Aes256Gcm,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+
+/// Marks an encrypted snapshot, followed by the nonce and the ciphertext.
+const MAGIC: &[u8] = b"CAE1";
+const NONCE_LEN: usize = 12;
+
+/// AES-256-GCM key for encrypting the files of a [`super::MemContactRepo`].
+#[derive(Clone)]
+pub struct StoreKey(Key<Aes256Gcm>);
+
+impl fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StoreKey(..)")
+    }
+}
+
+impl StoreKey {
+    /// Parses a base64 encoded 32 byte key, e.g. from `openssl rand -base64 32`.
+    pub fn from_base64(encoded: &str) -> Result<Self, String> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|err| format!("key is not valid base64: {err}"))?;
+        let key = Key::<Aes256Gcm>::try_from(bytes.as_slice())
+            .map_err(|_| format!("key must be 32 bytes, got {}", bytes.len()))?;
+        Ok(Self(key))
+    }
+
+    /// Encrypts `data` into a self-contained blob: magic, nonce, ciphertext.
+    pub fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let nonce = Nonce::<Aes256Gcm>::generate();
+        let ciphertext = Aes256Gcm::new(&self.0)
+            .encrypt(&nonce, data)
+            .expect("encryption succeed");
+        [MAGIC, nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// Decrypts a blob made by [`Self::seal`].
+    pub fn open(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let rest = sealed
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("data is not encrypted"))?;
+        if rest.len() < NONCE_LEN {
+            return Err(invalid("encrypted data is truncated"));
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::<Aes256Gcm>::try_from(nonce).expect("nonce length checked");
+        Aes256Gcm::new(&self.0)
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| invalid("decryption failed, wrong key?"))
+    }
+
+    /// Like [`Self::seal`], but base64 encoded to fit on a journal line.
+    pub fn seal_line(&self, data: &[u8]) -> String {
+        BASE64.encode(self.seal(data))
+    }
+
+    pub fn open_line(&self, line: &str) -> io::Result<Vec<u8>> {
+        let sealed = BASE64
+            .decode(line.trim_end())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.open(&sealed)
+    }
+}
+
+/// Whether `data` was made by [`StoreKey::seal`].
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
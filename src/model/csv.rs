@@ -0,0 +1,749 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::{Mutex, RwLock};
+
+use super::{
+    phones, unique_emails, unique_ids, unique_phones, write_atomic, BoxedTransaction, Changes,
+    Contact, ContactChange, ContactMethod, ContactRepo, EmailAddress, ImportantDate, NameOrder,
+    Page, PhoneNumber, RepoError, RepoStats, SearchQuery, SharedContactRepo, SocialProfile, Sort,
+    Stage, StagedTransaction,
+};
+
+/// Contact repository backed by a single CSV file, for address books kept in
+/// a spreadsheet or synced elsewhere.
+///
+/// Columns are mapped by their header, in any order: `id`, `name` (or
+/// `display name`, `full name`, written as [`Contact::display_name`] and
+/// only read into `first` for contacts without a first or last name),
+/// `prefix`, `first` (or `first_name`, `first name`, `given name`), `middle`
+/// (or `middle name`), `last` (or `last_name`, `last name`, `family name`,
+/// `surname`), `suffix`, `nickname`, `phone`, `email`, `emails` (or
+/// `other emails`), `birthday` (or `birth date`, `date of birth`, as
+/// `YYYY-MM-DD`), `dates` (the label and date of each important date, as
+/// in `anniversary: 2010-06-01`), `company` (or `organization`), `job title` (or
+/// `job_title`, `title`), `socials` (the network and handle of each profile,
+/// as in `GitHub: octocat; Mastodon: @user@example.social`), `website` (or
+/// `url`), `timezone` (or `time zone`), `notes`, `tags` (comma-separated),
+/// `groups` (the comma-separated ids of the contact's groups), `preferred`
+/// (or `preferred method`; `email`, `phone`, `sms` or blank), `starred`
+/// (`true` for starred contacts; anything but blank, `false`, `no` and `0`
+/// counts), `archived` (in the same way), `uuid`, `addresses`, `custom` (or
+/// `custom fields`) and `attachments` (each as JSON, the attachments'
+/// details only, their files are kept with the photos), `photo` (or
+/// `has photo`, like `starred`), `version`, and `created_at` (or `created`,
+/// `created at`) and `updated_at` (or `updated`, `updated at`, in RFC 3339),
+/// matched case-insensitively. Other columns are kept as they are when the
+/// file is written back. If there is no `id` column, one is added, and so is
+/// any other column but `name` once a contact has something to write to it,
+/// so that nothing is lost. A contact's phone numbers share the phone
+/// column, as in `mobile: 555-1234; 555-9876`, and its other emails the
+/// `emails` column in the same way.
+///
+/// Tombstones aren't written to the file, so they start over whenever it is
+/// loaded.
+#[derive(Debug, Clone)]
+pub struct CsvContactRepo {
+    path: PathBuf,
+    columns: Arc<Vec<Column>>,
+    rows: Arc<RwLock<BTreeMap<u64, Row>>>,
+    /// How the `name` column is written.
+    names: NameOrder,
+    /// Held by `save`, `delete` and for the whole of a transaction.
+    writer: Arc<Mutex<()>>,
+    /// When each deleted contact was deleted, by id.
+    tombstones: Arc<RwLock<HashMap<u64, DateTime<Utc>>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Column {
+    header: String,
+    field: Field,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Name,
+    Prefix,
+    First,
+    Middle,
+    Last,
+    Suffix,
+    Nickname,
+    Phone,
+    Email,
+    Emails,
+    Birthday,
+    Dates,
+    Company,
+    JobTitle,
+    Socials,
+    Website,
+    Timezone,
+    Notes,
+    Tags,
+    Groups,
+    Preferred,
+    Starred,
+    Archived,
+    Uuid,
+    Addresses,
+    Custom,
+    Attachments,
+    Photo,
+    Version,
+    CreatedAt,
+    UpdatedAt,
+    Other,
+}
+
+impl Field {
+    fn from_header(header: &str) -> Self {
+        match header.trim().to_lowercase().as_str() {
+            "id" => Self::Id,
+            "name" | "display name" | "full name" => Self::Name,
+            "prefix" => Self::Prefix,
+            "first" | "first_name" | "first name" | "given name" => Self::First,
+            "middle" | "middle name" => Self::Middle,
+            "last" | "last_name" | "last name" | "family name" | "surname" => Self::Last,
+            "suffix" => Self::Suffix,
+            "nickname" => Self::Nickname,
+            "phone" | "phone number" | "telephone" => Self::Phone,
+            "email" | "e-mail" | "email address" => Self::Email,
+            "emails" | "other emails" => Self::Emails,
+            "birthday" | "birth date" | "date of birth" => Self::Birthday,
+            "dates" | "important dates" => Self::Dates,
+            "company" | "organization" => Self::Company,
+            "job title" | "job_title" | "title" => Self::JobTitle,
+            "socials" | "social profiles" => Self::Socials,
+            "website" | "url" => Self::Website,
+            "timezone" | "time zone" => Self::Timezone,
+            "notes" => Self::Notes,
+            "tags" => Self::Tags,
+            "groups" => Self::Groups,
+            "preferred" | "preferred method" => Self::Preferred,
+            "starred" | "favorite" => Self::Starred,
+            "archived" => Self::Archived,
+            "uuid" => Self::Uuid,
+            "addresses" => Self::Addresses,
+            "custom" | "custom fields" => Self::Custom,
+            "attachments" => Self::Attachments,
+            "photo" | "has photo" => Self::Photo,
+            "version" => Self::Version,
+            "created_at" | "created" | "created at" => Self::CreatedAt,
+            "updated_at" | "updated" | "updated at" => Self::UpdatedAt,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// The columns, with their headers, that are added to a file lacking them
+/// once a contact has something to write to them, as the contact would
+/// lose it otherwise.
+const ADDED_COLUMNS: [(&str, Field); 30] = [
+    ("prefix", Field::Prefix),
+    ("first", Field::First),
+    ("middle", Field::Middle),
+    ("last", Field::Last),
+    ("suffix", Field::Suffix),
+    ("nickname", Field::Nickname),
+    ("phone", Field::Phone),
+    ("email", Field::Email),
+    ("emails", Field::Emails),
+    ("birthday", Field::Birthday),
+    ("dates", Field::Dates),
+    ("company", Field::Company),
+    ("job title", Field::JobTitle),
+    ("socials", Field::Socials),
+    ("website", Field::Website),
+    ("timezone", Field::Timezone),
+    ("notes", Field::Notes),
+    ("tags", Field::Tags),
+    ("groups", Field::Groups),
+    ("preferred", Field::Preferred),
+    ("starred", Field::Starred),
+    ("archived", Field::Archived),
+    ("uuid", Field::Uuid),
+    ("addresses", Field::Addresses),
+    ("custom", Field::Custom),
+    ("attachments", Field::Attachments),
+    ("photo", Field::Photo),
+    ("version", Field::Version),
+    ("created_at", Field::CreatedAt),
+    ("updated_at", Field::UpdatedAt),
+];
+
+/// `values` as the JSON of a cell, blank if there are none.
+fn to_json_cell<T: serde::Serialize>(values: &T, is_empty: bool) -> String {
+    if is_empty {
+        return String::new();
+    }
+    serde_json::to_string(values).expect("serializing succeed")
+}
+
+/// The values in the JSON of a cell, none if it is blank.
+fn from_json_cell<T: serde::de::DeserializeOwned + Default>(
+    value: Option<String>,
+) -> io::Result<T> {
+    match value {
+        None => Ok(T::default()),
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Row {
+    contact: Contact,
+    /// Values of [`Field::Other`] columns, by header.
+    other: HashMap<String, String>,
+}
+
+const DEFAULT_HEADERS: [&str; 5] = ["id", "first", "last", "phone", "email"];
+
+impl CsvContactRepo {
+    /// Loads the CSV file at `path`, creating it if it doesn't exist.
+    pub fn from_path(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let (columns, rows) = if path.exists() {
+            read_csv(&path).expect("a readable CSV file")
+        } else {
+            let columns = DEFAULT_HEADERS
+                .iter()
+                .map(|header| Column {
+                    header: header.to_string(),
+                    field: Field::from_header(header),
+                })
+                .collect();
+            (columns, BTreeMap::new())
+        };
+        let repo = Self {
+            path,
+            columns: Arc::new(columns),
+            rows: Arc::new(RwLock::new(rows)),
+            names: NameOrder::default(),
+            writer: Arc::default(),
+            tombstones: Arc::default(),
+        };
+        repo.write(&repo.rows.try_read().unwrap())
+            .expect("writing succeed");
+        repo
+    }
+
+    pub fn shared_from_path(path: &str) -> SharedContactRepo {
+        Arc::new(Self::from_path(path))
+    }
+
+    /// Writes the `name` column in `names` order from the next write on.
+    pub fn with_name_order(mut self, names: NameOrder) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// The columns of the file, with the [`ADDED_COLUMNS`] it lacks that a
+    /// contact in `rows` has something to write to.
+    fn columns(&self, rows: &BTreeMap<u64, Row>) -> Vec<Column> {
+        let mut columns = self.columns.to_vec();
+        for (header, field) in ADDED_COLUMNS {
+            if columns.iter().any(|column| column.field == field) {
+                continue;
+            }
+            let column = Column {
+                header: header.to_owned(),
+                field,
+            };
+            if rows.values().any(|row| !self.cell(row, &column).is_empty()) {
+                columns.push(column);
+            }
+        }
+        columns
+    }
+
+    /// What `row` has in `column`, blank if nothing.
+    fn cell(&self, row: &Row, column: &Column) -> String {
+        let contact = &row.contact;
+        match column.field {
+            Field::Id => contact.id.map(|id| id.to_string()).unwrap_or_default(),
+            Field::Name => contact.display_name(self.names),
+            Field::Prefix => contact.prefix.clone().unwrap_or_default(),
+            Field::First => contact.first.clone().unwrap_or_default(),
+            Field::Middle => contact.middle.clone().unwrap_or_default(),
+            Field::Last => contact.last.clone().unwrap_or_default(),
+            Field::Suffix => contact.suffix.clone().unwrap_or_default(),
+            Field::Nickname => contact.nickname.clone().unwrap_or_default(),
+            Field::Phone => join_labeled(
+                contact
+                    .phones()
+                    .iter()
+                    .map(|phone| (&phone.label, &phone.value)),
+            ),
+            Field::Email => contact.email.clone().unwrap_or_default(),
+            Field::Emails => join_labeled(
+                contact
+                    .emails()
+                    .iter()
+                    .map(|email| (&email.label, &email.value)),
+            ),
+            Field::Birthday => contact
+                .birthday
+                .map(|birthday| birthday.to_string())
+                .unwrap_or_default(),
+            Field::Dates => {
+                let dates: Vec<_> = contact
+                    .dates
+                    .iter()
+                    .map(|date| (date.label.clone(), date.date.to_string()))
+                    .collect();
+                join_labeled(dates.iter().map(|(label, date)| (label, date)))
+            }
+            Field::Company => contact.company.clone().unwrap_or_default(),
+            Field::JobTitle => contact.job_title.clone().unwrap_or_default(),
+            Field::Socials => join_labeled(
+                contact
+                    .socials
+                    .iter()
+                    .map(|social| (&social.network, &social.handle)),
+            ),
+            Field::Website => contact.website.clone().unwrap_or_default(),
+            Field::Timezone => contact.timezone.clone().unwrap_or_default(),
+            Field::Notes => contact.notes.clone().unwrap_or_default(),
+            Field::Tags => contact.tags.join(", "),
+            Field::Groups => contact
+                .groups
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            Field::Starred => if contact.starred { "true" } else { "" }.to_owned(),
+            Field::Preferred => match contact.preferred {
+                ContactMethod::None => "",
+                ContactMethod::Email => "email",
+                ContactMethod::Phone => "phone",
+                ContactMethod::Sms => "sms",
+            }
+            .to_owned(),
+            Field::Archived => if contact.archived { "true" } else { "" }.to_owned(),
+            Field::Uuid => contact
+                .uuid
+                .map(|uuid| uuid.to_string())
+                .unwrap_or_default(),
+            Field::Addresses => to_json_cell(&contact.addresses, contact.addresses.is_empty()),
+            Field::Custom => to_json_cell(&contact.custom, contact.custom.is_empty()),
+            Field::Attachments => {
+                to_json_cell(&contact.attachments, contact.attachments.is_empty())
+            }
+            Field::Photo => if contact.has_photo { "true" } else { "" }.to_owned(),
+            Field::Version => match contact.version {
+                0 => String::new(),
+                version => version.to_string(),
+            },
+            Field::CreatedAt => contact
+                .created_at
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+            Field::UpdatedAt => contact
+                .updated_at
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+            Field::Other => row.other.get(&column.header).cloned().unwrap_or_default(),
+        }
+    }
+
+    fn write(&self, rows: &BTreeMap<u64, Row>) -> io::Result<()> {
+        let columns = self.columns(rows);
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(columns.iter().map(|column| &column.header))?;
+        for row in rows.values() {
+            writer.write_record(columns.iter().map(|column| self.cell(row, column)))?;
+        }
+        let data = writer.into_inner().map_err(|err| err.into_error())?;
+        write_atomic(&self.path, &data)
+    }
+
+    fn validate(mut contact: Contact, rows: &BTreeMap<u64, Row>) -> Result<Contact, RepoError> {
+        if !contact.validate() {
+            return Err(RepoError::invalid(contact));
+        }
+        let duplicate = unique_emails()
+            && contact.email.is_some()
+            && rows
+                .values()
+                .any(|row| row.contact.id != contact.id && row.contact.email == contact.email);
+        if duplicate {
+            return Err(RepoError::email_taken(contact));
+        }
+        if unique_phones() {
+            let others = rows.values().map(|row| &row.contact);
+            return phones::check_phones(contact, others);
+        }
+        Ok(contact)
+    }
+}
+
+/// Labeled values as written to a column holding several, e.g. phone
+/// numbers, leaving out empty labels.
+fn join_labeled<'a>(values: impl Iterator<Item = (&'a String, &'a String)>) -> String {
+    let values: Vec<String> = values
+        .map(|(label, value)| match label.as_str() {
+            "" => value.clone(),
+            label => format!("{label}: {value}"),
+        })
+        .collect();
+    values.join("; ")
+}
+
+/// Reads the `(label, value)` pairs of a column written by [`join_labeled`].
+fn split_labeled(column: Option<&str>) -> impl Iterator<Item = (&str, &str)> {
+    column
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| match value.split_once(':') {
+            Some((label, value)) => (label.trim(), value.trim()),
+            None => ("", value),
+        })
+}
+
+/// The time in a column, as written in RFC 3339.
+fn time_from(value: Option<String>) -> io::Result<Option<DateTime<Utc>>> {
+    value
+        .map(|at| at.parse())
+        .transpose()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Whether a yes-or-no column is set: anything but blank, `false`, `no` and
+/// `0` counts.
+fn is_set(value: Option<String>) -> bool {
+    let value = value.unwrap_or_default().to_lowercase();
+    !matches!(value.as_str(), "" | "false" | "no" | "0")
+}
+
+fn read_csv(path: &Path) -> io::Result<(Vec<Column>, BTreeMap<u64, Row>)> {
+    let mut reader = csv::Reader::from_reader(fs::File::open(path)?);
+    let mut columns: Vec<Column> = reader
+        .headers()?
+        .iter()
+        .map(|header| Column {
+            header: header.to_string(),
+            field: Field::from_header(header),
+        })
+        .collect();
+    let has_id = columns.iter().any(|column| column.field == Field::Id);
+
+    let mut rows = BTreeMap::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let mut contact = Contact::default();
+        let mut other = HashMap::new();
+        let mut name = None;
+        for (column, value) in columns.iter().zip(record.iter()) {
+            let value = (!value.is_empty()).then(|| value.to_string());
+            match column.field {
+                Field::Id => {
+                    let id = value
+                        .map(|id| id.parse())
+                        .transpose()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                    contact.id = id;
+                }
+                Field::Name => name = value,
+                Field::Prefix => contact.prefix = value,
+                Field::First => contact.first = value,
+                Field::Middle => contact.middle = value,
+                Field::Last => contact.last = value,
+                Field::Suffix => contact.suffix = value,
+                Field::Nickname => contact.nickname = value,
+                Field::Phone => {
+                    contact.phones = split_labeled(value.as_deref())
+                        .map(|(label, number)| PhoneNumber::new(label, number))
+                        .collect();
+                }
+                Field::Email => contact.email = value,
+                Field::Emails => {
+                    contact.emails = split_labeled(value.as_deref())
+                        .map(|(label, email)| EmailAddress::new(label, email))
+                        .collect();
+                }
+                Field::Birthday => {
+                    let birthday = value
+                        .map(|birthday| birthday.parse())
+                        .transpose()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                    contact.birthday = birthday;
+                }
+                Field::Dates => {
+                    contact.dates = split_labeled(value.as_deref())
+                        .map(|(label, date)| {
+                            let date = date
+                                .parse()
+                                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                            Ok(ImportantDate::new(label, date))
+                        })
+                        .collect::<io::Result<_>>()?;
+                }
+                Field::Company => contact.company = value,
+                Field::JobTitle => contact.job_title = value,
+                Field::Socials => {
+                    contact.socials = split_labeled(value.as_deref())
+                        .map(|(network, handle)| SocialProfile::new(network, handle))
+                        .collect();
+                }
+                Field::Website => contact.website = value,
+                Field::Timezone => contact.timezone = value,
+                Field::Notes => contact.notes = value,
+                Field::Groups => {
+                    let groups = value.unwrap_or_default();
+                    let groups = groups
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|group| !group.is_empty())
+                        .map(str::parse)
+                        .collect::<Result<Vec<u64>, _>>()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                    contact.set_groups(groups);
+                }
+                Field::Starred => contact.starred = is_set(value),
+                Field::Preferred => {
+                    contact.preferred = value
+                        .unwrap_or_default()
+                        .parse()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                }
+                Field::Archived => contact.archived = is_set(value),
+                Field::Tags => {
+                    let tags = value.unwrap_or_default();
+                    contact.set_tags(tags.split(',').map(str::to_owned));
+                }
+                Field::Uuid => {
+                    let uuid = value
+                        .map(|uuid| uuid.parse())
+                        .transpose()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                    contact.uuid = uuid;
+                }
+                Field::Addresses => contact.addresses = from_json_cell(value)?,
+                Field::Custom => contact.custom = from_json_cell(value)?,
+                Field::Attachments => contact.attachments = from_json_cell(value)?,
+                Field::Photo => contact.has_photo = is_set(value),
+                Field::Version => {
+                    let version = value
+                        .map(|version| version.parse())
+                        .transpose()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                    contact.version = version.unwrap_or_default();
+                }
+                Field::CreatedAt => contact.created_at = time_from(value)?,
+                Field::UpdatedAt => contact.updated_at = time_from(value)?,
+                Field::Other => {
+                    other.insert(column.header.clone(), value.unwrap_or_default());
+                }
+            }
+        }
+        // Files from elsewhere may have names in one column only.
+        if contact.first.is_none() && contact.last.is_none() {
+            contact.first = name;
+        }
+        let id = contact.id.unwrap_or(index as u64 + 1);
+        contact.id = Some(id);
+        rows.insert(id, Row { contact, other });
+    }
+    if !has_id {
+        columns.insert(
+            0,
+            Column {
+                header: "id".into(),
+                field: Field::Id,
+            },
+        );
+    }
+    Ok((columns, rows))
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for CsvContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let rows = self.rows.read().await;
+        let mut contacts: Vec<Contact> = rows.values().map(|row| row.contact.clone()).collect();
+        sort.apply(&mut contacts);
+        contacts
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        let rows = self.rows.read().await;
+        match filter.map(SearchQuery::parse).as_ref() {
+            None => rows.len(),
+            Some(query) => rows
+                .values()
+                .filter(|row| row.contact.matches(query))
+                .count(),
+        }
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let rows = self.rows.read().await;
+        let matches = rows
+            .values()
+            .map(|row| &row.contact)
+            .filter(|contact| contact.matches(&query));
+        Page::of(matches, sort, number, size)
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        let _writer = self.writer.lock().await;
+        let mut rows = self.rows.write().await;
+        let mut contact = Self::validate(contact, &rows)?;
+        let id = match contact.id {
+            Some(id) if rows.contains_key(&id) => return Err(RepoError::id_taken(contact)),
+            Some(id) => id,
+            None => rows.keys().next_back().map_or(1, |id| id + 1),
+        };
+        contact.id = Some(id);
+        contact.bump();
+        let mut changed = rows.clone();
+        changed.insert(
+            id,
+            Row {
+                contact,
+                other: HashMap::new(),
+            },
+        );
+        self.write(&changed)?;
+        *rows = changed;
+        self.tombstones.write().await.remove(&id);
+        Ok(id)
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        let _writer = self.writer.lock().await;
+        let mut rows = self.rows.write().await;
+        let id = contact.id.expect("an updated contact to have an id");
+        match rows.get(&id) {
+            None => return Err(RepoError::NotFound(id)),
+            Some(row) if row.contact.version != contact.version => {
+                return Err(RepoError::stale(contact));
+            }
+            Some(_) => {}
+        }
+        let mut contact = Self::validate(contact, &rows)?;
+        contact.bump();
+        let mut changed = rows.clone();
+        let other = changed.remove(&id).map(|row| row.other).unwrap_or_default();
+        changed.insert(id, Row { contact, other });
+        self.write(&changed)?;
+        *rows = changed;
+        Ok(())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        let rows = self.rows.read().await;
+        Ok(rows
+            .values()
+            .find(|row| row.contact.email.as_deref() == Some(email))
+            .map(|row| row.contact.clone()))
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        let rows = self.rows.read().await;
+        Ok(rows.get(&id).map(|row| row.contact.clone()))
+    }
+
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        let rows = self.rows.read().await;
+        Ok(unique_ids(ids)
+            .into_iter()
+            .filter_map(|id| rows.get(&id).map(|row| row.contact.clone()))
+            .collect())
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let _writer = self.writer.lock().await;
+        let mut rows = self.rows.write().await;
+        let mut changed = rows.clone();
+        if changed.remove(&id).is_none() {
+            return Err(RepoError::NotFound(id));
+        }
+        self.write(&changed)?;
+        *rows = changed;
+        self.tombstones.write().await.insert(id, Utc::now());
+        Ok(())
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let rows = self.rows.read().await;
+        let tombstones = self.tombstones.read().await;
+        ContactChange::since(
+            rows.values().map(|row| row.contact.clone()),
+            tombstones.iter().map(|(&id, &at)| (id, at)),
+            since,
+        )
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        let writer = self.writer.clone().lock_owned().await;
+        Ok(Box::new(StagedTransaction::new(self.clone(), Some(writer))))
+    }
+
+    async fn stats(&self) -> RepoStats {
+        RepoStats::from_files(self.count(None).await, &[&self.path])
+    }
+}
+
+#[async_trait::async_trait]
+impl Stage for CsvContactRepo {
+    async fn email_owner(&self, email: &str) -> Result<Option<u64>, RepoError> {
+        let rows = self.rows.read().await;
+        Ok(rows
+            .values()
+            .find(|row| row.contact.email.as_deref() == Some(email))
+            .and_then(|row| row.contact.id))
+    }
+
+    async fn next_id(&self, changes: &Changes) -> Result<u64, RepoError> {
+        let rows = self.rows.read().await;
+        let tombstones = self.tombstones.read().await;
+        let max_id = rows
+            .keys()
+            .chain(tombstones.keys())
+            .chain(changes.keys())
+            .max();
+        Ok(max_id.map_or(1, |id| id + 1))
+    }
+
+    async fn apply(&self, changes: &Changes) -> Result<(), RepoError> {
+        // The transaction holds the writer lock, so nothing can conflict.
+        let mut rows = self.rows.write().await;
+        let mut changed = rows.clone();
+        for (id, change) in changes {
+            match change {
+                Some(contact) => {
+                    let other = changed.remove(id).map(|row| row.other).unwrap_or_default();
+                    let contact = contact.clone();
+                    changed.insert(*id, Row { contact, other });
+                }
+                None => {
+                    changed.remove(id);
+                }
+            }
+        }
+        self.write(&changed)?;
+        *rows = changed;
+        let deleted_at = Utc::now();
+        let mut tombstones = self.tombstones.write().await;
+        for (id, change) in changes {
+            match change {
+                Some(_) => tombstones.remove(id),
+                None => tombstones.insert(*id, deleted_at),
+            };
+        }
+        Ok(())
+    }
+}
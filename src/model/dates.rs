@@ -0,0 +1,105 @@
+use chrono::{Datelike, NaiveDate};
+
+use super::Contact;
+
+/// A labeled date of a contact besides the birthday, such as an anniversary
+/// or a contract renewal.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ImportantDate {
+    /// What the date is, e.g. `anniversary`; empty if not given.
+    #[serde(default)]
+    pub label: String,
+    pub date: NaiveDate,
+}
+
+impl ImportantDate {
+    pub fn new(label: impl Into<String>, date: NaiveDate) -> Self {
+        Self {
+            label: label.into(),
+            date,
+        }
+    }
+}
+
+impl Contact {
+    pub fn dates(&self) -> &[ImportantDate] {
+        &self.dates
+    }
+
+    pub fn set_dates(&mut self, dates: Vec<ImportantDate>) {
+        self.dates = dates;
+    }
+}
+
+/// The next time one of a contact's dates comes around, see [`upcoming`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UpcomingDate {
+    pub contact: Contact,
+    /// The date's label, `Birthday` for the birthday.
+    pub label: String,
+    pub date: NaiveDate,
+    /// When it next comes around, today or later.
+    pub next: NaiveDate,
+    /// The number of days from today to `next`.
+    pub days: i64,
+    /// How many years `next` is after `date`, if any.
+    pub years: Option<i32>,
+}
+
+/// The birthdays and important dates of `contacts` coming around within
+/// `days` days of `today`, soonest first. Dates come around every year,
+/// but dates after `today` only once, on the day itself, so that
+/// one-off dates such as a renewal can be entered ahead.
+pub fn upcoming(contacts: Vec<Contact>, today: NaiveDate, days: i64) -> Vec<UpcomingDate> {
+    let mut upcoming = Vec::new();
+    for contact in contacts {
+        let birthday = contact
+            .birthday
+            .map(|birthday| ("Birthday".to_owned(), birthday));
+        let dates = contact
+            .dates
+            .iter()
+            .map(|date| (date.label.clone(), date.date));
+        let occurrences: Vec<_> = birthday
+            .into_iter()
+            .chain(dates)
+            .filter_map(|(label, date)| {
+                let next = next_occurrence(date, today);
+                let until = (next - today).num_days();
+                (until <= days).then_some((label, date, next, until))
+            })
+            .collect();
+        for (label, date, next, until) in occurrences {
+            let years = next.year() - date.year();
+            upcoming.push(UpcomingDate {
+                contact: contact.clone(),
+                label,
+                date,
+                next,
+                days: until,
+                years: (years > 0).then_some(years),
+            });
+        }
+    }
+    upcoming.sort_by(|a, b| a.next.cmp(&b.next).then_with(|| a.label.cmp(&b.label)));
+    upcoming
+}
+
+/// `date` if it is today or later, else its anniversary this year or, if
+/// that has passed, next year; on February 28 in years without a 29th.
+fn next_occurrence(date: NaiveDate, today: NaiveDate) -> NaiveDate {
+    if date >= today {
+        return date;
+    }
+    let in_year = |year| {
+        NaiveDate::from_ymd_opt(year, date.month(), date.day())
+            .or_else(|| NaiveDate::from_ymd_opt(year, date.month(), date.day() - 1))
+            .expect("a valid day of the year")
+    };
+    let this_year = in_year(today.year());
+    if this_year >= today {
+        this_year
+    } else {
+        in_year(today.year() + 1)
+    }
+}
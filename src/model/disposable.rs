@@ -0,0 +1,106 @@
+//! Telling emails of disposable email services, which stop working after a
+//! while, from others.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use super::Contact;
+
+/// The domains known without a list of them given.
+const BUNDLED: &str = include_str!("data/disposable_domains.txt");
+
+/// The domains of disposable email services: those bundled, or those of a
+/// file of them one per line, reread when [refreshed](Self::refresh).
+#[derive(Debug)]
+pub struct DisposableDomains {
+    file: Option<PathBuf>,
+    domains: RwLock<HashSet<String>>,
+}
+
+pub type SharedDisposableDomains = Arc<DisposableDomains>;
+
+impl Default for DisposableDomains {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+impl DisposableDomains {
+    pub fn bundled() -> Self {
+        Self {
+            file: None,
+            domains: RwLock::new(parse(BUNDLED)),
+        }
+    }
+
+    /// The domains of `file`, with `#` starting comments.
+    pub fn from_file(file: impl Into<PathBuf>) -> io::Result<Self> {
+        let file = file.into();
+        let domains = parse(&fs::read_to_string(&file)?);
+        Ok(Self {
+            file: Some(file),
+            domains: RwLock::new(domains),
+        })
+    }
+
+    /// Rereads the file of domains, if there is one, returning how many
+    /// domains are known. If it can't be read, the domains are kept as
+    /// they were.
+    pub fn refresh(&self) -> io::Result<usize> {
+        if let Some(file) = &self.file {
+            let domains = parse(&fs::read_to_string(file)?);
+            *self.domains.write().expect("domains not poisoned") = domains;
+        }
+        Ok(self.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.read().expect("domains not poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `email` is at a disposable domain, or a subdomain of one.
+    pub fn is_disposable(&self, email: &str) -> bool {
+        let Some((_, domain)) = email.trim().rsplit_once('@') else {
+            return false;
+        };
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let domains = self.domains.read().expect("domains not poisoned");
+        let mut parent = domain.as_str();
+        loop {
+            if domains.contains(parent) {
+                return true;
+            }
+            match parent.split_once('.') {
+                Some((_, rest)) => parent = rest,
+                None => return false,
+            }
+        }
+    }
+
+    /// Whether any of the contact's emails is disposable.
+    pub fn suspect(&self, contact: &Contact) -> bool {
+        let others = contact.emails().iter().map(|email| email.value.as_str());
+        contact
+            .email
+            .as_deref()
+            .into_iter()
+            .chain(others)
+            .any(|email| self.is_disposable(email))
+    }
+}
+
+fn parse(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|domain| !domain.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+
+use super::{normalize_phone, normalize_text, Contact, PhoneRegion};
+
+/// Most contacts [`possible_duplicates`] gives.
+pub const MAX_DUPLICATES: usize = 5;
+
+/// Other contacts that may be the same person as `contact`: those with one
+/// of its phone numbers, both read as numbers of `region` if without a
+/// country code, and those with an email at one of the domains of its
+/// emails and a similar name. At most [`MAX_DUPLICATES`], in the order of
+/// `others`.
+pub fn possible_duplicates(
+    contact: &Contact,
+    others: &[Contact],
+    region: Option<PhoneRegion>,
+) -> Vec<Contact> {
+    let (own_phones, own_domains, own_name) =
+        (phones(contact, region), domains(contact), name(contact));
+    others
+        .iter()
+        .filter(|other| contact.id.is_none() || other.id != contact.id)
+        .filter(|other| {
+            let same_phone = !own_phones.is_disjoint(&phones(other, region));
+            let same_domain = !own_domains.is_disjoint(&domains(other));
+            let similar_name = match (&own_name, name(other)) {
+                (Some(own), Some(other)) => similar(own, &other),
+                _ => false,
+            };
+            same_phone || same_domain && similar_name
+        })
+        .take(MAX_DUPLICATES)
+        .cloned()
+        .collect()
+}
+
+fn phones(contact: &Contact, region: Option<PhoneRegion>) -> HashSet<String> {
+    contact
+        .phones
+        .iter()
+        .map(|phone| normalize_phone(&phone.value, region).unwrap_or_else(|| phone.value.clone()))
+        .collect()
+}
+
+/// The lowercase domains of the contact's emails, primary and other.
+fn domains(contact: &Contact) -> HashSet<String> {
+    let others = contact.emails.iter().map(|email| email.value.as_str());
+    contact
+        .email
+        .as_deref()
+        .into_iter()
+        .chain(others)
+        .filter_map(|email| email.trim().rsplit_once('@'))
+        .map(|(_, domain)| domain.trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+/// The contact's first and last names, lowercase, if it has either.
+fn name(contact: &Contact) -> Option<String> {
+    let first = contact.first.as_deref().unwrap_or_default();
+    let last = contact.last.as_deref().unwrap_or_default();
+    normalize_text(&format!("{first} {last}")).map(|name| name.to_lowercase())
+}
+
+/// Whether the names are the same but for a typo or two, the longer the
+/// names the more.
+fn similar(a: &str, b: &str) -> bool {
+    let shortest = a.chars().count().min(b.chars().count());
+    edit_distance(a, b) <= shortest / 5
+}
+
+/// The fewest characters to insert, delete or replace to make `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
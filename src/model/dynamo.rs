@@ -0,0 +1,499 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use aws_sdk_dynamodb::{
+    types::{
+        AttributeDefinition, AttributeValue, BillingMode, GlobalSecondaryIndex, KeySchemaElement,
+        KeyType, Projection, ProjectionType, Put, ReturnValue, ScalarAttributeType, Select,
+        TableStatus, TransactWriteItem,
+    },
+    Client,
+};
+use chrono::{DateTime, Utc};
+
+use super::{
+    phones, transaction::email_taken, unique_phones, BoxedTransaction, Changes, Contact,
+    ContactChange, ContactRepo, Page, RepoError, RepoStats, SaveMode, SearchQuery,
+    SharedContactRepo, Sort, Stage, StagedTransaction,
+};
+
+/// Contact repository backed by a DynamoDB table, so the app itself can run
+/// stateless, e.g. on Fargate or Lambda.
+///
+/// Each contact is an item keyed by its numeric `id`, with the contact as
+/// JSON in `data` and its `email` in an attribute of its own, indexed by the
+/// `email` global secondary index that saves check for duplicates. The index
+/// is only eventually consistent, so two instances saving the same email at
+/// the same moment can both succeed. The item with id 0 hands out ids.
+/// Deleting a contact replaces its item with a tombstone, which has when it
+/// was deleted in `deleted_at` instead of `data`.
+#[derive(Debug, Clone)]
+pub struct DynamoContactRepo {
+    client: Client,
+    table: String,
+}
+
+type Item = HashMap<String, AttributeValue>;
+
+const EMAIL_INDEX: &str = "email";
+
+/// Id of the item holding the id counter, in its `next_id` attribute.
+const COUNTER_ID: u64 = 0;
+
+/// Most changes DynamoDB applies in one transaction.
+const MAX_TRANSACTION_ITEMS: usize = 100;
+
+impl DynamoContactRepo {
+    /// Opens `table`, creating it if needed, with the usual AWS environment
+    /// variables: `AWS_REGION`, `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+    /// (or a profile or role) and, e.g. for DynamoDB Local,
+    /// `AWS_ENDPOINT_URL`.
+    pub async fn from_table(table: &str) -> Self {
+        let config = aws_config::load_from_env().await;
+        let repo = Self {
+            client: Client::new(&config),
+            table: table.to_owned(),
+        };
+        repo.create_table().await;
+        repo
+    }
+
+    pub async fn shared_from_table(table: &str) -> SharedContactRepo {
+        Arc::new(Self::from_table(table).await)
+    }
+
+    /// Creates the table unless it exists, and waits for it to be usable.
+    async fn create_table(&self) {
+        let described = self
+            .client
+            .describe_table()
+            .table_name(&self.table)
+            .send()
+            .await;
+        match described {
+            Ok(_) => return,
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_resource_not_found_exception()) => {}
+            Err(err) => panic!("describing table '{}' failed: {err:?}", self.table),
+        }
+        let created = self
+            .client
+            .create_table()
+            .table_name(&self.table)
+            .billing_mode(BillingMode::PayPerRequest)
+            .attribute_definitions(attribute("id", ScalarAttributeType::N))
+            .attribute_definitions(attribute("email", ScalarAttributeType::S))
+            .key_schema(hash_key("id"))
+            .global_secondary_indexes(
+                GlobalSecondaryIndex::builder()
+                    .index_name(EMAIL_INDEX)
+                    .key_schema(hash_key("email"))
+                    .projection(
+                        Projection::builder()
+                            .projection_type(ProjectionType::KeysOnly)
+                            .build(),
+                    )
+                    .build()
+                    .expect("a valid index"),
+            )
+            .send()
+            .await;
+        match created {
+            Ok(_) => {}
+            // Another instance is creating it.
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_resource_in_use_exception()) => {}
+            Err(err) => panic!("creating table '{}' failed: {err:?}", self.table),
+        }
+        loop {
+            let described = self
+                .client
+                .describe_table()
+                .table_name(&self.table)
+                .send()
+                .await
+                .expect("query succeed");
+            let status = described.table().and_then(|table| table.table_status());
+            if status == Some(&TableStatus::Active) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn put(&self, mut contact: Contact, mode: SaveMode) -> Result<u64, RepoError> {
+        if !contact.validate() {
+            return Err(RepoError::invalid(contact));
+        }
+        if unique_phones() {
+            contact = phones::check_phones(contact, &self.all(Sort::default()).await)?;
+        }
+        if contact.id.is_none() {
+            contact.id = Some(self.next_id(&Changes::new()).await?);
+        }
+        let id = contact.id.unwrap();
+        let owner = self.email_owner(contact.email.as_deref().unwrap()).await?;
+        if owner.is_some_and(|owner| owner != id) {
+            return Err(RepoError::email_taken(contact));
+        }
+        let mut put = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .set_item(Some(contact_to_item(&contact.bumped())))
+            .expression_attribute_names("#data", "data");
+        put = match mode {
+            SaveMode::Create => put.condition_expression("attribute_not_exists(#data)"),
+            SaveMode::Update => {
+                // Contacts saved before they had versions are at version 0.
+                let condition = if contact.version == 0 {
+                    "attribute_exists(#data) AND (attribute_not_exists(#version) OR #version = :version)"
+                } else {
+                    "attribute_exists(#data) AND #version = :version"
+                };
+                put.condition_expression(condition)
+                    .expression_attribute_names("#version", "version")
+                    .expression_attribute_values(
+                        ":version",
+                        AttributeValue::N(contact.version.to_string()),
+                    )
+            }
+        };
+        let put = put.send().await;
+        match put {
+            Ok(_) => Ok(id),
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_conditional_check_failed_exception()) =>
+            {
+                match mode {
+                    SaveMode::Create => Err(RepoError::id_taken(contact)),
+                    SaveMode::Update if self.find(id).await?.is_none() => {
+                        Err(RepoError::NotFound(id))
+                    }
+                    SaveMode::Update => Err(RepoError::stale(contact)),
+                }
+            }
+            Err(err) => Err(RepoError::io(err)),
+        }
+    }
+
+    /// All items in the table, including the counter and tombstones.
+    async fn scan_items(&self) -> Vec<Item> {
+        self.client
+            .scan()
+            .table_name(&self.table)
+            .consistent_read(true)
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await
+            .expect("query succeed")
+    }
+
+    async fn scan(&self) -> Vec<Contact> {
+        let items = self.scan_items().await;
+        let mut contacts: Vec<Contact> = items.iter().filter_map(contact_from_item).collect();
+        contacts.sort_by_key(|contact| contact.id);
+        contacts
+    }
+
+    /// Makes sure the counter hands out ids above `id`, e.g. after a backup
+    /// was restored with its ids.
+    async fn reserve_ids(&self, id: u64) -> Result<(), RepoError> {
+        let updated = self
+            .client
+            .update_item()
+            .table_name(&self.table)
+            .key("id", id_value(COUNTER_ID))
+            .update_expression("SET next_id = :id")
+            .condition_expression("attribute_not_exists(next_id) OR next_id < :id")
+            .expression_attribute_values(":id", id_value(id))
+            .send()
+            .await;
+        match updated {
+            Ok(_) => Ok(()),
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_conditional_check_failed_exception()) =>
+            {
+                Ok(())
+            }
+            Err(err) => Err(RepoError::io(err)),
+        }
+    }
+}
+
+fn attribute(name: &str, kind: ScalarAttributeType) -> AttributeDefinition {
+    AttributeDefinition::builder()
+        .attribute_name(name)
+        .attribute_type(kind)
+        .build()
+        .expect("a valid attribute")
+}
+
+fn hash_key(name: &str) -> KeySchemaElement {
+    KeySchemaElement::builder()
+        .attribute_name(name)
+        .key_type(KeyType::Hash)
+        .build()
+        .expect("a valid key")
+}
+
+fn id_value(id: u64) -> AttributeValue {
+    AttributeValue::N(id.to_string())
+}
+
+fn id_from_item(item: &Item) -> u64 {
+    let id = item.get("id").and_then(|id| id.as_n().ok());
+    id.and_then(|id| id.parse().ok()).expect("a numeric id")
+}
+
+/// The contact stored in `item`, `None` for the id counter.
+fn contact_from_item(item: &Item) -> Option<Contact> {
+    let data = item.get("data")?.as_s().expect("data to be a string");
+    let mut contact: Contact = serde_json::from_str(data).expect("valid JSON");
+    contact.id = Some(id_from_item(item));
+    Some(contact)
+}
+
+/// The id and deletion time of the tombstone in `item`, `None` for other
+/// items.
+fn tombstone_from_item(item: &Item) -> Option<(u64, DateTime<Utc>)> {
+    let deleted_at = item.get("deleted_at")?.as_s().expect("a string timestamp");
+    let deleted_at = DateTime::parse_from_rfc3339(deleted_at).expect("a valid timestamp");
+    Some((id_from_item(item), deleted_at.into()))
+}
+
+fn tombstone_item(id: u64, deleted_at: DateTime<Utc>) -> Item {
+    Item::from([
+        ("id".to_owned(), id_value(id)),
+        (
+            "deleted_at".to_owned(),
+            AttributeValue::S(deleted_at.to_rfc3339()),
+        ),
+    ])
+}
+
+fn contact_to_item(contact: &Contact) -> Item {
+    let data = serde_json::to_string(contact).expect("serializing succeed");
+    let mut item = Item::from([
+        ("id".to_owned(), id_value(contact.id.unwrap())),
+        ("data".to_owned(), AttributeValue::S(data)),
+        (
+            "version".to_owned(),
+            AttributeValue::N(contact.version.to_string()),
+        ),
+    ]);
+    if let Some(email) = &contact.email {
+        item.insert("email".to_owned(), AttributeValue::S(email.clone()));
+    }
+    item
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for DynamoContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let mut contacts = self.scan().await;
+        sort.apply(&mut contacts);
+        contacts
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        if let Some(query) = filter.map(SearchQuery::parse) {
+            return self
+                .scan()
+                .await
+                .iter()
+                .filter(|c| c.matches(&query))
+                .count();
+        }
+        let pages: Vec<_> = self
+            .client
+            .scan()
+            .table_name(&self.table)
+            .select(Select::Count)
+            .filter_expression("attribute_exists(#data)")
+            .expression_attribute_names("#data", "data")
+            .into_paginator()
+            .send()
+            .try_collect()
+            .await
+            .expect("query succeed");
+        pages.iter().map(|page| page.count() as usize).sum()
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let contacts = self.scan().await;
+        let matches = contacts.iter().filter(|contact| contact.matches(&query));
+        Page::of(matches, sort, number, size)
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        self.put(contact, SaveMode::Create).await
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        self.put(contact, SaveMode::Update).await?;
+        Ok(())
+    }
+
+    /// Looked up in the email index.
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        match self.email_owner(email).await? {
+            Some(id) => self.find(id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("id", id_value(id))
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(RepoError::io)?;
+        Ok(output.item().and_then(contact_from_item))
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let deleted = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .set_item(Some(tombstone_item(id, Utc::now())))
+            // Also keeps the id counter from being deleted.
+            .condition_expression("attribute_exists(#data)")
+            .expression_attribute_names("#data", "data")
+            .send()
+            .await;
+        match deleted {
+            Ok(_) => Ok(()),
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_conditional_check_failed_exception()) =>
+            {
+                Err(RepoError::NotFound(id))
+            }
+            Err(err) => Err(RepoError::io(err)),
+        }
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let items = self.scan_items().await;
+        ContactChange::since(
+            items.iter().filter_map(contact_from_item),
+            items.iter().filter_map(tombstone_from_item),
+            since,
+        )
+    }
+
+    /// Commits in one DynamoDB transaction, or in several, and so not
+    /// atomically, when more than 100 contacts changed.
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        Ok(Box::new(StagedTransaction::new(self.clone(), None)))
+    }
+
+    /// The size is DynamoDB's estimate, which it updates every few hours.
+    async fn stats(&self) -> RepoStats {
+        let described = self
+            .client
+            .describe_table()
+            .table_name(&self.table)
+            .send()
+            .await
+            .expect("query succeed");
+        let size = described.table().and_then(|table| table.table_size_bytes());
+        RepoStats {
+            count: self.count(None).await,
+            storage_size: size.map(|size| size as u64),
+            ..RepoStats::default()
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Stage for DynamoContactRepo {
+    async fn email_owner(&self, email: &str) -> Result<Option<u64>, RepoError> {
+        let output = self
+            .client
+            .query()
+            .table_name(&self.table)
+            .index_name(EMAIL_INDEX)
+            .key_condition_expression("email = :email")
+            .expression_attribute_values(":email", AttributeValue::S(email.to_owned()))
+            .send()
+            .await
+            .map_err(RepoError::io)?;
+        Ok(output.items().first().map(id_from_item))
+    }
+
+    async fn next_id(&self, _changes: &Changes) -> Result<u64, RepoError> {
+        let output = self
+            .client
+            .update_item()
+            .table_name(&self.table)
+            .key("id", id_value(COUNTER_ID))
+            .update_expression("ADD next_id :one")
+            .expression_attribute_values(":one", id_value(1))
+            .return_values(ReturnValue::UpdatedNew)
+            .send()
+            .await
+            .map_err(RepoError::io)?;
+        let next_id = output.attributes().and_then(|item| item.get("next_id"));
+        Ok(next_id
+            .and_then(|id| id.as_n().ok()?.parse().ok())
+            .expect("a numeric id"))
+    }
+
+    async fn apply(&self, changes: &Changes) -> Result<(), RepoError> {
+        for (id, change) in changes {
+            let Some(email) = change.as_ref().and_then(|contact| contact.email.as_deref()) else {
+                continue;
+            };
+            let owner = self.email_owner(email).await?;
+            if owner.is_some_and(|owner| !changes.contains_key(&owner)) {
+                return Err(email_taken(changes, *id));
+            }
+        }
+        let deleted_at = Utc::now();
+        let items: Vec<TransactWriteItem> = changes
+            .iter()
+            .map(|(id, change)| {
+                let item = match change {
+                    Some(contact) => contact_to_item(contact),
+                    None => tombstone_item(*id, deleted_at),
+                };
+                let put = Put::builder()
+                    .table_name(&self.table)
+                    .set_item(Some(item))
+                    .build()
+                    .expect("a valid put");
+                TransactWriteItem::builder().put(put).build()
+            })
+            .collect();
+        for batch in items.chunks(MAX_TRANSACTION_ITEMS) {
+            self.client
+                .transact_write_items()
+                .set_transact_items(Some(batch.to_vec()))
+                .send()
+                .await
+                .map_err(RepoError::io)?;
+        }
+        if let Some(max_id) = changes.keys().max() {
+            self.reserve_ids(*max_id).await?;
+        }
+        Ok(())
+    }
+}
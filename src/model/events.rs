@@ -0,0 +1,375 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::{
+    history::current_author, BoxedTransaction, Contact, ContactChange, ContactFilter, ContactRepo,
+    ContactTransaction, Cursor, CursorPage, NameOrder, Page, RepoError, RepoStats,
+    SharedContactRepo, Sort,
+};
+
+/// Sends a [`ContactEvent`] to the [subscribers](ContactRepo::subscribe) of
+/// another repo for each contact created, updated or deleted through it, so
+/// webhooks, live updates or an audit log can follow the store.
+///
+/// Events are sent once a change is stored, in transactions once they are
+/// committed. Changes made to the underlying repo by anything else, such as
+/// another instance sharing the database, are only sent if that repo
+/// [sends](ContactRepo::subscribe) them itself, as a watched JSON file does.
+/// While nobody subscribes, calls are passed through as they are.
+pub struct EventedRepo {
+    inner: SharedContactRepo,
+    events: broadcast::Sender<ContactEvent>,
+}
+
+/// Events a subscriber may fall behind by before it misses some, and gets a
+/// [`broadcast::error::RecvError::Lagged`] instead.
+pub const EVENT_BUFFER: usize = 256;
+
+/// A change made through an [`EventedRepo`], with the contact as stored and,
+/// if known, who saved it; see [`with_author`](super::with_author).
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum ContactEvent {
+    Created {
+        contact: Contact,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    Updated {
+        contact: Contact,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    Deleted {
+        id: u64,
+    },
+}
+
+/// A transaction on the underlying repo, sending the events of its changes
+/// when committed.
+struct EventedTransaction {
+    inner: BoxedTransaction,
+    repo: SharedContactRepo,
+    events: broadcast::Sender<ContactEvent>,
+    staged: Vec<Staged>,
+}
+
+/// A change made in an [`EventedTransaction`], to look up once committed.
+enum Staged {
+    Saved {
+        id: Option<u64>,
+        email: String,
+        created: bool,
+    },
+    Deleted(u64),
+}
+
+impl EventedRepo {
+    /// Wraps `inner`, passing on the changes it sends itself. Must be called
+    /// from within a tokio runtime if it sends any.
+    pub fn new(inner: SharedContactRepo) -> Self {
+        let events = broadcast::channel(EVENT_BUFFER).0;
+        if let Some(mut changes) = inner.subscribe() {
+            let events = events.clone();
+            tokio::spawn(async move {
+                loop {
+                    match changes.recv().await {
+                        Ok(event) => send(&events, event),
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            eprintln!("missed {missed} changes of the storage");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+        Self { inner, events }
+    }
+
+    pub fn shared(inner: SharedContactRepo) -> SharedContactRepo {
+        Arc::new(Self::new(inner))
+    }
+
+    fn listened(&self) -> bool {
+        self.events.receiver_count() > 0
+    }
+
+    /// Sends the event of saving the contact with `id`, as now stored.
+    async fn saved(&self, id: u64, created: bool) {
+        if !self.listened() {
+            return;
+        }
+        if let Ok(Some(contact)) = self.inner.find(id).await {
+            send(&self.events, saved(contact, created));
+        }
+    }
+}
+
+fn saved(contact: Contact, created: bool) -> ContactEvent {
+    let author = current_author();
+    if created {
+        ContactEvent::Created { contact, author }
+    } else {
+        ContactEvent::Updated { contact, author }
+    }
+}
+
+/// Sends `event`, which only fails when nobody is subscribed.
+fn send(events: &broadcast::Sender<ContactEvent>, event: ContactEvent) {
+    let _ = events.send(event);
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for EventedRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        self.inner.all(sort).await
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        self.inner.count(filter).await
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.search(query, sort, number, size).await
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        filter: &ContactFilter,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        self.inner
+            .search_filtered(query, filter, sort, number, size)
+            .await
+    }
+
+    async fn fuzzy_search(
+        &self,
+        query: &str,
+        threshold: f64,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        self.inner
+            .fuzzy_search(query, threshold, sort, number, size)
+            .await
+    }
+
+    async fn tagged(&self, tag: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.tagged(tag, sort, number, size).await
+    }
+
+    async fn tags(&self) -> BTreeMap<String, usize> {
+        self.inner.tags().await
+    }
+
+    async fn members(&self, group: u64, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.members(group, sort, number, size).await
+    }
+
+    async fn group_sizes(&self) -> BTreeMap<u64, usize> {
+        self.inner.group_sizes().await
+    }
+
+    async fn by_initial(
+        &self,
+        initial: char,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        self.inner.by_initial(initial, sort, number, size).await
+    }
+
+    async fn initials(&self, names: NameOrder) -> BTreeMap<char, usize> {
+        self.inner.initials(names).await
+    }
+
+    async fn starred(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.starred(sort, number, size).await
+    }
+
+    async fn archived(
+        &self,
+        archived: bool,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        self.inner.archived(archived, sort, number, size).await
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        let id = self.inner.create(contact).await?;
+        self.saved(id, true).await;
+        Ok(id)
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        let id = contact.id.expect("an updated contact to have an id");
+        self.inner.update(contact).await?;
+        self.saved(id, false).await;
+        Ok(())
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        self.inner.find(id).await
+    }
+
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        self.inner.find_many(ids).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        self.inner.find_by_email(email).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<Contact>, RepoError> {
+        self.inner.find_by_uuid(uuid).await
+    }
+
+    async fn exists_by_email(&self, email: &str) -> Result<bool, RepoError> {
+        self.inner.exists_by_email(email).await
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        self.inner.delete_by_id(id).await?;
+        send(&self.events, ContactEvent::Deleted { id });
+        Ok(())
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        Ok(Box::new(EventedTransaction {
+            inner: self.inner.begin().await?,
+            repo: self.inner.clone(),
+            events: self.events.clone(),
+            staged: Vec::new(),
+        }))
+    }
+
+    /// Sends an event for every contact replaced, telling created from
+    /// updated ones by the ids stored before.
+    async fn replace_all(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        if !self.listened() {
+            return self.inner.replace_all(contacts).await;
+        }
+        let before: HashSet<u64> = self
+            .inner
+            .all(Sort::default())
+            .await
+            .iter()
+            .filter_map(Contact::id)
+            .collect();
+        self.inner.replace_all(contacts).await?;
+        let after = self.inner.all(Sort::default()).await;
+        let kept: HashSet<u64> = after.iter().filter_map(Contact::id).collect();
+        for &id in before.difference(&kept) {
+            send(&self.events, ContactEvent::Deleted { id });
+        }
+        for contact in after {
+            let created = !contact.id().is_some_and(|id| before.contains(&id));
+            send(&self.events, saved(contact, created));
+        }
+        Ok(())
+    }
+
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.page(sort, number, size).await
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        self.inner.all_after(cursor, limit).await
+    }
+
+    fn stream_all(&self) -> BoxStream<'_, Contact> {
+        self.inner.stream_all()
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        self.inner.changed_since(since).await
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ContactEvent>> {
+        Some(self.events.subscribe())
+    }
+
+    async fn flush(&self) {
+        self.inner.flush().await;
+    }
+
+    async fn stats(&self) -> RepoStats {
+        self.inner.stats().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ContactTransaction for EventedTransaction {
+    async fn all(&mut self) -> Result<Vec<Contact>, RepoError> {
+        self.inner.all().await
+    }
+
+    async fn find(&mut self, id: u64) -> Result<Option<Contact>, RepoError> {
+        self.inner.find(id).await
+    }
+
+    async fn save(&mut self, contact: Contact) -> Result<u64, RepoError> {
+        let id = contact.id;
+        let created = match id {
+            Some(id) if self.events.receiver_count() > 0 => self.inner.find(id).await?.is_none(),
+            Some(_) => false,
+            None => true,
+        };
+        let email = contact.email.clone().unwrap_or_default();
+        let saved = self.inner.save(contact).await?;
+        self.staged.push(Staged::Saved { id, email, created });
+        Ok(saved)
+    }
+
+    async fn delete(&mut self, contact: Contact) -> Result<(), RepoError> {
+        let id = contact.id.expect("a deleted contact to have an id");
+        self.inner.delete(contact).await?;
+        self.staged.push(Staged::Deleted(id));
+        Ok(())
+    }
+
+    /// Sends the events once committed, looking the saved contacts up by id
+    /// or, for those created without one, by email.
+    async fn commit(self: Box<Self>) -> Result<(), RepoError> {
+        self.inner.commit().await?;
+        if self.events.receiver_count() == 0 {
+            return Ok(());
+        }
+        for staged in self.staged {
+            let event = match staged {
+                Staged::Saved { id, email, created } => {
+                    let stored = match id {
+                        Some(id) => self.repo.find(id).await,
+                        None => self.repo.find_by_email(&email).await,
+                    };
+                    match stored {
+                        Ok(Some(contact)) => saved(contact, created),
+                        _ => continue,
+                    }
+                }
+                Staged::Deleted(id) => ContactEvent::Deleted { id },
+            };
+            send(&self.events, event);
+        }
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) {
+        self.inner.rollback().await;
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::sync::RwLock;
+
+use super::write_atomic;
+
+/// An attribute users track on contacts besides the built-in ones, such as
+/// a member number. Contacts keep their values by field name in
+/// [`Contact::custom`](super::Contact::custom).
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct CustomField {
+    /// Unique among fields, and fixed once created so values stay found.
+    pub name: String,
+    #[serde(default)]
+    pub kind: FieldKind,
+    /// Whether saving a contact without a value fails.
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub errors: HashMap<String, String>,
+}
+
+/// The type of a [`CustomField`]'s values, and how it is entered.
+#[derive(
+    Debug, Clone, Copy, Default, Hash, PartialEq, Eq, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    /// A line of text, stored as a string.
+    #[default]
+    Text,
+    /// Stored as a JSON number.
+    Number,
+    /// Stored as a `YYYY-MM-DD` string.
+    Date,
+    /// A checkbox, stored as a boolean.
+    Checkbox,
+}
+
+impl CustomField {
+    pub fn new(name: impl Into<String>, kind: FieldKind, required: bool) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            required,
+            ..Default::default()
+        }
+    }
+
+    pub fn validate(&mut self) -> bool {
+        let name = self.name.trim();
+        if name.is_empty() {
+            self.errors.insert("name".into(), "Name Required".into());
+        } else if !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        {
+            self.errors.insert("name".into(), "Invalid Name".into());
+        }
+        self.errors.is_empty()
+    }
+}
+
+/// Why a [`CustomFieldRepo`] operation failed.
+#[derive(Debug)]
+pub enum FieldError {
+    /// There is no field with this name.
+    NotFound(String),
+    /// The field is invalid, its `errors` say why.
+    Validation(Box<CustomField>),
+    /// Another field has the field's name.
+    Conflict(Box<CustomField>),
+    /// The storage failed.
+    Io(io::Error),
+}
+
+impl FieldError {
+    /// Wraps an error of the storage.
+    pub fn io(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self::Io(io::Error::other(err))
+    }
+
+    /// [`Self::Conflict`] for a new field whose name another field has.
+    pub fn name_taken(mut field: CustomField) -> Self {
+        field
+            .errors
+            .insert("name".into(), "Name Already Exists".into());
+        Self::Conflict(Box::new(field))
+    }
+
+    /// The rejected field, if the error is one the user can fix by editing
+    /// it.
+    pub fn into_field(self) -> Result<CustomField, Self> {
+        match self {
+            Self::Validation(field) | Self::Conflict(field) => Ok(*field),
+            err => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(name) => write!(f, "field '{name}' not found"),
+            Self::Validation(field) => {
+                write!(f, "field '{}' is invalid: {:?}", field.name, field.errors)
+            }
+            Self::Conflict(field) => write!(
+                f,
+                "field '{}' conflicts with another: {:?}",
+                field.name, field.errors
+            ),
+            Self::Io(err) => write!(f, "storage failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// The schema of the custom fields, which can be changed while the app
+/// runs.
+#[async_trait::async_trait]
+pub trait CustomFieldRepo {
+    /// All fields, in the order they were created.
+    async fn all(&self) -> Vec<CustomField>;
+    async fn find(&self, name: &str) -> Result<Option<CustomField>, FieldError>;
+    /// Adds `field`, failing with [`FieldError::Conflict`] if its name is
+    /// taken.
+    async fn create(&self, field: CustomField) -> Result<(), FieldError>;
+    /// Replaces the field with `field`'s name, failing with
+    /// [`FieldError::NotFound`] if there is none.
+    async fn update(&self, field: CustomField) -> Result<(), FieldError>;
+    /// Deletes the field called `name`, failing with
+    /// [`FieldError::NotFound`] if there is none. Contacts keep their values,
+    /// which show up again if a field with the name is created.
+    async fn delete(&self, name: &str) -> Result<(), FieldError>;
+}
+
+pub type SharedCustomFieldRepo = Arc<dyn CustomFieldRepo + Sync + Send>;
+
+/// Custom field repository kept in memory and, if opened from a path,
+/// written to a JSON file on every change, for the storages that don't keep
+/// the schema themselves.
+#[derive(Debug, Default)]
+pub struct MemCustomFieldRepo {
+    path: Option<PathBuf>,
+    fields: RwLock<Vec<CustomField>>,
+}
+
+impl MemCustomFieldRepo {
+    pub fn new_shared() -> SharedCustomFieldRepo {
+        Arc::new(Self::default())
+    }
+
+    /// Loads the fields from the file at `path`, if there is one.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let fields = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).expect("a valid fields file"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => panic!("failed to read {}: {err}", path.display()),
+        };
+        Self {
+            path: Some(path.to_owned()),
+            fields: RwLock::new(fields),
+        }
+    }
+
+    pub fn shared_from_path(path: impl AsRef<Path>) -> SharedCustomFieldRepo {
+        Arc::new(Self::from_path(path))
+    }
+
+    fn write(&self, fields: &[CustomField]) -> Result<(), FieldError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec_pretty(fields).map_err(FieldError::io)?;
+        write_atomic(path, &data).map_err(FieldError::Io)
+    }
+}
+
+fn position(fields: &[CustomField], name: &str) -> Option<usize> {
+    fields.iter().position(|field| field.name == name)
+}
+
+#[async_trait::async_trait]
+impl CustomFieldRepo for MemCustomFieldRepo {
+    async fn all(&self) -> Vec<CustomField> {
+        self.fields.read().await.clone()
+    }
+
+    async fn find(&self, name: &str) -> Result<Option<CustomField>, FieldError> {
+        let fields = self.fields.read().await;
+        Ok(position(&fields, name).map(|index| fields[index].clone()))
+    }
+
+    async fn create(&self, mut field: CustomField) -> Result<(), FieldError> {
+        field.name = field.name.trim().to_owned();
+        if !field.validate() {
+            return Err(FieldError::Validation(Box::new(field)));
+        }
+        let mut fields = self.fields.write().await;
+        if position(&fields, &field.name).is_some() {
+            return Err(FieldError::name_taken(field));
+        }
+        fields.push(field);
+        self.write(&fields)
+    }
+
+    async fn update(&self, field: CustomField) -> Result<(), FieldError> {
+        let mut fields = self.fields.write().await;
+        let Some(index) = position(&fields, &field.name) else {
+            return Err(FieldError::NotFound(field.name));
+        };
+        fields[index] = field;
+        self.write(&fields)
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), FieldError> {
+        let mut fields = self.fields.write().await;
+        let index = position(&fields, name).ok_or_else(|| FieldError::NotFound(name.to_owned()))?;
+        fields.remove(index);
+        self.write(&fields)
+    }
+}
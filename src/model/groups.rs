@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::sync::RwLock;
+
+use super::write_atomic;
+
+/// A named set of contacts, such as a mailing list. Contacts list the ids of
+/// the groups they are in, see [`Contact::groups`](super::Contact::groups),
+/// so a contact can be in any number of groups.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct Group {
+    pub(super) id: Option<u64>,
+    /// Unique among groups.
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub errors: HashMap<String, String>,
+}
+
+impl Group {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    pub fn validate(&mut self) -> bool {
+        if self.name.trim().is_empty() {
+            self.errors.insert("name".into(), "Name Required".into());
+        }
+        self.errors.is_empty()
+    }
+}
+
+/// Why a [`GroupRepo`] operation failed.
+#[derive(Debug)]
+pub enum GroupError {
+    /// There is no group with this id.
+    NotFound(u64),
+    /// The group is invalid, its `errors` say why.
+    Validation(Box<Group>),
+    /// Another group has the group's name.
+    Conflict(Box<Group>),
+    /// The storage failed.
+    Io(io::Error),
+}
+
+impl GroupError {
+    /// Wraps an error of the storage.
+    pub fn io(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self::Io(io::Error::other(err))
+    }
+
+    /// [`Self::Conflict`] for a group whose name another group has.
+    pub fn name_taken(mut group: Group) -> Self {
+        group
+            .errors
+            .insert("name".into(), "Name Already Exists".into());
+        Self::Conflict(Box::new(group))
+    }
+
+    /// The rejected group, if the error is one the user can fix by editing
+    /// it.
+    pub fn into_group(self) -> Result<Group, Self> {
+        match self {
+            Self::Validation(group) | Self::Conflict(group) => Ok(*group),
+            err => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "group {id} not found"),
+            Self::Validation(group) => {
+                write!(f, "group '{}' is invalid: {:?}", group.name, group.errors)
+            }
+            Self::Conflict(group) => write!(
+                f,
+                "group '{}' conflicts with another: {:?}",
+                group.name, group.errors
+            ),
+            Self::Io(err) => write!(f, "storage failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for GroupError {}
+
+#[async_trait::async_trait]
+pub trait GroupRepo {
+    /// All groups, ordered by name.
+    async fn all(&self) -> Vec<Group>;
+    async fn find(&self, id: u64) -> Result<Option<Group>, GroupError>;
+    /// Adds `group` with a new id, never one of a deleted group, and
+    /// returns it. Fails with [`GroupError::Conflict`] if its name is taken.
+    async fn create(&self, group: Group) -> Result<u64, GroupError>;
+    /// Replaces the group with `group`'s id, failing with
+    /// [`GroupError::NotFound`] if there is none and with
+    /// [`GroupError::Conflict`] if another group has its name.
+    async fn update(&self, group: Group) -> Result<(), GroupError>;
+    /// Deletes the group with `id`, failing with [`GroupError::NotFound`] if
+    /// there is none. Contacts still listing it have to be updated apart.
+    async fn delete_by_id(&self, id: u64) -> Result<(), GroupError>;
+}
+
+pub type SharedGroupRepo = Arc<dyn GroupRepo + Sync + Send>;
+
+/// Orders groups by name, ignoring case.
+pub(super) fn sort_groups(groups: &mut [Group]) {
+    groups.sort_by_cached_key(|group| group.name.to_lowercase());
+}
+
+/// Group repository kept in memory and, if opened from a path, written to a
+/// JSON file on every change, for the storages that don't keep groups
+/// themselves.
+#[derive(Debug, Default)]
+pub struct MemGroupRepo {
+    path: Option<PathBuf>,
+    groups: RwLock<GroupFile>,
+}
+
+/// The groups of a [`MemGroupRepo`], as written to its file.
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct GroupFile {
+    /// Id of the next group created, so ids of deleted groups aren't reused
+    /// by contacts still listing them.
+    next_id: u64,
+    groups: Vec<Group>,
+}
+
+impl MemGroupRepo {
+    pub fn new_shared() -> SharedGroupRepo {
+        Arc::new(Self::default())
+    }
+
+    /// Loads the groups from the file at `path`, if there is one.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let groups = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).expect("a valid groups file"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => GroupFile::default(),
+            Err(err) => panic!("failed to read {}: {err}", path.display()),
+        };
+        Self {
+            path: Some(path.to_owned()),
+            groups: RwLock::new(groups),
+        }
+    }
+
+    pub fn shared_from_path(path: impl AsRef<Path>) -> SharedGroupRepo {
+        Arc::new(Self::from_path(path))
+    }
+
+    fn write(&self, groups: &GroupFile) -> Result<(), GroupError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec_pretty(groups).map_err(GroupError::io)?;
+        write_atomic(path, &data).map_err(GroupError::Io)
+    }
+}
+
+impl GroupFile {
+    fn check(&self, mut group: Group) -> Result<Group, GroupError> {
+        if !group.validate() {
+            return Err(GroupError::Validation(Box::new(group)));
+        }
+        let owner = self.groups.iter().find(|other| other.name == group.name);
+        if owner.is_some_and(|owner| owner.id != group.id) {
+            return Err(GroupError::name_taken(group));
+        }
+        Ok(group)
+    }
+
+    fn position(&self, id: u64) -> Result<usize, GroupError> {
+        self.groups
+            .iter()
+            .position(|group| group.id == Some(id))
+            .ok_or(GroupError::NotFound(id))
+    }
+}
+
+#[async_trait::async_trait]
+impl GroupRepo for MemGroupRepo {
+    async fn all(&self) -> Vec<Group> {
+        let mut groups = self.groups.read().await.groups.clone();
+        sort_groups(&mut groups);
+        groups
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Group>, GroupError> {
+        let groups = self.groups.read().await;
+        Ok(groups
+            .groups
+            .iter()
+            .find(|group| group.id == Some(id))
+            .cloned())
+    }
+
+    async fn create(&self, group: Group) -> Result<u64, GroupError> {
+        let mut groups = self.groups.write().await;
+        let mut group = groups.check(Group { id: None, ..group })?;
+        let id = groups.next_id.max(1);
+        group.id = Some(id);
+        groups.next_id = id + 1;
+        groups.groups.push(group);
+        self.write(&groups)?;
+        Ok(id)
+    }
+
+    async fn update(&self, group: Group) -> Result<(), GroupError> {
+        let id = group.id.expect("an updated group to have an id");
+        let mut groups = self.groups.write().await;
+        let index = groups.position(id)?;
+        groups.groups[index] = groups.check(group)?;
+        self.write(&groups)
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), GroupError> {
+        let mut groups = self.groups.write().await;
+        let index = groups.position(id)?;
+        groups.groups.remove(index);
+        self.write(&groups)
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    future::Future,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::{
+    sync::{broadcast::error::RecvError, Mutex, RwLock},
+    task::JoinHandle,
+};
+
+use super::{write_atomic, Contact, ContactEvent, SharedContactRepo, StoreKey};
+
+/// A saved version of a contact, with who saved it and what changed.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Revision {
+    pub contact_id: u64,
+    /// The [`Contact::version`] saved.
+    pub version: u64,
+    pub at: DateTime<Utc>,
+    /// Who saved it, see [`with_author`]; `None` if not known.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// The fields that differ from the revision before, or that are set if
+    /// there is none.
+    pub changes: Vec<FieldChange>,
+    /// The contact as saved, to revert to.
+    pub contact: Contact,
+}
+
+/// A field of a [`Revision`] that changed, with its values serialized as
+/// stored, `null` if it wasn't set.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Fields that identify the contact, that the repo sets on every save, or
+/// that [`ContactRepo::restore`](super::ContactRepo::restore) keeps, which
+/// aren't changes of their own.
+const UNTRACKED_FIELDS: [&str; 7] = [
+    "id",
+    "uuid",
+    "has_photo",
+    "attachments",
+    "version",
+    "created_at",
+    "updated_at",
+];
+
+impl Revision {
+    /// The revision of saving `contact` with a stored id, following the one
+    /// of `previous` if it was saved before.
+    pub fn new(contact: Contact, author: Option<String>, previous: Option<&Contact>) -> Self {
+        let after = serde_json::to_value(&contact).expect("a contact to serialize");
+        let before = previous.map_or(Value::Null, |previous| {
+            serde_json::to_value(previous).expect("a contact to serialize")
+        });
+        let fields: BTreeSet<&String> = [&before, &after]
+            .into_iter()
+            .filter_map(Value::as_object)
+            .flat_map(|object| object.keys())
+            .filter(|field| !UNTRACKED_FIELDS.contains(&field.as_str()))
+            .collect();
+        let changes = fields
+            .into_iter()
+            .filter_map(|field| {
+                let before = before.get(field).cloned().unwrap_or_default();
+                let after = after.get(field).cloned().unwrap_or_default();
+                let changed = before != after && !(is_blank(&before) && is_blank(&after));
+                changed.then(|| FieldChange {
+                    field: field.clone(),
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        Self {
+            contact_id: contact.id.expect("a stored contact to have an id"),
+            version: contact.version,
+            at: contact.updated_at.unwrap_or_else(Utc::now),
+            author,
+            changes,
+            contact,
+        }
+    }
+}
+
+/// Whether `value` is how a field that isn't set is serialized.
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(values) => values.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        Value::Number(_) | Value::Bool(true) => false,
+    }
+}
+
+tokio::task_local! {
+    static AUTHOR: Option<String>;
+}
+
+/// Runs `f`, recording `author` as who saved the contacts it saves.
+pub async fn with_author<F: Future>(author: Option<String>, f: F) -> F::Output {
+    AUTHOR.scope(author, f).await
+}
+
+/// The author set by [`with_author`] for the running task, if any.
+pub fn current_author() -> Option<String> {
+    AUTHOR.try_with(Clone::clone).ok().flatten()
+}
+
+/// The revisions of each contact.
+#[async_trait::async_trait]
+pub trait HistoryRepo {
+    /// The revisions of the contact with `contact_id`, newest first.
+    async fn revisions(&self, contact_id: u64) -> io::Result<Vec<Revision>>;
+
+    /// The revision of the contact with `contact_id` at `version`, if any.
+    async fn revision(&self, contact_id: u64, version: u64) -> io::Result<Option<Revision>> {
+        let revisions = self.revisions(contact_id).await?;
+        Ok(revisions
+            .into_iter()
+            .find(|revision| revision.version == version))
+    }
+
+    /// Adds `revision`, unless there is one of the contact at its version.
+    async fn add(&self, revision: Revision) -> io::Result<()>;
+
+    /// Forgets the revisions of the contact with `contact_id`, once deleted.
+    async fn clear(&self, contact_id: u64) -> io::Result<()>;
+}
+
+pub type SharedHistoryRepo = Arc<dyn HistoryRepo + Sync + Send>;
+
+/// Records a [`Revision`] of each contact saved through `repo` in `history`,
+/// from the events `repo` sends, and forgets those of deleted contacts.
+/// Does nothing if `repo` sends none, see
+/// [`EventedRepo`](super::EventedRepo).
+pub fn spawn(repo: &SharedContactRepo, history: SharedHistoryRepo) -> Option<JoinHandle<()>> {
+    let mut events = repo.subscribe()?;
+    Some(tokio::spawn(async move {
+        loop {
+            let recorded = match events.recv().await {
+                Ok(ContactEvent::Created { contact, author })
+                | Ok(ContactEvent::Updated { contact, author }) => {
+                    record(&history, contact, author).await
+                }
+                Ok(ContactEvent::Deleted { id }) => history.clear(id).await,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("history missed {missed} changes");
+                    Ok(())
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if let Err(err) = recorded {
+                eprintln!("recording history failed: {err}");
+            }
+        }
+    }))
+}
+
+async fn record(
+    history: &SharedHistoryRepo,
+    contact: Contact,
+    author: Option<String>,
+) -> io::Result<()> {
+    let id = contact.id.expect("a stored contact to have an id");
+    let previous = history.revisions(id).await?.into_iter().next();
+    let previous = previous.map(|revision| revision.contact);
+    history
+        .add(Revision::new(contact, author, previous.as_ref()))
+        .await
+}
+
+/// History repository kept in memory and, if opened from a path, appended to
+/// a file with one JSON line per revision, for the storages that don't keep
+/// the history themselves. With a key, each line is instead the encrypted
+/// JSON, base64 encoded, as in the [`Journal`](super::Journal).
+#[derive(Debug, Default)]
+pub struct MemHistoryRepo {
+    file: Option<(PathBuf, Mutex<fs::File>)>,
+    key: Option<StoreKey>,
+    revisions: RwLock<HashMap<u64, Vec<Revision>>>,
+}
+
+impl MemHistoryRepo {
+    pub fn new_shared() -> SharedHistoryRepo {
+        Arc::new(Self::default())
+    }
+
+    /// Loads the revisions from the file at `path`, creating it if there is
+    /// none. A trailing line without a newline, left by an append that was
+    /// cut short, is dropped.
+    pub fn from_path(path: impl AsRef<Path>, key: Option<StoreKey>) -> Self {
+        let path = path.as_ref();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => panic!("failed to read {}: {err}", path.display()),
+        };
+        let valid_len = data
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |end| end + 1);
+        let mut revisions: HashMap<u64, Vec<Revision>> = HashMap::new();
+        for line in data[..valid_len].split(|b| *b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let line = std::str::from_utf8(line).expect("a valid history file");
+            let revision = parse_revision(key.as_ref(), line).expect("a valid history file");
+            revisions
+                .entry(revision.contact_id)
+                .or_default()
+                .insert(0, revision);
+        }
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap_or_else(|err| panic!("failed to open {}: {err}", path.display()));
+        if valid_len < data.len() {
+            file.set_len(valid_len as u64)
+                .expect("a writable history file");
+        }
+        Self {
+            file: Some((path.to_owned(), Mutex::new(file))),
+            key,
+            revisions: RwLock::new(revisions),
+        }
+    }
+
+    pub fn shared_from_path(path: impl AsRef<Path>, key: Option<StoreKey>) -> SharedHistoryRepo {
+        Arc::new(Self::from_path(path, key))
+    }
+
+    fn line(&self, revision: &Revision) -> io::Result<Vec<u8>> {
+        let json = serde_json::to_vec(revision)?;
+        let mut line = match &self.key {
+            None => json,
+            Some(key) => key.seal_line(&json).into_bytes(),
+        };
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+fn parse_revision(key: Option<&StoreKey>, line: &str) -> io::Result<Revision> {
+    if line.starts_with('{') {
+        return Ok(serde_json::from_str(line)?);
+    }
+    let key = key.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "history is encrypted, but no key is configured",
+        )
+    })?;
+    Ok(serde_json::from_slice(&key.open_line(line)?)?)
+}
+
+#[async_trait::async_trait]
+impl HistoryRepo for MemHistoryRepo {
+    async fn revisions(&self, contact_id: u64) -> io::Result<Vec<Revision>> {
+        let revisions = self.revisions.read().await;
+        Ok(revisions.get(&contact_id).cloned().unwrap_or_default())
+    }
+
+    async fn add(&self, revision: Revision) -> io::Result<()> {
+        let mut revisions = self.revisions.write().await;
+        let contact = revisions.entry(revision.contact_id).or_default();
+        if contact
+            .iter()
+            .any(|stored| stored.version == revision.version)
+        {
+            return Ok(());
+        }
+        if let Some((_, file)) = &self.file {
+            let mut file = file.lock().await;
+            file.write_all(&self.line(&revision)?)?;
+            file.sync_data()?;
+        }
+        contact.insert(0, revision);
+        Ok(())
+    }
+
+    /// Rewrites the whole file without the contact's revisions.
+    async fn clear(&self, contact_id: u64) -> io::Result<()> {
+        let mut revisions = self.revisions.write().await;
+        if revisions.remove(&contact_id).is_none() {
+            return Ok(());
+        }
+        let Some((path, file)) = &self.file else {
+            return Ok(());
+        };
+        let mut file = file.lock().await;
+        let mut data = Vec::new();
+        for revision in revisions
+            .values()
+            .flat_map(|revisions| revisions.iter().rev())
+        {
+            data.extend(self.line(revision)?);
+        }
+        write_atomic(path, &data)?;
+        *file = fs::OpenOptions::new().append(true).open(path)?;
+        Ok(())
+    }
+}
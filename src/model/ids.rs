@@ -0,0 +1,78 @@
+use std::{fmt, str::FromStr};
+
+use uuid::Uuid;
+
+use super::Contact;
+
+/// How new contacts are identified outside the repo, set with `ID_STRATEGY`.
+///
+/// Repos always key contacts by their sequential id; with [`Self::Uuid`] each
+/// new contact also gets a UUIDv7, which is used in its URLs instead, so they
+/// don't reveal how many contacts there are and stay unique across stores
+/// whose exports are merged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdStrategy {
+    #[default]
+    Sequential,
+    Uuid,
+}
+
+impl IdStrategy {
+    /// Gives `contact` the ids the strategy adds, unless it has them.
+    pub fn assign(self, contact: &mut Contact) {
+        if self == Self::Uuid && contact.uuid.is_none() {
+            contact.uuid = Some(Uuid::now_v7());
+        }
+    }
+}
+
+impl FromStr for IdStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(Self::Sequential),
+            "uuid" => Ok(Self::Uuid),
+            _ => Err(format!("unknown id strategy '{s}'")),
+        }
+    }
+}
+
+/// A contact's id or uuid, as it appears in URLs; see [`Contact::key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum ContactKey {
+    Id(u64),
+    Uuid(Uuid),
+}
+
+impl FromStr for ContactKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = s.parse() {
+            return Ok(Self::Id(id));
+        }
+        match s.parse() {
+            Ok(uuid) => Ok(Self::Uuid(uuid)),
+            Err(_) => Err(format!("'{s}' is neither a contact id nor a uuid")),
+        }
+    }
+}
+
+impl TryFrom<String> for ContactKey {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ContactKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Uuid(uuid) => write!(f, "{uuid}"),
+        }
+    }
+}
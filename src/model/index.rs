@@ -0,0 +1,709 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::Hash,
+    str::FromStr,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use tokio::sync::{broadcast, RwLock};
+use uuid::Uuid;
+
+use super::{
+    phonetic::phonetic_query,
+    search::{fold, FIELD_SEPARATOR},
+    BoxedTransaction, Contact, ContactChange, ContactEvent, ContactFilter, ContactRepo,
+    ContactTransaction, Cursor, CursorPage, NameOrder, Page, RepoError, RepoStats, SearchQuery,
+    SharedContactRepo, Sort,
+};
+
+#[cfg(feature = "tantivy")]
+mod full_text;
+
+/// Searches an in-memory index of the contacts of another repo rather than
+/// the repo itself, so that searching doesn't scan the storage and fold
+/// every contact's fields on each keystroke. Text is looked up by the
+/// trigrams, runs of three characters, of the contacts' search texts, and by
+/// the codes of their phonetic texts, so that a search only checks the
+/// contacts that have them; queries shorter than a trigram check them all.
+/// Those are looked up in maps kept by the index or, with the `tantivy`
+/// feature, in a tantivy index, see [`IndexEngine`].
+///
+/// The index is built when the repo is [loaded](Self::load), on startup,
+/// and kept up to date with the contacts saved and deleted through it and
+/// those the underlying repo [sends](ContactRepo::subscribe) were, such as
+/// edits of a watched JSON file; after a transaction or
+/// [`ContactRepo::replace_all`] it is rebuilt. Other changes made to the
+/// underlying repo by anything else, such as another instance sharing the
+/// database, are not seen until the next startup.
+pub struct SearchIndexRepo {
+    inner: SharedContactRepo,
+    index: Arc<RwLock<SearchIndex>>,
+}
+
+/// What a [`SearchIndexRepo`] looks contacts up in by the trigrams and codes
+/// of a query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexEngine {
+    /// Maps kept by the index itself.
+    #[default]
+    Memory,
+    /// A tantivy index kept in memory, needs the `tantivy` feature.
+    Tantivy,
+}
+
+impl FromStr for IndexEngine {
+    type Err = String;
+
+    fn from_str(engine: &str) -> Result<Self, Self::Err> {
+        match engine {
+            "memory" => Ok(Self::Memory),
+            "tantivy" => Ok(Self::Tantivy),
+            _ => Err(format!("unknown search index '{engine}'")),
+        }
+    }
+}
+
+impl IndexEngine {
+    fn postings(self) -> Box<dyn Postings> {
+        match self {
+            Self::Memory => Box::<MemPostings>::default(),
+            #[cfg(feature = "tantivy")]
+            Self::Tantivy => Box::new(full_text::TantivyPostings::new()),
+            #[cfg(not(feature = "tantivy"))]
+            Self::Tantivy => {
+                panic!("SEARCH_INDEX=tantivy needs the app to be built with the tantivy feature")
+            }
+        }
+    }
+}
+
+/// The contacts of a repo with the [search](Contact::search_text) and
+/// [phonetic](Contact::phonetic_text) texts they are searched by, and the
+/// postings of the trigrams and codes of those.
+struct SearchIndex {
+    entries: BTreeMap<u64, Entry>,
+    postings: Box<dyn Postings>,
+}
+
+type Trigram = [char; 3];
+
+/// The trigrams of `text`, leaving out those across two fields.
+fn trigrams(text: &str) -> Vec<Trigram> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]])
+        .filter(|trigram| !trigram.contains(&FIELD_SEPARATOR))
+        .collect()
+}
+
+/// The [`metaphone`](super::phonetic::metaphone) codes of a phonetic text
+/// or query.
+fn codes(phonetic: &str) -> impl Iterator<Item = &str> {
+    phonetic.split_whitespace()
+}
+
+struct Entry {
+    contact: Contact,
+    search: String,
+    phonetic: String,
+}
+
+impl Entry {
+    /// Whether `filter` allows the contact and its search text has `folded`
+    /// or its phonetic text has `phonetic`, a phonetic query.
+    fn matches(&self, folded: &str, phonetic: Option<&str>, filter: &ContactFilter) -> bool {
+        filter.allows(&self.contact)
+            && (self.search.contains(folded)
+                || phonetic.is_some_and(|phonetic| self.phonetic.contains(phonetic)))
+    }
+}
+
+/// The ids of the entries of a [`SearchIndex`] by the trigrams of their
+/// search texts and the codes of their phonetic texts.
+trait Postings: Send + Sync {
+    fn insert(&mut self, id: u64, entry: &Entry);
+
+    fn remove(&mut self, id: u64, entry: &Entry);
+
+    fn clear(&mut self);
+
+    /// Makes the entries inserted and removed since last time seen by the
+    /// lookups.
+    fn commit(&mut self) {}
+
+    /// The ids of the entries whose search texts have every one of
+    /// `trigrams`, or `None` if there are none to look up.
+    fn having_trigrams(&self, trigrams: &[Trigram]) -> Option<BTreeSet<u64>>;
+
+    /// The ids of the entries whose phonetic texts have every one of
+    /// `codes`, or `None` if there are none to look up.
+    fn having_codes(&self, codes: &[&str]) -> Option<BTreeSet<u64>>;
+}
+
+#[derive(Default)]
+struct MemPostings {
+    trigrams: HashMap<Trigram, BTreeSet<u64>>,
+    codes: HashMap<String, BTreeSet<u64>>,
+}
+
+impl MemPostings {
+    /// The ids of the entries that have every one of `keys`, found in
+    /// `postings`, or `None` if there are no keys to look up.
+    fn having<K: Hash + Eq + Borrow<Q>, Q: Hash + Eq + ?Sized>(
+        postings: &HashMap<K, BTreeSet<u64>>,
+        keys: &[&Q],
+    ) -> Option<BTreeSet<u64>> {
+        let mut sets = Vec::new();
+        for &key in keys {
+            match postings.get(key) {
+                Some(ids) => sets.push(ids),
+                None => return Some(BTreeSet::new()),
+            }
+        }
+        sets.sort_by_key(|ids| ids.len());
+        let (smallest, others) = sets.split_first()?;
+        let ids = smallest
+            .iter()
+            .filter(|id| others.iter().all(|ids| ids.contains(id)))
+            .copied()
+            .collect();
+        Some(ids)
+    }
+}
+
+/// Removes `id` from the ids of `key` in `postings`, and the key once none
+/// has it.
+fn unpost<K: Hash + Eq + Borrow<Q>, Q: Hash + Eq + ?Sized>(
+    postings: &mut HashMap<K, BTreeSet<u64>>,
+    key: &Q,
+    id: u64,
+) {
+    if let Some(ids) = postings.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            postings.remove(key);
+        }
+    }
+}
+
+impl Postings for MemPostings {
+    fn insert(&mut self, id: u64, entry: &Entry) {
+        for trigram in trigrams(&entry.search) {
+            self.trigrams.entry(trigram).or_default().insert(id);
+        }
+        for code in codes(&entry.phonetic) {
+            self.codes.entry(code.to_owned()).or_default().insert(id);
+        }
+    }
+
+    fn remove(&mut self, id: u64, entry: &Entry) {
+        for trigram in trigrams(&entry.search) {
+            unpost(&mut self.trigrams, &trigram, id);
+        }
+        for code in codes(&entry.phonetic) {
+            unpost(&mut self.codes, code, id);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.trigrams.clear();
+        self.codes.clear();
+    }
+
+    fn having_trigrams(&self, trigrams: &[Trigram]) -> Option<BTreeSet<u64>> {
+        let keys: Vec<&Trigram> = trigrams.iter().collect();
+        Self::having(&self.trigrams, &keys)
+    }
+
+    fn having_codes(&self, codes: &[&str]) -> Option<BTreeSet<u64>> {
+        Self::having(&self.codes, codes)
+    }
+}
+
+impl SearchIndex {
+    fn build(contacts: Vec<Contact>, engine: IndexEngine) -> Self {
+        let mut index = Self {
+            entries: BTreeMap::new(),
+            postings: engine.postings(),
+        };
+        index.rebuild(contacts);
+        index
+    }
+
+    /// Indexes `contacts` instead of those indexed.
+    fn rebuild(&mut self, contacts: Vec<Contact>) {
+        self.entries.clear();
+        self.postings.clear();
+        for contact in contacts {
+            self.add(contact);
+        }
+        self.postings.commit();
+    }
+
+    fn insert(&mut self, contact: Contact) {
+        self.add(contact);
+        self.postings.commit();
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.unindex(id);
+        self.postings.commit();
+    }
+
+    /// Indexes `contact` unless a later version of it is indexed, as the
+    /// event of a save through the repo may come after the next save.
+    fn refresh(&mut self, contact: Contact) {
+        let id = contact.id.expect("a stored contact to have an id");
+        let indexed = self.entries.get(&id).map(|entry| entry.contact.version());
+        if indexed.is_none_or(|version| version <= contact.version()) {
+            self.insert(contact);
+        }
+    }
+
+    /// Indexes `contact`, to be seen by lookups once committed.
+    fn add(&mut self, contact: Contact) {
+        let id = contact.id.expect("a stored contact to have an id");
+        self.unindex(id);
+        let entry = Entry {
+            search: contact.search_text(),
+            phonetic: contact.phonetic_text(),
+            contact,
+        };
+        self.postings.insert(id, &entry);
+        self.entries.insert(id, entry);
+    }
+
+    fn unindex(&mut self, id: u64) {
+        if let Some(entry) = self.entries.remove(&id) {
+            self.postings.remove(id, &entry);
+        }
+    }
+
+    fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.entries.values().map(|entry| &entry.contact)
+    }
+
+    /// The contacts searching for `query` with `filter`
+    /// [finds](ContactFilter::finds), by id. Queries that are more than text
+    /// are matched against the contacts themselves.
+    fn search(&self, query: &str, filter: &ContactFilter) -> Vec<&Contact> {
+        let search = SearchQuery::parse(query);
+        let Some(text) = search.plain_text().map(str::to_owned) else {
+            return self
+                .contacts()
+                .filter(|contact| filter.finds(&search, contact))
+                .collect();
+        };
+        let (folded, phonetic) = (fold(&text), phonetic_query(&text));
+        let Some(mut ids) = self.postings.having_trigrams(&trigrams(&folded)) else {
+            // Too short to have a trigram.
+            return self
+                .entries
+                .values()
+                .filter(|entry| entry.matches(&folded, phonetic.as_deref(), filter))
+                .map(|entry| &entry.contact)
+                .collect();
+        };
+        if let Some(phonetic) = &phonetic {
+            let query_codes: Vec<&str> = codes(phonetic).collect();
+            ids.extend(self.postings.having_codes(&query_codes).unwrap_or_default());
+        }
+        ids.iter()
+            .filter_map(|id| self.entries.get(id))
+            .filter(|entry| entry.matches(&folded, phonetic.as_deref(), filter))
+            .map(|entry| &entry.contact)
+            .collect()
+    }
+}
+
+/// A transaction on the underlying repo, rebuilding the index once
+/// committed.
+struct IndexedTransaction {
+    inner: BoxedTransaction,
+    repo: SharedContactRepo,
+    index: Arc<RwLock<SearchIndex>>,
+}
+
+impl SearchIndexRepo {
+    /// Builds the index of the contacts of `inner`, looking them up in
+    /// `engine`, and follows the changes `inner` sends. Must be called from
+    /// within a tokio runtime if it sends any.
+    pub async fn load(inner: SharedContactRepo, engine: IndexEngine) -> Self {
+        let index = SearchIndex::build(inner.all(Sort::default()).await, engine);
+        let index = Arc::new(RwLock::new(index));
+        if let Some(changes) = inner.subscribe() {
+            tokio::spawn(follow(changes, inner.clone(), index.clone()));
+        }
+        Self { inner, index }
+    }
+
+    pub async fn shared(inner: SharedContactRepo, engine: IndexEngine) -> SharedContactRepo {
+        Arc::new(Self::load(inner, engine).await)
+    }
+
+    /// Indexes the contact with `id` as now stored.
+    async fn reindex(&self, id: u64) {
+        let stored = self.inner.find(id).await;
+        let mut index = self.index.write().await;
+        match stored {
+            Ok(Some(contact)) => index.insert(contact),
+            Ok(None) => index.remove(id),
+            // Left as it was, to be rebuilt on the next startup.
+            Err(_) => {}
+        }
+    }
+}
+
+/// Rebuilds the `index` of the contacts of `repo`.
+async fn rebuild(repo: &SharedContactRepo, index: &RwLock<SearchIndex>) {
+    let contacts = repo.all(Sort::default()).await;
+    index.write().await.rebuild(contacts);
+}
+
+/// Keeps the `index` of the contacts of `repo` up to date with the
+/// `changes` it sends, rebuilding it if some were missed.
+async fn follow(
+    mut changes: broadcast::Receiver<ContactEvent>,
+    repo: SharedContactRepo,
+    index: Arc<RwLock<SearchIndex>>,
+) {
+    loop {
+        match changes.recv().await {
+            Ok(ContactEvent::Created { contact, .. } | ContactEvent::Updated { contact, .. }) => {
+                index.write().await.refresh(contact);
+            }
+            Ok(ContactEvent::Deleted { id }) => index.write().await.remove(id),
+            Err(broadcast::error::RecvError::Lagged(_)) => rebuild(&repo, &index).await,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for SearchIndexRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        self.inner.all(sort).await
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        match filter {
+            None => self.inner.count(None).await,
+            Some(query) => {
+                let index = self.index.read().await;
+                index.search(query, &ContactFilter::default()).len()
+            }
+        }
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let index = self.index.read().await;
+        let matches = index.search(query, &ContactFilter::default());
+        Page::of(matches.into_iter(), sort, number, size)
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        filter: &ContactFilter,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let index = self.index.read().await;
+        Page::of(index.search(query, filter).into_iter(), sort, number, size)
+    }
+
+    async fn fuzzy_search(
+        &self,
+        query: &str,
+        threshold: f64,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let index = self.index.read().await;
+        let unarchived = index.contacts().filter(|contact| !contact.archived);
+        Page::by_similarity(unarchived, query, threshold, sort, number, size)
+    }
+
+    async fn tagged(&self, tag: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.tagged(tag, sort, number, size).await
+    }
+
+    async fn tags(&self) -> BTreeMap<String, usize> {
+        self.inner.tags().await
+    }
+
+    async fn members(&self, group: u64, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.members(group, sort, number, size).await
+    }
+
+    async fn group_sizes(&self) -> BTreeMap<u64, usize> {
+        self.inner.group_sizes().await
+    }
+
+    async fn by_initial(
+        &self,
+        initial: char,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        self.inner.by_initial(initial, sort, number, size).await
+    }
+
+    async fn initials(&self, names: NameOrder) -> BTreeMap<char, usize> {
+        self.inner.initials(names).await
+    }
+
+    async fn starred(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.starred(sort, number, size).await
+    }
+
+    async fn archived(
+        &self,
+        archived: bool,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        self.inner.archived(archived, sort, number, size).await
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        let id = self.inner.create(contact).await?;
+        self.reindex(id).await;
+        Ok(id)
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        let id = contact.id.expect("an updated contact to have an id");
+        self.inner.update(contact).await?;
+        self.reindex(id).await;
+        Ok(())
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        self.inner.find(id).await
+    }
+
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        self.inner.find_many(ids).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        self.inner.find_by_email(email).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<Contact>, RepoError> {
+        self.inner.find_by_uuid(uuid).await
+    }
+
+    async fn exists_by_email(&self, email: &str) -> Result<bool, RepoError> {
+        self.inner.exists_by_email(email).await
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        self.inner.delete_by_id(id).await?;
+        self.index.write().await.remove(id);
+        Ok(())
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        Ok(Box::new(IndexedTransaction {
+            inner: self.inner.begin().await?,
+            repo: self.inner.clone(),
+            index: self.index.clone(),
+        }))
+    }
+
+    async fn replace_all(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let replaced = self.inner.replace_all(contacts).await;
+        rebuild(&self.inner, &self.index).await;
+        replaced
+    }
+
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        self.inner.page(sort, number, size).await
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        self.inner.all_after(cursor, limit).await
+    }
+
+    fn stream_all(&self) -> BoxStream<'_, Contact> {
+        self.inner.stream_all()
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        self.inner.changed_since(since).await
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ContactEvent>> {
+        self.inner.subscribe()
+    }
+
+    async fn flush(&self) {
+        self.inner.flush().await;
+    }
+
+    async fn stats(&self) -> RepoStats {
+        self.inner.stats().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ContactTransaction for IndexedTransaction {
+    async fn all(&mut self) -> Result<Vec<Contact>, RepoError> {
+        self.inner.all().await
+    }
+
+    async fn find(&mut self, id: u64) -> Result<Option<Contact>, RepoError> {
+        self.inner.find(id).await
+    }
+
+    async fn save(&mut self, contact: Contact) -> Result<u64, RepoError> {
+        self.inner.save(contact).await
+    }
+
+    async fn delete(&mut self, contact: Contact) -> Result<(), RepoError> {
+        self.inner.delete(contact).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), RepoError> {
+        self.inner.commit().await?;
+        rebuild(&self.repo, &self.index).await;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) {
+        self.inner.rollback().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(id: u64, first: &str, last: &str) -> Contact {
+        Contact {
+            id: Some(id),
+            first: Some(first.to_owned()),
+            last: Some(last.to_owned()),
+            email: Some(format!("{}@example.com", first.to_lowercase())),
+            ..Default::default()
+        }
+    }
+
+    fn contacts() -> Vec<Contact> {
+        vec![
+            contact(1, "Ann", "Smith"),
+            contact(2, "Bob", "Jones"),
+            contact(3, "Jon", "Smyth"),
+            contact(4, "Åsa", "Öberg"),
+        ]
+    }
+
+    fn engines() -> Vec<IndexEngine> {
+        let engines = vec![IndexEngine::Memory];
+        #[cfg(feature = "tantivy")]
+        let engines = [engines, vec![IndexEngine::Tantivy]].concat();
+        engines
+    }
+
+    fn ids(found: Vec<&Contact>) -> Vec<u64> {
+        found.into_iter().filter_map(Contact::id).collect()
+    }
+
+    /// The ids of the contacts searching for `query` finds without an index.
+    fn scanned(contacts: &[Contact], query: &str) -> Vec<u64> {
+        let (filter, query) = (ContactFilter::default(), SearchQuery::parse(query));
+        contacts
+            .iter()
+            .filter(|contact| filter.finds(&query, contact))
+            .filter_map(Contact::id)
+            .collect()
+    }
+
+    #[test]
+    fn trigrams_leave_out_those_across_fields() {
+        let text = format!("ab{FIELD_SEPARATOR}cde");
+        assert_eq!(trigrams(&text), vec![['c', 'd', 'e']]);
+        assert!(trigrams("ab").is_empty());
+    }
+
+    #[test]
+    fn finds_what_a_scan_finds() {
+        for engine in engines() {
+            let index = SearchIndex::build(contacts(), engine);
+            let filter = ContactFilter::default();
+            for query in [
+                "",
+                "o",
+                "jo",
+                "smith",
+                "SMITH",
+                "asa",
+                "oberg",
+                "example.com",
+                "smyth",
+            ]
+            .into_iter()
+            .chain(["ann OR bob", "last:smith", "\"ann smith\"", "nobody"])
+            {
+                assert_eq!(
+                    ids(index.search(query, &filter)),
+                    scanned(&contacts(), query),
+                    "{engine:?} searching for {query:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finds_names_that_sound_alike() {
+        for engine in engines() {
+            let index = SearchIndex::build(contacts(), engine);
+            let filter = ContactFilter::default();
+            let found = ids(index.search("smith", &filter));
+            assert!(found.contains(&1) && found.contains(&3), "{engine:?}");
+            assert_eq!(found, scanned(&contacts(), "smith"), "{engine:?}");
+        }
+    }
+
+    #[test]
+    fn follows_inserts_and_removes() {
+        for engine in engines() {
+            let mut index = SearchIndex::build(contacts(), engine);
+            let filter = ContactFilter::default();
+            index.insert(contact(5, "Anna", "Smithson"));
+            assert_eq!(
+                ids(index.search("smithson", &filter)),
+                vec![5],
+                "{engine:?}"
+            );
+            index.insert(contact(5, "Anna", "Karlsson"));
+            assert!(index.search("smithson", &filter).is_empty(), "{engine:?}");
+            assert_eq!(
+                ids(index.search("karlsson", &filter)),
+                vec![5],
+                "{engine:?}"
+            );
+            index.remove(1);
+            assert!(
+                !ids(index.search("smith", &filter)).contains(&1),
+                "{engine:?}"
+            );
+            index.rebuild(contacts());
+            assert!(
+                ids(index.search("smith", &filter)).contains(&1),
+                "{engine:?}"
+            );
+            assert!(index.search("karlsson", &filter).is_empty(), "{engine:?}");
+        }
+    }
+}
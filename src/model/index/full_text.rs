@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+
+use tantivy::{
+    collector::DocSetCollector,
+    doc,
+    query::{BooleanQuery, Query, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED},
+    tokenizer::{NgramTokenizer, WhitespaceTokenizer},
+    Index, IndexReader, IndexWriter, ReloadPolicy, Term,
+};
+
+use super::{Entry, Postings, Trigram};
+
+/// What the index writer may buffer before writing a segment, the least
+/// tantivy takes.
+const WRITER_MEMORY: usize = 15_000_000;
+
+/// The [`Postings`] of a [`SearchIndex`](super::SearchIndex) kept in a
+/// tantivy index in memory, with a document per entry: its id, its search
+/// text split into trigrams and its phonetic text into codes.
+pub struct TantivyPostings {
+    writer: IndexWriter,
+    reader: IndexReader,
+    id: Field,
+    search: Field,
+    phonetic: Field,
+}
+
+/// Options of a text field split by `tokenizer`, only recording which
+/// documents have each term.
+fn terms(tokenizer: &str) -> TextOptions {
+    TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(tokenizer)
+            .set_index_option(IndexRecordOption::Basic),
+    )
+}
+
+impl TantivyPostings {
+    pub fn new() -> Self {
+        let mut schema = Schema::builder();
+        let id = schema.add_u64_field("id", INDEXED | FAST);
+        let search = schema.add_text_field("search", terms("trigram"));
+        let phonetic = schema.add_text_field("phonetic", terms("codes"));
+        let index = Index::create_in_ram(schema.build());
+        // Trigrams across two fields are indexed too, but never looked up.
+        let trigram = NgramTokenizer::new(3, 3, false).expect("a valid n-gram size");
+        index.tokenizers().register("trigram", trigram);
+        index
+            .tokenizers()
+            .register("codes", WhitespaceTokenizer::default());
+        let writer = index
+            .writer_with_num_threads(1, WRITER_MEMORY)
+            .expect("creating the index writer succeed");
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .expect("creating the index reader succeed");
+        Self {
+            writer,
+            reader,
+            id,
+            search,
+            phonetic,
+        }
+    }
+
+    /// The ids of the documents having every one of `terms`, or `None` if
+    /// there are none to look up.
+    fn having(&self, terms: Vec<Term>) -> Option<BTreeSet<u64>> {
+        if terms.is_empty() {
+            return None;
+        }
+        let queries = terms
+            .into_iter()
+            .map(|term| Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>)
+            .collect();
+        let searcher = self.reader.searcher();
+        let found = searcher
+            .search(&BooleanQuery::intersection(queries), &DocSetCollector)
+            .expect("searching the index succeed");
+        let ids = found
+            .into_iter()
+            .filter_map(|address| {
+                searcher
+                    .segment_reader(address.segment_ord)
+                    .fast_fields()
+                    .u64("id")
+                    .expect("documents to have an id")
+                    .first(address.doc_id)
+            })
+            .collect();
+        Some(ids)
+    }
+}
+
+impl Postings for TantivyPostings {
+    fn insert(&mut self, id: u64, entry: &Entry) {
+        self.writer
+            .add_document(doc!(
+                self.id => id,
+                self.search => entry.search.as_str(),
+                self.phonetic => entry.phonetic.as_str(),
+            ))
+            .expect("indexing the contact succeed");
+    }
+
+    fn remove(&mut self, id: u64, _entry: &Entry) {
+        self.writer.delete_term(Term::from_field_u64(self.id, id));
+    }
+
+    fn clear(&mut self) {
+        self.writer
+            .delete_all_documents()
+            .expect("clearing the index succeed");
+    }
+
+    fn commit(&mut self) {
+        self.writer.commit().expect("committing the index succeed");
+        self.reader.reload().expect("reloading the index succeed");
+    }
+
+    fn having_trigrams(&self, trigrams: &[Trigram]) -> Option<BTreeSet<u64>> {
+        let terms = trigrams
+            .iter()
+            .map(|trigram| Term::from_field_text(self.search, &String::from_iter(trigram)))
+            .collect();
+        self.having(terms)
+    }
+
+    fn having_codes(&self, codes: &[&str]) -> Option<BTreeSet<u64>> {
+        let terms = codes
+            .iter()
+            .map(|code| Term::from_field_text(self.phonetic, code))
+            .collect();
+        self.having(terms)
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::{
+    BatchError, BoxedTransaction, Contact, ContactChange, ContactEvent, ContactFilter, ContactRepo,
+    Cursor, CursorPage, NameOrder, Page, RepoError, RepoStats, SharedContactRepo, Sort, Upserted,
+};
+
+/// Times the calls to another repo, counting them and their errors per
+/// method, so slow searches or saves show up in its [`RepoStats::calls`].
+///
+/// Calls made within a transaction are timed together with the transaction
+/// committing, under `save_many`, `create_many`, `update_many`,
+/// `delete_many` or `replace_all`; a transaction started with `begin` is
+/// only timed until it starts.
+pub struct InstrumentedRepo {
+    inner: SharedContactRepo,
+    calls: Arc<Mutex<BTreeMap<&'static str, CallStats>>>,
+}
+
+/// Upper bounds, in milliseconds, of the latency buckets of [`CallStats`].
+pub const LATENCY_BUCKETS_MS: [u64; 8] = [1, 2, 5, 10, 50, 100, 500, 1000];
+
+/// The calls made to one method of an [`InstrumentedRepo`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CallStats {
+    pub method: &'static str,
+    pub calls: u64,
+    /// Calls that returned an error.
+    pub errors: u64,
+    /// Serialized, like `max`, in milliseconds.
+    #[serde(serialize_with = "millis")]
+    pub total: Duration,
+    #[serde(serialize_with = "millis")]
+    pub max: Duration,
+    /// Calls by latency: the `i`th bucket counts those that took at most
+    /// [`LATENCY_BUCKETS_MS`]`[i]` milliseconds and longer than the bucket
+    /// before, and the last one counts the slower ones.
+    pub buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+}
+
+impl CallStats {
+    /// Average latency of the calls.
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 {
+            return Duration::ZERO;
+        }
+        self.total.div_f64(self.calls as f64)
+    }
+
+    fn record(&mut self, elapsed: Duration, failed: bool) {
+        self.calls += 1;
+        self.errors += u64::from(failed);
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| elapsed <= Duration::from_millis(bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+    }
+}
+
+impl InstrumentedRepo {
+    pub fn new(inner: SharedContactRepo) -> Self {
+        Self {
+            inner,
+            calls: Arc::default(),
+        }
+    }
+
+    pub fn shared(inner: SharedContactRepo) -> SharedContactRepo {
+        Arc::new(Self::new(inner))
+    }
+
+    /// Stats of the calls made so far, by method name.
+    pub fn calls(&self) -> Vec<CallStats> {
+        self.calls.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Runs `call` to `method`, recording how long it took and whether
+    /// `failed` says its output is an error.
+    async fn time<T>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = T>,
+        failed: impl FnOnce(&T) -> bool,
+    ) -> T {
+        let start = Instant::now();
+        let output = call.await;
+        let elapsed = start.elapsed();
+        let failed = failed(&output);
+        let mut calls = self.calls.lock().unwrap();
+        let stats = calls.entry(method).or_insert_with(|| CallStats {
+            method,
+            ..CallStats::default()
+        });
+        stats.record(elapsed, failed);
+        output
+    }
+}
+
+fn millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+fn never<T>(_: &T) -> bool {
+    false
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for InstrumentedRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        self.time("all", self.inner.all(sort), never).await
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        self.time("count", self.inner.count(filter), never).await
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let searched = self.inner.search(query, sort, number, size);
+        self.time("search", searched, never).await
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        filter: &ContactFilter,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let searched = self
+            .inner
+            .search_filtered(query, filter, sort, number, size);
+        self.time("search_filtered", searched, never).await
+    }
+
+    async fn fuzzy_search(
+        &self,
+        query: &str,
+        threshold: f64,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let searched = self
+            .inner
+            .fuzzy_search(query, threshold, sort, number, size);
+        self.time("fuzzy_search", searched, never).await
+    }
+
+    async fn tagged(&self, tag: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let tagged = self.inner.tagged(tag, sort, number, size);
+        self.time("tagged", tagged, never).await
+    }
+
+    async fn tags(&self) -> BTreeMap<String, usize> {
+        self.time("tags", self.inner.tags(), never).await
+    }
+
+    async fn members(&self, group: u64, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let members = self.inner.members(group, sort, number, size);
+        self.time("members", members, never).await
+    }
+
+    async fn group_sizes(&self) -> BTreeMap<u64, usize> {
+        self.time("group_sizes", self.inner.group_sizes(), never)
+            .await
+    }
+
+    async fn by_initial(
+        &self,
+        initial: char,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let listed = self.inner.by_initial(initial, sort, number, size);
+        self.time("by_initial", listed, never).await
+    }
+
+    async fn initials(&self, names: NameOrder) -> BTreeMap<char, usize> {
+        self.time("initials", self.inner.initials(names), never)
+            .await
+    }
+
+    async fn starred(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let starred = self.inner.starred(sort, number, size);
+        self.time("starred", starred, never).await
+    }
+
+    async fn archived(
+        &self,
+        archived: bool,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let listed = self.inner.archived(archived, sort, number, size);
+        self.time("archived", listed, never).await
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        self.time("create", self.inner.create(contact), Result::is_err)
+            .await
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        self.time("update", self.inner.update(contact), Result::is_err)
+            .await
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        self.time("find", self.inner.find(id), Result::is_err).await
+    }
+
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        self.time("find_many", self.inner.find_many(ids), Result::is_err)
+            .await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        let found = self.inner.find_by_email(email);
+        self.time("find_by_email", found, Result::is_err).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<Contact>, RepoError> {
+        let found = self.inner.find_by_uuid(uuid);
+        self.time("find_by_uuid", found, Result::is_err).await
+    }
+
+    async fn exists_by_email(&self, email: &str) -> Result<bool, RepoError> {
+        let exists = self.inner.exists_by_email(email);
+        self.time("exists_by_email", exists, Result::is_err).await
+    }
+
+    async fn upsert_by_email(&self, contact: Contact) -> Result<Upserted, RepoError> {
+        let upserted = self.inner.upsert_by_email(contact);
+        self.time("upsert_by_email", upserted, Result::is_err).await
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let deleted = self.inner.delete_by_id(id);
+        self.time("delete_by_id", deleted, Result::is_err).await
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        self.time("begin", self.inner.begin(), Result::is_err).await
+    }
+
+    async fn replace_all(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let replaced = self.inner.replace_all(contacts);
+        self.time("replace_all", replaced, Result::is_err).await
+    }
+
+    async fn save_many(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let saved = self.inner.save_many(contacts);
+        self.time("save_many", saved, Result::is_err).await
+    }
+
+    async fn create_many(&self, contacts: Vec<Contact>) -> Result<Vec<u64>, BatchError> {
+        let created = self.inner.create_many(contacts);
+        self.time("create_many", created, Result::is_err).await
+    }
+
+    async fn update_many(&self, contacts: Vec<Contact>) -> Result<(), BatchError> {
+        let updated = self.inner.update_many(contacts);
+        self.time("update_many", updated, Result::is_err).await
+    }
+
+    async fn delete_many(&self, ids: &[u64]) -> Result<(), RepoError> {
+        let deleted = self.inner.delete_many(ids);
+        self.time("delete_many", deleted, Result::is_err).await
+    }
+
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let page = self.inner.page(sort, number, size);
+        self.time("page", page, never).await
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        let listed = self.inner.all_after(cursor, limit);
+        self.time("all_after", listed, never).await
+    }
+
+    /// Not timed, as the contacts are read while the stream is consumed.
+    fn stream_all(&self) -> BoxStream<'_, Contact> {
+        self.inner.stream_all()
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let changes = self.inner.changed_since(since);
+        self.time("changed_since", changes, never).await
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ContactEvent>> {
+        self.inner.subscribe()
+    }
+
+    async fn flush(&self) {
+        self.time("flush", self.inner.flush(), never).await
+    }
+
+    async fn stats(&self) -> RepoStats {
+        RepoStats {
+            calls: self.calls(),
+            ..self.inner.stats().await
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fs,
+    io::{self, BufRead, Seek, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+
+use super::{
+    migrate::{self, SCHEMA_VERSION},
+    Contact, StoreKey,
+};
+
+/// A single mutation of a [`super::ContactStore`].
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JournalEntry {
+    Put {
+        contact: Box<Contact>,
+    },
+    Delete {
+        id: u64,
+        /// `None` in journals written before tombstones were kept.
+        #[serde(default)]
+        deleted_at: Option<DateTime<Utc>>,
+    },
+    /// Mutations applied together, by a transaction.
+    Batch {
+        entries: Vec<JournalEntry>,
+    },
+}
+
+impl JournalEntry {
+    /// Ids of the contacts the entry changes.
+    pub fn ids(&self) -> Vec<u64> {
+        match self {
+            Self::Put { contact } => vec![contact.id.unwrap()],
+            Self::Delete { id, .. } => vec![*id],
+            Self::Batch { entries } => entries.iter().flat_map(Self::ids).collect(),
+        }
+    }
+}
+
+/// Append-only log of mutations, one JSON line per [`JournalEntry`], that is
+/// replayed on top of the last snapshot when the store is loaded.
+///
+/// With a key, each line is instead the encrypted JSON, base64 encoded.
+#[derive(Debug)]
+pub struct Journal {
+    file: fs::File,
+    len: usize,
+    key: Option<StoreKey>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if needed, and returns the
+    /// entries already in it, upgraded from `schema_version`, the version of
+    /// the snapshot the journal belongs to.
+    ///
+    /// A trailing line without a newline is the remains of an append that
+    /// was cut short and is dropped; any other line that doesn't parse is an
+    /// error.
+    pub fn open(
+        path: &Path,
+        key: Option<StoreKey>,
+        schema_version: u32,
+    ) -> io::Result<(Self, Vec<JournalEntry>)> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut journal = Self { file, len: 0, key };
+        let entries = journal.read_entries_from(schema_version)?;
+        journal.len = entries.len();
+        Ok((journal, entries))
+    }
+
+    /// Reads all entries in the journal, dropping a torn trailing line.
+    pub fn read_entries(&mut self) -> io::Result<Vec<JournalEntry>> {
+        self.read_entries_from(SCHEMA_VERSION)
+    }
+
+    fn read_entries_from(&mut self, schema_version: u32) -> io::Result<Vec<JournalEntry>> {
+        self.file.seek(io::SeekFrom::Start(0))?;
+        let mut entries = Vec::new();
+        let mut valid_len = 0;
+        let mut reader = io::BufReader::new(&self.file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            if !line.ends_with('\n') {
+                self.file.set_len(valid_len)?;
+                break;
+            }
+            entries.push(parse_entry(self.key.as_ref(), &line, schema_version)?);
+            valid_len += read as u64;
+        }
+        Ok(entries)
+    }
+
+    /// Appends `entries` and syncs them to disk.
+    pub fn append(&mut self, entries: &[JournalEntry]) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(&self.file);
+        for entry in entries {
+            let json = serde_json::to_vec(entry)?;
+            match &self.key {
+                None => writer.write_all(&json)?,
+                Some(key) => writer.write_all(key.seal_line(&json).as_bytes())?,
+            }
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.file.sync_data()?;
+        self.len += entries.len();
+        Ok(())
+    }
+
+    /// Empties the journal, once its entries are part of a snapshot.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Number of entries in the journal.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+fn parse_entry(
+    key: Option<&StoreKey>,
+    line: &str,
+    schema_version: u32,
+) -> io::Result<JournalEntry> {
+    let mut entry: serde_json::Value = if line.starts_with('{') {
+        serde_json::from_str(line)?
+    } else {
+        let key = key.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "journal is encrypted, but no key is configured",
+            )
+        })?;
+        serde_json::from_slice(&key.open_line(line)?)?
+    };
+    upgrade_entry(&mut entry, schema_version);
+    Ok(serde_json::from_value(entry)?)
+}
+
+fn upgrade_entry(entry: &mut serde_json::Value, schema_version: u32) {
+    if let Some(contact) = entry.get_mut("contact") {
+        migrate::upgrade_contact(contact, schema_version);
+    }
+    if let Some(serde_json::Value::Array(entries)) = entry.get_mut("entries") {
+        for entry in entries {
+            upgrade_entry(entry, schema_version);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Upgrades contacts persisted by older versions of the app.
+//!
+//! A snapshot records the schema version it was written with; the journal
+//! next to it is written with the same version. Contacts are read as plain
+//! JSON values and passed through the migrations from their version up to
+//! [`SCHEMA_VERSION`] before being deserialized.
+
+use std::io;
+
+use serde_json::{json, Value};
+
+/// Schema version written by this build.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// `MIGRATIONS[n]` upgrades a contact from version `n` to `n + 1`.
+const MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] = [
+    // 1: snapshots are wrapped in an object holding the schema version, the
+    // contacts themselves are unchanged.
+    |_| {},
+    // 2: the optional `phone` became a list of labeled `phones`.
+    |contact| {
+        let Some(contact) = contact.as_object_mut() else {
+            return;
+        };
+        let phones = match contact.remove("phone") {
+            Some(Value::String(value)) => json!([{ "label": "", "value": value }]),
+            _ => json!([]),
+        };
+        contact.insert("phones".into(), phones);
+    },
+];
+
+/// Splits a decoded snapshot into its schema version and its contacts.
+pub fn split_snapshot(doc: Value) -> io::Result<(u32, Vec<Value>)> {
+    let (version, contacts) = match doc {
+        // Version 0 snapshots are a bare list of contacts.
+        Value::Array(contacts) => (0, contacts),
+        Value::Object(mut doc) => {
+            let version = doc
+                .get("schema_version")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid("snapshot has no schema_version"))?;
+            let Some(Value::Array(contacts)) = doc.remove("contacts") else {
+                return Err(invalid("snapshot has no contacts"));
+            };
+            (version, contacts)
+        }
+        _ => return Err(invalid("snapshot is neither a list nor an object")),
+    };
+    Ok((check_version(version)?, contacts))
+}
+
+/// The schema `version` a store was written with, failing for versions
+/// written by a newer build, which this one can't read.
+pub fn check_version(version: u64) -> io::Result<u32> {
+    match u32::try_from(version) {
+        Ok(version) if version <= SCHEMA_VERSION => Ok(version),
+        _ => Err(invalid(&format!(
+            "store has schema version {version}, but this build only knows up to {SCHEMA_VERSION}"
+        ))),
+    }
+}
+
+/// Brings `contact` from schema version `from` up to [`SCHEMA_VERSION`].
+pub fn upgrade_contact(contact: &mut Value, from: u32) {
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(contact);
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ContactStore, StorageOptions};
+
+    #[test]
+    fn bare_lists_are_version_0() {
+        let (version, contacts) = split_snapshot(json!([{ "id": 1 }])).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(contacts, vec![json!({ "id": 1 })]);
+    }
+
+    #[test]
+    fn snapshots_without_a_version_are_refused() {
+        assert!(split_snapshot(json!({ "contacts": [] })).is_err());
+        assert!(split_snapshot(json!({ "schema_version": 1 })).is_err());
+        assert!(split_snapshot(json!("contacts")).is_err());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let doc = json!({ "schema_version": SCHEMA_VERSION + 1, "contacts": [] });
+        assert!(split_snapshot(doc).is_err());
+        // Would be 0 if truncated to 32 bits.
+        let doc = json!({ "schema_version": 1_u64 << 32, "contacts": [] });
+        assert!(split_snapshot(doc).is_err());
+    }
+
+    #[test]
+    fn phone_becomes_phones() {
+        let mut contact = json!({ "id": 1, "phone": "555-1234" });
+        upgrade_contact(&mut contact, 0);
+        assert_eq!(
+            contact,
+            json!({ "id": 1, "phones": [{ "label": "", "value": "555-1234" }] })
+        );
+        let mut contact = json!({ "id": 1, "phone": null });
+        upgrade_contact(&mut contact, 1);
+        assert_eq!(contact, json!({ "id": 1, "phones": [] }));
+    }
+
+    #[test]
+    fn current_contacts_are_unchanged() {
+        let mut contact = json!({ "id": 1, "phones": [{ "label": "work", "value": "555" }] });
+        let before = contact.clone();
+        upgrade_contact(&mut contact, SCHEMA_VERSION);
+        assert_eq!(contact, before);
+    }
+
+    #[test]
+    fn old_snapshots_round_trip_at_the_current_version() {
+        let options = StorageOptions::default();
+        let old = json!([{ "id": 1, "first": "Ann", "phone": "555-1234" }]);
+        let (store, info) = ContactStore::parse(serde_json::to_vec(&old).unwrap(), None).unwrap();
+        assert_eq!(info.schema_version, 0);
+        let written = store.snapshot(&options);
+        let (reread, info) = ContactStore::parse(written.clone(), None).unwrap();
+        assert_eq!(info.schema_version, SCHEMA_VERSION);
+        assert_eq!(reread.snapshot(&options), written);
+        let contacts = reread.into_contacts();
+        assert_eq!(contacts[0].first.as_deref(), Some("Ann"));
+        assert_eq!(contacts[0].phones()[0].value, "555-1234");
+    }
+}
@@ -0,0 +1,98 @@
+//! Checking that the domains of emails accept mail, by looking up their MX
+//! records. Enabled with `EMAIL_MX_CHECK`.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use hickory_resolver::{
+    config::ResolverConfig, name_server::TokioConnectionProvider, ResolveError, TokioResolver,
+};
+use tokio::sync::Mutex;
+
+/// How long a lookup may take before the domain is taken to be fine.
+pub const MX_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long what was looked up about a domain is kept.
+pub const MX_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Looks up whether the domains of emails accept mail, keeping what it
+/// finds for [`MX_CACHE_TTL`]. As a domain that doesn't may just be
+/// misconfigured, what it finds is only a warning and never keeps a contact
+/// from being saved.
+pub struct MailDomains {
+    resolver: TokioResolver,
+    cache: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+impl Default for MailDomains {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MailDomains {
+    /// Uses the system's name servers, or public ones if they can't be
+    /// read.
+    pub fn new() -> Self {
+        let mut builder = TokioResolver::builder_tokio().unwrap_or_else(|_| {
+            TokioResolver::builder_with_config(
+                ResolverConfig::default(),
+                TokioConnectionProvider::default(),
+            )
+        });
+        builder.options_mut().timeout = MX_TIMEOUT;
+        Self {
+            resolver: builder.build(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Why mail to `email` can't be delivered, such as a typo'd domain with
+    /// no mail servers, if it can't. Lookups that fail or time out count as
+    /// deliverable.
+    pub async fn warning(&self, email: &str) -> Option<String> {
+        let (_, domain) = email.trim().rsplit_once('@')?;
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        if domain.is_empty() || domain.starts_with('[') {
+            return None;
+        }
+        (!self.accepts_mail(&domain).await).then(|| format!("{domain} doesn't seem to accept mail"))
+    }
+
+    async fn accepts_mail(&self, domain: &str) -> bool {
+        if let Some((accepts, at)) = self.cache.lock().await.get(domain) {
+            if at.elapsed() < MX_CACHE_TTL {
+                return *accepts;
+            }
+        }
+        let accepts = match tokio::time::timeout(MX_TIMEOUT, self.lookup(domain)).await {
+            Ok(Ok(accepts)) => accepts,
+            // Not known, so neither warned about nor kept.
+            Ok(Err(_)) | Err(_) => return true,
+        };
+        let mut cache = self.cache.lock().await;
+        cache.retain(|_, (_, at)| at.elapsed() < MX_CACHE_TTL);
+        cache.insert(domain.to_owned(), (accepts, Instant::now()));
+        accepts
+    }
+
+    /// Whether `domain` has a mail server: an MX record other than the
+    /// "null MX" of RFC 7505 or, without MX records, an address to deliver
+    /// to directly as RFC 5321 allows.
+    async fn lookup(&self, domain: &str) -> Result<bool, ResolveError> {
+        let name = format!("{domain}.");
+        match self.resolver.mx_lookup(name.as_str()).await {
+            Ok(mx) => Ok(mx.iter().any(|mx| !mx.exchange().is_root())),
+            Err(err) if err.is_nx_domain() => Ok(false),
+            Err(err) if err.is_no_records_found() => {
+                match self.resolver.lookup_ip(name.as_str()).await {
+                    Ok(ips) => Ok(ips.iter().next().is_some()),
+                    Err(err) if err.is_no_records_found() => Ok(false),
+                    Err(err) => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
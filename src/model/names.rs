@@ -0,0 +1,126 @@
+use std::{iter, str::FromStr};
+
+use super::{search::fold, Contact};
+
+/// How contacts' names are written where they are listed, sorted and
+/// exported, set with `NAME_ORDER`; see [`Contact::display_name`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameOrder {
+    /// `Dr. Ada Maria Lovelace Jr.`
+    #[default]
+    FirstLast,
+    /// `Lovelace, Dr. Ada Maria, Jr.`
+    LastFirst,
+}
+
+impl NameOrder {
+    /// The name parts contacts are sorted by, most significant first.
+    pub(super) fn sort_fields(self) -> [&'static str; 3] {
+        match self {
+            Self::FirstLast => ["first", "middle", "last"],
+            Self::LastFirst => ["last", "first", "middle"],
+        }
+    }
+}
+
+impl FromStr for NameOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first_last" => Ok(Self::FirstLast),
+            "last_first" => Ok(Self::LastFirst),
+            _ => Err(format!("unknown name order '{s}'")),
+        }
+    }
+}
+
+impl Contact {
+    /// The contact's name written in `order`, leaving out the parts it
+    /// doesn't have; its nickname if it has no other, and empty if it has
+    /// none at all.
+    pub fn display_name(&self, order: NameOrder) -> String {
+        let words = |parts: &[&Option<String>]| {
+            let words: Vec<_> = parts.iter().filter_map(|p| part(p)).collect();
+            words.join(" ")
+        };
+        let name = match (order, part(&self.last)) {
+            (NameOrder::LastFirst, Some(last)) => {
+                let given = words(&[&self.prefix, &self.first, &self.middle]);
+                let given = (!given.is_empty()).then_some(given);
+                let suffix = part(&self.suffix).map(str::to_owned);
+                let parts: Vec<_> = iter::once(last.to_owned())
+                    .chain(given)
+                    .chain(suffix)
+                    .collect();
+                parts.join(", ")
+            }
+            _ => words(&[
+                &self.prefix,
+                &self.first,
+                &self.middle,
+                &self.last,
+                &self.suffix,
+            ]),
+        };
+        if name.is_empty() {
+            part(&self.nickname).unwrap_or_default().to_owned()
+        } else {
+            name
+        }
+    }
+
+    /// What [`SortBy::Name`](super::SortBy::Name) sorts by in `order`: the
+    /// non-empty parts of [`NameOrder::sort_fields`], joined by
+    /// [`SORT_SEPARATOR`] so that shorter parts sort first, or else the
+    /// nickname; lowercased.
+    pub(super) fn sort_name(&self, order: NameOrder) -> String {
+        let parts: Vec<_> = order
+            .sort_fields()
+            .into_iter()
+            .filter_map(|field| match field {
+                "first" => self.first.as_deref(),
+                "middle" => self.middle.as_deref(),
+                _ => self.last.as_deref(),
+            })
+            .filter(|part| !part.is_empty())
+            .collect();
+        let name = if parts.is_empty() {
+            self.nickname.clone().unwrap_or_default()
+        } else {
+            parts.join(&SORT_SEPARATOR.to_string())
+        };
+        name.to_lowercase()
+    }
+
+    /// The letter the contact is listed under in `order`: the first of the
+    /// name it is [sorted](Self::sort_name) by, uppercase and without
+    /// diacritics, or [`OTHER_INITIAL`] if that isn't one from `A` to `Z`.
+    pub fn initial(&self, order: NameOrder) -> char {
+        fold(&self.sort_name(order))
+            .chars()
+            .next()
+            .filter(char::is_ascii_lowercase)
+            .map_or(OTHER_INITIAL, |c| c.to_ascii_uppercase())
+    }
+}
+
+/// The initial of contacts whose names don't start with a letter from `A`
+/// to `Z`, or who have none, see [`Contact::initial`].
+pub const OTHER_INITIAL: char = '#';
+
+/// The initials contacts are listed under, in order.
+pub fn initials() -> impl Iterator<Item = char> {
+    ('A'..='Z').chain([OTHER_INITIAL])
+}
+
+/// Separates the parts of a name sorted by, sorting before any character
+/// of a name.
+pub(super) const SORT_SEPARATOR: char = '\u{1f}';
+
+/// The trimmed name part, `None` if it is blank.
+fn part(part: &Option<String>) -> Option<&str> {
+    part.as_deref()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
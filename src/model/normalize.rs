@@ -0,0 +1,85 @@
+use super::Contact;
+
+/// `value` trimmed, with each run of whitespace inside it collapsed to a
+/// single space, `None` if nothing is left.
+pub fn normalize_text(value: &str) -> Option<String> {
+    let text = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Normalizes an optional text field in place, see [`normalize_text`].
+fn normalize_field(field: &mut Option<String>) {
+    *field = field.as_deref().and_then(normalize_text);
+}
+
+/// Normalizes a text field that may not be left out in place, empty if
+/// nothing is left.
+fn normalize_entry(field: &mut String) {
+    *field = normalize_text(field).unwrap_or_default();
+}
+
+impl Contact {
+    /// Trims the contact's text fields and collapses the whitespace inside
+    /// them, setting those left empty to `None` and leaving out phone
+    /// numbers, emails, social profiles and addresses left without a value.
+    /// The notes, being Markdown, keep their line breaks and are only
+    /// trimmed.
+    pub fn normalize(&mut self) {
+        for field in [
+            &mut self.prefix,
+            &mut self.first,
+            &mut self.middle,
+            &mut self.last,
+            &mut self.suffix,
+            &mut self.nickname,
+            &mut self.company,
+            &mut self.job_title,
+            &mut self.website,
+            &mut self.timezone,
+        ] {
+            normalize_field(field);
+        }
+        self.notes = self
+            .notes
+            .as_deref()
+            .map(str::trim)
+            .filter(|notes| !notes.is_empty())
+            .map(str::to_owned);
+        for phone in &mut self.phones {
+            normalize_entry(&mut phone.label);
+            normalize_entry(&mut phone.value);
+        }
+        self.phones.retain(|phone| !phone.value.is_empty());
+        for email in &mut self.emails {
+            normalize_entry(&mut email.label);
+            normalize_entry(&mut email.value);
+        }
+        self.emails.retain(|email| !email.value.is_empty());
+        for date in &mut self.dates {
+            normalize_entry(&mut date.label);
+        }
+        for social in &mut self.socials {
+            normalize_entry(&mut social.network);
+            normalize_entry(&mut social.handle);
+        }
+        self.socials.retain(|social| !social.handle.is_empty());
+        for address in &mut self.addresses {
+            for field in [
+                &mut address.label,
+                &mut address.street,
+                &mut address.city,
+                &mut address.postal_code,
+                &mut address.country,
+            ] {
+                normalize_entry(field);
+            }
+        }
+        self.addresses.retain(|address| !address.is_empty());
+        let tags: Vec<_> = self
+            .tags
+            .iter()
+            .filter_map(|tag| normalize_text(tag))
+            .collect();
+        self.set_tags(tags);
+    }
+}
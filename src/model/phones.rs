@@ -0,0 +1,119 @@
+//! Reading and writing phone numbers with the [`phonenumber`] metadata, and
+//! keeping them unique.
+
+use std::collections::{HashMap, HashSet};
+
+use phonenumber::Mode;
+
+use super::{Contact, RepoError};
+
+/// The country a phone number entered without a `+` and country code is
+/// read as a number of, e.g. `SE`; set with `PHONE_REGION`.
+pub use phonenumber::country::Id as PhoneRegion;
+
+/// `value` in E.164, such as `+46701234567`, if it is a valid number, read
+/// as one of `region` unless it starts with `+`.
+pub fn normalize_phone(value: &str, region: Option<PhoneRegion>) -> Option<String> {
+    let number = phonenumber::parse(region, value.trim()).ok()?;
+    number
+        .is_valid()
+        .then(|| number.format().mode(Mode::E164).to_string())
+}
+
+/// `value` formatted for display: nationally if it is a number of `region`,
+/// and internationally otherwise. Numbers that don't parse are kept as they
+/// are.
+pub fn format_phone(value: &str, region: Option<PhoneRegion>) -> String {
+    let Ok(number) = phonenumber::parse(region, value) else {
+        return value.to_owned();
+    };
+    let national = region.is_some() && number.country().id() == region;
+    let mode = if national {
+        Mode::National
+    } else {
+        Mode::International
+    };
+    number.format().mode(mode).to_string()
+}
+
+/// Fails with the [`RepoError::Conflict`] of [`RepoError::phone_taken`] if
+/// one of `contact`'s phone numbers is also one of `others`' besides the
+/// contact itself, which the repos check as they save when
+/// [phone numbers are unique](super::unique_phones).
+///
+/// Numbers are compared as saved, which validating a contact normalizes to
+/// E.164, so `contact` must have been validated.
+pub fn check_phones<'a>(
+    contact: Contact,
+    others: impl IntoIterator<Item = &'a Contact>,
+) -> Result<Contact, RepoError> {
+    let taken: HashSet<&str> = others
+        .into_iter()
+        .filter(|other| other.id != contact.id)
+        .flat_map(|other| &other.phones)
+        .map(|phone| phone.value.as_str())
+        .collect();
+    reject_taken(contact, |value| taken.contains(value))
+}
+
+fn reject_taken(contact: Contact, taken: impl Fn(&str) -> bool) -> Result<Contact, RepoError> {
+    let index = contact.phones.iter().position(|phone| taken(&phone.value));
+    match index {
+        Some(index) => Err(RepoError::phone_taken(contact, index)),
+        None => Ok(contact),
+    }
+}
+
+/// The contacts having each phone number, read once at the start of a
+/// transaction and kept up to date as it saves and deletes contacts, so
+/// each save is [checked](check_phones) without reading them all again.
+#[derive(Debug, Default)]
+pub struct PhoneOwners {
+    owners: HashMap<String, HashSet<u64>>,
+    phones: HashMap<u64, Vec<String>>,
+}
+
+impl PhoneOwners {
+    pub fn new<'a>(contacts: impl IntoIterator<Item = &'a Contact>) -> Self {
+        let mut owners = Self::default();
+        for contact in contacts {
+            owners.save(contact);
+        }
+        owners
+    }
+
+    /// Like [`check_phones`] against the contacts as they are now.
+    pub fn check(&self, contact: Contact) -> Result<Contact, RepoError> {
+        let id = contact.id;
+        reject_taken(contact, |value| {
+            self.owners
+                .get(value)
+                .is_some_and(|owners| owners.iter().any(|owner| Some(*owner) != id))
+        })
+    }
+
+    /// Records the phone numbers of `contact`, which has an id, in place of
+    /// those it had.
+    pub fn save(&mut self, contact: &Contact) {
+        let id = contact.id.expect("a saved contact to have an id");
+        self.remove(id);
+        for phone in &contact.phones {
+            let owners = self.owners.entry(phone.value.clone()).or_default();
+            owners.insert(id);
+        }
+        let phones = contact.phones.iter().map(|phone| phone.value.clone());
+        self.phones.insert(id, phones.collect());
+    }
+
+    /// Forgets the phone numbers of the contact with `id`.
+    pub fn remove(&mut self, id: u64) {
+        for phone in self.phones.remove(&id).unwrap_or_default() {
+            if let Some(owners) = self.owners.get_mut(&phone) {
+                owners.remove(&id);
+                if owners.is_empty() {
+                    self.owners.remove(&phone);
+                }
+            }
+        }
+    }
+}
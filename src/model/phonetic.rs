@@ -0,0 +1,135 @@
+use super::{
+    search::{fold, words},
+    Contact,
+};
+
+/// How `word` sounds, by a variant of Metaphone that also reads `CHR` as
+/// `KR`: consonants grouped by sound, `0` for `TH`, with vowels left out
+/// but at the start. So `Christofferson` and `Kristoffersen` are both
+/// `KRSTFRSN`. Anything but ASCII letters is left out, so `word` should be
+/// [folded](fold) first.
+pub fn metaphone(word: &str) -> String {
+    let word: Vec<u8> = word
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let at = |i: usize| word.get(i).copied().unwrap_or_default();
+    let is_vowel = |c: u8| matches!(c, b'A' | b'E' | b'I' | b'O' | b'U');
+    let mut code = String::new();
+    // Letters not sounded at the start of a word.
+    let start = match (at(0), at(1)) {
+        (b'A', b'E') | (b'G' | b'K' | b'P', b'N') | (b'W', b'R') => 1,
+        (b'W', b'H') => {
+            code.push('W');
+            2
+        }
+        (b'X', _) => {
+            code.push('S');
+            1
+        }
+        _ => 0,
+    };
+    let mut i = start;
+    while i < word.len() {
+        let (c, next, after) = (word[i], at(i + 1), at(i + 2));
+        let prev = i.checked_sub(1).map_or(0, at);
+        let last = i + 1 == word.len();
+        if c == prev && c != b'C' {
+            i += 1;
+            continue;
+        }
+        match c {
+            c if is_vowel(c) => {
+                if i == start {
+                    code.push(c as char);
+                }
+            }
+            b'B' if prev == b'M' && last => {}
+            b'C' if next == b'I' && after == b'A' => code.push('X'),
+            b'C' if next == b'H' => code.push(if prev == b'S' || after == b'R' {
+                'K'
+            } else {
+                'X'
+            }),
+            b'C' if matches!(next, b'I' | b'E' | b'Y') => {
+                if prev != b'S' {
+                    code.push('S');
+                }
+            }
+            b'K' if prev == b'C' => {}
+            b'C' | b'K' | b'Q' => code.push('K'),
+            b'D' if next == b'G' && matches!(after, b'I' | b'E' | b'Y') => {
+                code.push('J');
+                i += 1;
+            }
+            b'D' => code.push('T'),
+            b'G' if next == b'H' && !(i + 2 == word.len() || is_vowel(after)) => {}
+            b'G' if next == b'N' && (i + 2 == word.len() || &word[i + 1..] == b"NED") => {}
+            b'G' if matches!(next, b'I' | b'E' | b'Y') && prev != b'G' => code.push('J'),
+            b'G' => code.push('K'),
+            b'H' => {
+                if is_vowel(next) && !matches!(prev, b'C' | b'S' | b'P' | b'T' | b'G') {
+                    code.push('H');
+                }
+            }
+            b'P' => code.push(if next == b'H' { 'F' } else { 'P' }),
+            b'S' if next == b'H' || next == b'I' && matches!(after, b'O' | b'A') => code.push('X'),
+            b'S' | b'Z' => code.push('S'),
+            b'T' if next == b'I' && matches!(after, b'O' | b'A') => code.push('X'),
+            b'T' if next == b'H' => code.push('0'),
+            b'T' if next == b'C' && after == b'H' => {}
+            b'T' => code.push('T'),
+            b'V' => code.push('F'),
+            b'W' | b'Y' => {
+                if is_vowel(next) {
+                    code.push(c as char);
+                }
+            }
+            b'X' => code.push_str("KS"),
+            c => code.push(c as char),
+        }
+        i += 1;
+    }
+    code
+}
+
+/// The [`metaphone`] codes of the words of `text`.
+fn codes(text: &str) -> Vec<String> {
+    words(&fold(text)).map(metaphone).collect()
+}
+
+/// `query` as the repos that search in the database look it up in the
+/// contacts' [phonetic texts](Contact::phonetic_text), see
+/// [`Contact::sounds_like`]: the codes of its words, each between spaces.
+/// `None` if it has no words or one of them sounds like nothing, such as a
+/// number.
+pub(super) fn phonetic_query(query: &str) -> Option<String> {
+    let codes = codes(query);
+    if codes.is_empty() || codes.iter().any(String::is_empty) {
+        return None;
+    }
+    Some(format!(" {} ", codes.join(" ")))
+}
+
+impl Contact {
+    /// How the contact's first and last names sound: the [`metaphone`]
+    /// codes of their words, each between spaces, as stored by the repos
+    /// that search in the database.
+    pub fn phonetic_text(&self) -> String {
+        let first = self.first.as_deref().unwrap_or_default();
+        let last = self.last.as_deref().unwrap_or_default();
+        let codes: Vec<_> = codes(&format!("{first} {last}"))
+            .into_iter()
+            .filter(|code| !code.is_empty())
+            .collect();
+        format!(" {} ", codes.join(" "))
+    }
+
+    /// Whether the words of `query` sound like words of the contact's first
+    /// and last names, in the same order, however they are spelled, so that
+    /// `Christofferson` finds `Kristoffersen`.
+    pub fn sounds_like(&self, query: &str) -> bool {
+        phonetic_query(query).is_some_and(|query| self.phonetic_text().contains(&query))
+    }
+}
@@ -0,0 +1,121 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::Arc};
+
+use tokio::sync::RwLock;
+
+use super::write_atomic;
+
+/// Largest photo accepted, in bytes.
+pub const MAX_PHOTO_SIZE: usize = 1024 * 1024;
+
+/// A contact's photo, as uploaded, see
+/// [`Contact::has_photo`](super::Contact::has_photo).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Photo {
+    /// The media type of `data`, sniffed from its first bytes.
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl Photo {
+    /// `data` as a photo, if it is a PNG, JPEG, GIF or WebP image.
+    pub fn new(data: Vec<u8>) -> Option<Self> {
+        let content_type = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            "image/png"
+        } else if data.starts_with(b"\xff\xd8\xff") {
+            "image/jpeg"
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            "image/gif"
+        } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            "image/webp"
+        } else {
+            return None;
+        };
+        Some(Self { content_type, data })
+    }
+}
+
+/// Where contacts' photos are kept, by contact id.
+#[async_trait::async_trait]
+pub trait PhotoRepo {
+    async fn find(&self, id: u64) -> io::Result<Option<Photo>>;
+    /// Stores `photo` as the photo of the contact with `id`, replacing any
+    /// it had.
+    async fn save(&self, id: u64, photo: Photo) -> io::Result<()>;
+    /// Deletes the photo of the contact with `id`, if it has one.
+    async fn delete(&self, id: u64) -> io::Result<()>;
+}
+
+pub type SharedPhotoRepo = Arc<dyn PhotoRepo + Sync + Send>;
+
+/// Photo repository kept in memory, for `memory://` storage.
+#[derive(Debug, Default)]
+pub struct MemPhotoRepo {
+    photos: RwLock<HashMap<u64, Photo>>,
+}
+
+impl MemPhotoRepo {
+    pub fn new_shared() -> SharedPhotoRepo {
+        Arc::new(Self::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl PhotoRepo for MemPhotoRepo {
+    async fn find(&self, id: u64) -> io::Result<Option<Photo>> {
+        Ok(self.photos.read().await.get(&id).cloned())
+    }
+
+    async fn save(&self, id: u64, photo: Photo) -> io::Result<()> {
+        self.photos.write().await.insert(id, photo);
+        Ok(())
+    }
+
+    async fn delete(&self, id: u64) -> io::Result<()> {
+        self.photos.write().await.remove(&id);
+        Ok(())
+    }
+}
+
+/// Keeps photos as files named by contact id in a media directory, creating
+/// it if needed. Their type is sniffed again when they are read.
+#[derive(Debug, Clone)]
+pub struct DirPhotoRepo {
+    dir: PathBuf,
+}
+
+impl DirPhotoRepo {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn shared(dir: impl Into<PathBuf>) -> SharedPhotoRepo {
+        Arc::new(Self::new(dir))
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+}
+
+#[async_trait::async_trait]
+impl PhotoRepo for DirPhotoRepo {
+    async fn find(&self, id: u64) -> io::Result<Option<Photo>> {
+        match fs::read(self.path(id)) {
+            Ok(data) => Ok(Photo::new(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn save(&self, id: u64, photo: Photo) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.path(id), &photo.data)
+    }
+
+    async fn delete(&self, id: u64) -> io::Result<()> {
+        match fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
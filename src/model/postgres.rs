@@ -0,0 +1,1281 @@
+use std::{collections::BTreeMap, io, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream::BoxStream, StreamExt};
+use sqlx::{
+    pool::PoolConnection,
+    postgres::{PgConnection, PgPool, PgPoolOptions, PgRow},
+    AssertSqlSafe, Connection as _, Postgres, Row,
+};
+
+use super::{
+    groups::sort_groups, phones, phonetic::phonetic_query, search::fold, searches::sort_searches,
+    sql_offset, sql_timestamp, unique_emails, unique_phones, BoxedTransaction, Contact,
+    ContactChange, ContactFilter, ContactRepo, ContactTransaction, Cursor, CursorPage, CustomField,
+    CustomFieldRepo, FieldError, Group, GroupError, GroupRepo, HistoryRepo, Page, Photo, PhotoRepo,
+    RepoError, RepoStats, Revision, SavedSearch, SavedSearchError, SavedSearchRepo, SearchQuery,
+    SharedContactRepo, SharedCustomFieldRepo, SharedGroupRepo, SharedHistoryRepo, SharedPhotoRepo,
+    SharedSavedSearchRepo, Sort,
+};
+
+/// Contact repository backed by PostgreSQL, suitable for running several
+/// app instances against one database.
+///
+/// Like [`super::SqliteContactRepo`] contacts are stored as JSON documents.
+/// Email uniqueness is enforced by a unique index rather than by a read
+/// before the write, so concurrent instances cannot race each other. Unless
+/// [emails are unique](unique_emails), the index is replaced by one that
+/// only speeds up lookups.
+#[derive(Debug, Clone)]
+pub struct PgContactRepo {
+    pool: PgPool,
+}
+
+const MAX_CONNECTIONS: u32 = 10;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contacts (
+    id BIGSERIAL PRIMARY KEY,
+    email TEXT,
+    data JSONB NOT NULL
+);
+-- Databases from before contacts' search and phonetic texts were stored.
+ALTER TABLE contacts ADD COLUMN IF NOT EXISTS search TEXT;
+ALTER TABLE contacts ADD COLUMN IF NOT EXISTS phonetic TEXT;
+CREATE TABLE IF NOT EXISTS tombstones (
+    id BIGINT PRIMARY KEY,
+    deleted_at TEXT NOT NULL
+);
+-- Contacts saved with a single phone, before they had a list of them.
+UPDATE contacts SET data = (data - 'phone') || jsonb_build_object('phones',
+    CASE WHEN jsonb_typeof(data->'phone') = 'string'
+        THEN jsonb_build_array(jsonb_build_object('label', '', 'value', data->'phone'))
+        ELSE '[]'::jsonb
+    END)
+WHERE data ? 'phone';
+";
+
+/// Fills in the search and phonetic texts of the contacts saved without
+/// them.
+async fn index_search_texts(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let rows =
+        sqlx::query("SELECT id, data FROM contacts WHERE search IS NULL OR phonetic IS NULL")
+            .fetch_all(pool)
+            .await?;
+    for row in rows {
+        let contact = contact_from_row(row);
+        sqlx::query("UPDATE contacts SET search = $2, phonetic = $3 WHERE id = $1")
+            .bind(contact.id.map(|id| id as i64))
+            .bind(contact.search_text())
+            .bind(contact.phonetic_text())
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Fails, keeping the other index, while two contacts share an email.
+const UNIQUE_EMAIL_INDEX: &str = "
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_email ON contacts (email);
+DROP INDEX IF EXISTS contacts_email_lookup;
+";
+
+const EMAIL_INDEX: &str = "
+CREATE INDEX IF NOT EXISTS contacts_email_lookup ON contacts (email);
+DROP INDEX IF EXISTS contacts_email;
+";
+
+/// Contacts whose search text has `$1`, a [folded](fold) query, or whose
+/// phonetic text has `$2`, its [phonetic query](phonetic_query), unless
+/// archived; see [`Contact::matches`].
+const SEARCH_FILTER: &str = "coalesce((data->>'archived')::boolean, false) = false
+    AND (strpos(search, $1) > 0 OR strpos(phonetic, $2) > 0)";
+
+/// Matches contacts that aren't archived with the tag bound to `$1`.
+const TAG_FILTER: &str =
+    "coalesce((data->>'archived')::boolean, false) = false AND coalesce(data->'tags', '[]') ? $1";
+
+/// Matches [starred](Contact::starred) contacts that aren't archived.
+const STARRED_FILTER: &str =
+    "coalesce((data->>'archived')::boolean, false) = false AND data @> '{\"starred\": true}'";
+
+/// Matches contacts whose archived flag is bound to `$1`.
+const ARCHIVED_FILTER: &str = "coalesce((data->>'archived')::boolean, false) = $1";
+
+/// Matches contacts that aren't archived in the group whose id is bound to
+/// `$1`.
+const GROUP_FILTER: &str = "coalesce((data->>'archived')::boolean, false) = false
+    AND coalesce(data->'groups', '[]') @> jsonb_build_array($1::bigint)";
+
+/// Matches the contacts [`SEARCH_FILTER`] does, archived too if `$5` is
+/// true, with the tag bound to `$3` and in the group whose id is bound to
+/// `$4`, either unless null; see [`ContactFilter`].
+const FILTERED_SEARCH_FILTER: &str = "($5 OR coalesce((data->>'archived')::boolean, false) = false)
+    AND (strpos(search, $1) > 0 OR strpos(phonetic, $2) > 0)
+    AND ($3::text IS NULL OR coalesce(data->'tags', '[]') ? $3::text)
+    AND ($4::bigint IS NULL OR coalesce(data->'groups', '[]') @> jsonb_build_array($4::bigint))";
+
+impl PgContactRepo {
+    /// Connects a pool to `url`, e.g. `postgres://user@localhost/contacts`,
+    /// and makes sure the schema exists.
+    pub async fn from_url(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .expect("database to connect");
+        sqlx::raw_sql(SCHEMA)
+            .execute(&pool)
+            .await
+            .expect("schema creation succeed");
+        let email_index = if unique_emails() {
+            UNIQUE_EMAIL_INDEX
+        } else {
+            EMAIL_INDEX
+        };
+        sqlx::raw_sql(email_index)
+            .execute(&pool)
+            .await
+            .expect("email index creation succeed");
+        index_search_texts(&pool)
+            .await
+            .expect("search texts to be indexed");
+        Self { pool }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedContactRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+
+    async fn connection(&self) -> Result<PoolConnection<Postgres>, RepoError> {
+        self.pool.acquire().await.map_err(RepoError::io)
+    }
+}
+
+/// A transaction on a [`PgContactRepo`].
+struct PgTransaction {
+    tx: sqlx::Transaction<'static, Postgres>,
+}
+
+fn contact_from_row(row: PgRow) -> Contact {
+    let id: i64 = row.get("id");
+    let data: serde_json::Value = row.get("data");
+    let mut contact: Contact = serde_json::from_value(data).expect("valid JSON");
+    contact.id = Some(id as u64);
+    contact
+}
+
+/// Validates `contact`, failing if another contact has one of its phone
+/// numbers when those are unique. Unlike emails they are checked by a read
+/// before the write, so two instances saving a number at once can both
+/// succeed.
+async fn validate(conn: &mut PgConnection, mut contact: Contact) -> Result<Contact, RepoError> {
+    if !contact.validate() {
+        return Err(RepoError::invalid(contact));
+    }
+    if !unique_phones() || contact.phones.is_empty() {
+        return Ok(contact);
+    }
+    let numbers: Vec<&str> = contact.phones.iter().map(|phone| &*phone.value).collect();
+    let owners = sqlx::query(
+        "SELECT id, data FROM contacts WHERE id IS DISTINCT FROM $1 AND EXISTS (
+             SELECT 1 FROM jsonb_array_elements(data->'phones') AS phone
+             WHERE phone->>'value' = ANY($2)
+         )",
+    )
+    .bind(contact.id.map(|id| id as i64))
+    .bind(numbers)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepoError::io)?;
+    let owners: Vec<Contact> = owners.into_iter().map(contact_from_row).collect();
+    phones::check_phones(contact, &owners)
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(err) if err.is_unique_violation())
+}
+
+/// `ORDER BY` terms for `sort`, built from fixed field names only.
+fn order_by(sort: Sort) -> String {
+    sort.to_sql(
+        |field| format!("(data->>'{field}') COLLATE \"C\""),
+        |field| format!("(data->>'{field}')::timestamptz"),
+    )
+}
+
+async fn select_all(conn: &mut PgConnection) -> Result<Vec<Contact>, RepoError> {
+    let rows = sqlx::query("SELECT id, data FROM contacts ORDER BY id")
+        .fetch_all(conn)
+        .await
+        .map_err(RepoError::io)?;
+    Ok(rows.into_iter().map(contact_from_row).collect())
+}
+
+async fn select(conn: &mut PgConnection, id: u64) -> Result<Option<Contact>, RepoError> {
+    let row = sqlx::query("SELECT id, data FROM contacts WHERE id = $1")
+        .bind(id as i64)
+        .fetch_optional(conn)
+        .await
+        .map_err(RepoError::io)?;
+    Ok(row.map(contact_from_row))
+}
+
+async fn upsert(conn: &mut PgConnection, contact: Contact) -> Result<u64, RepoError> {
+    let contact = validate(conn, contact).await?;
+    let data = serde_json::to_value(&contact).expect("serializing succeed");
+    let result: Result<i64, _> = match contact.id {
+        None => {
+            sqlx::query_scalar(
+                "INSERT INTO contacts (email, data, search, phonetic) VALUES ($1, $2, $3, $4) \
+                 RETURNING id",
+            )
+            .bind(&contact.email)
+            .bind(data)
+            .bind(contact.search_text())
+            .bind(contact.phonetic_text())
+            .fetch_one(&mut *conn)
+            .await
+        }
+        Some(id) => {
+            sqlx::query_scalar(
+                "INSERT INTO contacts (id, email, data, search, phonetic)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (id) DO UPDATE
+                 SET email = excluded.email, data = excluded.data, search = excluded.search,
+                     phonetic = excluded.phonetic
+                 RETURNING id",
+            )
+            .bind(id as i64)
+            .bind(&contact.email)
+            .bind(data)
+            .bind(contact.search_text())
+            .bind(contact.phonetic_text())
+            .fetch_one(&mut *conn)
+            .await
+        }
+    };
+    match result {
+        Ok(id) => {
+            if contact.id.is_some() {
+                set_tombstone(conn, id as u64, None).await?;
+            }
+            Ok(id as u64)
+        }
+        Err(err) if is_unique_violation(&err) => Err(RepoError::email_taken(contact)),
+        Err(err) => Err(RepoError::io(err)),
+    }
+}
+
+/// Adds `contact`, which must not have the id of an existing one.
+async fn insert(conn: &mut PgConnection, contact: Contact) -> Result<u64, RepoError> {
+    let contact = validate(conn, contact).await?;
+    let data = serde_json::to_value(contact.bumped()).expect("serializing succeed");
+    let result: Result<i64, _> = match contact.id {
+        None => {
+            sqlx::query_scalar(
+                "INSERT INTO contacts (email, data, search, phonetic) VALUES ($1, $2, $3, $4) \
+                 RETURNING id",
+            )
+            .bind(&contact.email)
+            .bind(data)
+            .bind(contact.search_text())
+            .bind(contact.phonetic_text())
+            .fetch_one(&mut *conn)
+            .await
+        }
+        Some(id) => {
+            sqlx::query_scalar(
+                "INSERT INTO contacts (id, email, data, search, phonetic) \
+                 VALUES ($1, $2, $3, $4, $5) RETURNING id",
+            )
+            .bind(id as i64)
+            .bind(&contact.email)
+            .bind(data)
+            .bind(contact.search_text())
+            .bind(contact.phonetic_text())
+            .fetch_one(&mut *conn)
+            .await
+        }
+    };
+    match result {
+        Ok(id) => {
+            // Only ids chosen by the caller can have been used before.
+            if contact.id.is_some() {
+                set_tombstone(conn, id as u64, None).await?;
+            }
+            Ok(id as u64)
+        }
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("contacts_pkey") => {
+            Err(RepoError::id_taken(contact))
+        }
+        Err(err) if is_unique_violation(&err) => Err(RepoError::email_taken(contact)),
+        Err(err) => Err(RepoError::io(err)),
+    }
+}
+
+/// Replaces the contact with `contact`'s id, if it is still at `contact`'s
+/// version.
+async fn replace(conn: &mut PgConnection, contact: Contact) -> Result<(), RepoError> {
+    let contact = validate(conn, contact).await?;
+    let id = contact.id.expect("an updated contact to have an id");
+    let data = serde_json::to_value(contact.bumped()).expect("serializing succeed");
+    let result = sqlx::query(
+        "UPDATE contacts SET email = $2, data = $3, search = $5, phonetic = $6
+         WHERE id = $1 AND COALESCE((data->>'version')::bigint, 0) = $4",
+    )
+    .bind(id as i64)
+    .bind(&contact.email)
+    .bind(data)
+    .bind(contact.version as i64)
+    .bind(contact.search_text())
+    .bind(contact.phonetic_text())
+    .execute(&mut *conn)
+    .await;
+    match result {
+        Ok(result) if result.rows_affected() == 0 => match select(conn, id).await? {
+            None => Err(RepoError::NotFound(id)),
+            Some(_) => Err(RepoError::stale(contact)),
+        },
+        Ok(_) => Ok(()),
+        Err(err) if is_unique_violation(&err) => Err(RepoError::email_taken(contact)),
+        Err(err) => Err(RepoError::io(err)),
+    }
+}
+
+/// Deletes the contact with `id`, returning whether there was one.
+async fn remove(conn: &mut PgConnection, id: u64) -> Result<bool, RepoError> {
+    let result = sqlx::query("DELETE FROM contacts WHERE id = $1")
+        .bind(id as i64)
+        .execute(&mut *conn)
+        .await
+        .map_err(RepoError::io)?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    set_tombstone(conn, id, Some(Utc::now())).await?;
+    Ok(true)
+}
+
+/// Records that the contact with `id` was deleted at `deleted_at`, or with
+/// `None` that it exists again.
+async fn set_tombstone(
+    conn: &mut PgConnection,
+    id: u64,
+    deleted_at: Option<DateTime<Utc>>,
+) -> Result<(), RepoError> {
+    let query = match deleted_at {
+        Some(deleted_at) => sqlx::query(
+            "INSERT INTO tombstones (id, deleted_at) VALUES ($1, $2)
+             ON CONFLICT (id) DO UPDATE SET deleted_at = excluded.deleted_at",
+        )
+        .bind(id as i64)
+        .bind(sql_timestamp(deleted_at)),
+        None => sqlx::query("DELETE FROM tombstones WHERE id = $1").bind(id as i64),
+    };
+    query.execute(conn).await.map_err(RepoError::io)?;
+    Ok(())
+}
+
+fn tombstone_from_row((id, deleted_at): (i64, String)) -> (u64, DateTime<Utc>) {
+    let deleted_at = DateTime::parse_from_rfc3339(&deleted_at).expect("a valid timestamp");
+    (id as u64, deleted_at.into())
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for PgContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let query = format!("SELECT id, data FROM contacts ORDER BY {}", order_by(sort));
+        let rows = sqlx::query(AssertSqlSafe(query))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        rows.into_iter().map(contact_from_row).collect()
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        let count: i64 = match filter {
+            None => {
+                sqlx::query_scalar("SELECT COUNT(*) FROM contacts")
+                    .fetch_one(&self.pool)
+                    .await
+            }
+            Some(query) => {
+                // Only the text searched for as a whole can be looked up
+                // in the columns of search texts.
+                let search = SearchQuery::parse(query);
+                let Some(text) = search.plain_text().map(str::to_owned) else {
+                    let contacts = self.all(Sort::default()).await;
+                    return contacts
+                        .iter()
+                        .filter(|contact| contact.matches(&search))
+                        .count();
+                };
+                let count_query = format!("SELECT COUNT(*) FROM contacts WHERE {SEARCH_FILTER}");
+                sqlx::query_scalar(AssertSqlSafe(count_query))
+                    .bind(fold(&text))
+                    .bind(phonetic_query(&text))
+                    .fetch_one(&self.pool)
+                    .await
+            }
+        }
+        .expect("query succeed");
+        count as usize
+    }
+
+    /// Streams the rows of one query.
+    fn stream_all(&self) -> BoxStream<'_, Contact> {
+        sqlx::query("SELECT id, data FROM contacts ORDER BY id")
+            .fetch(&self.pool)
+            .map(|row| contact_from_row(row.expect("query succeed")))
+            .boxed()
+    }
+
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = format!(
+            "SELECT id, data FROM contacts ORDER BY {} LIMIT $1 OFFSET $2",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query))
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, self.count(None).await)
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        let after = cursor.map_or(0, Cursor::id);
+        let rows = sqlx::query("SELECT id, data FROM contacts WHERE id > $1 ORDER BY id LIMIT $2")
+            .bind(after as i64)
+            .bind(limit as i64 + 1)
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        CursorPage::from_sorted(rows.into_iter().map(contact_from_row).collect(), limit)
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let search = SearchQuery::parse(query);
+        let Some(text) = search.plain_text().map(str::to_owned) else {
+            let contacts = self.all(sort).await;
+            let matches = contacts.iter().filter(|contact| contact.matches(&search));
+            return Page::of(matches, sort, number, size);
+        };
+        let query_rows = format!(
+            "SELECT id, data FROM contacts WHERE {SEARCH_FILTER} ORDER BY {} LIMIT $3 OFFSET $4",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query_rows))
+            .bind(fold(&text))
+            .bind(phonetic_query(&text))
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, self.count(Some(query)).await)
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        filter: &ContactFilter,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let search = SearchQuery::parse(query);
+        let Some(text) = search.plain_text().map(str::to_owned) else {
+            let contacts = self.all(sort).await;
+            let matches = contacts
+                .iter()
+                .filter(|contact| filter.finds(&search, contact));
+            return Page::of(matches, sort, number, size);
+        };
+        let (folded, phonetic) = (fold(&text), phonetic_query(&text));
+        let group = filter.group.map(|group| group as i64);
+        let query_rows = format!(
+            "SELECT id, data FROM contacts WHERE {FILTERED_SEARCH_FILTER}
+                ORDER BY {} LIMIT $6 OFFSET $7",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query_rows))
+            .bind(&folded)
+            .bind(&phonetic)
+            .bind(filter.tag.as_deref())
+            .bind(group)
+            .bind(filter.archived)
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let count_query = format!("SELECT COUNT(*) FROM contacts WHERE {FILTERED_SEARCH_FILTER}");
+        let total: i64 = sqlx::query_scalar(AssertSqlSafe(count_query))
+            .bind(&folded)
+            .bind(&phonetic)
+            .bind(filter.tag.as_deref())
+            .bind(group)
+            .bind(filter.archived)
+            .fetch_one(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, total as usize)
+    }
+
+    async fn tagged(&self, tag: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query_rows = format!(
+            "SELECT id, data FROM contacts WHERE {TAG_FILTER} ORDER BY {} LIMIT $2 OFFSET $3",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query_rows))
+            .bind(tag)
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let count_query = format!("SELECT COUNT(*) FROM contacts WHERE {TAG_FILTER}");
+        let total: i64 = sqlx::query_scalar(AssertSqlSafe(count_query))
+            .bind(tag)
+            .fetch_one(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, total as usize)
+    }
+
+    async fn tags(&self) -> BTreeMap<String, usize> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT tag, COUNT(*) FROM contacts,
+                jsonb_array_elements_text(coalesce(data->'tags', '[]')) AS tag
+             WHERE coalesce((data->>'archived')::boolean, false) = false
+             GROUP BY tag",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .expect("query succeed");
+        rows.into_iter()
+            .map(|(tag, count)| (tag, count as usize))
+            .collect()
+    }
+
+    async fn members(&self, group: u64, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query_rows = format!(
+            "SELECT id, data FROM contacts WHERE {GROUP_FILTER} ORDER BY {} LIMIT $2 OFFSET $3",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query_rows))
+            .bind(group as i64)
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let count_query = format!("SELECT COUNT(*) FROM contacts WHERE {GROUP_FILTER}");
+        let total: i64 = sqlx::query_scalar(AssertSqlSafe(count_query))
+            .bind(group as i64)
+            .fetch_one(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, total as usize)
+    }
+
+    async fn starred(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query_rows = format!(
+            "SELECT id, data FROM contacts WHERE {STARRED_FILTER} ORDER BY {} LIMIT $1 OFFSET $2",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query_rows))
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let count_query = format!("SELECT COUNT(*) FROM contacts WHERE {STARRED_FILTER}");
+        let total: i64 = sqlx::query_scalar(AssertSqlSafe(count_query))
+            .fetch_one(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, total as usize)
+    }
+
+    async fn archived(
+        &self,
+        archived: bool,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Page<Contact> {
+        let query_rows = format!(
+            "SELECT id, data FROM contacts WHERE {ARCHIVED_FILTER} ORDER BY {} LIMIT $2 OFFSET $3",
+            order_by(sort)
+        );
+        let rows = sqlx::query(AssertSqlSafe(query_rows))
+            .bind(archived)
+            .bind(size as i64)
+            .bind(sql_offset(number, size))
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let count_query = format!("SELECT COUNT(*) FROM contacts WHERE {ARCHIVED_FILTER}");
+        let total: i64 = sqlx::query_scalar(AssertSqlSafe(count_query))
+            .bind(archived)
+            .fetch_one(&self.pool)
+            .await
+            .expect("query succeed");
+        let items = rows.into_iter().map(contact_from_row).collect();
+        Page::new(items, number, size, total as usize)
+    }
+
+    async fn group_sizes(&self) -> BTreeMap<u64, usize> {
+        let rows: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT grp::bigint, COUNT(*) FROM contacts,
+                jsonb_array_elements_text(coalesce(data->'groups', '[]')) AS grp
+             WHERE coalesce((data->>'archived')::boolean, false) = false
+             GROUP BY grp",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .expect("query succeed");
+        rows.into_iter()
+            .map(|(group, count)| (group as u64, count as usize))
+            .collect()
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        insert(&mut *self.connection().await?, contact).await
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        replace(&mut *self.connection().await?, contact).await
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        select(&mut *self.connection().await?, id).await
+    }
+
+    async fn find_many(&self, ids: &[u64]) -> Result<Vec<Contact>, RepoError> {
+        let rows = sqlx::query("SELECT id, data FROM contacts WHERE id = ANY($1) ORDER BY id")
+            .bind(ids.iter().map(|&id| id as i64).collect::<Vec<_>>())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(RepoError::io)?;
+        Ok(rows.into_iter().map(contact_from_row).collect())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        let row = sqlx::query("SELECT id, data FROM contacts WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RepoError::io)?;
+        Ok(row.map(contact_from_row))
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        if !remove(&mut *self.connection().await?, id).await? {
+            return Err(RepoError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let rows = sqlx::query(
+            "SELECT id, data FROM contacts
+             WHERE (data->>'updated_at')::timestamptz >= $1::timestamptz",
+        )
+        .bind(sql_timestamp(since))
+        .fetch_all(&self.pool)
+        .await
+        .expect("query succeed");
+        let tombstones: Vec<(i64, String)> =
+            sqlx::query_as("SELECT id, deleted_at FROM tombstones WHERE deleted_at >= $1")
+                .bind(sql_timestamp(since))
+                .fetch_all(&self.pool)
+                .await
+                .expect("query succeed");
+        ContactChange::since(
+            rows.into_iter().map(contact_from_row),
+            tombstones.into_iter().map(tombstone_from_row),
+            since,
+        )
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        let tx = self.pool.begin().await.map_err(RepoError::io)?;
+        Ok(Box::new(PgTransaction { tx }))
+    }
+
+    async fn replace_all(&self, contacts: Vec<Contact>) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::io)?;
+        // The contacts restored again lose their tombstones when upserted.
+        sqlx::query(
+            "INSERT INTO tombstones (id, deleted_at) SELECT id, $1 FROM contacts
+             ON CONFLICT (id) DO UPDATE SET deleted_at = excluded.deleted_at",
+        )
+        .bind(sql_timestamp(Utc::now()))
+        .execute(&mut *tx)
+        .await
+        .map_err(RepoError::io)?;
+        sqlx::query("DELETE FROM contacts")
+            .execute(&mut *tx)
+            .await
+            .map_err(RepoError::io)?;
+        for contact in contacts {
+            upsert(&mut tx, contact).await?;
+        }
+        // Inserting explicit ids doesn't advance the sequence handing out
+        // new ones, which must stay above those of deleted contacts too.
+        sqlx::query(
+            "SELECT setval(pg_get_serial_sequence('contacts', 'id'), coalesce(max(id), 0) + 1, false)
+             FROM (SELECT id FROM contacts UNION ALL SELECT id FROM tombstones) AS ids",
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RepoError::io)?;
+        tx.commit().await.map_err(RepoError::io)
+    }
+
+    async fn stats(&self) -> RepoStats {
+        let size: i64 = sqlx::query_scalar("SELECT pg_total_relation_size('contacts')")
+            .fetch_one(&self.pool)
+            .await
+            .expect("query succeed");
+        RepoStats {
+            count: self.count(None).await,
+            storage_size: Some(size as u64),
+            ..RepoStats::default()
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ContactTransaction for PgTransaction {
+    async fn all(&mut self) -> Result<Vec<Contact>, RepoError> {
+        select_all(&mut self.tx).await
+    }
+
+    async fn find(&mut self, id: u64) -> Result<Option<Contact>, RepoError> {
+        select(&mut self.tx, id).await
+    }
+
+    async fn save(&mut self, contact: Contact) -> Result<u64, RepoError> {
+        // A failed statement aborts the whole transaction, a rejected save
+        // should only undo itself.
+        let mut savepoint = self.tx.begin().await.map_err(RepoError::io)?;
+        let result = upsert(&mut savepoint, contact).await;
+        match result {
+            Ok(_) => savepoint.commit().await.map_err(RepoError::io)?,
+            Err(_) => savepoint.rollback().await.map_err(RepoError::io)?,
+        }
+        result
+    }
+
+    async fn delete(&mut self, contact: Contact) -> Result<(), RepoError> {
+        remove(&mut self.tx, contact.id.unwrap()).await?;
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), RepoError> {
+        self.tx.commit().await.map_err(RepoError::io)
+    }
+
+    async fn rollback(self: Box<Self>) {
+        self.tx.rollback().await.expect("rolling back succeed");
+    }
+}
+
+/// Group repository kept in a table of the database a [`PgContactRepo`]
+/// uses, with the name pulled out of the JSON document to keep it unique.
+#[derive(Debug, Clone)]
+pub struct PgGroupRepo {
+    pool: PgPool,
+}
+
+const GROUP_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contact_groups (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    data JSONB NOT NULL
+);
+";
+
+impl PgGroupRepo {
+    /// Connects a pool to `url`, as [`PgContactRepo::from_url`] does, and
+    /// makes sure the table exists.
+    pub async fn from_url(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .expect("database to connect");
+        sqlx::raw_sql(GROUP_SCHEMA)
+            .execute(&pool)
+            .await
+            .expect("schema creation succeed");
+        Self { pool }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedGroupRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+}
+
+fn group_from_row((id, data): (i64, serde_json::Value)) -> Group {
+    let mut group: Group = serde_json::from_value(data).expect("valid JSON");
+    group.id = Some(id as u64);
+    group
+}
+
+/// `err` as a [`GroupError`], [`GroupError::Conflict`] if `group`'s name is
+/// taken.
+fn group_error(err: sqlx::Error, group: Group) -> GroupError {
+    if is_unique_violation(&err) {
+        GroupError::name_taken(group)
+    } else {
+        GroupError::io(err)
+    }
+}
+
+#[async_trait::async_trait]
+impl GroupRepo for PgGroupRepo {
+    async fn all(&self) -> Vec<Group> {
+        let rows = sqlx::query_as("SELECT id, data FROM contact_groups")
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let mut groups: Vec<Group> = rows.into_iter().map(group_from_row).collect();
+        sort_groups(&mut groups);
+        groups
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Group>, GroupError> {
+        let row = sqlx::query_as("SELECT id, data FROM contact_groups WHERE id = $1")
+            .bind(id as i64)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(GroupError::io)?;
+        Ok(row.map(group_from_row))
+    }
+
+    async fn create(&self, mut group: Group) -> Result<u64, GroupError> {
+        group.id = None;
+        if !group.validate() {
+            return Err(GroupError::Validation(Box::new(group)));
+        }
+        let data = serde_json::to_value(&group).map_err(GroupError::io)?;
+        let id: Result<i64, _> = sqlx::query_scalar(
+            "INSERT INTO contact_groups (name, data) VALUES ($1, $2) RETURNING id",
+        )
+        .bind(&group.name)
+        .bind(data)
+        .fetch_one(&self.pool)
+        .await;
+        id.map(|id| id as u64)
+            .map_err(|err| group_error(err, group))
+    }
+
+    async fn update(&self, mut group: Group) -> Result<(), GroupError> {
+        let id = group.id.expect("an updated group to have an id");
+        if !group.validate() {
+            return Err(GroupError::Validation(Box::new(group)));
+        }
+        let data = serde_json::to_value(&group).map_err(GroupError::io)?;
+        let updated = sqlx::query("UPDATE contact_groups SET name = $1, data = $2 WHERE id = $3")
+            .bind(&group.name)
+            .bind(data)
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|err| group_error(err, group))?;
+        if updated.rows_affected() == 0 {
+            return Err(GroupError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), GroupError> {
+        let deleted = sqlx::query("DELETE FROM contact_groups WHERE id = $1")
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(GroupError::io)?;
+        if deleted.rows_affected() == 0 {
+            return Err(GroupError::NotFound(id));
+        }
+        Ok(())
+    }
+}
+
+/// Saved search repository kept in a table of the database a
+/// [`PgContactRepo`] uses, with the name pulled out of the JSON document
+/// to keep it unique.
+#[derive(Debug, Clone)]
+pub struct PgSavedSearchRepo {
+    pool: PgPool,
+}
+
+const SAVED_SEARCH_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    data JSONB NOT NULL
+);
+";
+
+impl PgSavedSearchRepo {
+    /// Connects a pool to `url`, as [`PgContactRepo::from_url`] does, and
+    /// makes sure the table exists.
+    pub async fn from_url(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .expect("database to connect");
+        sqlx::raw_sql(SAVED_SEARCH_SCHEMA)
+            .execute(&pool)
+            .await
+            .expect("schema creation succeed");
+        Self { pool }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedSavedSearchRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+}
+
+fn saved_search_from_row((id, data): (i64, serde_json::Value)) -> SavedSearch {
+    let mut search: SavedSearch = serde_json::from_value(data).expect("valid JSON");
+    search.id = Some(id as u64);
+    search
+}
+
+#[async_trait::async_trait]
+impl SavedSearchRepo for PgSavedSearchRepo {
+    async fn all(&self) -> Vec<SavedSearch> {
+        let rows = sqlx::query_as("SELECT id, data FROM saved_searches")
+            .fetch_all(&self.pool)
+            .await
+            .expect("query succeed");
+        let mut searches: Vec<SavedSearch> = rows.into_iter().map(saved_search_from_row).collect();
+        sort_searches(&mut searches);
+        searches
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<SavedSearch>, SavedSearchError> {
+        let row = sqlx::query_as("SELECT id, data FROM saved_searches WHERE id = $1")
+            .bind(id as i64)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(SavedSearchError::io)?;
+        Ok(row.map(saved_search_from_row))
+    }
+
+    async fn create(&self, mut search: SavedSearch) -> Result<u64, SavedSearchError> {
+        search.id = None;
+        if !search.validate() {
+            return Err(SavedSearchError::Validation(Box::new(search)));
+        }
+        let data = serde_json::to_value(&search).map_err(SavedSearchError::io)?;
+        let id: Result<i64, _> = sqlx::query_scalar(
+            "INSERT INTO saved_searches (name, data) VALUES ($1, $2) RETURNING id",
+        )
+        .bind(&search.name)
+        .bind(data)
+        .fetch_one(&self.pool)
+        .await;
+        match id {
+            Ok(id) => Ok(id as u64),
+            Err(err) if is_unique_violation(&err) => Err(SavedSearchError::name_taken(search)),
+            Err(err) => Err(SavedSearchError::io(err)),
+        }
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), SavedSearchError> {
+        let deleted = sqlx::query("DELETE FROM saved_searches WHERE id = $1")
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(SavedSearchError::io)?;
+        if deleted.rows_affected() == 0 {
+            return Err(SavedSearchError::NotFound(id));
+        }
+        Ok(())
+    }
+}
+
+/// Photo repository kept in a table of the database a [`PgContactRepo`]
+/// uses.
+#[derive(Debug, Clone)]
+pub struct PgPhotoRepo {
+    pool: PgPool,
+}
+
+const PHOTO_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contact_photos (
+    id BIGINT PRIMARY KEY,
+    data BYTEA NOT NULL
+);
+";
+
+impl PgPhotoRepo {
+    /// Connects a pool to `url`, as
+    /// [`PgContactRepo::from_url`] does, and makes sure the table exists.
+    pub async fn from_url(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .expect("database to connect");
+        sqlx::raw_sql(PHOTO_SCHEMA)
+            .execute(&pool)
+            .await
+            .expect("schema creation succeed");
+        Self { pool }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedPhotoRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+}
+
+#[async_trait::async_trait]
+impl PhotoRepo for PgPhotoRepo {
+    async fn find(&self, id: u64) -> io::Result<Option<Photo>> {
+        let data: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT data FROM contact_photos WHERE id = $1")
+                .bind(id as i64)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(io::Error::other)?;
+        Ok(data.and_then(Photo::new))
+    }
+
+    async fn save(&self, id: u64, photo: Photo) -> io::Result<()> {
+        sqlx::query(
+            "INSERT INTO contact_photos (id, data) VALUES ($1, $2) \
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+        )
+        .bind(id as i64)
+        .bind(photo.data)
+        .execute(&self.pool)
+        .await
+        .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: u64) -> io::Result<()> {
+        sqlx::query("DELETE FROM contact_photos WHERE id = $1")
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+/// Custom field repository kept in a table of the database a
+/// [`PgContactRepo`] uses, in the order the fields were created.
+#[derive(Debug, Clone)]
+pub struct PgCustomFieldRepo {
+    pool: PgPool,
+}
+
+const FIELD_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS custom_fields (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    data JSONB NOT NULL
+);
+";
+
+impl PgCustomFieldRepo {
+    /// Connects a pool to `url`, as
+    /// [`PgContactRepo::from_url`] does, and makes sure the table exists.
+    pub async fn from_url(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .expect("database to connect");
+        sqlx::raw_sql(FIELD_SCHEMA)
+            .execute(&pool)
+            .await
+            .expect("schema creation succeed");
+        Self { pool }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedCustomFieldRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+}
+
+fn field_from_data(data: serde_json::Value) -> CustomField {
+    serde_json::from_value(data).expect("valid JSON")
+}
+
+#[async_trait::async_trait]
+impl CustomFieldRepo for PgCustomFieldRepo {
+    async fn all(&self) -> Vec<CustomField> {
+        let rows: Vec<serde_json::Value> =
+            sqlx::query_scalar("SELECT data FROM custom_fields ORDER BY id")
+                .fetch_all(&self.pool)
+                .await
+                .expect("query succeed");
+        rows.into_iter().map(field_from_data).collect()
+    }
+
+    async fn find(&self, name: &str) -> Result<Option<CustomField>, FieldError> {
+        let data = sqlx::query_scalar("SELECT data FROM custom_fields WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(FieldError::io)?;
+        Ok(data.map(field_from_data))
+    }
+
+    async fn create(&self, mut field: CustomField) -> Result<(), FieldError> {
+        field.name = field.name.trim().to_owned();
+        if !field.validate() {
+            return Err(FieldError::Validation(Box::new(field)));
+        }
+        let data = serde_json::to_value(&field).map_err(FieldError::io)?;
+        let created = sqlx::query("INSERT INTO custom_fields (name, data) VALUES ($1, $2)")
+            .bind(&field.name)
+            .bind(data)
+            .execute(&self.pool)
+            .await;
+        match created {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(db)) if db.is_unique_violation() => {
+                Err(FieldError::name_taken(field))
+            }
+            Err(err) => Err(FieldError::io(err)),
+        }
+    }
+
+    async fn update(&self, field: CustomField) -> Result<(), FieldError> {
+        let data = serde_json::to_value(&field).map_err(FieldError::io)?;
+        let updated = sqlx::query("UPDATE custom_fields SET data = $2 WHERE name = $1")
+            .bind(&field.name)
+            .bind(data)
+            .execute(&self.pool)
+            .await
+            .map_err(FieldError::io)?;
+        if updated.rows_affected() == 0 {
+            return Err(FieldError::NotFound(field.name));
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), FieldError> {
+        let deleted = sqlx::query("DELETE FROM custom_fields WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(FieldError::io)?;
+        if deleted.rows_affected() == 0 {
+            return Err(FieldError::NotFound(name.to_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// History repository kept in a table of the database a
+/// [`PgContactRepo`] uses.
+#[derive(Debug, Clone)]
+pub struct PgHistoryRepo {
+    pool: PgPool,
+}
+
+const HISTORY_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contact_history (
+    contact_id BIGINT NOT NULL,
+    version BIGINT NOT NULL,
+    data JSONB NOT NULL,
+    PRIMARY KEY (contact_id, version)
+);
+";
+
+impl PgHistoryRepo {
+    /// Connects a pool to `url`, as [`PgContactRepo::from_url`] does, and
+    /// makes sure the table exists.
+    pub async fn from_url(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .expect("database to connect");
+        sqlx::raw_sql(HISTORY_SCHEMA)
+            .execute(&pool)
+            .await
+            .expect("schema creation succeed");
+        Self { pool }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedHistoryRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+}
+
+fn revision_from_data(data: String) -> Revision {
+    serde_json::from_str(&data).expect("valid JSON")
+}
+
+#[async_trait::async_trait]
+impl HistoryRepo for PgHistoryRepo {
+    async fn revisions(&self, contact_id: u64) -> io::Result<Vec<Revision>> {
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT data::text FROM contact_history WHERE contact_id = $1 ORDER BY version DESC",
+        )
+        .bind(contact_id as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(io::Error::other)?;
+        Ok(rows.into_iter().map(revision_from_data).collect())
+    }
+
+    async fn revision(&self, contact_id: u64, version: u64) -> io::Result<Option<Revision>> {
+        let data: Option<String> = sqlx::query_scalar(
+            "SELECT data::text FROM contact_history WHERE contact_id = $1 AND version = $2",
+        )
+        .bind(contact_id as i64)
+        .bind(version as i64)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(io::Error::other)?;
+        Ok(data.map(revision_from_data))
+    }
+
+    async fn add(&self, revision: Revision) -> io::Result<()> {
+        let data = serde_json::to_string(&revision)?;
+        sqlx::query(
+            "INSERT INTO contact_history (contact_id, version, data) \
+             VALUES ($1, $2, $3::jsonb) ON CONFLICT DO NOTHING",
+        )
+        .bind(revision.contact_id as i64)
+        .bind(revision.version as i64)
+        .bind(data)
+        .execute(&self.pool)
+        .await
+        .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn clear(&self, contact_id: u64) -> io::Result<()> {
+        sqlx::query("DELETE FROM contact_history WHERE contact_id = $1")
+            .bind(contact_id as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,570 @@
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+
+use super::{search::fold, Address, Contact};
+
+/// Most characters the pattern of a regex search may have.
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// Most bytes the pattern of a regex search may take compiled, so that one
+/// like `\w{1000}{1000}` is refused rather than taking the server's memory.
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// A field a [`SearchQuery`] may be narrowed to, named as in the JSON file
+/// storage, such as `email` in `email:gmail.com`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchField {
+    First,
+    Middle,
+    Last,
+    Nickname,
+    /// Any of the names.
+    Name,
+    /// The primary email or another one.
+    Email,
+    Phone,
+    Company,
+    JobTitle,
+    Notes,
+    Street,
+    City,
+    PostalCode,
+    Country,
+    /// Any part of an address.
+    Address,
+    Tag,
+}
+
+impl FromStr for SearchField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Self::First),
+            "middle" => Ok(Self::Middle),
+            "last" => Ok(Self::Last),
+            "nickname" => Ok(Self::Nickname),
+            "name" => Ok(Self::Name),
+            "email" => Ok(Self::Email),
+            "phone" => Ok(Self::Phone),
+            "company" => Ok(Self::Company),
+            "job_title" => Ok(Self::JobTitle),
+            "notes" => Ok(Self::Notes),
+            "street" => Ok(Self::Street),
+            "city" => Ok(Self::City),
+            "postal_code" => Ok(Self::PostalCode),
+            "country" => Ok(Self::Country),
+            "address" => Ok(Self::Address),
+            "tag" => Ok(Self::Tag),
+            _ => Err(format!("unknown search field '{s}'")),
+        }
+    }
+}
+
+/// A search query parsed into the words narrowed to a field, written
+/// `field:value` as in `email:gmail.com last:Smith`, and the text searched
+/// for as a whole, as [`Contact::matches`] does without fields. Words with
+/// a prefix that isn't a [`SearchField`], such as `10:30`, are text.
+///
+/// Queries with a quote or with `AND`, `OR` or `NOT` in capitals combine
+/// their parts with those, in that order of precedence and grouped by
+/// parentheses, such as `"van der Berg" AND (stockholm OR NOT tag:work)`.
+/// A phrase in quotes is text, also after a field as in `last:"van der"`,
+/// and so are words not separated by anything else. Parts without an
+/// operator between them must all match, as do those of other queries.
+///
+/// A query written `/pattern/` is a regular expression instead, matched
+/// against each searched field as it is, so case sensitive unless it
+/// starts with `(?i)`. One that doesn't compile, or is longer than
+/// [`MAX_PATTERN_LEN`], finds nothing; see [`Self::error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    expr: Expr,
+    /// Why the query's pattern didn't compile, if it didn't.
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    /// Searched for as a whole, see [`Contact::matches_text`].
+    Text(String),
+    /// [Folded](fold) and looked for in the field only.
+    Field(SearchField, String),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    /// Matched against each [searched field](Contact::searched_fields).
+    Pattern(Pattern),
+}
+
+/// A compiled regex search pattern, the same as another if written the
+/// same.
+#[derive(Debug, Clone)]
+struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Self {
+        if let Some(pattern) = regex_pattern(query) {
+            return match compile_pattern(pattern) {
+                Ok(regex) => Self {
+                    expr: Expr::Pattern(Pattern(regex)),
+                    error: None,
+                },
+                Err(error) => Self {
+                    expr: Expr::Or(Vec::new()),
+                    error: Some(error),
+                },
+            };
+        }
+        let boolean = query.contains('"')
+            || query
+                .split_whitespace()
+                .any(|word| matches!(word, "AND" | "OR" | "NOT"));
+        let expr = if boolean {
+            Parser::new(query).parse()
+        } else {
+            let mut text = Vec::new();
+            let mut parts = Vec::new();
+            for word in query.split_whitespace() {
+                match field_word(word) {
+                    Some((field, value)) => parts.push(Expr::Field(field, fold(value))),
+                    None => text.push(word),
+                }
+            }
+            if !text.is_empty() {
+                parts.insert(0, Expr::Text(text.join(" ")));
+            }
+            combine(parts, Expr::And).unwrap_or(Expr::And(Vec::new()))
+        };
+        Self { expr, error: None }
+    }
+
+    /// Why the query's regex pattern doesn't compile, if it is one that
+    /// doesn't.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// The text searched for, if the query is nothing else, as the repos
+    /// that search in the database can look up; the others leave it to
+    /// [`Self::matches`]. Empty if the query is.
+    pub fn plain_text(&self) -> Option<&str> {
+        match &self.expr {
+            Expr::Text(text) => Some(text),
+            Expr::And(all) if all.is_empty() => Some(""),
+            _ => None,
+        }
+    }
+
+    /// The [folded](fold) texts and field values the query looks for, but
+    /// not those it rules out with `NOT`, to [highlight](super::highlights).
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.expr.collect_terms(&mut terms);
+        terms
+    }
+
+    /// Whether `contact` isn't [archived](Contact::archived) and matches
+    /// the query.
+    pub fn matches(&self, contact: &Contact) -> bool {
+        !contact.archived && self.expr.matches(contact)
+    }
+
+    /// Whether `contact` matches the query, archived or not.
+    pub fn matches_archived(&self, contact: &Contact) -> bool {
+        self.expr.matches(contact)
+    }
+}
+
+impl Expr {
+    fn collect_terms(&self, terms: &mut Vec<String>) {
+        match self {
+            Self::Text(text) => terms.push(fold(text)),
+            Self::Field(_, value) => terms.push(value.clone()),
+            Self::And(parts) | Self::Or(parts) => {
+                for part in parts {
+                    part.collect_terms(terms);
+                }
+            }
+            Self::Not(_) | Self::Pattern(_) => {}
+        }
+    }
+
+    fn matches(&self, contact: &Contact) -> bool {
+        match self {
+            Self::Text(text) => contact.matches_text(text),
+            Self::Field(field, value) => contact
+                .field_values(*field)
+                .iter()
+                .any(|own| fold(own).contains(value.as_str())),
+            Self::And(all) => all.iter().all(|expr| expr.matches(contact)),
+            Self::Or(any) => any.iter().any(|expr| expr.matches(contact)),
+            Self::Not(expr) => !expr.matches(contact),
+            Self::Pattern(Pattern(regex)) => {
+                contact.searched_fields().any(|field| regex.is_match(field))
+            }
+        }
+    }
+}
+
+/// The pattern of `query` if it is written `/pattern/`.
+pub fn regex_pattern(query: &str) -> Option<&str> {
+    let query = query.trim();
+    (query.len() >= 2)
+        .then(|| query.strip_prefix('/')?.strip_suffix('/'))
+        .flatten()
+}
+
+/// `pattern` compiled, or why it can't be.
+fn compile_pattern(pattern: &str) -> Result<Regex, String> {
+    if pattern.chars().count() > MAX_PATTERN_LEN {
+        return Err(format!("longer than {MAX_PATTERN_LEN} characters"));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .dfa_size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+/// `parts` as one expression, `None` if there are none.
+fn combine(mut parts: Vec<Expr>, join: fn(Vec<Expr>) -> Expr) -> Option<Expr> {
+    match parts.len() {
+        0 => None,
+        1 => parts.pop(),
+        _ => Some(join(parts)),
+    }
+}
+
+/// The field and value of a `field:value` word, if it is one.
+fn field_word(word: &str) -> Option<(SearchField, &str)> {
+    let (field, value) = word.split_once(':')?;
+    let field = field.to_lowercase().parse().ok()?;
+    (!value.is_empty()).then_some((field, value))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Field(SearchField, String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// Parses queries with operators, leniently: an operator missing an
+/// operand, an unclosed quote or parenthesis and a stray `)` are read as if
+/// left out or closed at the end.
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn new(query: &str) -> Self {
+        Self {
+            tokens: tokenize(query),
+            at: 0,
+        }
+    }
+
+    fn parse(mut self) -> Expr {
+        let mut parts = Vec::new();
+        while self.at < self.tokens.len() {
+            parts.extend(self.or());
+            // Past a stray `)`.
+            self.at += 1;
+        }
+        combine(parts, Expr::And).unwrap_or(Expr::And(Vec::new()))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn or(&mut self) -> Option<Expr> {
+        let mut any = Vec::new();
+        loop {
+            any.extend(self.and());
+            if self.peek() != Some(&Token::Or) {
+                break;
+            }
+            self.at += 1;
+        }
+        combine(any, Expr::Or)
+    }
+
+    fn and(&mut self) -> Option<Expr> {
+        let mut all = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or | Token::Close) => break,
+                Some(Token::And) => self.at += 1,
+                Some(_) => all.extend(self.unary()),
+            }
+        }
+        combine(all, Expr::And)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        let token = self.peek()?.clone();
+        self.at += 1;
+        match token {
+            Token::Not => self.unary().map(|expr| Expr::Not(Box::new(expr))),
+            Token::Open => {
+                let expr = self.or();
+                if self.peek() == Some(&Token::Close) {
+                    self.at += 1;
+                }
+                expr
+            }
+            Token::Word(word) => {
+                let mut words = vec![word];
+                while let Some(Token::Word(word)) = self.peek() {
+                    words.push(word.clone());
+                    self.at += 1;
+                }
+                Some(Expr::Text(words.join(" ")))
+            }
+            Token::Phrase(phrase) => Some(Expr::Text(phrase)),
+            Token::Field(field, value) => Some(Expr::Field(field, fold(&value))),
+            Token::And | Token::Or | Token::Close => None,
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    let phrase = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let phrase: String = chars.by_ref().take_while(|c| *c != '"').collect();
+        phrase.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => tokens.push(Token::Phrase(phrase(&mut chars))),
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let field = word
+                    .strip_suffix(':')
+                    .and_then(|field| field.to_lowercase().parse().ok());
+                if let (Some(field), Some('"')) = (field, chars.peek()) {
+                    chars.next();
+                    tokens.push(Token::Field(field, phrase(&mut chars)));
+                    continue;
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match field_word(&word) {
+                        Some((field, value)) => Token::Field(field, value.to_owned()),
+                        None => Token::Word(word),
+                    },
+                });
+            }
+        }
+    }
+    tokens
+}
+
+impl Contact {
+    /// The values of `field` a [`SearchQuery`] narrowed to it looks in.
+    fn field_values(&self, field: SearchField) -> Vec<&str> {
+        let address = |part: fn(&Address) -> &String| -> Vec<&str> {
+            self.addresses
+                .iter()
+                .map(|address| part(address).as_str())
+                .collect()
+        };
+        match field {
+            SearchField::First => optional(&[&self.first]),
+            SearchField::Middle => optional(&[&self.middle]),
+            SearchField::Last => optional(&[&self.last]),
+            SearchField::Nickname => optional(&[&self.nickname]),
+            SearchField::Name => optional(&[&self.first, &self.middle, &self.last, &self.nickname]),
+            SearchField::Email => optional(&[&self.email])
+                .into_iter()
+                .chain(self.emails.iter().map(|email| email.value.as_str()))
+                .collect(),
+            SearchField::Phone => self
+                .phones
+                .iter()
+                .map(|phone| phone.value.as_str())
+                .collect(),
+            SearchField::Company => optional(&[&self.company]),
+            SearchField::JobTitle => optional(&[&self.job_title]),
+            SearchField::Notes => optional(&[&self.notes]),
+            SearchField::Street => address(|address| &address.street),
+            SearchField::City => address(|address| &address.city),
+            SearchField::PostalCode => address(|address| &address.postal_code),
+            SearchField::Country => address(|address| &address.country),
+            SearchField::Address => [
+                SearchField::Street,
+                SearchField::City,
+                SearchField::PostalCode,
+                SearchField::Country,
+            ]
+            .into_iter()
+            .flat_map(|part| self.field_values(part))
+            .collect(),
+            SearchField::Tag => self.tags.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// The values of those of `fields` that are set.
+fn optional<'a>(fields: &[&'a Option<String>]) -> Vec<&'a str> {
+    fields.iter().filter_map(|field| field.as_deref()).collect()
+}
+
+/// Narrows a search to the contacts with a tag and in a group, or widens
+/// it to the archived contacts too.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContactFilter {
+    /// A tag as stored, lowercase.
+    pub tag: Option<String>,
+    /// The id of a [`Group`](super::Group).
+    pub group: Option<u64>,
+    /// Whether [archived](Contact::archived) contacts are found too.
+    pub archived: bool,
+}
+
+impl ContactFilter {
+    /// Whether the filter changes nothing a search finds.
+    pub fn is_empty(&self) -> bool {
+        self.tag.is_none() && self.group.is_none() && !self.archived
+    }
+
+    /// Whether `contact` has the tag and is in the group, those set, and
+    /// isn't archived unless archived contacts are found too.
+    pub fn allows(&self, contact: &Contact) -> bool {
+        (self.archived || !contact.archived)
+            && self.tag.as_deref().is_none_or(|tag| contact.has_tag(tag))
+            && self.group.is_none_or(|group| contact.in_group(group))
+    }
+
+    /// Whether searching for `query` with the filter finds `contact`: the
+    /// filter allows it and it [matches](SearchQuery::matches_archived)
+    /// `query`.
+    pub fn finds(&self, query: &SearchQuery, contact: &Contact) -> bool {
+        self.allows(contact) && query.matches_archived(contact)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(first: &str, last: &str, email: &str) -> Contact {
+        Contact {
+            first: Some(first.to_owned()),
+            last: Some(last.to_owned()),
+            email: Some(email.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn finds(query: &str, contact: &Contact) -> bool {
+        SearchQuery::parse(query).matches(contact)
+    }
+
+    #[test]
+    fn plain_queries_are_text() {
+        let query = SearchQuery::parse("Ann  Lee");
+        assert_eq!(query.plain_text(), Some("Ann Lee"));
+        assert_eq!(SearchQuery::parse("10:30").plain_text(), Some("10:30"));
+        assert_eq!(SearchQuery::parse("").plain_text(), Some(""));
+    }
+
+    #[test]
+    fn field_words_narrow_to_their_field() {
+        let ann = contact("Ann", "Smith", "ann@gmail.com");
+        let bob = contact("Bob", "Gmail", "bob@example.com");
+        assert!(finds("email:gmail.com last:Smith", &ann));
+        assert!(!finds("email:gmail.com", &bob));
+        assert!(finds("last:smith", &ann));
+        assert!(!finds("first:smith", &ann));
+        assert!(finds("gmail", &bob));
+    }
+
+    #[test]
+    fn text_and_fields_must_all_match() {
+        let ann = contact("Ann", "Smith", "ann@gmail.com");
+        assert!(finds("ann email:gmail", &ann));
+        assert!(!finds("bob email:gmail", &ann));
+    }
+
+    #[test]
+    fn boolean_operators_combine_parts() {
+        let ann = contact("Ann", "Smith", "ann@example.com");
+        let bob = contact("Bob", "Jones", "bob@example.com");
+        assert!(finds("ann OR bob", &ann));
+        assert!(finds("ann OR bob", &bob));
+        assert!(finds("ann AND smith", &ann));
+        assert!(!finds("ann AND jones", &ann));
+        assert!(finds("NOT ann", &bob));
+        assert!(!finds("NOT ann", &ann));
+        assert!(finds("example AND NOT last:smith", &bob));
+        assert!(!finds("example AND NOT last:smith", &ann));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let ann = contact("Ann", "Smith", "ann@example.com");
+        assert!(finds("bob AND jones OR ann", &ann));
+        assert!(!finds("bob AND (jones OR ann)", &ann));
+        assert!(finds("ann AND (jones OR smith)", &ann));
+    }
+
+    #[test]
+    fn lowercase_operators_are_text() {
+        let ann = contact("Ann", "Smith", "ann@example.com");
+        assert!(!finds("ann or bob", &ann));
+    }
+
+    #[test]
+    fn quoted_phrases_are_matched_whole() {
+        let mut berg = contact("Anna", "van der Berg", "anna@example.com");
+        berg.addresses = vec![Address {
+            city: "Stockholm".to_owned(),
+            ..Default::default()
+        }];
+        let other = contact("Per", "van Berg der", "per@example.com");
+        assert!(finds("\"van der Berg\" AND stockholm", &berg));
+        assert!(!finds("\"van der Berg\"", &other));
+        assert!(finds("last:\"van der\"", &berg));
+        assert!(!finds("\"van der Berg\" AND city:goteborg", &berg));
+    }
+
+    #[test]
+    fn archived_contacts_only_match_archived() {
+        let mut ann = contact("Ann", "Smith", "ann@example.com");
+        ann.archived = true;
+        let query = SearchQuery::parse("ann");
+        assert!(!query.matches(&ann));
+        assert!(query.matches_archived(&ann));
+    }
+}
@@ -0,0 +1,433 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+
+use super::{
+    page_offset, phones, transaction::email_taken, unique_phones, BoxedTransaction, Changes,
+    Contact, ContactChange, ContactRepo, Cursor, CursorPage, Direction, Page, RepoError, SaveMode,
+    SearchQuery, SharedContactRepo, Sort, SortBy, Stage, StagedTransaction,
+};
+
+/// Contact repository backed by Redis, for sharing contacts across replicas.
+///
+/// Each contact is a hash at `contact:<id>` whose fields are the contact's
+/// fields, JSON encoded. `contacts:ids` is a sorted set of all ids,
+/// `contacts:emails` maps emails to ids and `contacts:next_id` hands out ids.
+/// `contacts:tombstones` is a sorted set of deleted ids, scored by when they
+/// were deleted in microseconds since the epoch.
+/// Saves and deletes run as Lua scripts so the email index stays consistent
+/// when several instances write at once.
+#[derive(Clone)]
+pub struct RedisContactRepo {
+    conn: ConnectionManager,
+    save_script: Script,
+    delete_script: Script,
+    commit_script: Script,
+}
+
+const IDS: &str = "contacts:ids";
+const EMAILS: &str = "contacts:emails";
+const NEXT_ID: &str = "contacts:next_id";
+const TOMBSTONES: &str = "contacts:tombstones";
+
+fn contact_key(id: u64) -> String {
+    format!("contact:{id}")
+}
+
+/// KEYS: contact hash, email index, id set, tombstones.
+/// ARGV: id, email, `create` or `update`, the version updated from, followed
+/// by the hash's field/value pairs.
+/// Returns 0 if the email belongs to another contact, -1 if the contact
+/// exists when created or doesn't when updated, -2 if it is at another
+/// version when updated, and 1 once saved.
+const SAVE: &str = r"
+local id, email = ARGV[1], ARGV[2]
+local exists = redis.call('EXISTS', KEYS[1]) == 1
+if (ARGV[3] == 'create') == exists then
+  return -1
+end
+if exists and tonumber(redis.call('HGET', KEYS[1], 'version') or 0) ~= tonumber(ARGV[4]) then
+  return -2
+end
+local owner = redis.call('HGET', KEYS[2], email)
+if owner and owner ~= id then
+  return 0
+end
+local old = redis.call('HGET', KEYS[1], 'email')
+if old then
+  local old_email = cjson.decode(old)
+  if old_email ~= cjson.null and old_email ~= email then
+    redis.call('HDEL', KEYS[2], old_email)
+  end
+end
+redis.call('DEL', KEYS[1])
+redis.call('HSET', KEYS[1], unpack(ARGV, 5))
+redis.call('HSET', KEYS[2], email, id)
+redis.call('ZADD', KEYS[3], id, id)
+redis.call('ZREM', KEYS[4], id)
+return 1
+";
+
+/// KEYS: contact hash, email index, id set, tombstones.
+/// ARGV: id, when it is deleted.
+/// Returns 0 if there is no such contact.
+const DELETE: &str = r"
+local old = redis.call('HGET', KEYS[1], 'email')
+if old then
+  local old_email = cjson.decode(old)
+  if old_email ~= cjson.null then
+    redis.call('HDEL', KEYS[2], old_email)
+  end
+end
+redis.call('DEL', KEYS[1])
+local removed = redis.call('ZREM', KEYS[3], ARGV[1])
+if removed == 1 then
+  redis.call('ZADD', KEYS[4], ARGV[2], ARGV[1])
+end
+return removed
+";
+
+/// KEYS: email index, id set, id counter, tombstones, followed by the hash of
+/// each changed contact.
+/// ARGV: for each changed contact, in the order of KEYS, a JSON object
+/// `{"id": …, "email": …, "fields": [field, value, …]}`, with only the id
+/// and `deleted_at` for deleted contacts.
+/// Returns 0, or the position of a contact whose email belongs to a contact
+/// outside the batch, in which case nothing is written.
+const COMMIT: &str = r"
+local changes = {}
+for i, change in ipairs(ARGV) do
+  changes[i] = cjson.decode(change)
+end
+local released = {}
+for i, change in ipairs(changes) do
+  local old = redis.call('HGET', KEYS[i + 4], 'email')
+  if old then
+    local old_email = cjson.decode(old)
+    if old_email ~= cjson.null and redis.call('HGET', KEYS[1], old_email) == change.id then
+      released[old_email] = true
+    end
+  end
+end
+local claimed = {}
+for i, change in ipairs(changes) do
+  if change.email then
+    local owner = redis.call('HGET', KEYS[1], change.email)
+    if claimed[change.email] or (owner and owner ~= change.id and not released[change.email]) then
+      return i
+    end
+    claimed[change.email] = true
+  end
+end
+for email in pairs(released) do
+  redis.call('HDEL', KEYS[1], email)
+end
+local max_id = 0
+for i, change in ipairs(changes) do
+  redis.call('DEL', KEYS[i + 4])
+  if change.email then
+    redis.call('HSET', KEYS[i + 4], unpack(change.fields))
+    redis.call('HSET', KEYS[1], change.email, change.id)
+    redis.call('ZADD', KEYS[2], change.id, change.id)
+    redis.call('ZREM', KEYS[4], change.id)
+  else
+    redis.call('ZREM', KEYS[2], change.id)
+    redis.call('ZADD', KEYS[4], change.deleted_at, change.id)
+  end
+  max_id = math.max(max_id, tonumber(change.id))
+end
+-- Ids may have been chosen by the caller, e.g. when restoring a backup.
+if tonumber(redis.call('GET', KEYS[3]) or 0) < max_id then
+  redis.call('SET', KEYS[3], max_id)
+end
+return 0
+";
+
+impl RedisContactRepo {
+    /// Connects to `url`, e.g. `redis://127.0.0.1/`.
+    pub async fn from_url(url: &str) -> Self {
+        let client = redis::Client::open(url).expect("a valid redis url");
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("redis to connect");
+        Self {
+            conn,
+            save_script: Script::new(SAVE),
+            delete_script: Script::new(DELETE),
+            commit_script: Script::new(COMMIT),
+        }
+    }
+
+    pub async fn shared_from_url(url: &str) -> SharedContactRepo {
+        Arc::new(Self::from_url(url).await)
+    }
+
+    async fn put(&self, mut contact: Contact, mode: SaveMode) -> Result<u64, RepoError> {
+        if !contact.validate() {
+            return Err(RepoError::invalid(contact));
+        }
+        if unique_phones() {
+            contact = phones::check_phones(contact, &self.all(Sort::default()).await)?;
+        }
+        let mut conn = self.conn.clone();
+        if contact.id.is_none() {
+            let id: u64 = conn.incr(NEXT_ID, 1).await.map_err(RepoError::io)?;
+            contact.id = Some(id);
+        }
+        let id = contact.id.unwrap();
+        let mut script = self.save_script.prepare_invoke();
+        script
+            .key(contact_key(id))
+            .key(EMAILS)
+            .key(IDS)
+            .key(TOMBSTONES)
+            .arg(id)
+            .arg(contact.email.as_deref().unwrap())
+            .arg(match mode {
+                SaveMode::Create => "create",
+                SaveMode::Update => "update",
+            })
+            .arg(contact.version);
+        for (field, value) in contact_to_hash(&contact.bumped()) {
+            script.arg(field).arg(value);
+        }
+        let saved: i64 = script
+            .invoke_async(&mut conn)
+            .await
+            .map_err(RepoError::io)?;
+        match saved {
+            1 => Ok(id),
+            0 => Err(RepoError::email_taken(contact)),
+            -2 => Err(RepoError::stale(contact)),
+            _ if mode == SaveMode::Create => Err(RepoError::id_taken(contact)),
+            _ => Err(RepoError::NotFound(id)),
+        }
+    }
+
+    async fn load(&self, ids: &[u64]) -> Vec<Contact> {
+        if ids.is_empty() {
+            return Vec::new();
+        }
+        let mut pipe = redis::pipe();
+        for id in ids {
+            pipe.hgetall(contact_key(*id));
+        }
+        let hashes: Vec<HashMap<String, String>> = pipe
+            .query_async(&mut self.conn.clone())
+            .await
+            .expect("query succeed");
+        hashes
+            .into_iter()
+            .filter(|hash| !hash.is_empty())
+            .map(contact_from_hash)
+            .collect()
+    }
+}
+
+fn contact_from_hash(hash: HashMap<String, String>) -> Contact {
+    let fields = hash
+        .into_iter()
+        .map(|(field, value)| {
+            let value = serde_json::from_str(&value).expect("valid JSON");
+            (field, value)
+        })
+        .collect();
+    serde_json::from_value(serde_json::Value::Object(fields)).expect("a valid contact")
+}
+
+fn contact_to_hash(contact: &Contact) -> Vec<(String, String)> {
+    let serde_json::Value::Object(fields) =
+        serde_json::to_value(contact).expect("serializing succeed")
+    else {
+        unreachable!("contacts serialize to objects");
+    };
+    fields
+        .into_iter()
+        .map(|(field, value)| (field, value.to_string()))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for RedisContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let ids: Vec<u64> = self
+            .conn
+            .clone()
+            .zrange(IDS, 0, -1)
+            .await
+            .expect("query succeed");
+        let mut contacts = self.load(&ids).await;
+        sort.apply(&mut contacts);
+        contacts
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        match filter.map(SearchQuery::parse).as_ref() {
+            None => self.conn.clone().zcard(IDS).await.expect("query succeed"),
+            Some(query) => {
+                let contacts = self.all(Sort::default()).await;
+                contacts.iter().filter(|c| c.matches(query)).count()
+            }
+        }
+    }
+
+    /// Only loads the page's contacts when sorted by id, other orders load
+    /// them all.
+    async fn page(&self, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        if sort.by != SortBy::Created {
+            return Page::of(self.all(sort).await.iter(), sort, number, size);
+        }
+        let start = isize::try_from(page_offset(number, size)).unwrap_or(isize::MAX);
+        let stop = start.saturating_add(size as isize - 1);
+        let mut conn = self.conn.clone();
+        let ids: Vec<u64> = match sort.direction {
+            Direction::Asc => conn.zrange(IDS, start, stop).await,
+            Direction::Desc => conn.zrevrange(IDS, start, stop).await,
+        }
+        .expect("query succeed");
+        Page::new(self.load(&ids).await, number, size, self.count(None).await)
+    }
+
+    async fn all_after(&self, cursor: Option<Cursor>, limit: usize) -> CursorPage<Contact> {
+        let min = cursor.map_or("-inf".to_owned(), |cursor| format!("({}", cursor.id()));
+        let ids: Vec<u64> = self
+            .conn
+            .clone()
+            .zrangebyscore_limit(IDS, min, "+inf", 0, limit as isize + 1)
+            .await
+            .expect("query succeed");
+        CursorPage::from_sorted(self.load(&ids).await, limit)
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let contacts = self.all(sort).await;
+        let matches = contacts.iter().filter(|contact| contact.matches(&query));
+        Page::of(matches, sort, number, size)
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        self.put(contact, SaveMode::Create).await
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        self.put(contact, SaveMode::Update).await?;
+        Ok(())
+    }
+
+    /// Looked up in the email index.
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        match self.email_owner(email).await? {
+            Some(id) => self.find(id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        let hash: HashMap<String, String> = self
+            .conn
+            .clone()
+            .hgetall(contact_key(id))
+            .await
+            .map_err(RepoError::io)?;
+        Ok((!hash.is_empty()).then(|| contact_from_hash(hash)))
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let found: bool = self
+            .delete_script
+            .key(contact_key(id))
+            .key(EMAILS)
+            .key(IDS)
+            .key(TOMBSTONES)
+            .arg(id)
+            .arg(Utc::now().timestamp_micros())
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(RepoError::io)?;
+        if !found {
+            return Err(RepoError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let tombstones: Vec<(u64, f64)> = self
+            .conn
+            .clone()
+            .zrangebyscore_withscores(TOMBSTONES, since.timestamp_micros(), "+inf")
+            .await
+            .expect("query succeed");
+        let tombstones = tombstones.into_iter().map(|(id, deleted_at)| {
+            let deleted_at =
+                DateTime::from_timestamp_micros(deleted_at as i64).expect("a valid time");
+            (id, deleted_at)
+        });
+        ContactChange::since(self.all(Sort::default()).await, tombstones, since)
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        Ok(Box::new(StagedTransaction::new(self.clone(), None)))
+    }
+}
+
+#[async_trait::async_trait]
+impl Stage for RedisContactRepo {
+    async fn email_owner(&self, email: &str) -> Result<Option<u64>, RepoError> {
+        self.conn
+            .clone()
+            .hget(EMAILS, email)
+            .await
+            .map_err(RepoError::io)
+    }
+
+    async fn next_id(&self, _changes: &Changes) -> Result<u64, RepoError> {
+        let mut conn = self.conn.clone();
+        conn.incr(NEXT_ID, 1).await.map_err(RepoError::io)
+    }
+
+    async fn apply(&self, changes: &Changes) -> Result<(), RepoError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let deleted_at = Utc::now().timestamp_micros();
+        let mut script = self.commit_script.prepare_invoke();
+        script.key(EMAILS).key(IDS).key(NEXT_ID).key(TOMBSTONES);
+        for id in changes.keys() {
+            script.key(contact_key(*id));
+        }
+        for (id, change) in changes {
+            let change = match change {
+                Some(contact) => {
+                    let fields: Vec<String> = contact_to_hash(contact)
+                        .into_iter()
+                        .flat_map(|(field, value)| [field, value])
+                        .collect();
+                    serde_json::json!({
+                        "id": id.to_string(),
+                        "email": contact.email,
+                        "fields": fields,
+                    })
+                }
+                None => serde_json::json!({
+                    "id": id.to_string(),
+                    "deleted_at": deleted_at,
+                }),
+            };
+            script.arg(change.to_string());
+        }
+        let failed: usize = script
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(RepoError::io)?;
+        match failed {
+            0 => Ok(()),
+            position => Err(email_taken(
+                changes,
+                *changes.keys().nth(position - 1).unwrap(),
+            )),
+        }
+    }
+}
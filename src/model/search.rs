@@ -0,0 +1,220 @@
+use std::ops::Range;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::{page_offset, Contact, Page, SearchQuery, Sort};
+
+/// Separates the fields of a contact's [search text](Contact::search_text),
+/// which [`fold`] leaves out of queries, so that none matches across two.
+pub(super) const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// How similar a word of a contact must be to each word of a query for a
+/// [fuzzy search](super::ContactRepo::fuzzy_search) to find it, unless
+/// configured otherwise; see [`Contact::similarity`].
+pub const DEFAULT_FUZZY_THRESHOLD: f64 = 0.85;
+
+/// `text` as searched: lowercase and without diacritics, so that `jose`
+/// finds `José` and `asa` finds `Åsa`. Letters that don't decompose into a
+/// base letter and marks, such as `ø` or `ß`, are spelled out in ASCII.
+/// Whitespace such as line breaks is read as spaces, and other control
+/// characters are left out.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            c if c.is_whitespace() => folded.push(' '),
+            c if c.is_control() => {}
+            'ø' | 'Ø' => folded.push('o'),
+            'æ' | 'Æ' => folded.push_str("ae"),
+            'œ' | 'Œ' => folded.push_str("oe"),
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'đ' | 'Đ' | 'ð' | 'Ð' => folded.push('d'),
+            'ł' | 'Ł' => folded.push('l'),
+            'þ' | 'Þ' => folded.push_str("th"),
+            'ı' => folded.push('i'),
+            c => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// The byte ranges of `text` where searching for `query` finds one of its
+/// [terms](SearchQuery::terms), whatever the case and diacritics, in order
+/// and merged where they overlap.
+pub fn highlights(text: &str, query: &str) -> Vec<Range<usize>> {
+    // Patterns have no terms, so aren't compiled again for each field shown.
+    if super::regex_pattern(query).is_some() {
+        return Vec::new();
+    }
+    // The folded text, with the range in `text` of the character each of
+    // its bytes was folded from.
+    let mut folded = String::new();
+    let mut origins = Vec::new();
+    for (start, c) in text.char_indices() {
+        let part = fold(c.encode_utf8(&mut [0; 4]));
+        origins.extend(std::iter::repeat_n(start..start + c.len_utf8(), part.len()));
+        folded.push_str(&part);
+    }
+    let mut ranges: Vec<Range<usize>> = SearchQuery::parse(query)
+        .terms()
+        .iter()
+        .filter(|term| !term.is_empty())
+        .flat_map(|term| {
+            folded
+                .match_indices(term.as_str())
+                .map(|(at, term)| origins[at].start..origins[at + term.len() - 1].end)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+impl Contact {
+    /// The fields searching finds the contact by, see [`Self::matches`].
+    pub(super) fn searched_fields(&self) -> impl Iterator<Item = &str> {
+        let names = [&self.first, &self.last, &self.middle, &self.nickname];
+        let work = [&self.company, &self.job_title, &self.notes];
+        let emails = self.emails.iter().map(|email| email.value.as_str());
+        let addresses = self.addresses.iter().flat_map(|address| {
+            [
+                &address.street,
+                &address.city,
+                &address.postal_code,
+                &address.country,
+            ]
+            .map(String::as_str)
+        });
+        names
+            .into_iter()
+            .chain([&self.email])
+            .chain(work)
+            .filter_map(Option::as_deref)
+            .chain(self.phones.iter().map(|phone| phone.value.as_str()))
+            .chain(emails)
+            .chain(addresses)
+    }
+
+    /// The [folded](fold) fields searching finds the contact by, each
+    /// separated from the next, as stored by the repos that search in the
+    /// database.
+    pub fn search_text(&self) -> String {
+        let fields: Vec<_> = self.searched_fields().map(fold).collect();
+        fields.join(&FIELD_SEPARATOR.to_string())
+    }
+
+    /// Whether searching for `query` finds the contact: it isn't
+    /// [archived](Self::archived) and `query` is part of one of its names,
+    /// phone numbers, emails, addresses, company, job title or notes,
+    /// whatever the case and diacritics of either, or it
+    /// [sounds like](Self::sounds_like) the contact's name. Words of `query`
+    /// narrowed to a field are only looked for there, see [`SearchQuery`].
+    pub fn matches(&self, query: &SearchQuery) -> bool {
+        query.matches(self)
+    }
+
+    /// Whether `text` is part of one of the searched fields or sounds like
+    /// the contact's name, see [`Self::matches`].
+    pub(super) fn matches_text(&self, text: &str) -> bool {
+        let folded = fold(text);
+        self.searched_fields()
+            .any(|field| fold(field).contains(&folded))
+            || self.sounds_like(text)
+    }
+
+    /// How similar the contact is to `query`, from 0 to 1: how close the
+    /// word of its searched fields most like each word of `query` is, by
+    /// Jaro-Winkler similarity and [folded](fold), for the word of `query`
+    /// least like any. 1 if `query` has no words.
+    pub fn similarity(&self, query: &str) -> f64 {
+        let fields: Vec<_> = self.searched_fields().map(fold).collect();
+        let own: Vec<_> = fields.iter().flat_map(|field| words(field)).collect();
+        words(&fold(query))
+            .map(|word| {
+                own.iter()
+                    .map(|own| jaro_winkler(word, own))
+                    .fold(0.0, f64::max)
+            })
+            .fold(1.0, f64::min)
+    }
+}
+
+impl Page<Contact> {
+    /// Page `number` of `contacts` at least `threshold`
+    /// [similar](Contact::similarity) to `query`, most similar first and
+    /// those as similar in `sort` order.
+    pub fn by_similarity<'a>(
+        contacts: impl Iterator<Item = &'a Contact>,
+        query: &str,
+        threshold: f64,
+        sort: Sort,
+        number: usize,
+        size: usize,
+    ) -> Self {
+        let mut scored: Vec<(f64, &Contact)> = contacts
+            .map(|contact| (contact.similarity(query), contact))
+            .filter(|(score, _)| *score >= threshold)
+            .collect();
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.total_cmp(a_score).then_with(|| sort.compare(a, b))
+        });
+        let items = scored
+            .iter()
+            .skip(page_offset(number, size))
+            .take(size)
+            .map(|(_, contact)| (*contact).clone())
+            .collect();
+        Self::new(items, number, size, scored.len())
+    }
+}
+
+/// The words of folded `text`, split at anything but letters and digits.
+pub(super) fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// The Jaro similarity of `a` and `b`, raised for a common prefix of up to
+/// four characters, so that `jonh` is 0.93 similar to `john`.
+fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    // Characters match if the same and no further apart than this.
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut matched = vec![false; b.len()];
+    let mut a_matches = Vec::new();
+    for (i, c) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        if let Some(j) = (start..end).find(|&j| !matched[j] && b[j] == *c) {
+            matched[j] = true;
+            a_matches.push(*c);
+        }
+    }
+    if a_matches.is_empty() {
+        return 0.0;
+    }
+    let b_matches = b
+        .iter()
+        .zip(&matched)
+        .filter_map(|(c, matched)| matched.then_some(c));
+    let transposed = a_matches
+        .iter()
+        .zip(b_matches)
+        .filter(|(a, b)| a != b)
+        .count();
+    let m = a_matches.len() as f64;
+    let t = (transposed / 2) as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - t) / m) / 3.0;
+    let prefix = a.iter().zip(&b).take(4).take_while(|(a, b)| a == b).count();
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::sync::RwLock;
+
+use super::write_atomic;
+
+/// A named search of the contact list, such as everyone tagged `work` in
+/// Stockholm, to run again without typing it in.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct SavedSearch {
+    pub(super) id: Option<u64>,
+    /// Unique among saved searches.
+    pub name: String,
+    /// The query string of the listing searched, such as
+    /// `q=stockholm&tag=work`, with the query and the filters but no page.
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub errors: HashMap<String, String>,
+}
+
+impl SavedSearch {
+    pub fn new(name: impl Into<String>, query: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            query: query.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    pub fn validate(&mut self) -> bool {
+        if self.name.trim().is_empty() {
+            self.errors.insert("name".into(), "Name Required".into());
+        }
+        self.errors.is_empty()
+    }
+}
+
+/// Why a [`SavedSearchRepo`] operation failed.
+#[derive(Debug)]
+pub enum SavedSearchError {
+    /// There is no saved search with this id.
+    NotFound(u64),
+    /// The search is invalid, its `errors` say why.
+    Validation(Box<SavedSearch>),
+    /// Another saved search has the search's name.
+    Conflict(Box<SavedSearch>),
+    /// The storage failed.
+    Io(io::Error),
+}
+
+impl SavedSearchError {
+    /// Wraps an error of the storage.
+    pub fn io(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self::Io(io::Error::other(err))
+    }
+
+    /// [`Self::Conflict`] for a search whose name another search has.
+    pub fn name_taken(mut search: SavedSearch) -> Self {
+        search
+            .errors
+            .insert("name".into(), "Name Already Exists".into());
+        Self::Conflict(Box::new(search))
+    }
+}
+
+impl fmt::Display for SavedSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "saved search {id} not found"),
+            Self::Validation(search) => {
+                write!(
+                    f,
+                    "search '{}' is invalid: {:?}",
+                    search.name, search.errors
+                )
+            }
+            Self::Conflict(search) => write!(
+                f,
+                "search '{}' conflicts with another: {:?}",
+                search.name, search.errors
+            ),
+            Self::Io(err) => write!(f, "storage failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SavedSearchError {}
+
+#[async_trait::async_trait]
+pub trait SavedSearchRepo {
+    /// All saved searches, ordered by name.
+    async fn all(&self) -> Vec<SavedSearch>;
+    async fn find(&self, id: u64) -> Result<Option<SavedSearch>, SavedSearchError>;
+    /// Adds `search` with a new id and returns it. Fails with
+    /// [`SavedSearchError::Conflict`] if its name is taken.
+    async fn create(&self, search: SavedSearch) -> Result<u64, SavedSearchError>;
+    /// Deletes the saved search with `id`, failing with
+    /// [`SavedSearchError::NotFound`] if there is none.
+    async fn delete_by_id(&self, id: u64) -> Result<(), SavedSearchError>;
+}
+
+pub type SharedSavedSearchRepo = Arc<dyn SavedSearchRepo + Sync + Send>;
+
+/// Orders saved searches by name, ignoring case.
+pub(super) fn sort_searches(searches: &mut [SavedSearch]) {
+    searches.sort_by_cached_key(|search| search.name.to_lowercase());
+}
+
+/// Saved search repository kept in memory and, if opened from a path,
+/// written to a JSON file on every change, for the storages that don't keep
+/// saved searches themselves.
+#[derive(Debug, Default)]
+pub struct MemSavedSearchRepo {
+    path: Option<PathBuf>,
+    searches: RwLock<SearchFile>,
+}
+
+/// The saved searches of a [`MemSavedSearchRepo`], as written to its file.
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct SearchFile {
+    /// Id of the next search saved, so links to deleted ones don't run
+    /// another.
+    next_id: u64,
+    searches: Vec<SavedSearch>,
+}
+
+impl MemSavedSearchRepo {
+    pub fn new_shared() -> SharedSavedSearchRepo {
+        Arc::new(Self::default())
+    }
+
+    /// Loads the saved searches from the file at `path`, if there is one.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let searches = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).expect("a valid saved searches file"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => SearchFile::default(),
+            Err(err) => panic!("failed to read {}: {err}", path.display()),
+        };
+        Self {
+            path: Some(path.to_owned()),
+            searches: RwLock::new(searches),
+        }
+    }
+
+    pub fn shared_from_path(path: impl AsRef<Path>) -> SharedSavedSearchRepo {
+        Arc::new(Self::from_path(path))
+    }
+
+    fn write(&self, searches: &SearchFile) -> Result<(), SavedSearchError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec_pretty(searches).map_err(SavedSearchError::io)?;
+        write_atomic(path, &data).map_err(SavedSearchError::Io)
+    }
+}
+
+#[async_trait::async_trait]
+impl SavedSearchRepo for MemSavedSearchRepo {
+    async fn all(&self) -> Vec<SavedSearch> {
+        let mut searches = self.searches.read().await.searches.clone();
+        sort_searches(&mut searches);
+        searches
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<SavedSearch>, SavedSearchError> {
+        let searches = self.searches.read().await;
+        Ok(searches
+            .searches
+            .iter()
+            .find(|search| search.id == Some(id))
+            .cloned())
+    }
+
+    async fn create(&self, mut search: SavedSearch) -> Result<u64, SavedSearchError> {
+        search.id = None;
+        if !search.validate() {
+            return Err(SavedSearchError::Validation(Box::new(search)));
+        }
+        let mut searches = self.searches.write().await;
+        if searches
+            .searches
+            .iter()
+            .any(|other| other.name == search.name)
+        {
+            return Err(SavedSearchError::name_taken(search));
+        }
+        let id = searches.next_id.max(1);
+        search.id = Some(id);
+        searches.next_id = id + 1;
+        searches.searches.push(search);
+        self.write(&searches)?;
+        Ok(id)
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), SavedSearchError> {
+        let mut searches = self.searches.write().await;
+        let index = searches
+            .searches
+            .iter()
+            .position(|search| search.id == Some(id))
+            .ok_or(SavedSearchError::NotFound(id))?;
+        searches.searches.remove(index);
+        self.write(&searches)
+    }
+}
@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures_util::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+
+use super::{
+    phones, transaction::email_taken, unique_phones, BoxedTransaction, Changes, Contact,
+    ContactChange, ContactRepo, Page, RepoError, RepoStats, SaveMode, SearchQuery,
+    SharedContactRepo, Sort, Stage, StagedTransaction,
+};
+
+/// Contact repository backed by an embedded sled database.
+///
+/// Contacts are stored as JSON in the `contacts` tree keyed by big-endian
+/// id, and the `emails` tree maps each email to the id owning it, so saves
+/// only touch the affected entries instead of rewriting the whole store.
+/// The `tombstones` tree holds when each deleted contact was deleted, as
+/// JSON keyed by id.
+#[derive(Debug, Clone)]
+pub struct SledContactRepo {
+    db: sled::Db,
+    contacts: sled::Tree,
+    emails: sled::Tree,
+    tombstones: sled::Tree,
+}
+
+/// Aborts a transaction, with the id of the contact that was being saved.
+struct DuplicateEmail(u64);
+
+/// Why saving a single contact was aborted.
+enum Rejected {
+    EmailTaken,
+    IdTaken,
+    NotFound,
+    Stale,
+}
+
+impl SledContactRepo {
+    pub fn from_path(path: &str) -> Self {
+        let db = sled::open(path).expect("database to open");
+        let contacts = db.open_tree("contacts").expect("tree to open");
+        let emails = db.open_tree("emails").expect("tree to open");
+        let tombstones = db.open_tree("tombstones").expect("tree to open");
+        Self {
+            db,
+            contacts,
+            emails,
+            tombstones,
+        }
+    }
+
+    pub fn shared_from_path(path: &str) -> SharedContactRepo {
+        Arc::new(Self::from_path(path))
+    }
+
+    fn new_id(&self) -> Result<u64, RepoError> {
+        // `generate_id` is atomic and monotonic, but starts at 0 and knows
+        // nothing of ids saved explicitly, e.g. by a restore.
+        let generated = self.db.generate_id().map_err(RepoError::io)? + 1;
+        let last = self.contacts.last().map_err(RepoError::io)?;
+        let after_last = last.map_or(0, |(key, _)| {
+            u64::from_be_bytes(key.as_ref().try_into().expect("an id")) + 1
+        });
+        Ok(generated.max(after_last))
+    }
+
+    async fn put(&self, mut contact: Contact, mode: SaveMode) -> Result<u64, RepoError> {
+        if !contact.validate() {
+            return Err(RepoError::invalid(contact));
+        }
+        if unique_phones() {
+            contact = phones::check_phones(contact, &self.all(Sort::default()).await)?;
+        }
+        if contact.id.is_none() {
+            contact.id = Some(self.new_id()?);
+        }
+        let id = contact.id.unwrap();
+        let key = id.to_be_bytes();
+        let email = contact.email.clone().unwrap();
+        let data = serde_json::to_vec(&contact.bumped()).expect("serializing succeed");
+
+        let trees = (&self.contacts, &self.emails, &self.tombstones);
+        let result = trees.transaction(|(contacts, emails, tombstones)| {
+            let current = contacts.get(key)?;
+            match (mode, current) {
+                (SaveMode::Create, Some(_)) => {
+                    return Err(ConflictableTransactionError::Abort(Rejected::IdTaken));
+                }
+                (SaveMode::Update, None) => {
+                    return Err(ConflictableTransactionError::Abort(Rejected::NotFound));
+                }
+                (SaveMode::Update, Some(current))
+                    if contact_from_bytes(&current).version != contact.version =>
+                {
+                    return Err(ConflictableTransactionError::Abort(Rejected::Stale));
+                }
+                _ => {}
+            }
+            if let Some(owner) = emails.get(email.as_bytes())? {
+                if owner.as_ref() != key {
+                    return Err(ConflictableTransactionError::Abort(Rejected::EmailTaken));
+                }
+            }
+            if let Some(old) = contacts.insert(&key, data.as_slice())? {
+                if let Some(old_email) = contact_from_bytes(&old).email {
+                    if old_email != email {
+                        emails.remove(old_email.as_bytes())?;
+                    }
+                }
+            }
+            emails.insert(email.as_bytes(), &key)?;
+            tombstones.remove(&key)?;
+            Ok(())
+        });
+        match result {
+            Ok(()) => {
+                self.db.flush_async().await.map_err(RepoError::io)?;
+                Ok(id)
+            }
+            Err(TransactionError::Abort(Rejected::EmailTaken)) => {
+                Err(RepoError::email_taken(contact))
+            }
+            Err(TransactionError::Abort(Rejected::IdTaken)) => Err(RepoError::id_taken(contact)),
+            Err(TransactionError::Abort(Rejected::NotFound)) => Err(RepoError::NotFound(id)),
+            Err(TransactionError::Abort(Rejected::Stale)) => Err(RepoError::stale(contact)),
+            Err(TransactionError::Storage(err)) => Err(RepoError::io(err)),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Contact> + '_ {
+        self.contacts
+            .iter()
+            .values()
+            .map(|value| contact_from_bytes(&value.expect("reading succeed")))
+    }
+}
+
+fn contact_from_bytes(bytes: &[u8]) -> Contact {
+    serde_json::from_slice(bytes).expect("valid JSON")
+}
+
+fn tombstone_bytes(deleted_at: DateTime<Utc>) -> Vec<u8> {
+    serde_json::to_vec(&deleted_at).expect("serializing succeed")
+}
+
+#[async_trait::async_trait]
+impl ContactRepo for SledContactRepo {
+    async fn all(&self, sort: Sort) -> Vec<Contact> {
+        let mut contacts: Vec<Contact> = self.iter().collect();
+        sort.apply(&mut contacts);
+        contacts
+    }
+
+    fn stream_all(&self) -> BoxStream<'_, Contact> {
+        stream::iter(self.iter()).boxed()
+    }
+
+    async fn count(&self, filter: Option<&str>) -> usize {
+        match filter.map(SearchQuery::parse).as_ref() {
+            None => self.contacts.len(),
+            Some(query) => self.iter().filter(|c| c.matches(query)).count(),
+        }
+    }
+
+    async fn search(&self, query: &str, sort: Sort, number: usize, size: usize) -> Page<Contact> {
+        let query = SearchQuery::parse(query);
+        let matches: Vec<Contact> = self
+            .iter()
+            .filter(|contact| contact.matches(&query))
+            .collect();
+        Page::of(matches.iter(), sort, number, size)
+    }
+
+    async fn create(&self, contact: Contact) -> Result<u64, RepoError> {
+        self.put(contact, SaveMode::Create).await
+    }
+
+    async fn update(&self, contact: Contact) -> Result<(), RepoError> {
+        self.put(contact, SaveMode::Update).await?;
+        Ok(())
+    }
+
+    /// Looked up in the email index.
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, RepoError> {
+        match self.email_owner(email).await? {
+            Some(id) => self.find(id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn find(&self, id: u64) -> Result<Option<Contact>, RepoError> {
+        let value = self.contacts.get(id.to_be_bytes()).map_err(RepoError::io)?;
+        Ok(value.map(|value| contact_from_bytes(&value)))
+    }
+
+    async fn delete_by_id(&self, id: u64) -> Result<(), RepoError> {
+        let key = id.to_be_bytes();
+        let deleted_at = tombstone_bytes(Utc::now());
+        let found = (&self.contacts, &self.emails, &self.tombstones)
+            .transaction(|(contacts, emails, tombstones)| {
+                let Some(old) = contacts.remove(&key)? else {
+                    return Ok(false);
+                };
+                if let Some(email) = contact_from_bytes(&old).email {
+                    emails.remove(email.as_bytes())?;
+                }
+                tombstones.insert(&key, deleted_at.as_slice())?;
+                Ok::<_, ConflictableTransactionError>(true)
+            })
+            .map_err(RepoError::io)?;
+        if !found {
+            return Err(RepoError::NotFound(id));
+        }
+        self.db.flush_async().await.map_err(RepoError::io)?;
+        Ok(())
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> Vec<ContactChange> {
+        let tombstones = self.tombstones.iter().map(|entry| {
+            let (key, value) = entry.expect("reading succeed");
+            let id = u64::from_be_bytes(key.as_ref().try_into().expect("an id"));
+            (id, serde_json::from_slice(&value).expect("valid JSON"))
+        });
+        ContactChange::since(self.iter(), tombstones, since)
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, RepoError> {
+        Ok(Box::new(StagedTransaction::new(self.clone(), None)))
+    }
+
+    async fn stats(&self) -> RepoStats {
+        RepoStats {
+            count: self.count(None).await,
+            storage_size: self.db.size_on_disk().ok(),
+            ..RepoStats::default()
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Stage for SledContactRepo {
+    async fn email_owner(&self, email: &str) -> Result<Option<u64>, RepoError> {
+        let owner = self.emails.get(email.as_bytes()).map_err(RepoError::io)?;
+        Ok(owner.map(|owner| u64::from_be_bytes(owner.as_ref().try_into().expect("an id"))))
+    }
+
+    async fn next_id(&self, _changes: &Changes) -> Result<u64, RepoError> {
+        self.new_id()
+    }
+
+    async fn apply(&self, changes: &Changes) -> Result<(), RepoError> {
+        let deleted_at = tombstone_bytes(Utc::now());
+        let trees = (&self.contacts, &self.emails, &self.tombstones);
+        let result = trees.transaction(|(contacts, emails, tombstones)| {
+            // Release the changed contacts' emails first, so they can move
+            // between contacts within the batch.
+            for id in changes.keys() {
+                let key = id.to_be_bytes();
+                if let Some(old) = contacts.remove(&key)? {
+                    if let Some(old_email) = contact_from_bytes(&old).email {
+                        if emails.get(old_email.as_bytes())?.as_deref() == Some(&key[..]) {
+                            emails.remove(old_email.as_bytes())?;
+                        }
+                    }
+                }
+            }
+            for (id, contact) in changes {
+                let key = id.to_be_bytes();
+                let Some(contact) = contact else {
+                    tombstones.insert(&key, deleted_at.as_slice())?;
+                    continue;
+                };
+                tombstones.remove(&key)?;
+                let email = contact.email.as_deref().unwrap();
+                if emails.get(email.as_bytes())?.is_some() {
+                    return Err(ConflictableTransactionError::Abort(DuplicateEmail(*id)));
+                }
+                let data = serde_json::to_vec(contact).expect("serializing succeed");
+                contacts.insert(&key, data)?;
+                emails.insert(email.as_bytes(), &key)?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => {
+                self.db.flush_async().await.map_err(RepoError::io)?;
+                Ok(())
+            }
+            Err(TransactionError::Abort(DuplicateEmail(id))) => Err(email_taken(changes, id)),
+            Err(TransactionError::Storage(err)) => Err(RepoError::io(err)),
+        }
+    }
+}
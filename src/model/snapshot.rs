@@ -0,0 +1,101 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+
+use super::Contact;
+
+/// Encoding of the snapshot file of a [`super::MemContactRepo`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    #[default]
+    Json,
+    /// MessagePack with named fields, so snapshots stay readable when
+    /// `Contact` gains fields.
+    MessagePack,
+}
+
+/// Contents of a snapshot file.
+#[derive(Debug, serde::Serialize)]
+pub struct Snapshot<'a> {
+    pub schema_version: u32,
+    pub contacts: Vec<&'a Contact>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// Marks a deleted contact, see [`super::ContactRepo::changed_since`].
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub struct Tombstone {
+    pub id: u64,
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl SnapshotFormat {
+    /// Guesses the format of a snapshot from its contents.
+    pub fn detect(data: &[u8]) -> Self {
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            // fixmap, map 16 or map 32, or arrays for version 0 snapshots
+            Some(0x80..=0x9f | 0xdc..=0xdf) => Self::MessagePack,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn encode(self, snapshot: &Snapshot) -> Vec<u8> {
+        match self {
+            Self::Json => serde_json::to_vec(snapshot).expect("serializing succeed"),
+            Self::MessagePack => rmp_serde::to_vec_named(snapshot).expect("serializing succeed"),
+        }
+    }
+
+    /// Decodes a snapshot as written, see [`super::migrate`].
+    pub fn decode(self, data: &[u8]) -> io::Result<serde_json::Value> {
+        match self {
+            Self::Json => Ok(serde_json::from_slice(data)?),
+            Self::MessagePack => rmp_serde::from_slice(data)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "msgpack" | "messagepack" => Ok(Self::MessagePack),
+            _ => Err(format!("unknown snapshot format '{s}'")),
+        }
+    }
+}
+
+/// Replaces the file at `path` with `data` without ever leaving it half
+/// written: the data goes to a temporary file next to it, which is synced to
+/// disk and then renamed over `path`.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, path)?;
+
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
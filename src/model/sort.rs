@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+
+use chrono::Utc;
+
+use super::{names::SORT_SEPARATOR, Contact, NameOrder};
+
+/// The order contacts are listed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Sort {
+    #[serde(default)]
+    pub by: SortBy,
+    #[serde(default)]
+    pub direction: Direction,
+    /// The order of the name parts [`SortBy::Name`] sorts by, set from the
+    /// config rather than the query.
+    #[serde(skip)]
+    pub names: NameOrder,
+}
+
+/// What contacts are sorted by. Missing names and emails sort as empty, and
+/// ties are broken by id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// The name as written in the configured [`NameOrder`], so listings
+    /// are in the order of the names shown.
+    Name,
+    First,
+    Last,
+    Email,
+    /// The next birthday from today, so the coming ones are listed first and
+    /// contacts without one last.
+    Birthday,
+    /// When contacts were created, which is the order of their ids.
+    #[default]
+    Created,
+    /// When contacts were last saved, those saved before this was recorded
+    /// first.
+    Updated,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Sort {
+    pub fn new(by: SortBy, direction: Direction) -> Self {
+        Self {
+            by,
+            direction,
+            names: NameOrder::default(),
+        }
+    }
+
+    /// Sorts [`SortBy::Name`] by the parts of the name in `names` order.
+    pub fn with_names(mut self, names: NameOrder) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Compares names and emails ignoring case.
+    pub fn compare(&self, a: &Contact, b: &Contact) -> Ordering {
+        let key = |contact| self.by.key(contact, self.names);
+        let ordering = key(a).cmp(&key(b)).then(a.id.cmp(&b.id));
+        match self.direction {
+            Direction::Asc => ordering,
+            Direction::Desc => ordering.reverse(),
+        }
+    }
+
+    pub fn apply(&self, contacts: &mut [Contact]) {
+        contacts.sort_unstable_by(|a, b| self.compare(a, b));
+    }
+
+    /// `ORDER BY` terms for a table storing each contact as a JSON document,
+    /// with `field` turning a field name into the expression extracting it,
+    /// and `time` into one extracting it as a time, which sorts as such.
+    pub fn to_sql(&self, field: impl Fn(&str) -> String, time: impl Fn(&str) -> String) -> String {
+        let direction = match self.direction {
+            Direction::Asc => "ASC",
+            Direction::Desc => "DESC",
+        };
+        if self.by == SortBy::Updated {
+            // Missing times first, as backends put nulls either end.
+            let updated_at = time("updated_at");
+            return format!(
+                "{updated_at} IS NOT NULL {direction}, {updated_at} {direction}, id {direction}"
+            );
+        }
+        if self.by == SortBy::Birthday {
+            let birthday = field("birthday");
+            let day = format!("substr({birthday}, 6, 5)");
+            let today = Utc::now().format("%m-%d");
+            return format!(
+                "CASE WHEN {birthday} IS NULL THEN '2' \
+                 WHEN {day} < '{today}' THEN '1' || {day} \
+                 ELSE '0' || {day} END {direction}, id {direction}"
+            );
+        }
+        if self.by == SortBy::Name {
+            // As `Contact::sort_name` has it.
+            let parts: Vec<_> = self
+                .names
+                .sort_fields()
+                .into_iter()
+                .map(|name| format!("NULLIF({}, '')", field(name)))
+                .collect();
+            return format!(
+                "lower(COALESCE(NULLIF(concat_ws('{SORT_SEPARATOR}', {}), ''), {}, '')) \
+                 {direction}, id {direction}",
+                parts.join(", "),
+                field("nickname")
+            );
+        }
+        match self.by.field() {
+            Some(name) => {
+                format!(
+                    "lower(COALESCE({}, '')) {direction}, id {direction}",
+                    field(name)
+                )
+            }
+            None => format!("id {direction}"),
+        }
+    }
+}
+
+impl SortBy {
+    /// Name of the contact field sorted by, `None` for the id, for
+    /// [`Self::Name`], which is several, and for [`Self::Updated`], a time.
+    fn field(self) -> Option<&'static str> {
+        match self {
+            Self::Name => None,
+            Self::First => Some("first"),
+            Self::Last => Some("last"),
+            Self::Email => Some("email"),
+            Self::Birthday => Some("birthday"),
+            Self::Created | Self::Updated => None,
+        }
+    }
+
+    fn key(self, contact: &Contact, names: NameOrder) -> Option<String> {
+        let value = match self {
+            Self::Name => return Some(contact.sort_name(names)),
+            Self::First => &contact.first,
+            Self::Last => &contact.last,
+            Self::Email => &contact.email,
+            Self::Birthday => return Some(upcoming(contact)),
+            Self::Created => return None,
+            Self::Updated => {
+                return contact
+                    .updated_at()
+                    .map(|at| at.format("%Y%m%d%H%M%S%6f").to_string())
+            }
+        };
+        Some(value.as_deref().unwrap_or_default().to_lowercase())
+    }
+}
+
+/// Orders birthdays by month and day from today on, then those passed this
+/// year, then missing ones.
+fn upcoming(contact: &Contact) -> String {
+    let Some(birthday) = contact.birthday else {
+        return "2".into();
+    };
+    let day = birthday.format("%m-%d").to_string();
+    if day < Utc::now().format("%m-%d").to_string() {
+        format!("1{day}")
+    } else {
+        format!("0{day}")
+    }
+}
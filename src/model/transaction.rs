@@ -0,0 +1,187 @@
+//! All-or-nothing batches of changes, see [`ContactRepo::begin`].
+
+use std::collections::BTreeMap;
+
+use tokio::sync::OwnedMutexGuard;
+
+use super::{
+    phones::PhoneOwners, unique_emails, unique_phones, Contact, ContactRepo, RepoError, Sort,
+};
+
+/// Changes made through [`ContactRepo::begin`], applied together by
+/// [`Self::commit`] or not at all.
+///
+/// Reads see the transaction's own changes. Dropping a transaction without
+/// committing it rolls it back.
+#[async_trait::async_trait]
+pub trait ContactTransaction: Send {
+    async fn all(&mut self) -> Result<Vec<Contact>, RepoError>;
+    async fn find(&mut self, id: u64) -> Result<Option<Contact>, RepoError>;
+    /// Adds or replaces `contact`, with a new id unless it has one, and
+    /// returns its id.
+    async fn save(&mut self, contact: Contact) -> Result<u64, RepoError>;
+    async fn delete(&mut self, contact: Contact) -> Result<(), RepoError>;
+
+    /// Applies the changes. If another writer got in the way, nothing is
+    /// applied and the error is a [`RepoError::Conflict`] with the contact
+    /// that no longer fits.
+    async fn commit(self: Box<Self>) -> Result<(), RepoError>;
+
+    async fn rollback(self: Box<Self>) {}
+}
+
+pub type BoxedTransaction = Box<dyn ContactTransaction>;
+
+/// Why a batch of changes made in one transaction, such as
+/// [`ContactRepo::create_many`], failed. None of its changes were applied.
+#[derive(Debug)]
+pub enum BatchError {
+    /// The items at these indices of the batch were rejected, each for its
+    /// error; the others would have been applied.
+    Rejected(BTreeMap<usize, RepoError>),
+    /// The transaction failed as a whole, e.g. the storage did.
+    Failed(RepoError),
+}
+
+impl From<RepoError> for BatchError {
+    fn from(err: RepoError) -> Self {
+        Self::Failed(err)
+    }
+}
+
+/// Final state of each contact a transaction touched, `None` once deleted.
+pub type Changes = BTreeMap<u64, Option<Contact>>;
+
+/// A repo that can apply a batch of [`Changes`] atomically, and so run a
+/// [`StagedTransaction`].
+#[async_trait::async_trait]
+pub trait Stage: ContactRepo + Send + Sync + 'static {
+    /// Id of the stored contact with `email`, if any.
+    async fn email_owner(&self, email: &str) -> Result<Option<u64>, RepoError>;
+
+    /// Id for a contact created in a transaction with `changes` so far.
+    async fn next_id(&self, changes: &Changes) -> Result<u64, RepoError>;
+
+    /// Applies `changes`, or fails with [`email_taken`] if a changed
+    /// contact's email is now taken.
+    async fn apply(&self, changes: &Changes) -> Result<(), RepoError>;
+}
+
+/// Error for applying `changes` when the email of the contact with `id` has
+/// been taken by another writer.
+pub fn email_taken(changes: &Changes, id: u64) -> RepoError {
+    let contact = changes[&id].clone().expect("a saved contact");
+    RepoError::email_taken(contact)
+}
+
+/// Transaction keeping its changes in memory, on top of the unchanged
+/// contacts of the repo, until they are applied by [`Stage::apply`].
+pub struct StagedTransaction<R> {
+    repo: R,
+    changes: Changes,
+    /// The phone numbers of the contacts as changed, once a save checked
+    /// them.
+    phones: Option<PhoneOwners>,
+    /// Held by repos that can't detect conflicting writes at commit time.
+    _writer: Option<OwnedMutexGuard<()>>,
+}
+
+impl<R: Stage> StagedTransaction<R> {
+    pub fn new(repo: R, writer: Option<OwnedMutexGuard<()>>) -> Self {
+        Self {
+            repo,
+            changes: Changes::new(),
+            phones: None,
+            _writer: writer,
+        }
+    }
+
+    async fn email_taken(&self, contact: &Contact) -> Result<bool, RepoError> {
+        let Some(email) = contact.email.as_deref().filter(|_| unique_emails()) else {
+            return Ok(false);
+        };
+        let staged = self.changes.iter().find_map(|(id, change)| {
+            let change = change.as_ref()?;
+            (change.email.as_deref() == Some(email)).then_some(*id)
+        });
+        let owner = match staged {
+            Some(id) => Some(id),
+            None => self
+                .repo
+                .email_owner(email)
+                .await?
+                .filter(|id| !self.changes.contains_key(id)),
+        };
+        Ok(owner.is_some_and(|owner| Some(owner) != contact.id))
+    }
+
+    async fn check_phones(&mut self, contact: Contact) -> Result<Contact, RepoError> {
+        if self.phones.is_none() {
+            let contacts = self.all().await?;
+            self.phones = Some(PhoneOwners::new(&contacts));
+        }
+        self.phones.as_ref().unwrap().check(contact)
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: Stage> ContactTransaction for StagedTransaction<R> {
+    async fn all(&mut self) -> Result<Vec<Contact>, RepoError> {
+        let mut contacts: BTreeMap<u64, Contact> = self
+            .repo
+            .all(Sort::default())
+            .await
+            .into_iter()
+            .map(|contact| (contact.id.unwrap(), contact))
+            .collect();
+        for (id, change) in &self.changes {
+            match change {
+                Some(contact) => contacts.insert(*id, contact.clone()),
+                None => contacts.remove(id),
+            };
+        }
+        Ok(contacts.into_values().collect())
+    }
+
+    async fn find(&mut self, id: u64) -> Result<Option<Contact>, RepoError> {
+        match self.changes.get(&id) {
+            Some(change) => Ok(change.clone()),
+            None => self.repo.find(id).await,
+        }
+    }
+
+    async fn save(&mut self, mut contact: Contact) -> Result<u64, RepoError> {
+        if !contact.validate() {
+            return Err(RepoError::invalid(contact));
+        }
+        if self.email_taken(&contact).await? {
+            return Err(RepoError::email_taken(contact));
+        }
+        if unique_phones() {
+            contact = self.check_phones(contact).await?;
+        }
+        let id = match contact.id {
+            Some(id) => id,
+            None => self.repo.next_id(&self.changes).await?,
+        };
+        contact.id = Some(id);
+        if let Some(phones) = &mut self.phones {
+            phones.save(&contact);
+        }
+        self.changes.insert(id, Some(contact));
+        Ok(id)
+    }
+
+    async fn delete(&mut self, contact: Contact) -> Result<(), RepoError> {
+        let id = contact.id.unwrap();
+        if let Some(phones) = &mut self.phones {
+            phones.remove(id);
+        }
+        self.changes.insert(id, None);
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), RepoError> {
+        self.repo.apply(&self.changes).await
+    }
+}
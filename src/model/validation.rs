@@ -0,0 +1,709 @@
+//! Rules contacts are checked against before they are saved.
+//!
+//! Each rule is a [`Validator`], recording why a contact breaks it in
+//! [`ValidationErrors`] by field, or warning about what may be a mistake
+//! though it doesn't block saving; [`Validators`] run several in turn, and
+//! [`ContactRules`] are those [`Contact::validate`] checks, as configured by
+//! a [`ValidationConfig`] once [set](set_contact_rules).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+    sync::OnceLock,
+};
+
+use chrono::Utc;
+use serde_json::Value;
+
+use super::{normalize_phone, Contact, ContactMethod, PhoneRegion};
+
+/// Why a record isn't valid, as an error per field, and warnings about
+/// fields that may be mistaken though they don't keep it from being saved.
+/// Only kept while the record is being edited: it is never stored with it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: BTreeMap<String, ValidationError>,
+    warnings: BTreeMap<String, ValidationError>,
+}
+
+/// An error or a warning about a field, with a code that stays the same
+/// whatever its message says, such as `email.duplicate`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ValidationError {
+    /// The field, without the index of a row such as `phones.0`, and the
+    /// `reason` it was recorded for, separated by a dot.
+    pub code: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, reason: &str, message: String) -> Self {
+        let field: Vec<_> = field
+            .split('.')
+            .filter(|part| part.parse::<usize>().is_err())
+            .collect();
+        Self {
+            code: format!("{}.{reason}", field.join(".")),
+            message,
+        }
+    }
+}
+
+impl ValidationErrors {
+    /// Where errors about the record as a whole are recorded, rather than
+    /// about one of its fields, such as those of rules across fields.
+    pub const FORM: &'static str = "form";
+
+    /// Records why `field` isn't valid, replacing any earlier error: for
+    /// `reason`, such as `required`, and as `message`.
+    pub fn insert(&mut self, field: impl Into<String>, reason: &str, message: impl Into<String>) {
+        let field = field.into();
+        let error = ValidationError::new(&field, reason, message.into());
+        self.errors.insert(field, error);
+    }
+
+    /// The message of the error about `field`, if any.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.errors.get(field).map(|error| error.message.as_str())
+    }
+
+    /// The code of the error about `field`, if any.
+    pub fn code(&self, field: &str) -> Option<&str> {
+        self.errors.get(field).map(|error| error.code.as_str())
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        self.errors.contains_key(field)
+    }
+
+    /// Whether there are no errors, whatever the warnings.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Forgets both the errors and the warnings.
+    pub fn clear(&mut self) {
+        self.errors.clear();
+        self.warnings.clear();
+    }
+
+    /// The fields and their messages, by field.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.errors
+            .iter()
+            .map(|(field, error)| (field.as_str(), error.message.as_str()))
+    }
+
+    /// The fields and their errors, with their codes, by field.
+    pub fn errors(&self) -> &BTreeMap<String, ValidationError> {
+        &self.errors
+    }
+
+    /// Warns that `field` may be mistaken, for `reason` and as `message`,
+    /// replacing any earlier warning.
+    pub fn warn(&mut self, field: impl Into<String>, reason: &str, message: impl Into<String>) {
+        let field = field.into();
+        let warning = ValidationError::new(&field, reason, message.into());
+        self.warnings.insert(field, warning);
+    }
+
+    pub fn warning(&self, field: &str) -> Option<&str> {
+        self.warnings
+            .get(field)
+            .map(|warning| warning.message.as_str())
+    }
+
+    /// The fields warned about and their warnings' messages, by field.
+    pub fn warnings(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.warnings
+            .iter()
+            .map(|(field, warning)| (field.as_str(), warning.message.as_str()))
+    }
+}
+
+/// Serializes as the messages of the errors by field, as templates look
+/// them up; the codes are [passed on](Self::errors) by themselves, and so
+/// are the warnings.
+impl serde::Serialize for ValidationErrors {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (field, message)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{field}: {message}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A rule records are checked against. It may also normalize the fields it
+/// checks, e.g. to store a URL with its scheme.
+pub trait Validator<T: ?Sized>: Send + Sync {
+    /// Records in `errors` why `value` breaks the rule, if it does.
+    fn validate(&self, value: &mut T, errors: &mut ValidationErrors);
+}
+
+impl<T: ?Sized, F> Validator<T> for F
+where
+    F: Fn(&mut T, &mut ValidationErrors) + Send + Sync,
+{
+    fn validate(&self, value: &mut T, errors: &mut ValidationErrors) {
+        self(value, errors)
+    }
+}
+
+/// Runs each of its validators in the order added, recording all their
+/// errors.
+pub struct Validators<T: ?Sized> {
+    validators: Vec<Box<dyn Validator<T>>>,
+}
+
+impl<T: ?Sized> Default for Validators<T> {
+    fn default() -> Self {
+        Self {
+            validators: Vec::new(),
+        }
+    }
+}
+
+impl<T: ?Sized> Validators<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `validator`, to run after those added before it.
+    pub fn with(mut self, validator: impl Validator<T> + 'static) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+}
+
+impl<T: ?Sized> Validator<T> for Validators<T> {
+    fn validate(&self, value: &mut T, errors: &mut ValidationErrors) {
+        for validator in &self.validators {
+            validator.validate(value, errors);
+        }
+    }
+}
+
+/// Which fields contacts must have and how long they may be, and whether
+/// their emails must be unique, as read from the JSON file of
+/// `VALIDATION_RULES`, e.g.
+/// `{"required": ["email", "last"], "lengths": {"first": {"max": 50}}}`.
+/// Fields are named as in the stored contact. By default only the email is
+/// required, and it is unique, and contacts need an email or a phone
+/// number.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// Fields that may not be left empty, such as `first` or `phones`.
+    pub required: BTreeSet<String>,
+    /// The fewest and most characters of text fields, or entries of lists,
+    /// when given.
+    pub lengths: BTreeMap<String, Length>,
+    /// Whether no two contacts may have the same email.
+    pub unique_email: bool,
+    /// Whether contacts must have an email, primary or other, or a phone
+    /// number to reach them by.
+    pub reachable: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Length {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+/// Fields of the stored contact that are kept by the app rather than
+/// entered, or checked by rules of their own such as custom fields.
+const UNCONFIGURABLE_FIELDS: [&str; 11] = [
+    "id",
+    "uuid",
+    "version",
+    "created_at",
+    "updated_at",
+    "has_photo",
+    "attachments",
+    "starred",
+    "archived",
+    "preferred",
+    "custom",
+];
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            required: BTreeSet::from(["email".to_owned()]),
+            lengths: BTreeMap::new(),
+            unique_email: true,
+            reachable: true,
+        }
+    }
+}
+
+impl ValidationConfig {
+    /// Reads the config from a JSON file, failing on fields contacts don't
+    /// have.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let config: Self = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        let fields = configurable_fields();
+        let unknown = config
+            .required
+            .iter()
+            .chain(config.lengths.keys())
+            .find(|field| !fields.contains(field.as_str()));
+        match unknown {
+            Some(field) => Err(format!("contacts have no field '{field}' to validate")),
+            None => Ok(config),
+        }
+    }
+}
+
+/// The fields a [`ValidationConfig`] may name.
+fn configurable_fields() -> BTreeSet<String> {
+    let Ok(Value::Object(fields)) = serde_json::to_value(Contact::default()) else {
+        unreachable!("contacts serialize to objects");
+    };
+    fields
+        .into_iter()
+        .map(|(field, _)| field)
+        .filter(|field| !UNCONFIGURABLE_FIELDS.contains(&field.as_str()))
+        .collect()
+}
+
+/// The rules every contact is checked against when saved, as `config` has
+/// them, reading phone numbers without a country code as numbers of
+/// `phone_region`.
+pub fn contact_rules(
+    config: &ValidationConfig,
+    phone_region: Option<PhoneRegion>,
+) -> Validators<Contact> {
+    let rules = Validators::new()
+        .with(configured_fields(config))
+        .with(texts_not_too_long(config))
+        .with(primary_email)
+        .with(other_emails)
+        .with(phone_numbers(phone_region))
+        .with(website_url)
+        .with(known_timezone)
+        .with(reachable_by_preferred)
+        .with(birthday_not_in_future)
+        .with(phone_missing)
+        .with(names_in_caps);
+    if config.reachable {
+        rules.with(email_or_phone)
+    } else {
+        rules
+    }
+}
+
+/// The [`contact_rules`] of a [`ValidationConfig`], and whether it has
+/// emails unique and whether phone numbers are, which the repos check as
+/// they save.
+pub struct ContactRules {
+    validators: Validators<Contact>,
+    unique_email: bool,
+    unique_phones: bool,
+}
+
+impl ContactRules {
+    pub fn new(
+        config: &ValidationConfig,
+        phone_region: Option<PhoneRegion>,
+        unique_phones: bool,
+    ) -> Self {
+        Self {
+            validators: contact_rules(config, phone_region),
+            unique_email: config.unique_email,
+            unique_phones,
+        }
+    }
+}
+
+impl Validator<Contact> for ContactRules {
+    fn validate(&self, contact: &mut Contact, errors: &mut ValidationErrors) {
+        self.validators.validate(contact, errors);
+    }
+}
+
+static CONTACT_RULES: OnceLock<ContactRules> = OnceLock::new();
+
+fn rules() -> &'static ContactRules {
+    CONTACT_RULES.get_or_init(|| ContactRules::new(&ValidationConfig::default(), None, false))
+}
+
+/// Makes [`Contact::validate`] check `rules`, such as those of the
+/// configured [`ValidationConfig`], rather than the default ones. Only the
+/// first rules set count, and only if no contact was validated before:
+/// otherwise `rules` are given back.
+pub fn set_contact_rules(rules: ContactRules) -> Result<(), ContactRules> {
+    CONTACT_RULES.set(rules)
+}
+
+/// Whether no two contacts may have the same email, by the
+/// [rules set](set_contact_rules).
+pub fn unique_emails() -> bool {
+    rules().unique_email
+}
+
+/// Whether no two contacts may have the same phone number, by the
+/// [rules set](set_contact_rules).
+pub fn unique_phones() -> bool {
+    rules().unique_phones
+}
+
+impl Contact {
+    /// Checks the contact against the [rules set](set_contact_rules),
+    /// recording why it isn't valid in its `errors`.
+    pub fn validate(&mut self) -> bool {
+        self.validate_with(rules())
+    }
+
+    /// The errors and warnings checking a copy of the contact against the
+    /// [rules set](set_contact_rules) records, leaving the contact as is.
+    pub fn validation(&self) -> ValidationErrors {
+        let mut contact = self.clone();
+        contact.validate();
+        contact.errors
+    }
+
+    /// [Normalizes](Self::normalize) the contact and checks it against
+    /// `validator`, recording why it isn't valid in its `errors`, besides
+    /// those already there.
+    pub fn validate_with(&mut self, validator: &dyn Validator<Contact>) -> bool {
+        self.normalize();
+        let mut errors = std::mem::take(&mut self.errors);
+        validator.validate(self, &mut errors);
+        self.errors = errors;
+        self.errors.is_empty()
+    }
+}
+
+/// The fields `config` requires aren't empty, and the text fields and lists
+/// it limits have as many characters or entries as it allows, if given.
+fn configured_fields(config: &ValidationConfig) -> impl Validator<Contact> {
+    let (required, lengths) = (config.required.clone(), config.lengths.clone());
+    move |contact: &mut Contact, errors: &mut ValidationErrors| {
+        let Ok(Value::Object(fields)) = serde_json::to_value(&*contact) else {
+            return;
+        };
+        for field in &required {
+            if fields.get(field).is_none_or(is_empty) {
+                errors.insert(
+                    field,
+                    "required",
+                    format!("{} Required", field_label(field)),
+                );
+            }
+        }
+        for (field, length) in &lengths {
+            let (len, unit) = match fields.get(field) {
+                Some(Value::String(text)) => (text.trim().chars().count(), "Characters"),
+                Some(Value::Array(entries)) => (entries.len(), "Entries"),
+                _ => continue,
+            };
+            let (reason, error) = match (length.min, length.max) {
+                _ if len == 0 => continue,
+                (Some(min), _) if len < min => ("too_short", format!("At Least {min} {unit}")),
+                (_, Some(max)) if len > max => ("too_long", format!("At Most {max} {unit}")),
+                _ => continue,
+            };
+            errors.insert(field, reason, error);
+        }
+    }
+}
+
+/// The most characters of a text field the [`ValidationConfig`] sets no
+/// maximum for, and of each text of the entries of lists, such as a phone
+/// number or the street of an address.
+pub const MAX_TEXT_LENGTH: usize = 500;
+
+/// The most characters of the notes, unless configured otherwise.
+pub const MAX_NOTES_LENGTH: usize = 20_000;
+
+/// No text field is longer than `config` allows or else than
+/// [`MAX_TEXT_LENGTH`], or [`MAX_NOTES_LENGTH`] for the notes, and no text of
+/// an entry of a list longer than [`MAX_TEXT_LENGTH`], so nothing posted can
+/// grow a contact without bound.
+fn texts_not_too_long(config: &ValidationConfig) -> impl Validator<Contact> {
+    let lengths = config.lengths.clone();
+    move |contact: &mut Contact, errors: &mut ValidationErrors| {
+        let Ok(Value::Object(fields)) = serde_json::to_value(&*contact) else {
+            return;
+        };
+        for (field, value) in &fields {
+            if errors.contains(field) || UNCONFIGURABLE_FIELDS.contains(&field.as_str()) {
+                continue;
+            }
+            let max = match value {
+                Value::String(_) if lengths.get(field).is_some_and(|l| l.max.is_some()) => continue,
+                Value::String(_) if field == "notes" => MAX_NOTES_LENGTH,
+                _ => MAX_TEXT_LENGTH,
+            };
+            if longest_text(value) > max {
+                errors.insert(field, "too_long", format!("At Most {max} Characters"));
+            }
+        }
+    }
+}
+
+/// The number of characters of the longest text in `value`.
+fn longest_text(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.chars().count(),
+        Value::Array(values) => values.iter().map(longest_text).max().unwrap_or_default(),
+        Value::Object(values) => values.values().map(longest_text).max().unwrap_or_default(),
+        Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        Value::Array(entries) => entries.is_empty(),
+        Value::Object(entries) => entries.is_empty(),
+        Value::Bool(_) | Value::Number(_) => false,
+    }
+}
+
+/// A field's name as the forms label it, e.g. `Job Title` for `job_title`.
+fn field_label(field: &str) -> String {
+    let field = match field {
+        "first" | "middle" | "last" => format!("{field}_name"),
+        _ => field.to_owned(),
+    };
+    field
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_uppercase().chain(chars).collect()
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The primary email, if given, is valid, stored trimmed and lowercased so
+/// that it is unique however it is written.
+fn primary_email(contact: &mut Contact, errors: &mut ValidationErrors) {
+    let email = contact.email.as_deref().unwrap_or_default();
+    if email.trim().is_empty() {
+        contact.email = None;
+        return;
+    }
+    match normalize_email(email) {
+        Ok(email) => contact.email = Some(email),
+        Err((reason, error)) => errors.insert("email", reason, error),
+    }
+}
+
+/// The further emails are valid, stored like the primary one, and aren't
+/// the primary one.
+fn other_emails(contact: &mut Contact, errors: &mut ValidationErrors) {
+    for (index, email) in contact.emails.iter_mut().enumerate() {
+        let (reason, error) = match normalize_email(&email.value) {
+            Ok(value) if Some(&value) == contact.email.as_ref() => {
+                ("same_as_primary", "Same As Primary Email")
+            }
+            Ok(value) => {
+                email.value = value;
+                continue;
+            }
+            Err(error) => error,
+        };
+        errors.insert(format!("emails.{index}"), reason, error);
+    }
+}
+
+/// `email` trimmed and lowercased, or why it isn't an address as RFC 5321
+/// has them, as a reason and a message: a dot-atom or quoted local part,
+/// `@`, and a domain name of at least two labels or an address literal such
+/// as `[192.0.2.1]`.
+fn normalize_email(email: &str) -> Result<String, (&'static str, &'static str)> {
+    let email = email.trim().to_lowercase();
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return Err(("missing_at", "Missing @"));
+    };
+    if email.len() > 254 || local.len() > 64 {
+        return Err(("too_long", "Email Too Long"));
+    }
+    let atext = |c: char| c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c);
+    let local_valid = match local.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+        Some(quoted) => {
+            let mut escaped = false;
+            quoted.chars().all(|c| match (escaped, c) {
+                (true, _) => {
+                    escaped = false;
+                    c.is_ascii_graphic() || c == ' '
+                }
+                (false, '\\') => {
+                    escaped = true;
+                    true
+                }
+                (false, '"') => false,
+                (false, _) => c.is_ascii_graphic() || c == ' ' || !c.is_ascii(),
+            }) && !escaped
+        }
+        None => local
+            .split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(atext)),
+    };
+    if local.is_empty() || !local_valid {
+        return Err(("invalid", "Invalid Email"));
+    }
+    if !valid_domain(domain) {
+        return Err(("invalid_domain", "Invalid Domain"));
+    }
+    Ok(email)
+}
+
+/// A domain name of labels of letters, digits and inner hyphens, with a
+/// top-level one that isn't all digits, or a bracketed IP address.
+fn valid_domain(domain: &str) -> bool {
+    if let Some(literal) = domain.strip_prefix('[').and_then(|d| d.strip_suffix(']')) {
+        return match literal.strip_prefix("ipv6:") {
+            Some(ip) => ip.parse::<std::net::Ipv6Addr>().is_ok(),
+            None => literal.parse::<std::net::Ipv4Addr>().is_ok(),
+        };
+    }
+    let labels: Vec<_> = domain.split('.').collect();
+    let label_valid = |label: &&str| {
+        (1..=63).contains(&label.len())
+            && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    };
+    domain.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(label_valid)
+        && !labels[labels.len() - 1].chars().all(|c| c.is_ascii_digit())
+}
+
+/// The phone numbers are valid, read as numbers of `region` if they have no
+/// country code, and are stored in E.164.
+fn phone_numbers(region: Option<PhoneRegion>) -> impl Validator<Contact> {
+    move |contact: &mut Contact, errors: &mut ValidationErrors| {
+        for (index, phone) in contact.phones.iter_mut().enumerate() {
+            match normalize_phone(&phone.value, region) {
+                Some(number) => phone.value = number,
+                None => errors.insert(format!("phones.{index}"), "invalid", "Invalid Phone Number"),
+            }
+        }
+    }
+}
+
+/// The website is an `http` or `https` URL, stored with the scheme added if
+/// it was left out.
+fn website_url(contact: &mut Contact, errors: &mut ValidationErrors) {
+    let Some(website) = &contact.website else {
+        return;
+    };
+    match normalize_website(website) {
+        Some(website) => contact.website = Some(website),
+        None => errors.insert("website", "invalid", "Invalid URL"),
+    }
+}
+
+/// `website` with `https://` added if it has no scheme, or `None` if it
+/// isn't an `http` or `https` URL of a named host.
+fn normalize_website(website: &str) -> Option<String> {
+    let website = website.trim();
+    let url = if website.contains("://") {
+        url::Url::parse(website)
+    } else {
+        url::Url::parse(&format!("https://{website}"))
+    };
+    let url = url.ok()?;
+    let host = url.host_str()?;
+    let named = host.contains('.') || host == "localhost";
+    (matches!(url.scheme(), "http" | "https") && named).then(|| url.into())
+}
+
+/// The time zone is an IANA one, stored by its canonical name.
+fn known_timezone(contact: &mut Contact, errors: &mut ValidationErrors) {
+    let Some(timezone) = &contact.timezone else {
+        return;
+    };
+    match timezone.trim().parse::<chrono_tz::Tz>() {
+        Ok(tz) => contact.timezone = Some(tz.name().to_owned()),
+        Err(_) => errors.insert("timezone", "unknown", "Unknown Time Zone"),
+    }
+}
+
+/// The contact has what it takes to be reached the way it prefers.
+fn reachable_by_preferred(contact: &mut Contact, errors: &mut ValidationErrors) {
+    let unreachable = match contact.preferred {
+        ContactMethod::Email if contact.email.as_deref().unwrap_or_default().is_empty() => {
+            Some("No Email To Use")
+        }
+        ContactMethod::Phone | ContactMethod::Sms if contact.phones.is_empty() => {
+            Some("No Phone Number To Use")
+        }
+        _ => None,
+    };
+    if let Some(error) = unreachable {
+        errors.insert("preferred", "unreachable", error);
+    }
+}
+
+fn birthday_not_in_future(contact: &mut Contact, errors: &mut ValidationErrors) {
+    if contact.birthday > Some(Utc::now().date_naive()) {
+        errors.insert("birthday", "in_future", "Birthday In The Future");
+    }
+}
+
+/// A contact has some email or a phone number, or else an error about the
+/// whole [form](ValidationErrors::FORM).
+fn email_or_phone(contact: &mut Contact, errors: &mut ValidationErrors) {
+    if contact.email.is_none() && contact.emails.is_empty() && contact.phones.is_empty() {
+        errors.insert(
+            ValidationErrors::FORM,
+            "unreachable",
+            "Email Or Phone Number Required",
+        );
+    }
+}
+
+/// Warns about a contact without a phone number, unless one is required.
+fn phone_missing(contact: &mut Contact, errors: &mut ValidationErrors) {
+    if contact.phones.is_empty() && !errors.contains("phones") {
+        errors.warn("phones", "missing", "No Phone Number");
+    }
+}
+
+/// Warns about names written in capitals only, as when typed with caps
+/// lock on, leaving out short ones such as initials.
+fn names_in_caps(contact: &mut Contact, errors: &mut ValidationErrors) {
+    let names = [
+        ("first", &contact.first),
+        ("middle", &contact.middle),
+        ("last", &contact.last),
+        ("nickname", &contact.nickname),
+    ];
+    for (field, name) in names {
+        let letters: Vec<_> = name
+            .as_deref()
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .collect();
+        if letters.len() > 2 && letters.iter().all(|c| c.is_uppercase()) {
+            errors.warn(
+                field,
+                "all_caps",
+                format!("{} In All Caps", field_label(field)),
+            );
+        }
+    }
+}
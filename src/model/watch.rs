@@ -0,0 +1,206 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Mutex},
+    time::Duration,
+};
+
+use notify::{RecursiveMode, Watcher as _};
+use tokio::sync::broadcast;
+
+use super::{digest, Contact, ContactEvent, ContactStore, MemContactRepo, EVENT_BUFFER};
+
+/// State of [`MemContactRepo::with_file_watch`].
+#[derive(Debug)]
+pub struct FileWatch {
+    _watcher: Mutex<notify::RecommendedWatcher>,
+    /// [`contact_digest`] of each contact in the last snapshot, to tell which
+    /// contacts an external edit touched.
+    base: Mutex<HashMap<u64, u64>>,
+    /// Sends the changes reloads make to the store.
+    events: broadcast::Sender<ContactEvent>,
+}
+
+impl FileWatch {
+    pub fn subscribe(&self) -> broadcast::Receiver<ContactEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sends `events`, which only fails when nobody is subscribed, letting
+    /// subscribers take each one so a reload of many doesn't outrun them.
+    async fn send(&self, events: Vec<ContactEvent>) {
+        for event in events {
+            let _ = self.events.send(event);
+            tokio::task::yield_now().await;
+        }
+    }
+
+    pub fn set_base(&self, store: &ContactStore) {
+        *self.base.lock().unwrap() = store
+            .contacts
+            .iter()
+            .map(|(id, contact)| (*id, contact_digest(contact)))
+            .collect();
+    }
+}
+
+fn contact_digest(contact: &Contact) -> u64 {
+    digest(&serde_json::to_vec(contact).expect("serializing succeed"))
+}
+
+/// The events of the changes from the contacts of `old` to those of `new`,
+/// by id, made by nobody known.
+fn changes(old: &ContactStore, new: &ContactStore) -> Vec<ContactEvent> {
+    let ids: BTreeSet<u64> = old
+        .contacts
+        .keys()
+        .chain(new.contacts.keys())
+        .copied()
+        .collect();
+    ids.into_iter()
+        .filter_map(|id| match (old.contacts.get(&id), new.contacts.get(&id)) {
+            (None, Some(contact)) => Some(ContactEvent::Created {
+                contact: contact.clone(),
+                author: None,
+            }),
+            (Some(old), Some(contact)) if contact_digest(old) != contact_digest(contact) => {
+                Some(ContactEvent::Updated {
+                    contact: contact.clone(),
+                    author: None,
+                })
+            }
+            (Some(_), None) => Some(ContactEvent::Deleted { id }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Time for a burst of file events, e.g. from an editor saving, to settle
+/// before the file is read.
+const SETTLE: Duration = Duration::from_millis(200);
+
+impl MemContactRepo {
+    /// Watches the snapshot file and reloads the store when something other
+    /// than this repo changes it, e.g. a text editor.
+    ///
+    /// Local changes that are not in the snapshot yet are replayed on top of
+    /// the reloaded file, so they win over external edits of the same
+    /// contacts; each such conflict is logged. The contacts a reload changes
+    /// are sent to the [subscribers](super::ContactRepo::subscribe) of the repo.
+    /// Must be called from within a tokio runtime, on a repo loaded from a
+    /// path.
+    pub async fn with_file_watch(self) -> Self {
+        let path = self.path.clone().expect("a repo loaded from a path");
+        let file_name = path.file_name().expect("a file path").to_owned();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from("."),
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    let ours = event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == Some(&file_name));
+                    if ours && !event.kind.is_access() {
+                        let _ = tx.send(());
+                    }
+                }
+            })
+            .expect("file watcher to start");
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .expect("watching succeed");
+        let watch = FileWatch {
+            _watcher: Mutex::new(watcher),
+            base: Mutex::default(),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        };
+        // Right after loading, the store matches its snapshot.
+        watch.set_base(&*self.store.read().await);
+        self.watch.set(watch).expect("a repo watched only once");
+
+        let repo = self.clone();
+        tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                tokio::time::sleep(SETTLE).await;
+                while rx.try_recv().is_ok() {}
+                repo.reload(&path).await;
+            }
+        });
+        self
+    }
+
+    async fn reload(&self, path: &Path) {
+        let mut journal = self.journal.lock().await;
+        let Some(journal) = journal.as_mut() else {
+            return;
+        };
+        // The file may be missing for a moment while it is being replaced.
+        let Ok(data) = fs::read(path) else {
+            return;
+        };
+        let data_digest = digest(&data);
+        if data_digest == self.snapshot_digest.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut external = match ContactStore::parse(data, self.options.key.as_ref()) {
+            Ok((external, _)) => external,
+            Err(err) => {
+                eprintln!("not reloading {}: {err}", path.display());
+                return;
+            }
+        };
+
+        let watch = self.watch.get().unwrap();
+        let mut store = self.store.write().await;
+        let local = journal.read_entries().expect("a readable journal");
+        if local.is_empty() {
+            self.snapshot_digest.store(data_digest, Ordering::SeqCst);
+            watch.set_base(&external);
+            let events = changes(&store, &external);
+            *store = external;
+            drop(store);
+            println!("reloaded {}", path.display());
+            watch.send(events).await;
+            return;
+        }
+
+        let base = watch.base.lock().unwrap().clone();
+        let mut touched = BTreeMap::new();
+        for entry in local {
+            for id in entry.ids() {
+                touched
+                    .entry(id)
+                    .or_insert_with(|| external.contacts.get(&id).map(contact_digest));
+            }
+            external.apply(entry);
+        }
+        // Contacts changed both here and externally since the last snapshot.
+        let conflicts: Vec<u64> = touched
+            .into_iter()
+            .filter(|(id, external)| base.get(id) != external.as_ref())
+            .map(|(id, _)| id)
+            .collect();
+
+        let events = changes(&store, &external);
+        *store = external;
+        self.write_snapshot(path, &store);
+        journal.clear().expect("writing succeed");
+        drop(store);
+        watch.send(events).await;
+
+        if conflicts.is_empty() {
+            println!("reloaded {}", path.display());
+        } else {
+            eprintln!(
+                "reloaded {}, keeping local changes to contacts {conflicts:?} over external edits",
+                path.display()
+            );
+        }
+    }
+}